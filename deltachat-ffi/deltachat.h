@@ -245,6 +245,88 @@ uint32_t        dc_get_id                    (dc_context_t* context);
 dc_event_emitter_t* dc_get_event_emitter(dc_context_t* context);
 
 
+/**
+ * Coalesce events of the given type.
+ *
+ * Events of this type are then emitted at most once per interval
+ * and chat or contact; events in between are merged into a single event
+ * that is emitted when the interval is over or when the library goes idle.
+ * Merged #DC_EVENT_MSGS_CHANGED events for different messages
+ * of the same chat have data2 set to 0.
+ *
+ * This is useful to avoid UIs being flooded with events eg. during the first sync.
+ * Only #DC_EVENT_MSGS_CHANGED, #DC_EVENT_MSGS_NOTICED, #DC_EVENT_CHAT_MODIFIED,
+ * #DC_EVENT_CONTACTS_CHANGED and #DC_EVENT_CONNECTIVITY_CHANGED can be coalesced.
+ * By default, no events are coalesced.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param event_id One of the @ref DC_EVENT constants listed above.
+ * @param milliseconds Minimum interval between two events of the type for the same chat or contact,
+ *     0 to disable coalescing for the event type.
+ * @return 1=success, 0=error, eg. the event type cannot be coalesced.
+ */
+int             dc_set_event_coalescing      (dc_context_t* context, int event_id, uint32_t milliseconds);
+
+
+/**
+ * Deliver events additionally to a webhook.
+ *
+ * This is meant for headless deployments such as bots,
+ * so supervisors can observe account activity.
+ * Each event is POSTed as a JSON object with the fields
+ * `context_id`, `event_id`, `event` and `timestamp`,
+ * see dc_get_next_event_json() for the format of `event`.
+ * If a secret is given, the body is signed with HMAC-SHA256
+ * and the hex-encoded signature is sent in the `X-Deltachat-Signature` header.
+ * Failed deliveries are retried 3 times with exponential backoff.
+ *
+ * Events are still delivered to the event emitters as usual.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param endpoint HTTP URL, eg. `http://localhost:8080/events`,
+ *     or `unix:` followed by the path of a unix socket accepting HTTP requests.
+ *     NULL or an empty string disables the webhook.
+ * @param secret Secret used to sign the requests, NULL to send unsigned requests.
+ */
+void            dc_set_event_webhook         (dc_context_t* context, const char* endpoint, const char* secret);
+
+
+/**
+ * Wait for the next event and get it as JSON.
+ *
+ * This is an alternative to dc_get_event_emitter()
+ * for bindings that cannot easily handle event objects,
+ * eg. because they poll events from a JavaScript event loop.
+ * The JSON object contains the fields
+ * `context_id`, `event_id` (one of the @ref DC_EVENT constants)
+ * and `event`, an object with the name of the event in `type`, eg. `MsgsChanged`,
+ * and its fields, if any, in `data`, eg. `{"chat_id": 10, "msg_id": 1}`.
+ *
+ * Events are delivered by this function in addition to the event emitters,
+ * starting with the first call to this function.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param timeout_ms Maximum time to wait for an event in milliseconds.
+ * @return JSON string which must be released using dc_str_unref() after usage.
+ *     NULL on timeout or if interrupted by dc_interrupt_event_polling().
+ */
+char*           dc_get_next_event_json       (dc_context_t* context, uint32_t timeout_ms);
+
+
+/**
+ * Make a pending call to dc_get_next_event_json() return NULL immediately,
+ * eg. to shut down a polling thread.
+ * If no call is pending, the next call returns immediately.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ */
+void            dc_interrupt_event_polling   (dc_context_t* context);
+
+
 /**
  * Get the blob directory.
  *
@@ -256,6 +338,22 @@ dc_event_emitter_t* dc_get_event_emitter(dc_context_t* context);
 char*           dc_get_blobdir               (const dc_context_t* context);
 
 
+/**
+ * Get the accent color of the account,
+ * e.g. to color-code accounts identically in multi-account UIs.
+ *
+ * The color is generated from the address, as for contacts, when it is first requested.
+ * It is stored in the `self_color` option afterwards,
+ * so it does not change if the address changes and is included in exported settings.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @return Color as 0x00rrggbb with rr=red, gg=green, bb=blue,
+ *     each in the range 0-255. 0 on errors.
+ */
+uint32_t        dc_get_self_color            (dc_context_t* context);
+
+
 /**
  * Configure the context.  The configuration is handled by key=value pairs as:
  *
@@ -281,6 +379,8 @@ char*           dc_get_blobdir               (const dc_context_t* context);
  *                    As for `displayname` and `selfstatus`, also the avatar is sent to the recipients.
  *                    To save traffic, however, the avatar is attached only as needed
  *                    and also recoded to a reasonable size.
+ * - `self_color`   = Accent color of the account as 24-bit RGB,
+ *                    generated on first use of dc_get_self_color().
  * - `e2ee_enabled` = 0=no end-to-end-encryption, 1=prefer end-to-end-encryption (default)
  * - `mdns_enabled` = 0=do not send or request read receipts,
  *                    1=send and request read receipts (default)
@@ -317,15 +417,64 @@ char*           dc_get_blobdir               (const dc_context_t* context);
  *                    0=do not save mime headers (default)
  * - `delete_device_after` = 0=do not delete messages from device automatically (default),
  *                    >=1=seconds, after which messages are deleted automatically from the device.
- *                    Messages in the "saved messages" chat (see dc_chat_is_self_talk()) are skipped.
+ *                    Messages in the "saved messages" chat (see dc_chat_is_self_talk()) are skipped
+ *                    unless `delete_device_after_includes_self_chat` is set.
  *                    Messages are deleted whether they were seen or not, the UI should clearly point that out.
  *                    See also dc_estimate_deletion_cnt().
+ * - `delete_device_chat_after` = 0=do not delete messages in the device chat automatically (default),
+ *                    >=1=seconds, after which messages in the device chat (see dc_chat_is_device_talk())
+ *                    are deleted automatically. `delete_device_after` does not apply to the device chat.
+ * - `delete_device_after_includes_self_chat` = 1=`delete_device_after` also deletes messages
+ *                    in the "saved messages" chat,
+ *                    0=keep messages in the "saved messages" chat (default).
+ * - `protect_starred_from_deletion` = 1=starred messages (see dc_star_msgs()) are neither deleted
+ *                    by ephemeral timers nor by `delete_device_after`, #DC_EVENT_MSG_PRESERVED is emitted instead,
+ *                    0=delete starred messages as any other message (default).
+ * - `ephemeral_grace_period` = 0=delete messages as soon as their ephemeral timer expires (default),
+ *                    >=1=seconds, after which messages with an expired ephemeral timer are deleted.
+ *                    Until then, the user can keep the message, see dc_cancel_msg_expiry().
+ * - `undelivered_grace_period` = 0=outgoing messages not sent until their ephemeral timer expires
+ *                    are marked as failed (default),
+ *                    >=1=seconds, during which such messages are still sent, eg. after a short network outage.
+ * - `sync_device_settings` = 1=synchronize `delete_device_after` and `delete_server_after`
+ *                    with other devices of the user having this option set,
+ *                    using hidden, encrypted messages to the "Saved messages" chat,
+ *                    0=the settings apply to this device only (default).
+ * - `slow_query_threshold` = 0=do not log slow database queries (default),
+ *                    >=1=milliseconds, after which database queries are logged
+ *                    as #DC_EVENT_WARNING along with their query plan.
+ *                    Meant for debugging performance issues of large accounts.
+ * - `delete_info_msgs_after` = 0=do not delete info messages automatically (default),
+ *                    >=1=seconds, after which info messages, e.g. about group changes, are deleted from the device.
+ *                    The latest info message of each kind, e.g. the latest group image change, is kept in each chat.
  * - `delete_server_after` = 0=do not delete messages from server automatically (default),
  *                    1=delete messages directly after receiving from server, mvbox is skipped.
  *                    >1=seconds, after which messages are deleted automatically from the server, mvbox is used as defined.
  *                    "Saved messages" are deleted from the server as well as
  *                    emails matching the `show_emails` settings above, the UI should clearly point that out.
  *                    See also dc_estimate_deletion_cnt().
+ * - `delete_server_after_self` = 0=use `delete_server_after` for outgoing messages as well (default),
+ *                    1=delete outgoing messages, eg. copies sent to self, directly after receiving from server,
+ *                    >1=seconds, after which outgoing messages are deleted automatically from the server.
+ *                    This allows to keep copies sent to self for a shorter time than received messages.
+ * - `delete_server_only_seen` = 1=`delete_server_after` deletes received messages from the server
+ *                    only after they are marked as seen, so unread messages can still be fetched by other devices,
+ *                    0=delete received messages regardless of their state (default).
+ *                    Ephemeral messages are deleted when their timer expires in any case.
+ * - `passthrough_attachments` = 1=leave attachments at their original location
+ *                    when a message is prepared with dc_prepare_msg() and copy them to the blob directory
+ *                    only when the message is sent with dc_send_msg().
+ *                    The files must not be removed or changed until the messages are sent.
+ *                    Images are still copied on preparation as they are recoded.
+ *                    0=copy attachments to the blob directory (default).
+ * - `blob_sync` = 0=leave writing blob files to disk to the operating system (default),
+ *                    1=sync each blob file after writing it,
+ *                    2=sync blob files in batches, e.g. after fetching messages.
+ *                    Syncing protects received attachments against power loss at the cost of speed.
+ * - `max_text_length` = 0=do not limit the length of message texts (default),
+ *                    >=1=maximum number of characters of message texts.
+ *                    Longer outgoing texts are sent as text attachment with a preview,
+ *                    longer incoming texts are truncated, see dc_msg_get_full_text().
  * - `media_quality` = DC_MEDIA_QUALITY_BALANCED (0) =
  *                    good outgoing images/videos/voice quality at reasonable sizes (default)
  *                    DC_MEDIA_QUALITY_WORSE (1)
@@ -346,6 +495,43 @@ char*           dc_get_blobdir               (const dc_context_t* context);
  * - `fetch_existing_msgs` = 1=fetch most recent existing messages on configure (default),
  *                    0=do not fetch existing messages on configure.
  *                    In both cases, existing recipients are added to the contact database.
+ * - `show_remote_content` = 1=keep remote images and stylesheets in the HTML returned by dc_get_msg_html(),
+ *                    0=remove remote content to avoid tracking (default)
+ * - `group_threads_by_references` = 1=group classic emails into chats by their References-chain,
+ *                    so that long threads with changing subjects and recipients stay in one chat,
+ *                    0=use the default heuristics (default)
+ * - `deletion_undo_window` = time in seconds during which deleting messages and chats
+ *                    can be reverted using dc_undo_last_deletion(),
+ *                    0=deletions are irreversible immediately (default)
+ * - `wkd_lookup` = 1=look up OpenPGP keys of new contacts not using Autocrypt
+ *                    in the Web Key Directory of their email provider,
+ *                    found keys are used to encrypt messages to the contact,
+ *                    see #DC_EVENT_CONTACT_KEY_IMPORTED.
+ *                    0=do not look up keys (default).
+ * - `publish_key` = 1=publish the own public key on keys.openpgp.org,
+ *                    so correspondents using classic OpenPGP software can find it.
+ *                    The verification mail sent by the keyserver is confirmed automatically
+ *                    and the key is uploaded again every 30 days.
+ *                    0=do not publish the key (default).
+ * - `group_rekeying` = 1=generate a new group secret when removing a member from a protected group;
+ *                    the secret is sent to the remaining members only
+ *                    and messages without the current secret are rejected,
+ *                    so removed members cannot send messages to the group anymore.
+ *                    0=keep the group secret (default).
+ * - `quiet_hours_start` = start of the quiet hours in minutes after midnight, local time.
+ *                    #DC_EVENT_INCOMING_MSG events during the quiet hours are marked as muted,
+ *                    see dc_event_get_data2_str().
+ *                    If start and end are equal, there are no quiet hours (default).
+ * - `quiet_hours_end` = end of the quiet hours in minutes after midnight, local time,
+ *                    may be before `quiet_hours_start` to span midnight.
+ * - `auto_backup_dir` = directory automatic backups are written to.
+ *                    Automatic backups are written in the background while IO is running,
+ *                    see #DC_EVENT_AUTO_BACKUP_WRITTEN and #DC_EVENT_AUTO_BACKUP_FAILED.
+ * - `auto_backup_interval` = 0=do not write automatic backups (default),
+ *                    >=1=seconds after which the next automatic backup is written.
+ * - `auto_backup_keep` = number of backups kept in `auto_backup_dir`,
+ *                    older backups are deleted after an automatic backup was written,
+ *                    including backups not written automatically, 0=keep all backups, defaults to 3.
  *
  * If you want to retrieve a value, use dc_get_config().
  *
@@ -431,6 +617,27 @@ int             dc_set_config_from_qr   (dc_context_t* context, const char* qr);
 char*           dc_get_info                  (const dc_context_t* context);
 
 
+/**
+ * Get local usage statistics of the account.
+ *
+ * The statistics are collected per week and never leave the device.
+ * The result is a JSON array with one object per week overlapping the given range,
+ * oldest first; weeks without any activity are omitted.
+ * Each object has the fields `week_start` (timestamp of the start of the week),
+ * `msgs_sent`, `msgs_received`, `msgs_encrypted` (number of sent and received
+ * messages that were encrypted) and `storage_bytes` (size of the database and
+ * the blob directory, 0 if not measured during the week).
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param timestamp_begin Start of the range, unix timestamp.
+ * @param timestamp_end End of the range, unix timestamp, exclusive.
+ * @return JSON string which must be released using dc_str_unref() after usage.
+ *     On errors, an empty string is returned.
+ */
+char*           dc_get_usage_stats           (dc_context_t* context, int64_t timestamp_begin, int64_t timestamp_end);
+
+
 /**
  * Get url that can be used to initiate an OAuth2 authorisation.
  *
@@ -639,6 +846,97 @@ void            dc_stop_io(dc_context_t* context);
 void            dc_maybe_network             (dc_context_t* context);
 
 
+#define         DC_HINT_APP_FOREGROUNDED     0
+#define         DC_HINT_APP_BACKGROUNDED     1
+#define         DC_HINT_LOW_POWER            2
+
+
+/**
+ * Tell the library about the state of the app.
+ *
+ * Instead of calling dc_stop_io() and dc_start_io() when the app goes to the background,
+ * the app should pass this hint.
+ * The library then adjusts IDLE keepalive and polling intervals,
+ * executes more jobs in a row and postpones housekeeping in power saving mode.
+ * When the app comes to the foreground, new messages are fetched immediately.
+ *
+ * @memberof dc_context_t
+ * @param context The context as created by dc_context_new().
+ * @param hint One of:
+ *     - DC_HINT_APP_FOREGROUNDED (0): the app is in the foreground, this is the default.
+ *     - DC_HINT_APP_BACKGROUNDED (1): the app is in the background.
+ *     - DC_HINT_LOW_POWER (2): the device is in a power saving mode, e.g. doze mode.
+ * @return 1=success, 0=error
+ */
+int             dc_maintenance_hint          (dc_context_t* context, int hint);
+
+
+/**
+ * Delete the account.
+ *
+ * If also_remove_server_data is set, the messages in the DeltaChat folder
+ * and the messages sent by the user are deleted from the server first.
+ * IO is started for this if needed.
+ * If the server data cannot be deleted, no local data is deleted.
+ *
+ * Afterwards, IO is stopped and the database and all files of the account are deleted,
+ * the context object must not be used anymore except for dc_context_unref().
+ * When using the account manager, remove the account using dc_accounts_remove_account() afterwards.
+ *
+ * The function may take a while, the progress is reported by #DC_EVENT_DELETE_ACCOUNT_PROGRESS events.
+ *
+ * @memberof dc_context_t
+ * @param context The context as created by dc_context_new().
+ * @param also_remove_server_data 1=delete messages from the server before, 0=delete local data only.
+ * @return 1=success, 0=error
+ */
+int             dc_delete_account            (dc_context_t* context, int also_remove_server_data);
+
+
+#define         DC_NETWORK_POLICY_UNRESTRICTED 0
+#define         DC_NETWORK_POLICY_METERED      1
+
+
+/**
+ * Set the network policy, typically called when the kind of connection changes.
+ *
+ * With DC_NETWORK_POLICY_METERED, e.g. on mobile data,
+ * messages larger than 256 KiB are not downloaded but deferred
+ * until the policy is set back to DC_NETWORK_POLICY_UNRESTRICTED, e.g. on Wi-Fi.
+ * Deferred messages can be listed using dc_get_deferred_downloads_json()
+ * and downloaded anyway using dc_download_deferred().
+ *
+ * @memberof dc_context_t
+ * @param context The context as created by dc_context_new().
+ * @param policy DC_NETWORK_POLICY_UNRESTRICTED (0, default) or DC_NETWORK_POLICY_METERED (1).
+ * @return 1=success, 0=error
+ */
+int             dc_set_network_policy        (dc_context_t* context, int policy);
+
+
+/**
+ * Get the messages whose download was deferred because of DC_NETWORK_POLICY_METERED.
+ *
+ * @memberof dc_context_t
+ * @param context The context as created by dc_context_new().
+ * @return JSON array of objects with the keys `id`, `sender` and `size` (in bytes).
+ *     NULL on errors.
+ *     Must be freed using dc_str_unref() after usage.
+ */
+char*           dc_get_deferred_downloads_json (dc_context_t* context);
+
+
+/**
+ * Download a deferred message regardless of the network policy.
+ *
+ * @memberof dc_context_t
+ * @param context The context as created by dc_context_new().
+ * @param id The ID of the deferred message as returned by dc_get_deferred_downloads_json().
+ * @return 1=success, 0=error
+ */
+int             dc_download_deferred         (dc_context_t* context, uint32_t id);
+
+
 
 /**
  * Save a keypair as the default keys for the user.
@@ -667,6 +965,8 @@ int             dc_preconfigure_keypair        (dc_context_t* context, const cha
 #define         DC_GCL_NO_SPECIALS           0x02
 #define         DC_GCL_ADD_ALLDONE_HINT      0x04
 #define         DC_GCL_FOR_FORWARDING        0x08
+#define         DC_GCL_CONTACT_REQUESTS_ONLY 0x10
+#define         DC_GCL_NO_CONTACT_REQUESTS   0x20
 
 
 /**
@@ -707,6 +1007,13 @@ int             dc_preconfigure_keypair        (dc_context_t* context, const cha
  *       not needed when DC_GCL_ARCHIVED_ONLY is already set)
  *     - if the flag DC_GCL_ADD_ALLDONE_HINT is set, DC_CHAT_ID_ALLDONE_HINT
  *       is added as needed.
+ *     - if the flag DC_GCL_CONTACT_REQUESTS_ONLY is set, only contact requests
+ *       are returned, e.g. to show them in a separate "Requests" inbox.
+ *       Contact requests are accepted using dc_accept_chat() or blocked using dc_block_chat();
+ *       messages in contact requests are not marked as seen and no read receipts
+ *       are sent until the request is accepted.
+ *     - if the flag DC_GCL_NO_CONTACT_REQUESTS is set, contact requests
+ *       are not added to the list.
  * @param query_str An optional query for filtering the list.  Only chats matching this query
  *     are returned.  Give NULL for no filtering.
  * @param query_id An optional contact ID for filtering the list.  Only chats including this contact ID
@@ -800,6 +1107,25 @@ uint32_t        dc_get_chat_id_by_contact_id (dc_context_t* context, uint32_t co
 uint32_t        dc_prepare_msg               (dc_context_t* context, uint32_t chat_id, dc_msg_t* msg);
 
 
+/**
+ * Estimate the size of a message when sent to a chat.
+ *
+ * The message is rendered as it would be sent,
+ * including encryption and the encoding of attachments,
+ * but it is neither added to the chat nor sent.
+ * This can be used to warn the user before sending attachments
+ * that exceed the message size limit of the provider.
+ *
+ * @memberof dc_context_t
+ * @param context The context object as returned from dc_context_new().
+ * @param chat_id Chat ID the message would be sent to.
+ * @param msg Message object to estimate the size of.
+ *     The object is not modified.
+ * @return Estimated size of the message in bytes, 0 on errors.
+ */
+uint64_t        dc_estimate_msg_size         (dc_context_t* context, uint32_t chat_id, const dc_msg_t* msg);
+
+
 /**
  * Send a message defined by a dc_msg_t object to a chat.
  *
@@ -1048,6 +1374,21 @@ dc_msg_t*       dc_get_draft                 (dc_context_t* context, uint32_t ch
 dc_array_t*     dc_get_chat_msgs             (dc_context_t* context, uint32_t chat_id, uint32_t flags, uint32_t marker1before);
 
 
+/**
+ * Get the first message of a chat sent on or after a given timestamp.
+ *
+ * This can be used to implement a "jump to date" function in long chats
+ * without loading all messages of the chat.
+ *
+ * @memberof dc_context_t
+ * @param context The context object as returned from dc_context_new().
+ * @param chat_id The chat ID to search the message in.
+ * @param timestamp Unix timestamp, the returned message is the first one sent on or after this time.
+ * @return The ID of the message, 0 if there is no such message or on errors.
+ */
+uint32_t        dc_get_first_msg_on_or_after (dc_context_t* context, uint32_t chat_id, int64_t timestamp);
+
+
 /**
  * Get the total number of messages in a chat.
  *
@@ -1093,6 +1434,31 @@ int             dc_get_fresh_msg_cnt         (dc_context_t* context, uint32_t ch
 int             dc_estimate_deletion_cnt    (dc_context_t* context, int from_server, int64_t seconds);
 
 
+/**
+ * Repair inconsistent database state,
+ * e.g. left behind by crashes or bugs in previous versions.
+ * This is typically offered in a debug menu.
+ *
+ * @memberof dc_context_t
+ * @param context The context object as returned from dc_context_new().
+ * @param kind What to repair, one of:
+ *     - DC_REPAIR_EPHEMERAL_TIMERS (1) starts the ephemeral timers of seen messages
+ *       whose timer was not started
+ *     - DC_REPAIR_CHAT_MEMBERS (2) restores the contact of one-to-one chats without members
+ *       and removes members of deleted chats or contacts
+ *     - DC_REPAIR_UNREAD_COUNTERS (3), DC_REPAIR_SEARCH_INDEX (4) and DC_REPAIR_ORPHANED_BLOBS (5)
+ *       are not supported by this version and always fail
+ * @return Number of fixed database rows, -1 on errors, unknown or unsupported kind.
+ */
+int             dc_repair                   (dc_context_t* context, int kind);
+
+#define DC_REPAIR_EPHEMERAL_TIMERS 1
+#define DC_REPAIR_CHAT_MEMBERS     2
+#define DC_REPAIR_UNREAD_COUNTERS  3
+#define DC_REPAIR_SEARCH_INDEX     4
+#define DC_REPAIR_ORPHANED_BLOBS   5
+
+
 /**
  * Returns the message IDs of all _fresh_ messages of any chat.
  * Typically used for implementing notification summaries
@@ -1307,6 +1673,17 @@ char*           dc_get_chat_encrinfo (dc_context_t* context, uint32_t chat_id);
  */
 uint32_t dc_get_chat_ephemeral_timer (dc_context_t* context, uint32_t chat_id);
 
+/**
+ * Get the time at which the next message is deleted by its ephemeral timer.
+ * The time at which a single message is deleted
+ * can be retrieved using dc_msg_get_ephemeral_timestamp().
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @return Unix timestamp of the next deletion, 0 if no ephemeral timer is running or on errors.
+ */
+int64_t dc_get_next_expiration_timestamp (dc_context_t* context);
+
 /**
  * Search messages containing the given query string.
  * Searching can be done globally (chat_id=0) or in a specified chat only (chat_id
@@ -1333,6 +1710,24 @@ uint32_t dc_get_chat_ephemeral_timer (dc_context_t* context, uint32_t chat_id);
 dc_array_t*     dc_search_msgs               (dc_context_t* context, uint32_t chat_id, const char* query);
 
 
+/**
+ * Search messages on the server that are no longer stored on the device,
+ * eg. because of the `delete_device_after` option,
+ * and download matching messages again.
+ *
+ * The search is done in the background in the DeltaChat and Inbox folders.
+ * When done, #DC_EVENT_MSGS_CHANGED is emitted
+ * and the downloaded messages can be found using dc_search_msgs().
+ * The messages are deleted from the device again according to `delete_device_after`.
+ *
+ * @memberof dc_context_t
+ * @param context The context object as returned from dc_context_new().
+ * @param query The query to search for.
+ * @return 1=search started, 0=error, eg. the query is empty.
+ */
+int             dc_search_server             (dc_context_t* context, const char* query);
+
+
 /**
  * Get chat object by a chat ID.
  *
@@ -1444,6 +1839,66 @@ int             dc_remove_contact_from_chat  (dc_context_t* context, uint32_t ch
  */
 int             dc_set_chat_name             (dc_context_t* context, uint32_t chat_id, const char* name);
 
+
+/**
+ * Set a prefix that is added to the subjects of outgoing messages in a chat, eg. `[project]`.
+ * The prefix is not added to subjects set by dc_msg_set_subject()
+ * or to subjects that already contain the prefix.
+ *
+ * Sends out #DC_EVENT_CHAT_MODIFIED.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id The chat ID to set the subject prefix for.
+ * @param prefix The new prefix. NULL or an empty string removes the prefix.
+ * @return 1=success, 0=error
+ */
+int             dc_set_chat_subject_prefix   (dc_context_t* context, uint32_t chat_id, const char* prefix);
+
+
+#define         DC_MDN_POLICY_DEFAULT        0
+#define         DC_MDN_POLICY_ENABLED        1
+#define         DC_MDN_POLICY_DISABLED       2
+
+
+/**
+ * Set the read receipt policy of a chat.
+ * This allows e.g. sending read receipts only to close contacts
+ * while the global `mdns_enabled` option is disabled.
+ *
+ * Sends out #DC_EVENT_CHAT_MODIFIED.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id The chat ID to set the policy for.
+ * @param policy One of:
+ *     - DC_MDN_POLICY_DEFAULT (0): follow the `mdns_enabled` option,
+ *       read receipts are never sent to mailing lists.
+ *     - DC_MDN_POLICY_ENABLED (1): request and send read receipts
+ *       regardless of the `mdns_enabled` option.
+ *     - DC_MDN_POLICY_DISABLED (2): neither request nor send read receipts.
+ * @return 1=success, 0=error
+ */
+int             dc_set_chat_mdn_policy       (dc_context_t* context, uint32_t chat_id, int policy);
+
+
+/**
+ * Download the history of a chat from the server again,
+ * eg. after the messages were deleted from the device because of the `delete_device_after` option.
+ *
+ * The DeltaChat and Inbox folders are searched in the background
+ * for messages of the chat that are not stored on the device anymore.
+ * When done, #DC_EVENT_MSGS_CHANGED is emitted.
+ * The messages are deleted from the device again according to `delete_device_after`.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id The chat ID to download the history for.
+ * @param limit The maximum number of messages to download from each folder.
+ * @return 1=download started, 0=error.
+ */
+int             dc_redownload_chat_history   (dc_context_t* context, uint32_t chat_id, uint32_t limit);
+
 /**
  * Set the chat's ephemeral message timer.
  *
@@ -1463,6 +1918,213 @@ int             dc_set_chat_name             (dc_context_t* context, uint32_t ch
  */
 int dc_set_chat_ephemeral_timer (dc_context_t* context, uint32_t chat_id, uint32_t timer);
 
+
+#define DC_EPHEMERAL_TIMER_START_READ     0
+#define DC_EPHEMERAL_TIMER_START_DELIVERY 1
+
+
+/**
+ * Get when the chat's ephemeral message timer starts,
+ * see dc_set_chat_ephemeral_timer_start().
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id The chat ID.
+ * @return DC_EPHEMERAL_TIMER_START_READ (0) or DC_EPHEMERAL_TIMER_START_DELIVERY (1)
+ */
+int dc_get_chat_ephemeral_timer_start (dc_context_t* context, uint32_t chat_id);
+
+
+/**
+ * Set when the chat's ephemeral message timer starts.
+ *
+ * By default, the timer of incoming messages starts once dc_markseen_msgs() is called.
+ * With DC_EPHEMERAL_TIMER_START_DELIVERY, the timer starts when the message is received,
+ * so messages disappear even if they are never displayed.
+ *
+ * The setting is sent along with the ephemeral timer
+ * and applied by the other chat members when receiving the next message.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id The chat ID.
+ * @param start DC_EPHEMERAL_TIMER_START_READ (0) or DC_EPHEMERAL_TIMER_START_DELIVERY (1)
+ * @return 1=success, 0=error
+ */
+int dc_set_chat_ephemeral_timer_start (dc_context_t* context, uint32_t chat_id, int start);
+
+
+#define DC_EPHEMERAL_EXPIRY_FULL            0
+#define DC_EPHEMERAL_EXPIRY_ATTACHMENT_ONLY 1
+
+
+/**
+ * Get what is deleted when the chat's ephemeral message timer expires,
+ * see dc_set_chat_ephemeral_expiry_mode().
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id The chat ID.
+ * @return DC_EPHEMERAL_EXPIRY_FULL (0) or DC_EPHEMERAL_EXPIRY_ATTACHMENT_ONLY (1)
+ */
+int dc_get_chat_ephemeral_expiry_mode (dc_context_t* context, uint32_t chat_id);
+
+
+/**
+ * Set what is deleted when the chat's ephemeral message timer expires.
+ *
+ * By default, expired messages are deleted completely.
+ * With DC_EPHEMERAL_EXPIRY_ATTACHMENT_ONLY, only the attachment is deleted
+ * and the message text is kept in the chat.
+ * The copy on the server is deleted in both cases.
+ *
+ * The setting is local to this device and not synchronized to other chat members.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id The chat ID.
+ * @param mode DC_EPHEMERAL_EXPIRY_FULL (0) or DC_EPHEMERAL_EXPIRY_ATTACHMENT_ONLY (1)
+ * @return 1=success, 0=error
+ */
+int dc_set_chat_ephemeral_expiry_mode (dc_context_t* context, uint32_t chat_id, int mode);
+
+
+/**
+ * Restrict which ephemeral timer changes received from other chat members are applied.
+ *
+ * By default, every chat member can change or disable the timer for all members.
+ * Rejected changes are not applied and an info message is added to the chat instead.
+ * Changes made on your own other devices are always applied.
+ * The policy is not synchronized to other members or devices.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id The chat ID to set the policy for.
+ * @param verified_only 1=apply only timer changes from verified contacts, 0=apply changes from all members.
+ * @param max_timer Maximum timer value in seconds that is applied;
+ *     changes disabling the timer or setting a longer timer are rejected.
+ *     0=no limit.
+ * @param approve_unverified 1=apply timer changes from contacts which are not verified
+ *     only after approval with dc_approve_chat_ephemeral_timer_change(),
+ *     0=apply them at once. Ignored if verified_only is set.
+ * @return 1=success, 0=error
+ */
+int dc_set_chat_ephemeral_timer_policy (dc_context_t* context, uint32_t chat_id, int verified_only, uint32_t max_timer, int approve_unverified);
+
+
+/**
+ * Approve or decline an ephemeral timer change waiting for approval.
+ *
+ * If the chat's policy requires approval, see dc_set_chat_ephemeral_timer_policy(),
+ * timer changes from contacts which are not verified are not applied at once.
+ * Instead, an info message of the type DC_INFO_EPHEMERAL_TIMER_CHANGE_PENDING is added to the chat
+ * and the UI can offer to approve the change.
+ * An approved change is applied on this device only.
+ *
+ * Sends out #DC_EVENT_CHAT_MODIFIED.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id The chat ID.
+ * @param approve 1=apply the timer change, 0=decline it.
+ * @return 1=success, 0=error, e.g. no change is waiting for approval
+ */
+int dc_approve_chat_ephemeral_timer_change (dc_context_t* context, uint32_t chat_id, int approve);
+
+
+/**
+ * Get the ephemeral timer policy of a chat
+ * as set by dc_set_chat_ephemeral_timer_policy().
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id The chat ID to get the policy for.
+ * @return JSON object with the fields `verified_only` (boolean),
+ *     `max_timer` (seconds, 0=no limit) and `approve_unverified` (boolean).
+ *     Must be released using dc_str_unref() after usage.
+ *     On errors, an empty string is returned.
+ */
+char* dc_get_chat_ephemeral_timer_policy (dc_context_t* context, uint32_t chat_id);
+
+
+/**
+ * Get statistics about the messages of a chat whose ephemeral timer is running,
+ * e.g. to show the effect of the current timer in the chat settings.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id The chat ID to get the statistics for.
+ * @return JSON object with the fields `count` (number of messages),
+ *     `first_expiry` and `last_expiry` (timestamps, 0 if no message expires)
+ *     and `bytes` (size of the texts and attachments to be deleted).
+ *     Must be released using dc_str_unref() after usage.
+ *     On errors, an empty string is returned.
+ */
+char* dc_get_chat_ephemeral_stats (dc_context_t* context, uint32_t chat_id);
+
+
+/**
+ * Get the ephemeral timer values that should be offered to the user,
+ * so all apps offer the same choice.
+ *
+ * @return JSON array of timer values in seconds, shortest first, 0=disabled.
+ *     Must be released using dc_str_unref() after usage.
+ */
+char* dc_get_ephemeral_timer_presets (void);
+
+
+/**
+ * Remove messages from the trash which are older than the given number of seconds,
+ * even if they may still have a copy on the server.
+ *
+ * Normally, deleted messages are kept in the database
+ * until their copy on the server is deleted.
+ * If the server copy was deleted by another client and this was not detected,
+ * these entries would stay forever.
+ * Messages still waiting for deletion from the server are not removed.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param older_than Minimum age of the removed entries in seconds.
+ * @return Number of removed entries, -1 on errors.
+ */
+int dc_purge_stale_trash (dc_context_t* context, int64_t older_than);
+
+/**
+ * Set the chat's ephemeral message timer
+ * and apply it also to the messages already existing in the chat.
+ *
+ * Works like dc_set_chat_ephemeral_timer(),
+ * but additionally, already seen messages are deleted once the timer expires,
+ * and unseen messages are deleted after being seen.
+ * Timers already running on existing messages are only shortened.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id The chat ID to set the ephemeral message timer for.
+ * @param timer The timer value in seconds or 0 to disable the timer.
+ *
+ * @return 1=success, 0=error
+ */
+int dc_set_chat_ephemeral_timer_including_history (dc_context_t* context, uint32_t chat_id, uint32_t timer);
+
+/**
+ * Set the ephemeral message timer of a one-to-one chat
+ * without adding a message about the change to the chat.
+ *
+ * Works like dc_set_chat_ephemeral_timer(),
+ * but instead of sending a message about the change,
+ * the new timer is sent along with the next message to the chat.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id The ID of the one-to-one chat to set the ephemeral message timer for.
+ * @param timer The timer value in seconds or 0 to disable the timer.
+ *
+ * @return 1=success, 0=error, eg. if the chat is not a one-to-one chat
+ */
+int dc_set_chat_ephemeral_timer_silently (dc_context_t* context, uint32_t chat_id, uint32_t timer);
+
 /**
  * Set group profile image.
  *
@@ -1509,21 +2171,149 @@ int             dc_set_chat_profile_image    (dc_context_t* context, uint32_t ch
  */
 int             dc_set_chat_mute_duration             (dc_context_t* context, uint32_t chat_id, int64_t duration);
 
+
+/**
+ * Set the notification profile of a chat.
+ *
+ * The profile is stored in the core and passed to the UI
+ * with each #DC_EVENT_INCOMING_MSG event of the chat,
+ * so all UIs using the account notify the same way.
+ * The profile is a JSON object with the optional fields
+ * `sound` (string, identifier of the notification sound),
+ * `vibration` (string, key of the vibration pattern) and
+ * `priority` (integer, 0 is the default priority).
+ * The values are defined by the UIs, the core does not interpret them.
+ *
+ * Sends out #DC_EVENT_CHAT_MODIFIED.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id The chat ID to set the notification profile for.
+ * @param json The profile as JSON, NULL to reset to the default notifications.
+ * @return 1=success, 0=error
+ */
+int             dc_set_chat_notification_profile      (dc_context_t* context, uint32_t chat_id, const char* json);
+
+
+/**
+ * Get the notification profile of a chat,
+ * see dc_set_chat_notification_profile() for details.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id The chat ID to get the notification profile for.
+ * @return The profile as JSON, NULL if no profile is set or on errors.
+ *     Must be released using dc_str_unref() after usage.
+ */
+char*           dc_get_chat_notification_profile      (dc_context_t* context, uint32_t chat_id);
+
+
+/**
+ * Save the raw mime-headers of incoming messages of a chat.
+ *
+ * This works like the `save_mime_headers` option of dc_set_config(),
+ * but only for the given chat, so the sources are available for exporting the chat
+ * without storing the sources of all other chats.
+ * Only messages received after enabling the option are saved.
+ *
+ * Sends out #DC_EVENT_CHAT_MODIFIED.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id The chat ID.
+ * @param save 1=save the mime-headers of incoming messages, 0=follow `save_mime_headers` (default).
+ * @return 1=success, 0=error
+ */
+int             dc_set_chat_save_mime                 (dc_context_t* context, uint32_t chat_id, int save);
+
+
+/**
+ * Check if the raw mime-headers of incoming messages of a chat are saved,
+ * see dc_set_chat_save_mime().
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id The chat ID.
+ * @return 1=mime-headers are saved for the chat, 0=follow `save_mime_headers` or error.
+ */
+int             dc_get_chat_save_mime                 (dc_context_t* context, uint32_t chat_id);
+
+
+/**
+ * Get the previous avatars of a chat.
+ *
+ * When the avatar of a group or contact is changed or removed,
+ * the previous avatar is kept, so old messages can be shown
+ * with the avatar that was valid when they were sent.
+ * For one-to-one chats, the previous avatars of the contact are returned.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param chat_id The chat ID to get the avatar history for.
+ * @return JSON array of objects with the fields `path` (path to the image file)
+ *     and `valid_until` (unix timestamp at which the avatar was replaced), oldest first.
+ *     On errors, an empty string is returned.
+ *     Must be released using dc_str_unref() after usage.
+ */
+char*           dc_get_chat_avatar_history            (dc_context_t* context, uint32_t chat_id);
+
 // handle messages
 
 /**
  * Get an informational text for a single message. The text is multiline and may
  * contain e.g. the raw text of the message.
  *
- * The max. text returned is typically longer (about 100000 characters) than the
- * max. text returned by dc_msg_get_text() (about 30000 characters).
+ * The max. text returned is typically longer (about 100000 characters) than the
+ * max. text returned by dc_msg_get_text() (about 30000 characters).
+ *
+ * @memberof dc_context_t
+ * @param context The context object object.
+ * @param msg_id The message id for which information should be generated
+ * @return Text string, must be released using dc_str_unref() after usage
+ */
+char*           dc_get_msg_info              (dc_context_t* context, uint32_t msg_id);
+
+
+/**
+ * Get a message as JSON.
+ *
+ * The JSON object contains the message ID, chat ID, sender, view type, state,
+ * text, timestamps, attachment and further properties of the message,
+ * so bindings do not need to query them one by one.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param msg_id The message ID.
+ * @return JSON string which must be released using dc_str_unref() after usage.
+ *     On errors, an empty string is returned.
+ */
+char*           dc_get_msg_json              (dc_context_t* context, uint32_t msg_id);
+
+
+/**
+ * Download a single new message and get the information needed
+ * to show a notification for it.
+ *
+ * This is meant for notification extensions, eg. on iOS,
+ * which run in a separate process with a tight time and memory budget.
+ * IO must not be started for this, see dc_start_io().
+ * Only the given message is downloaded and received as usual,
+ * so the main app does not download it again.
+ * Messages larger than 512 KiB are not downloaded
+ * and the function gives up after 20 seconds.
+ *
+ * The returned JSON object contains
+ * `chat_id`, `chat_name`, `msg_id`, `sender` and `snippet`.
  *
  * @memberof dc_context_t
- * @param context The context object object.
- * @param msg_id The message id for which information should be generated
- * @return Text string, must be released using dc_str_unref() after usage
+ * @param context The context object.
+ * @param folder The IMAP folder containing the new message, eg. `INBOX`.
+ * @param uid_hint The IMAP UID of the new message
+ *     or 0 to download the newest new message in the folder.
+ * @return JSON string which must be released using dc_str_unref() after usage.
+ *     NULL if there is no message to notify about or on errors.
  */
-char*           dc_get_msg_info              (dc_context_t* context, uint32_t msg_id);
+char*           dc_fetch_one_notification    (dc_context_t* context, const char* folder, uint32_t uid_hint);
 
 
 /**
@@ -1580,7 +2370,7 @@ char*           dc_get_msg_html              (dc_context_t* context, uint32_t ms
  * Get the raw mime-headers of the given message.
  * Raw headers are saved for incoming messages
  * only if `dc_set_config(context, "save_mime_headers", "1")`
- * was called before.
+ * or dc_set_chat_save_mime() for the chat was called before.
  *
  * @memberof dc_context_t
  * @param context The context object.
@@ -1593,6 +2383,34 @@ char*           dc_get_msg_html              (dc_context_t* context, uint32_t ms
 char*           dc_get_mime_headers          (dc_context_t* context, uint32_t msg_id);
 
 
+/**
+ * Get the private note of the user for a message,
+ * see dc_set_msg_note().
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param msg_id The message ID.
+ * @return The note, must be released using dc_str_unref() after usage.
+ *     Returns NULL if the message has no note.
+ */
+char*           dc_get_msg_note              (dc_context_t* context, uint32_t msg_id);
+
+
+/**
+ * Set a private note for a message, eg. to add a ticket number in support workflows.
+ * Notes are stored on this device only and never sent to anyone.
+ * They are found by dc_search_msgs()
+ * and removed when the message is deleted.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param msg_id The message ID.
+ * @param note The note, NULL or an empty string removes the note.
+ * @return 1=success, 0=error
+ */
+int             dc_set_msg_note              (dc_context_t* context, uint32_t msg_id, const char* note);
+
+
 /**
  * Delete messages. The messages are deleted on the current device and
  * on the IMAP server.
@@ -1605,6 +2423,21 @@ char*           dc_get_mime_headers          (dc_context_t* context, uint32_t ms
 void            dc_delete_msgs               (dc_context_t* context, const uint32_t* msg_ids, int msg_cnt);
 
 
+/**
+ * Revert the last deletion of messages or of a chat
+ * done by dc_delete_msgs() or dc_delete_chat().
+ *
+ * This is possible only if the config-option `deletion_undo_window` was set
+ * at the time of the deletion and the window has not passed yet.
+ * On success, #DC_EVENT_MSGS_CHANGED is emitted.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @return 1=deletion reverted, 0=there is no deletion to revert or an error occurred.
+ */
+int             dc_undo_last_deletion        (dc_context_t* context);
+
+
 /**
  * Forward messages to another chat.
  *
@@ -1645,6 +2478,39 @@ void            dc_forward_msgs              (dc_context_t* context, const uint3
 void            dc_markseen_msgs             (dc_context_t* context, const uint32_t* msg_ids, int msg_cnt);
 
 
+/**
+ * Star or unstar messages.
+ *
+ * If the option `protect_starred_from_deletion` is set (see dc_set_config()),
+ * starred messages are neither deleted by ephemeral timers nor by `delete_device_after`.
+ *
+ * One #DC_EVENT_MSGS_CHANGED event is emitted per message.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param msg_ids An array of uint32_t containing all the messages IDs that should be starred or unstarred.
+ * @param msg_cnt The number of message IDs in msg_ids.
+ * @param star 1=star messages, 0=unstar messages.
+ */
+void            dc_star_msgs                 (dc_context_t* context, const uint32_t* msg_ids, int msg_cnt, int star);
+
+
+/**
+ * Keep a message whose ephemeral timer is running or has expired.
+ *
+ * The ephemeral timer of the message is stopped, so the message is not deleted.
+ * If the option `ephemeral_grace_period` is set (see dc_set_config()),
+ * this can be offered as an undo action
+ * until the grace period after the expiry of the message has passed.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param msg_id The ID of the message to keep.
+ * @return 1=success, 0=error, e.g. the message is deleted already.
+ */
+int             dc_cancel_msg_expiry         (dc_context_t* context, uint32_t msg_id);
+
+
 /**
  * Get a single message object of the type dc_msg_t.
  * For a list of messages in a chat, see dc_get_chat_msgs()
@@ -1800,6 +2666,82 @@ dc_array_t*     dc_get_blocked_contacts      (dc_context_t* context);
 void            dc_block_contact             (dc_context_t* context, uint32_t contact_id, int block);
 
 
+/**
+ * Allow or disallow loading remote content for HTML messages from a contact.
+ * By default, remote content as images is removed by dc_get_msg_html()
+ * as it may be used to track whether and when a message was read.
+ * To load remote content for all contacts, set the config-option `show_remote_content`.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param contact_id The ID of the contact.
+ * @param allow 1=load remote content for messages from this contact, 0=remove remote content
+ */
+void            dc_set_contact_load_remote_content (dc_context_t* context, uint32_t contact_id, int allow);
+
+
+/**
+ * Enable or disable the classic email mode for a contact.
+ * In classic email mode, messages to the contact are sent without chat-specific headers
+ * and without Autocrypt-Gossip, replies use the subject of the replied message
+ * and contain the replied message quoted below the text.
+ * This makes conversations look like normal email for correspondents not using Delta Chat.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param contact_id The ID of the contact.
+ * @param plain_email 1=send messages as classic email, 0=send normal chat messages
+ */
+void            dc_set_contact_plain_email   (dc_context_t* context, uint32_t contact_id, int plain_email);
+
+
+/**
+ * Enable or disable the signed-only mode for a contact.
+ * In signed-only mode, messages to the contact are not encrypted,
+ * but sent as PGP/MIME signed messages.
+ * This is useful for correspondents who cannot decrypt messages,
+ * but still want to check their authenticity.
+ * Messages in protected chats are always encrypted.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param contact_id The ID of the contact.
+ * @param sign_only 1=sign messages without encrypting them, 0=encrypt messages if possible
+ */
+void            dc_set_contact_sign_only     (dc_context_t* context, uint32_t contact_id, int sign_only);
+
+
+/**
+ * Mark a contact as the user on another account,
+ * eg. while migrating to a new address gradually.
+ * Messages from this contact in groups are not counted as fresh messages
+ * and do not cause notifications.
+ * The UI should render them like outgoing messages, see dc_msg_is_from_self_alias().
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param contact_id The ID of the contact.
+ * @param self_alias 1=the contact is the user on another account, 0=normal contact
+ */
+void            dc_set_contact_self_alias    (dc_context_t* context, uint32_t contact_id, int self_alias);
+
+
+/**
+ * Import the public key of a contact manually,
+ * eg. for contacts using an OpenPGP client without Autocrypt support.
+ * The key is used for encryption until the contact announces another key via Autocrypt;
+ * unencrypted messages from the contact do not disable encryption.
+ * dc_get_contact_encrinfo() shows that the key was imported manually.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param contact_id The ID of the contact.
+ * @param asc The ASCII-armored public key.
+ * @return 1=success, 0=error, eg. the key is invalid.
+ */
+int             dc_import_contact_key        (dc_context_t* context, uint32_t contact_id, const char* asc);
+
+
 /**
  * Get encryption info for a contact.
  * Get a multi-line encryption info, containing your fingerprint and the
@@ -1949,6 +2891,51 @@ void            dc_imex                      (dc_context_t* context, int what, c
 char*           dc_imex_has_backup           (dc_context_t* context, const char* dir);
 
 
+/**
+ * Export the account settings to a file.
+ * The file contains the configuration including the password, the own keys,
+ * contacts with their encryption and verification states
+ * and chat settings as ephemeral timers and mute durations,
+ * but no messages.
+ * It can be used to quickly set up a new device using dc_import_settings().
+ * As the file contains the private key and the password, it must be kept secret.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param file The file to write the settings to.
+ * @return 1=success, 0=error
+ */
+int             dc_export_settings           (dc_context_t* context, const char* file);
+
+
+/**
+ * Import account settings written by dc_export_settings().
+ * Existing configuration values are overwritten.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param file The file to read the settings from.
+ * @return 1=success, 0=error
+ */
+int             dc_import_settings           (dc_context_t* context, const char* file);
+
+
+/**
+ * Export the account data to a directory in an open, documented format,
+ * eg. to use it with other software.
+ * The directory gets a file `account.json` with the configuration without passwords,
+ * the contacts, the chats and the messages,
+ * and a directory `media` with the attachments.
+ * Unlike backups, the export cannot be imported again.
+ *
+ * @memberof dc_context_t
+ * @param context The context object.
+ * @param dir The directory to write the data to.
+ * @return 1=success, 0=error
+ */
+int             dc_export_data_portability   (dc_context_t* context, const char* dir);
+
+
 /**
  * Initiate Autocrypt Setup Transfer.
  * Before starting the setup transfer with this function, the user should be asked:
@@ -3161,6 +4148,16 @@ int             dc_chat_can_send              (const dc_chat_t* chat);
 int             dc_chat_is_protected         (const dc_chat_t* chat);
 
 
+/**
+ * Get the read receipt policy of a chat as set by dc_set_chat_mdn_policy().
+ *
+ * @memberof dc_chat_t
+ * @param chat The chat object.
+ * @return One of DC_MDN_POLICY_DEFAULT, DC_MDN_POLICY_ENABLED or DC_MDN_POLICY_DISABLED.
+ */
+int             dc_chat_get_mdn_policy       (const dc_chat_t* chat);
+
+
 /**
  * Check if locations are sent to the chat
  * at the time the object was created using dc_get_chat().
@@ -3406,6 +4403,21 @@ int64_t          dc_msg_get_sort_timestamp     (const dc_msg_t* msg);
 char*           dc_msg_get_text               (const dc_msg_t* msg);
 
 
+/**
+ * Get the full message text.
+ *
+ * Unlike dc_msg_get_text(), the text is not truncated.
+ * If the text of a received message was longer than the `max_text_length` setting,
+ * the message text is truncated and the full text is loaded from disk.
+ * Meant to be used when the user explicitly wants to see the whole text.
+ *
+ * @memberof dc_msg_t
+ * @param msg The message object.
+ * @return Full message text. The result must be released using dc_str_unref(). Never returns NULL.
+ */
+char*           dc_msg_get_full_text          (const dc_msg_t* msg);
+
+
 /**
  * Get the subject of the email.
  * If there is no subject associated with the message, an empty string is returned.
@@ -3537,6 +4549,20 @@ int             dc_msg_get_duration           (const dc_msg_t* msg);
  */
 int             dc_msg_get_showpadlock        (const dc_msg_t* msg);
 
+
+/**
+ * Check if a message is not encrypted, but signed with a valid signature of the sender.
+ * UIs may show a distinct indicator for these messages,
+ * which are authentic, but were readable during transport.
+ *
+ * For encrypted messages, dc_msg_get_showpadlock() is used instead.
+ *
+ * @memberof dc_msg_t
+ * @param msg The message object.
+ * @return 1=message is signed, but not encrypted, 0=message is encrypted, unsigned or the signature is not valid.
+ */
+int             dc_msg_is_signed_only         (const dc_msg_t* msg);
+
 /**
  * Check if incoming message is a bot message, i.e. automatically submitted.
  *
@@ -3576,6 +4602,56 @@ uint32_t        dc_msg_get_ephemeral_timer    (const dc_msg_t* msg);
 int64_t          dc_msg_get_ephemeral_timestamp (const dc_msg_t* msg);
 
 
+/**
+ * Get the number of seconds until an ephemeral message is deleted.
+ *
+ * Together with dc_msg_get_ephemeral_timer(),
+ * this can be used to render a countdown for each message.
+ *
+ * @memberof dc_msg_t
+ * @param msg The message object.
+ * @return Remaining seconds. If the timer is not yet started, this is the full timer duration.
+ *     0 if no timer is set or the message is about to be deleted.
+ */
+int64_t          dc_msg_get_ephemeral_remaining_secs (const dc_msg_t* msg);
+
+
+/**
+ * Check if a message is deleted after it is displayed once.
+ *
+ * Incoming view-once messages are deleted as soon as dc_markseen_msgs() is called,
+ * independently of the ephemeral timer of the chat.
+ * The copy of the sender is kept.
+ *
+ * @memberof dc_msg_t
+ * @param msg The message object.
+ * @return 1=message is deleted after it is displayed once, 0=normal message.
+ */
+int             dc_msg_is_view_once           (const dc_msg_t* msg);
+
+
+/**
+ * Check if a message is starred, see dc_star_msgs().
+ *
+ * @memberof dc_msg_t
+ * @param msg The message object.
+ * @return 1=message is starred, 0=message is not starred.
+ */
+int             dc_msg_is_starred             (const dc_msg_t* msg);
+
+
+/**
+ * Check if a message was sent to a group by the user from another account,
+ * see dc_set_contact_self_alias().
+ * Such messages should be rendered like outgoing messages.
+ *
+ * @memberof dc_msg_t
+ * @param msg The message object.
+ * @return 1=message was sent by the user from another account, 0=other message.
+ */
+int             dc_msg_is_from_self_alias     (const dc_msg_t* msg);
+
+
 /**
  * Get a summary for a message.
  *
@@ -3736,6 +4812,9 @@ int             dc_msg_is_info                (const dc_msg_t* msg);
  * Currently, the following types are defined:
  * - DC_INFO_PROTECTION_ENABLED (11) - Info-message for "Chat is now protected"
  * - DC_INFO_PROTECTION_DISABLED (12) - Info-message for "Chat is no longer protected"
+ * - DC_INFO_MSGS_DISAPPEARED (14) - Info-message counting messages deleted by the ephemeral timer
+ * - DC_INFO_EPHEMERAL_TIMER_CHANGE_PENDING (15) - Info-message about a timer change waiting for approval,
+ *   see dc_approve_chat_ephemeral_timer_change()
  *
  * Even when you display an icon,
  * you should still display the text of the informational message using dc_msg_get_text()
@@ -3752,6 +4831,8 @@ int             dc_msg_get_info_type          (const dc_msg_t* msg);
 // DC_INFO* uses the same values as SystemMessage in rust-land
 #define         DC_INFO_PROTECTION_ENABLED     11
 #define         DC_INFO_PROTECTION_DISABLED    12
+#define         DC_INFO_MSGS_DISAPPEARED       14
+#define         DC_INFO_EPHEMERAL_TIMER_CHANGE_PENDING 15
 
 
 /**
@@ -3940,6 +5021,20 @@ void            dc_msg_set_text               (dc_msg_t* msg, const char* text);
 void            dc_msg_set_html               (dc_msg_t* msg, const char* html);
 
 
+/**
+ * Set the subject of a message.
+ * By default, the subject is derived from the chat,
+ * eg. "Re: <last subject>" or the group name.
+ * This is useful when corresponding with classic email users who care about subjects.
+ * A subject set by this function is used as is, no prefix is added.
+ *
+ * @memberof dc_msg_t
+ * @param msg The message object.
+ * @param subject The subject to use. NULL or an empty string uses the default subject.
+ */
+void            dc_msg_set_subject            (dc_msg_t* msg, const char* subject);
+
+
 /**
  * Set different sender name for a message.
  * This overrides the name set by the dc_set_config()-option `displayname`.
@@ -3983,6 +5078,19 @@ void            dc_msg_set_file               (dc_msg_t* msg, const char* file,
 void            dc_msg_set_dimension          (dc_msg_t* msg, int width, int height);
 
 
+/**
+ * Set whether a message should be deleted by the recipients after it is displayed once.
+ * This is typically used for images, videos or voice messages,
+ * but works for all view types.
+ * This does not alter any information in the database; this may be done by dc_send_msg() later.
+ *
+ * @memberof dc_msg_t
+ * @param msg The message object.
+ * @param view_once 1=delete after the message is displayed once, 0=normal message.
+ */
+void            dc_msg_set_view_once          (dc_msg_t* msg, int view_once);
+
+
 /**
  * Set the duration associated with message object.
  * Typically this is the duration of an audio or video associated using dc_msg_set_file().
@@ -4285,6 +5393,42 @@ char*           dc_contact_get_status        (const dc_contact_t* contact);
 int             dc_contact_is_blocked        (const dc_contact_t* contact);
 
 
+/**
+ * Check if remote content is loaded for HTML messages from a contact.
+ *
+ * To change this setting, use dc_set_contact_load_remote_content().
+ *
+ * @memberof dc_contact_t
+ * @param contact The contact object.
+ * @return 1=remote content is loaded, 0=remote content is removed.
+ */
+int             dc_contact_get_load_remote_content (const dc_contact_t* contact);
+
+
+/**
+ * Check if messages to a contact are sent as classic email.
+ *
+ * To change this setting, use dc_set_contact_plain_email().
+ *
+ * @memberof dc_contact_t
+ * @param contact The contact object.
+ * @return 1=classic email mode is enabled, 0=normal chat messages are sent.
+ */
+int             dc_contact_is_plain_email    (const dc_contact_t* contact);
+
+
+/**
+ * Check if a contact is the user on another account.
+ *
+ * To change this setting, use dc_set_contact_self_alias().
+ *
+ * @memberof dc_contact_t
+ * @param contact The contact object.
+ * @return 1=the contact is the user on another account, 0=normal contact.
+ */
+int             dc_contact_is_self_alias     (const dc_contact_t* contact);
+
+
 /**
  * Check if a contact was verified. E.g. by a secure-join QR code scan
  * and if the key has not changed since this verification.
@@ -4821,6 +5965,19 @@ int dc_event_get_data2_int(dc_event_t* event);
 char* dc_event_get_data2_str(dc_event_t* event);
 
 
+/**
+ * Get additional data of an event object as JSON.
+ * Some events carry more data than fits into data1 and data2,
+ * the events documented to have additional data are listed with the @ref DC_EVENT constants.
+ *
+ * @memberof dc_event_t
+ * @param event Event object as returned from dc_get_next_event().
+ * @return JSON object or NULL if the event has no additional data.
+ *     Once you're done with the string, you have to unref it using dc_unref_str().
+ */
+char* dc_event_get_extra_json(dc_event_t* event);
+
+
 /**
  * Get account-id this event belongs to.
  * The account-id is of interest only when using the dc_accounts_t account manager.
@@ -4990,6 +6147,12 @@ void dc_event_unref(dc_event_t* event);
  *
  * @param data1 (int) chat_id
  * @param data2 (int) msg_id
+ *     If the chat has a notification profile set with dc_set_chat_notification_profile()
+ *     or the message arrived during the quiet hours set by the config-options
+ *     `quiet_hours_start` and `quiet_hours_end`,
+ *     dc_event_get_extra_json() returns notification hints as JSON, otherwise NULL.
+ *     The hints contain the fields of the notification profile
+ *     and the field `muted` which is true during the quiet hours.
  */
 #define DC_EVENT_INCOMING_MSG             2005
 
@@ -5040,6 +6203,17 @@ void dc_event_unref(dc_event_t* event);
 #define DC_EVENT_MSG_READ                 2015
 
 
+/**
+ * A starred message was due for deletion by its ephemeral timer or by `delete_device_after`,
+ * but is kept because the option `protect_starred_from_deletion` is set, see dc_set_config().
+ * The event is emitted once per message.
+ *
+ * @param data1 (int) chat_id
+ * @param data2 (int) msg_id
+ */
+#define DC_EVENT_MSG_PRESERVED            2017
+
+
 /**
  * Chat changed.  The name or the image of a chat group was changed or members were added or removed.
  * Or the verify state of a chat has changed.
@@ -5053,6 +6227,13 @@ void dc_event_unref(dc_event_t* event);
 
 /**
  * Chat ephemeral timer changed.
+ *
+ * @param data1 (int) chat_id
+ * @param data2 (int) The new timer value in seconds, 0=disabled.
+ *     dc_event_get_extra_json() returns a JSON object with the fields
+ *     `contact_id` (the contact who changed the timer, #DC_CONTACT_ID_SELF for own changes)
+ *     and `remote` (true if the change was received in a message,
+ *     including messages from own other devices).
  */
 #define DC_EVENT_CHAT_EPHEMERAL_TIMER_MODIFIED 2021
 
@@ -5066,6 +6247,17 @@ void dc_event_unref(dc_event_t* event);
 #define DC_EVENT_CONTACTS_CHANGED         2030
 
 
+/**
+ * A key was imported for a contact from somewhere else than an Autocrypt header,
+ * e.g. from the Web Key Directory if `wkd_lookup` is enabled.
+ * Messages to the contact can be encrypted from now on.
+ *
+ * @param data1 (int) contact_id of the contact.
+ * @param data2 (int) Source of the key: 1=Web Key Directory, 2=imported using dc_import_contact_key().
+ */
+#define DC_EVENT_CONTACT_KEY_IMPORTED     2032
+
+
 
 /**
  * Location of one or more contact has changed.
@@ -5087,6 +6279,15 @@ void dc_event_unref(dc_event_t* event);
 #define DC_EVENT_CONFIGURE_PROGRESS       2041
 
 
+/**
+ * Inform about the progress of an account deletion started by dc_delete_account().
+ *
+ * @param data1 (int) 0=error, 1-999=progress in permille, 1000=success and done
+ * @param data2 0
+ */
+#define DC_EVENT_DELETE_ACCOUNT_PROGRESS  2045
+
+
 /**
  * Inform about the import/export progress started by dc_imex().
  *
@@ -5109,6 +6310,38 @@ void dc_event_unref(dc_event_t* event);
 #define DC_EVENT_IMEX_FILE_WRITTEN        2052
 
 
+/**
+ * An automatic backup was written to the directory set by the `auto_backup_dir` option,
+ * see dc_set_config().
+ *
+ * @param data1 0
+ * @param data2 (char*) Path and file name of the backup.
+ */
+#define DC_EVENT_AUTO_BACKUP_WRITTEN      2053
+
+
+/**
+ * Writing an automatic backup failed.
+ *
+ * @param data1 0
+ * @param data2 (char*) Error message.
+ */
+#define DC_EVENT_AUTO_BACKUP_FAILED       2054
+
+
+/**
+ * The database was taken over by another process,
+ * or its lock was acquired again after the other context was closed.
+ *
+ * IO is stopped when the database is taken over.
+ * Once the lock is acquired again, IO can be started again using dc_start_io().
+ *
+ * @param data1 (int) 1=the context owns the database again, 0=the database was taken over.
+ * @param data2 0
+ */
+#define DC_EVENT_DB_OWNER_CHANGED         2055
+
+
 /**
  * Progress information of a secure-join handshake from the view of the inviter
  * (Alice, the person who shows the QR code).
@@ -5158,7 +6391,7 @@ void dc_event_unref(dc_event_t* event);
 
 
 #define DC_EVENT_DATA1_IS_STRING(e)  0    // not used anymore 
-#define DC_EVENT_DATA2_IS_STRING(e)  ((e)==DC_EVENT_CONFIGURE_PROGRESS || (e)==DC_EVENT_IMEX_FILE_WRITTEN || ((e)>=100 && (e)<=499))
+#define DC_EVENT_DATA2_IS_STRING(e)  ((e)==DC_EVENT_CONFIGURE_PROGRESS || (e)==DC_EVENT_IMEX_FILE_WRITTEN || (e)==DC_EVENT_AUTO_BACKUP_WRITTEN || (e)==DC_EVENT_AUTO_BACKUP_FAILED || ((e)>=100 && (e)<=499))
 
 
 /*
@@ -5674,6 +6907,29 @@ void dc_event_unref(dc_event_t* event);
 /// Used in message summary text for notifications and chatlist.
 #define DC_STR_FORWARDED                  97
 
+/// "Message deletion timer change by %1$s was rejected."
+///
+/// Used in info messages if a received timer change is not allowed by the chat's policy.
+/// - %1$s will be replaced by the name and address of the contact.
+#define DC_STR_EPHEMERAL_TIMER_CHANGE_REJECTED 98
+
+/// "The key of this contact was imported manually."
+///
+/// Used in the encryption info of contacts, see dc_get_contact_encrinfo() and dc_import_contact_key().
+#define DC_STR_KEY_IMPORTED_MANUALLY      99
+
+/// "%1$s messages disappeared."
+///
+/// Used in info messages replacing messages deleted by the ephemeral timer.
+/// - %1$s will be replaced by the number of deleted messages.
+#define DC_STR_MSGS_DISAPPEARED           100
+
+/// "Message deletion timer change by %1$s is waiting for approval."
+///
+/// Used in info messages if a received timer change needs to be approved by the user.
+/// - %1$s will be replaced by the name and address of the contact.
+#define DC_STR_EPHEMERAL_TIMER_CHANGE_PENDING 101
+
 /**
  * @}
  */
@@ -25,14 +25,20 @@
 use async_std::task::{block_on, spawn};
 use num_traits::{FromPrimitive, ToPrimitive};
 
-use deltachat::chat::{ChatId, ChatVisibility, MuteDuration, ProtectionStatus};
+use deltachat::chat::{ChatId, ChatVisibility, MdnPolicy, MuteDuration, ProtectionStatus};
 use deltachat::constants::DC_MSG_ID_LAST_SPECIAL;
 use deltachat::contact::{Contact, Origin};
-use deltachat::context::Context;
-use deltachat::ephemeral::Timer as EphemeralTimer;
+use deltachat::context::{Context, Hint};
+use deltachat::ephemeral::{
+    self, ExpiryMode as EphemeralExpiryMode, Scope as EphemeralScope, Timer as EphemeralTimer,
+    TimerPolicy as EphemeralTimerPolicy, TimerStart as EphemeralTimerStart,
+};
 use deltachat::key::DcKey;
 use deltachat::message::MsgId;
+use deltachat::network_policy::NetworkPolicy;
+use deltachat::repair::RepairKind;
 use deltachat::stock_str::StockMessage;
+use deltachat::webhook::{Webhook, WebhookEndpoint};
 use deltachat::*;
 use deltachat::{accounts::Accounts, log::LogExt};
 
@@ -120,6 +126,20 @@
     ctx.get_blobdir().to_string_lossy().strdup()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_self_color(context: *mut dc_context_t) -> u32 {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_self_color()");
+        return 0;
+    }
+    let ctx = &*context;
+    block_on(async move {
+        ctx.get_self_color()
+            .await
+            .unwrap_or_log_default(ctx, "Failed to get self color")
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_set_config(
     context: *mut dc_context_t,
@@ -245,6 +265,30 @@
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_usage_stats(
+    context: *mut dc_context_t,
+    timestamp_begin: i64,
+    timestamp_end: i64,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_usage_stats()");
+        return "".strdup();
+    }
+    let ctx = &*context;
+    block_on(async move {
+        match ctx.get_usage_stats(timestamp_begin..timestamp_end).await {
+            Ok(stats) => serde_json::to_string(&stats)
+                .unwrap_or_log_default(ctx, "dc_get_usage_stats() failed to serialise to json")
+                .strdup(),
+            Err(err) => {
+                warn!(ctx, "failed to get usage stats: {}", err);
+                "".strdup()
+            }
+        }
+    })
+}
+
 fn render_info(
     info: BTreeMap<&'static str, String>,
 ) -> std::result::Result<String, std::fmt::Error> {
@@ -408,18 +452,23 @@ fn render_info(
         | EventType::MsgDelivered { chat_id, .. }
         | EventType::MsgFailed { chat_id, .. }
         | EventType::MsgRead { chat_id, .. }
+        | EventType::MsgPreserved { chat_id, .. }
         | EventType::ChatModified(chat_id)
         | EventType::ChatEphemeralTimerModified { chat_id, .. } => chat_id.to_u32() as libc::c_int,
         EventType::ContactsChanged(id) | EventType::LocationChanged(id) => {
             let id = id.unwrap_or_default();
             id as libc::c_int
         }
-        EventType::ConfigureProgress { progress, .. } | EventType::ImexProgress(progress) => {
-            *progress as libc::c_int
-        }
-        EventType::ImexFileWritten(_) => 0,
+        EventType::ConfigureProgress { progress, .. }
+        | EventType::DeleteAccountProgress(progress)
+        | EventType::ImexProgress(progress) => *progress as libc::c_int,
+        EventType::ImexFileWritten(_)
+        | EventType::AutoBackupWritten(_)
+        | EventType::AutoBackupFailed(_) => 0,
+        EventType::DbOwnerChanged(owner) => *owner as libc::c_int,
         EventType::SecurejoinInviterProgress { contact_id, .. }
-        | EventType::SecurejoinJoinerProgress { contact_id, .. } => *contact_id as libc::c_int,
+        | EventType::SecurejoinJoinerProgress { contact_id, .. }
+        | EventType::ContactKeyImported { contact_id, .. } => *contact_id as libc::c_int,
     }
 }
 
@@ -447,8 +496,12 @@ fn render_info(
         | EventType::ContactsChanged(_)
         | EventType::LocationChanged(_)
         | EventType::ConfigureProgress { .. }
+        | EventType::DeleteAccountProgress(_)
         | EventType::ImexProgress(_)
         | EventType::ImexFileWritten(_)
+        | EventType::AutoBackupWritten(_)
+        | EventType::AutoBackupFailed(_)
+        | EventType::DbOwnerChanged(_)
         | EventType::MsgsNoticed(_)
         | EventType::ConnectivityChanged
         | EventType::ChatModified(_) => 0,
@@ -456,10 +509,12 @@ fn render_info(
         | EventType::IncomingMsg { msg_id, .. }
         | EventType::MsgDelivered { msg_id, .. }
         | EventType::MsgFailed { msg_id, .. }
-        | EventType::MsgRead { msg_id, .. } => msg_id.to_u32() as libc::c_int,
+        | EventType::MsgRead { msg_id, .. }
+        | EventType::MsgPreserved { msg_id, .. } => msg_id.to_u32() as libc::c_int,
         EventType::SecurejoinInviterProgress { progress, .. }
         | EventType::SecurejoinJoinerProgress { progress, .. } => *progress as libc::c_int,
         EventType::ChatEphemeralTimerModified { timer, .. } => timer.to_u32() as libc::c_int,
+        EventType::ContactKeyImported { source, .. } => *source as libc::c_int,
     }
 }
 
@@ -483,23 +538,28 @@ fn render_info(
         | EventType::DeletedBlobFile(msg)
         | EventType::Warning(msg)
         | EventType::Error(msg)
-        | EventType::ErrorSelfNotInGroup(msg) => {
+        | EventType::ErrorSelfNotInGroup(msg)
+        | EventType::AutoBackupFailed(msg) => {
             let data2 = msg.to_c_string().unwrap_or_default();
             data2.into_raw()
         }
         EventType::MsgsChanged { .. }
-        | EventType::IncomingMsg { .. }
         | EventType::MsgsNoticed(_)
         | EventType::MsgDelivered { .. }
         | EventType::MsgFailed { .. }
         | EventType::MsgRead { .. }
+        | EventType::MsgPreserved { .. }
         | EventType::ChatModified(_)
         | EventType::ContactsChanged(_)
         | EventType::LocationChanged(_)
+        | EventType::DeleteAccountProgress(_)
         | EventType::ImexProgress(_)
         | EventType::SecurejoinInviterProgress { .. }
         | EventType::SecurejoinJoinerProgress { .. }
         | EventType::ConnectivityChanged
+        | EventType::DbOwnerChanged(_)
+        | EventType::ContactKeyImported { .. }
+        | EventType::IncomingMsg { .. }
         | EventType::ChatEphemeralTimerModified { .. } => ptr::null_mut(),
         EventType::ConfigureProgress { comment, .. } => {
             if let Some(comment) = comment {
@@ -508,13 +568,46 @@ fn render_info(
                 ptr::null_mut()
             }
         }
-        EventType::ImexFileWritten(file) => {
+        EventType::ImexFileWritten(file) | EventType::AutoBackupWritten(file) => {
             let data2 = file.to_c_string().unwrap_or_default();
             data2.into_raw()
         }
     }
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_event_get_extra_json(event: *mut dc_event_t) -> *mut libc::c_char {
+    if event.is_null() {
+        eprintln!("ignoring careless call to dc_event_get_extra_json()");
+        return ptr::null_mut();
+    }
+
+    match &(*event).typ {
+        EventType::IncomingMsg {
+            notification_profile,
+            muted,
+            ..
+        } => {
+            if notification_profile.is_none() && !muted {
+                return ptr::null_mut();
+            }
+            let mut hints = serde_json::to_value(notification_profile.clone().unwrap_or_default())
+                .unwrap_or_default();
+            hints["muted"] = (*muted).into();
+            hints.to_string().strdup()
+        }
+        EventType::ChatEphemeralTimerModified {
+            contact_id, remote, ..
+        } => serde_json::json!({
+            "contact_id": contact_id,
+            "remote": remote,
+        })
+        .to_string()
+        .strdup(),
+        _ => ptr::null_mut(),
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_event_get_account_id(event: *mut dc_event_t) -> u32 {
     if event.is_null() {
@@ -539,6 +632,70 @@ fn render_info(
     Box::into_raw(Box::new(ctx.get_event_emitter()))
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_event_coalescing(
+    context: *mut dc_context_t,
+    event_id: libc::c_int,
+    milliseconds: u32,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_event_coalescing()");
+        return 0;
+    }
+    let ctx = &*context;
+    ctx.set_event_coalescing(event_id, Duration::from_millis(milliseconds.into()))
+        .log_err(ctx, "Failed to set event coalescing")
+        .is_ok() as libc::c_int
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_event_webhook(
+    context: *mut dc_context_t,
+    endpoint: *const libc::c_char,
+    secret: *const libc::c_char,
+) {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_event_webhook()");
+        return;
+    }
+    let ctx = &*context;
+    let webhook = to_opt_string_lossy(endpoint)
+        .filter(|endpoint| !endpoint.is_empty())
+        .map(|endpoint| {
+            let endpoint = match endpoint.strip_prefix("unix:") {
+                Some(path) => WebhookEndpoint::UnixSocket(path.into()),
+                None => WebhookEndpoint::Http(endpoint),
+            };
+            Webhook::new(endpoint, to_opt_string_lossy(secret))
+        });
+    ctx.set_event_webhook(webhook);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_next_event_json(
+    context: *mut dc_context_t,
+    timeout_ms: u32,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_next_event_json()");
+        return ptr::null_mut();
+    }
+    let ctx = &*context;
+
+    block_on(ctx.get_next_event(Duration::from_millis(timeout_ms.into())))
+        .map_or_else(ptr::null_mut, |event| event.strdup())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_interrupt_event_polling(context: *mut dc_context_t) {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_interrupt_event_polling()");
+        return;
+    }
+    let ctx = &*context;
+    ctx.interrupt_event_polling();
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_event_emitter_unref(emitter: *mut dc_event_emitter_t) {
     if emitter.is_null() {
@@ -587,6 +744,111 @@ fn render_info(
     block_on(async move { ctx.maybe_network().await })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_maintenance_hint(
+    context: *mut dc_context_t,
+    hint: libc::c_int,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_maintenance_hint()");
+        return 0;
+    }
+    let ctx = &*context;
+    let hint = if let Some(hint) = Hint::from_i32(hint) {
+        hint
+    } else {
+        warn!(ctx, "bad hint-value for dc_maintenance_hint()");
+        return 0;
+    };
+
+    block_on(async move {
+        ctx.maintenance_hint(hint).await;
+        1
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_delete_account(
+    context: *mut dc_context_t,
+    also_remove_server_data: libc::c_int,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_delete_account()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        ctx.delete_account(also_remove_server_data != 0)
+            .await
+            .log_err(ctx, "Failed to delete account")
+            .is_ok() as libc::c_int
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_network_policy(
+    context: *mut dc_context_t,
+    policy: libc::c_int,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_network_policy()");
+        return 0;
+    }
+    let ctx = &*context;
+    let policy = if let Some(policy) = NetworkPolicy::from_i32(policy) {
+        policy
+    } else {
+        warn!(ctx, "bad policy-value for dc_set_network_policy()");
+        return 0;
+    };
+
+    block_on(async move {
+        ctx.set_network_policy(policy).await;
+        1
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_deferred_downloads_json(
+    context: *mut dc_context_t,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_deferred_downloads_json()");
+        return ptr::null_mut();
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        match ctx
+            .get_deferred_downloads()
+            .await
+            .log_err(ctx, "Failed to get deferred downloads")
+        {
+            Ok(deferred) => serde_json::to_string(&deferred)
+                .unwrap_or_log_default(ctx, "Failed to serialise deferred downloads")
+                .strdup(),
+            Err(_) => ptr::null_mut(),
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_download_deferred(context: *mut dc_context_t, id: u32) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_download_deferred()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        ctx.download_deferred(id)
+            .await
+            .log_err(ctx, "Failed to download deferred message")
+            .is_ok() as libc::c_int
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_preconfigure_keypair(
     context: *mut dc_context_t,
@@ -704,6 +966,26 @@ fn render_info(
     .to_u32()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_estimate_msg_size(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    msg: *const dc_msg_t,
+) -> u64 {
+    if context.is_null() || msg.is_null() {
+        eprintln!("ignoring careless call to dc_estimate_msg_size()");
+        return 0;
+    }
+    let ctx = &*context;
+    let ffi_msg: &MessageWrapper = &*msg;
+
+    block_on(async move {
+        chat::estimate_msg_size(ctx, ChatId::new(chat_id), &ffi_msg.message)
+            .await
+            .unwrap_or_log_default(ctx, "Failed to estimate message size") as u64
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_send_msg(
     context: *mut dc_context_t,
@@ -910,6 +1192,27 @@ fn render_info(
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_first_msg_on_or_after(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    timestamp: i64,
+) -> u32 {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_first_msg_on_or_after()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        chat::get_first_msg_on_or_after(&ctx, ChatId::new(chat_id), timestamp)
+            .await
+            .unwrap_or_log_default(ctx, "failed to get first msg on or after timestamp")
+            .map(|msg_id| msg_id.to_u32())
+            .unwrap_or_default()
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_get_msg_cnt(context: *mut dc_context_t, chat_id: u32) -> libc::c_int {
     if context.is_null() {
@@ -963,6 +1266,31 @@ fn render_info(
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_repair(context: *mut dc_context_t, kind: libc::c_int) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_repair()");
+        return -1;
+    }
+    let ctx = &*context;
+    let kind = match RepairKind::from_i32(kind) {
+        Some(kind) => kind,
+        None => {
+            eprintln!("ignoring careless call to dc_repair(): unknown kind");
+            return -1;
+        }
+    };
+    block_on(async move {
+        match ctx.repair(kind).await {
+            Ok(report) => report.fixed as libc::c_int,
+            Err(err) => {
+                error!(ctx, "Failed to repair database: {:#}", err);
+                -1
+            }
+        }
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_get_fresh_msgs(
     context: *mut dc_context_t,
@@ -1245,6 +1573,24 @@ fn from_prim<S, T>(s: S) -> Option<T>
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_search_server(
+    context: *mut dc_context_t,
+    query: *const libc::c_char,
+) -> libc::c_int {
+    if context.is_null() || query.is_null() {
+        eprintln!("ignoring careless call to dc_search_server()");
+        return 0;
+    }
+    let ctx = &*context;
+    block_on(async move {
+        ctx.search_server(&to_string_lossy(query))
+            .await
+            .log_err(ctx, "Failed search_server")
+            .is_ok() as libc::c_int
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_get_chat(context: *mut dc_context_t, chat_id: u32) -> *mut dc_chat_t {
     if context.is_null() {
@@ -1366,82 +1712,293 @@ fn from_prim<S, T>(s: S) -> Option<T>
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn dc_set_chat_profile_image(
+pub unsafe extern "C" fn dc_redownload_chat_history(
     context: *mut dc_context_t,
     chat_id: u32,
-    image: *const libc::c_char,
+    limit: u32,
 ) -> libc::c_int {
-    if context.is_null() || chat_id <= constants::DC_CHAT_ID_LAST_SPECIAL.to_u32() {
-        eprintln!("ignoring careless call to dc_set_chat_profile_image()");
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_redownload_chat_history()");
         return 0;
     }
     let ctx = &*context;
-
     block_on(async move {
-        chat::set_chat_profile_image(&ctx, ChatId::new(chat_id), to_string_lossy(image))
+        chat::redownload_history(&ctx, ChatId::new(chat_id), limit)
             .await
-            .map(|_| 1)
-            .unwrap_or_log_default(&ctx, "Failed to set profile image")
+            .log_err(ctx, "Failed to redownload chat history")
+            .is_ok() as libc::c_int
     })
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn dc_set_chat_mute_duration(
+pub unsafe extern "C" fn dc_set_chat_subject_prefix(
     context: *mut dc_context_t,
     chat_id: u32,
-    duration: i64,
+    prefix: *const libc::c_char,
 ) -> libc::c_int {
-    if context.is_null() {
-        eprintln!("ignoring careless call to dc_set_chat_mute_duration()");
+    if context.is_null() || chat_id <= constants::DC_CHAT_ID_LAST_SPECIAL.to_u32() {
+        eprintln!("ignoring careless call to dc_set_chat_subject_prefix()");
         return 0;
     }
     let ctx = &*context;
-    let muteDuration = match duration {
-        0 => MuteDuration::NotMuted,
-        -1 => MuteDuration::Forever,
-        n if n > 0 => SystemTime::now()
-            .checked_add(Duration::from_secs(duration as u64))
-            .map_or(MuteDuration::Forever, MuteDuration::Until),
-        _ => {
-            warn!(
-                ctx,
-                "dc_chat_set_mute_duration(): Can not use negative duration other than -1",
-            );
-            return 0;
-        }
-    };
 
     block_on(async move {
-        chat::set_muted(&ctx, ChatId::new(chat_id), muteDuration)
+        ChatId::new(chat_id)
+            .set_subject_prefix(&ctx, to_opt_string_lossy(prefix).as_deref())
             .await
             .map(|_| 1)
-            .unwrap_or_log_default(&ctx, "Failed to set mute duration")
+            .unwrap_or_log_default(&ctx, "Failed to set subject prefix")
     })
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn dc_get_chat_encrinfo(
+pub unsafe extern "C" fn dc_set_chat_mdn_policy(
     context: *mut dc_context_t,
     chat_id: u32,
-) -> *mut libc::c_char {
-    if context.is_null() {
-        eprintln!("ignoring careless call to dc_get_chat_encrinfo()");
-        return "".strdup();
+    policy: libc::c_int,
+) -> libc::c_int {
+    if context.is_null() || chat_id <= constants::DC_CHAT_ID_LAST_SPECIAL.to_u32() {
+        eprintln!("ignoring careless call to dc_set_chat_mdn_policy()");
+        return 0;
     }
     let ctx = &*context;
+    let policy = if let Some(policy) = MdnPolicy::from_i32(policy) {
+        policy
+    } else {
+        warn!(ctx, "bad policy-value for dc_set_chat_mdn_policy()");
+        return 0;
+    };
 
     block_on(async move {
         ChatId::new(chat_id)
-            .get_encryption_info(&ctx)
+            .set_mdn_policy(&ctx, policy)
             .await
-            .map(|s| s.strdup())
-            .unwrap_or_else(|e| {
-                error!(&ctx, "{}", e);
-                ptr::null_mut()
+            .log_err(ctx, "Failed to set mdn policy")
+            .is_ok() as libc::c_int
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_chat_profile_image(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    image: *const libc::c_char,
+) -> libc::c_int {
+    if context.is_null() || chat_id <= constants::DC_CHAT_ID_LAST_SPECIAL.to_u32() {
+        eprintln!("ignoring careless call to dc_set_chat_profile_image()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        chat::set_chat_profile_image(&ctx, ChatId::new(chat_id), to_string_lossy(image))
+            .await
+            .map(|_| 1)
+            .unwrap_or_log_default(&ctx, "Failed to set profile image")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_chat_mute_duration(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    duration: i64,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_chat_mute_duration()");
+        return 0;
+    }
+    let ctx = &*context;
+    let muteDuration = match duration {
+        0 => MuteDuration::NotMuted,
+        -1 => MuteDuration::Forever,
+        n if n > 0 => SystemTime::now()
+            .checked_add(Duration::from_secs(duration as u64))
+            .map_or(MuteDuration::Forever, MuteDuration::Until),
+        _ => {
+            warn!(
+                ctx,
+                "dc_chat_set_mute_duration(): Can not use negative duration other than -1",
+            );
+            return 0;
+        }
+    };
+
+    block_on(async move {
+        chat::set_muted(&ctx, ChatId::new(chat_id), muteDuration)
+            .await
+            .map(|_| 1)
+            .unwrap_or_log_default(&ctx, "Failed to set mute duration")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_chat_notification_profile(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    json: *const libc::c_char,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_chat_notification_profile()");
+        return 0;
+    }
+    let ctx = &*context;
+    let profile: Option<chat::NotificationProfile> = match to_opt_string_lossy(json)
+        .map(|json| serde_json::from_str(&json))
+        .transpose()
+    {
+        Ok(profile) => profile,
+        Err(err) => {
+            warn!(
+                ctx,
+                "dc_set_chat_notification_profile(): invalid JSON: {}", err
+            );
+            return 0;
+        }
+    };
+
+    block_on(async move {
+        ChatId::new(chat_id)
+            .set_notification_profile(&ctx, profile.as_ref())
+            .await
+            .log_err(ctx, "Failed to set notification profile")
+            .is_ok() as libc::c_int
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_chat_save_mime(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    save: libc::c_int,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_chat_save_mime()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        ChatId::new(chat_id)
+            .set_save_mime(&ctx, save != 0)
+            .await
+            .log_err(ctx, "Failed to set save_mime")
+            .is_ok() as libc::c_int
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_chat_save_mime(
+    context: *mut dc_context_t,
+    chat_id: u32,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_chat_save_mime()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(ChatId::new(chat_id).get_save_mime(&ctx))
+        .log_err(ctx, "Failed to get save_mime")
+        .unwrap_or_default() as libc::c_int
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_chat_avatar_history(
+    context: *mut dc_context_t,
+    chat_id: u32,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_chat_avatar_history()");
+        return "".strdup();
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        match ChatId::new(chat_id)
+            .get_avatar_history(&ctx)
+            .await
+            .log_err(ctx, "Failed to get avatar history")
+        {
+            Ok(history) => {
+                let history: Vec<_> = history
+                    .iter()
+                    .map(|entry| {
+                        serde_json::json!({
+                            "path": entry.path.to_string_lossy(),
+                            "valid_until": entry.valid_until,
+                        })
+                    })
+                    .collect();
+                serde_json::Value::from(history).to_string().strdup()
+            }
+            Err(_) => "".strdup(),
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_chat_notification_profile(
+    context: *mut dc_context_t,
+    chat_id: u32,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_chat_notification_profile()");
+        return ptr::null_mut();
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        match ChatId::new(chat_id)
+            .get_notification_profile(&ctx)
+            .await
+            .log_err(ctx, "Failed to get notification profile")
+        {
+            Ok(Some(profile)) => serde_json::to_string(&profile)
+                .unwrap_or_log_default(ctx, "Failed to serialise notification profile")
+                .strdup(),
+            _ => ptr::null_mut(),
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_chat_encrinfo(
+    context: *mut dc_context_t,
+    chat_id: u32,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_chat_encrinfo()");
+        return "".strdup();
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        ChatId::new(chat_id)
+            .get_encryption_info(&ctx)
+            .await
+            .map(|s| s.strdup())
+            .unwrap_or_else(|e| {
+                error!(&ctx, "{}", e);
+                ptr::null_mut()
             })
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_next_expiration_timestamp(context: *mut dc_context_t) -> i64 {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_next_expiration_timestamp()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(ephemeral::next_expiration_timestamp(ctx))
+        .log_err(ctx, "Failed to get next expiration timestamp")
+        .unwrap_or_default()
+        .unwrap_or_default()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_get_chat_ephemeral_timer(
     context: *mut dc_context_t,
@@ -1485,6 +2042,286 @@ fn from_prim<S, T>(s: S) -> Option<T>
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_chat_ephemeral_timer_start(
+    context: *mut dc_context_t,
+    chat_id: u32,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_chat_ephemeral_timer_start()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move { ChatId::new(chat_id).get_ephemeral_timer_start(ctx).await })
+        .log_err(ctx, "Failed to get ephemeral timer start")
+        .unwrap_or_default()
+        .to_i32()
+        .unwrap_or_default()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_chat_ephemeral_timer_start(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    start: libc::c_int,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_chat_ephemeral_timer_start()");
+        return 0;
+    }
+    let ctx = &*context;
+    let start = if let Some(start) = EphemeralTimerStart::from_i32(start) {
+        start
+    } else {
+        warn!(
+            ctx,
+            "bad start-value for dc_set_chat_ephemeral_timer_start()"
+        );
+        return 0;
+    };
+
+    block_on(async move {
+        ChatId::new(chat_id)
+            .set_ephemeral_timer_start(ctx, start)
+            .await
+            .log_err(ctx, "Failed to set ephemeral timer start")
+            .is_ok() as libc::c_int
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_chat_ephemeral_expiry_mode(
+    context: *mut dc_context_t,
+    chat_id: u32,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_chat_ephemeral_expiry_mode()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move { ChatId::new(chat_id).get_ephemeral_expiry_mode(ctx).await })
+        .log_err(ctx, "Failed to get ephemeral expiry mode")
+        .unwrap_or_default()
+        .to_i32()
+        .unwrap_or_default()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_chat_ephemeral_expiry_mode(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    mode: libc::c_int,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_chat_ephemeral_expiry_mode()");
+        return 0;
+    }
+    let ctx = &*context;
+    let mode = if let Some(mode) = EphemeralExpiryMode::from_i32(mode) {
+        mode
+    } else {
+        warn!(
+            ctx,
+            "bad mode-value for dc_set_chat_ephemeral_expiry_mode()"
+        );
+        return 0;
+    };
+
+    block_on(async move {
+        ChatId::new(chat_id)
+            .set_ephemeral_expiry_mode(ctx, mode)
+            .await
+            .log_err(ctx, "Failed to set ephemeral expiry mode")
+            .is_ok() as libc::c_int
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_chat_ephemeral_timer_policy(
+    context: *mut dc_context_t,
+    chat_id: u32,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_chat_ephemeral_timer_policy()");
+        return "".strdup();
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        ChatId::new(chat_id)
+            .get_ephemeral_timer_policy(ctx)
+            .await
+            .map(|policy| {
+                serde_json::json!({
+                    "verified_only": policy.verified_only,
+                    "max_timer": policy.max_duration.unwrap_or_default(),
+                    "approve_unverified": policy.approve_unverified,
+                })
+                .to_string()
+            })
+            .unwrap_or_log_default(ctx, "Failed to get ephemeral timer policy")
+            .strdup()
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_chat_ephemeral_stats(
+    context: *mut dc_context_t,
+    chat_id: u32,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_chat_ephemeral_stats()");
+        return "".strdup();
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        ChatId::new(chat_id)
+            .get_ephemeral_stats(ctx)
+            .await
+            .map(|stats| {
+                serde_json::json!({
+                    "count": stats.count,
+                    "first_expiry": stats.first_expiry.unwrap_or_default(),
+                    "last_expiry": stats.last_expiry.unwrap_or_default(),
+                    "bytes": stats.bytes,
+                })
+                .to_string()
+            })
+            .unwrap_or_log_default(ctx, "Failed to get ephemeral stats")
+            .strdup()
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_approve_chat_ephemeral_timer_change(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    approve: libc::c_int,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_approve_chat_ephemeral_timer_change()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        ChatId::new(chat_id)
+            .approve_ephemeral_timer_change(ctx, approve != 0)
+            .await
+            .log_err(ctx, "Failed to approve ephemeral timer change")
+            .is_ok() as libc::c_int
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_ephemeral_timer_presets() -> *mut libc::c_char {
+    let presets: Vec<u32> = EphemeralTimer::presets()
+        .iter()
+        .map(|timer| timer.to_u32())
+        .collect();
+    serde_json::to_string(&presets).unwrap_or_default().strdup()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_purge_stale_trash(
+    context: *mut dc_context_t,
+    older_than: i64,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_purge_stale_trash()");
+        return -1;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        ephemeral::purge_stale_trash(ctx, older_than)
+            .await
+            .log_err(ctx, "Failed to purge stale trash")
+            .map_or(-1, |purged| purged as libc::c_int)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_chat_ephemeral_timer_policy(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    verified_only: libc::c_int,
+    max_timer: u32,
+    approve_unverified: libc::c_int,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_chat_ephemeral_timer_policy()");
+        return 0;
+    }
+    let ctx = &*context;
+    let policy = EphemeralTimerPolicy {
+        verified_only: verified_only != 0,
+        max_duration: if max_timer == 0 {
+            None
+        } else {
+            Some(max_timer)
+        },
+        approve_unverified: approve_unverified != 0,
+    };
+
+    block_on(async move {
+        ChatId::new(chat_id)
+            .set_ephemeral_timer_policy(ctx, policy)
+            .await
+            .log_err(ctx, "Failed to set ephemeral timer policy")
+            .is_ok() as libc::c_int
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_chat_ephemeral_timer_including_history(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    timer: u32,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_chat_ephemeral_timer_including_history()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        ChatId::new(chat_id)
+            .set_ephemeral_timer_with_scope(
+                ctx,
+                EphemeralTimer::from_u32(timer),
+                EphemeralScope::IncludingHistory,
+            )
+            .await
+            .log_err(ctx, "Failed to set ephemeral timer")
+            .is_ok() as libc::c_int
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_chat_ephemeral_timer_silently(
+    context: *mut dc_context_t,
+    chat_id: u32,
+    timer: u32,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_chat_ephemeral_timer_silently()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        ChatId::new(chat_id)
+            .set_ephemeral_timer_silently(ctx, EphemeralTimer::from_u32(timer))
+            .await
+            .log_err(ctx, "Failed to set ephemeral timer")
+            .is_ok() as libc::c_int
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_get_msg_info(
     context: *mut dc_context_t,
@@ -1504,6 +2341,53 @@ fn from_prim<S, T>(s: S) -> Option<T>
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_msg_json(
+    context: *mut dc_context_t,
+    msg_id: u32,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_msg_json()");
+        return "".strdup();
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        MsgId::new(msg_id)
+            .to_json(&ctx)
+            .await
+            .unwrap_or_log_default(ctx, "dc_get_msg_json() failed")
+            .strdup()
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_fetch_one_notification(
+    context: *mut dc_context_t,
+    folder: *const libc::c_char,
+    uid_hint: u32,
+) -> *mut libc::c_char {
+    if context.is_null() || folder.is_null() {
+        eprintln!("ignoring careless call to dc_fetch_one_notification()");
+        return ptr::null_mut();
+    }
+    let ctx = &*context;
+    let folder = to_string_lossy(folder);
+    let uid_hint = if uid_hint == 0 { None } else { Some(uid_hint) };
+
+    block_on(async move {
+        match deltachat::context::fetch_one_notification(ctx, &folder, uid_hint)
+            .await
+            .log_err(ctx, "dc_fetch_one_notification() failed")
+        {
+            Ok(Some(payload)) => serde_json::to_string(&payload)
+                .unwrap_or_log_default(ctx, "Failed to serialize notification")
+                .strdup(),
+            Ok(None) | Err(_) => ptr::null_mut(),
+        }
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_get_msg_html(
     context: *mut dc_context_t,
@@ -1515,30 +2399,67 @@ fn from_prim<S, T>(s: S) -> Option<T>
     }
     let ctx = &*context;
 
-    block_on(MsgId::new(msg_id).get_html(&ctx))
-        .unwrap_or_log_default(ctx, "Failed get_msg_html")
+    block_on(MsgId::new(msg_id).get_html(&ctx))
+        .unwrap_or_log_default(ctx, "Failed get_msg_html")
+        .strdup()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_mime_headers(
+    context: *mut dc_context_t,
+    msg_id: u32,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_mime_headers()");
+        return ptr::null_mut(); // NULL explicitly defined as "no mime headers"
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        let mime = message::get_mime_headers(&ctx, MsgId::new(msg_id))
+            .await
+            .unwrap_or_log_default(ctx, "failed to get mime headers");
+        if mime.is_empty() {
+            return ptr::null_mut();
+        }
+        mime.strdup()
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_get_msg_note(
+    context: *mut dc_context_t,
+    msg_id: u32,
+) -> *mut libc::c_char {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_get_msg_note()");
+        return ptr::null_mut();
+    }
+    let ctx = &*context;
+
+    block_on(MsgId::new(msg_id).get_note(&ctx))
+        .unwrap_or_log_default(ctx, "Failed to get message note")
         .strdup()
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn dc_get_mime_headers(
+pub unsafe extern "C" fn dc_set_msg_note(
     context: *mut dc_context_t,
     msg_id: u32,
-) -> *mut libc::c_char {
+    note: *const libc::c_char,
+) -> libc::c_int {
     if context.is_null() {
-        eprintln!("ignoring careless call to dc_get_mime_headers()");
-        return ptr::null_mut(); // NULL explicitly defined as "no mime headers"
+        eprintln!("ignoring careless call to dc_set_msg_note()");
+        return 0;
     }
     let ctx = &*context;
 
     block_on(async move {
-        let mime = message::get_mime_headers(&ctx, MsgId::new(msg_id))
+        MsgId::new(msg_id)
+            .set_note(&ctx, &to_string_lossy(note))
             .await
-            .unwrap_or_log_default(ctx, "failed to get mime headers");
-        if mime.is_empty() {
-            return ptr::null_mut();
-        }
-        mime.strdup()
+            .log_err(ctx, "Failed to set message note")
+            .is_ok() as libc::c_int
     })
 }
 
@@ -1558,6 +2479,21 @@ fn from_prim<S, T>(s: S) -> Option<T>
     block_on(message::delete_msgs(&ctx, &msg_ids))
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_undo_last_deletion(context: *mut dc_context_t) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_undo_last_deletion()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        ctx.undo_last_deletion()
+            .await
+            .unwrap_or_log_default(&ctx, "Failed to undo deletion") as libc::c_int
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_forward_msgs(
     context: *mut dc_context_t,
@@ -1601,6 +2537,41 @@ fn from_prim<S, T>(s: S) -> Option<T>
         .ok();
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_star_msgs(
+    context: *mut dc_context_t,
+    msg_ids: *const u32,
+    msg_cnt: libc::c_int,
+    star: libc::c_int,
+) {
+    if context.is_null() || msg_ids.is_null() || msg_cnt <= 0 {
+        eprintln!("ignoring careless call to dc_star_msgs()");
+        return;
+    }
+    let msg_ids = convert_and_prune_message_ids(msg_ids, msg_cnt);
+    let ctx = &*context;
+
+    block_on(message::star_msgs(&ctx, msg_ids, star != 0))
+        .log_err(ctx, "failed dc_star_msgs() call")
+        .ok();
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_cancel_msg_expiry(
+    context: *mut dc_context_t,
+    msg_id: u32,
+) -> libc::c_int {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_cancel_msg_expiry()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(MsgId::new(msg_id).cancel_expiry(ctx))
+        .log_err(ctx, "failed dc_cancel_msg_expiry() call")
+        .is_ok() as libc::c_int
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_get_msg(context: *mut dc_context_t, msg_id: u32) -> *mut dc_msg_t {
     if context.is_null() {
@@ -1783,6 +2754,113 @@ fn from_prim<S, T>(s: S) -> Option<T>
     });
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_contact_load_remote_content(
+    context: *mut dc_context_t,
+    contact_id: u32,
+    allow: libc::c_int,
+) {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_contact_load_remote_content()");
+        return;
+    }
+    let ctx = &*context;
+    block_on(async move {
+        match Contact::load_from_db(&ctx, contact_id).await {
+            Ok(mut contact) => contact
+                .set_load_remote_content(&ctx, allow != 0)
+                .await
+                .ok_or_log_msg(&ctx, "Can't set remote content permission"),
+            Err(err) => error!(ctx, "Can't load contact {}: {}", contact_id, err),
+        }
+    });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_contact_plain_email(
+    context: *mut dc_context_t,
+    contact_id: u32,
+    plain_email: libc::c_int,
+) {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_contact_plain_email()");
+        return;
+    }
+    let ctx = &*context;
+    block_on(async move {
+        match Contact::load_from_db(&ctx, contact_id).await {
+            Ok(mut contact) => contact
+                .set_plain_email(&ctx, plain_email != 0)
+                .await
+                .ok_or_log_msg(&ctx, "Can't set classic email mode"),
+            Err(err) => error!(ctx, "Can't load contact {}: {}", contact_id, err),
+        }
+    });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_contact_sign_only(
+    context: *mut dc_context_t,
+    contact_id: u32,
+    sign_only: libc::c_int,
+) {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_contact_sign_only()");
+        return;
+    }
+    let ctx = &*context;
+    block_on(async move {
+        match Contact::load_from_db(&ctx, contact_id).await {
+            Ok(mut contact) => contact
+                .set_sign_only(&ctx, sign_only != 0)
+                .await
+                .ok_or_log_msg(&ctx, "Can't set signed-only mode"),
+            Err(err) => error!(ctx, "Can't load contact {}: {}", contact_id, err),
+        }
+    });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_set_contact_self_alias(
+    context: *mut dc_context_t,
+    contact_id: u32,
+    self_alias: libc::c_int,
+) {
+    if context.is_null() {
+        eprintln!("ignoring careless call to dc_set_contact_self_alias()");
+        return;
+    }
+    let ctx = &*context;
+    block_on(async move {
+        match Contact::load_from_db(&ctx, contact_id).await {
+            Ok(mut contact) => contact
+                .set_self_alias(&ctx, self_alias != 0)
+                .await
+                .ok_or_log_msg(&ctx, "Can't set self alias"),
+            Err(err) => error!(ctx, "Can't load contact {}: {}", contact_id, err),
+        }
+    });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_import_contact_key(
+    context: *mut dc_context_t,
+    contact_id: u32,
+    asc: *const libc::c_char,
+) -> libc::c_int {
+    if context.is_null() || asc.is_null() {
+        eprintln!("ignoring careless call to dc_import_contact_key()");
+        return 0;
+    }
+    let ctx = &*context;
+    block_on(async move {
+        Contact::import_public_key(&ctx, contact_id, &to_string_lossy(asc))
+            .await
+            .log_err(&ctx, "Cannot import key")
+            .is_ok() as libc::c_int
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_get_contact_encrinfo(
     context: *mut dc_context_t,
@@ -1899,6 +2977,63 @@ fn from_prim<S, T>(s: S) -> Option<T>
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_export_settings(
+    context: *mut dc_context_t,
+    file: *const libc::c_char,
+) -> libc::c_int {
+    if context.is_null() || file.is_null() {
+        eprintln!("ignoring careless call to dc_export_settings()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        imex::export_settings(&ctx, to_string_lossy(file).as_ref())
+            .await
+            .map(|_| 1)
+            .unwrap_or_log_default(&ctx, "Failed to export settings")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_import_settings(
+    context: *mut dc_context_t,
+    file: *const libc::c_char,
+) -> libc::c_int {
+    if context.is_null() || file.is_null() {
+        eprintln!("ignoring careless call to dc_import_settings()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        imex::import_settings(&ctx, to_string_lossy(file).as_ref())
+            .await
+            .map(|_| 1)
+            .unwrap_or_log_default(&ctx, "Failed to import settings")
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_export_data_portability(
+    context: *mut dc_context_t,
+    dir: *const libc::c_char,
+) -> libc::c_int {
+    if context.is_null() || dir.is_null() {
+        eprintln!("ignoring careless call to dc_export_data_portability()");
+        return 0;
+    }
+    let ctx = &*context;
+
+    block_on(async move {
+        imex::export_data_portability(&ctx, to_string_lossy(dir).as_ref())
+            .await
+            .map(|_| 1)
+            .unwrap_or_log_default(&ctx, "Failed to export account data")
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_initiate_key_transfer(context: *mut dc_context_t) -> *mut libc::c_char {
     if context.is_null() {
@@ -2594,6 +3729,16 @@ pub struct ChatWrapper {
     ffi_chat.chat.is_protected() as libc::c_int
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_chat_get_mdn_policy(chat: *mut dc_chat_t) -> libc::c_int {
+    if chat.is_null() {
+        eprintln!("ignoring careless call to dc_chat_get_mdn_policy()");
+        return 0;
+    }
+    let ffi_chat = &*chat;
+    ffi_chat.chat.get_mdn_policy() as libc::c_int
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_chat_is_sending_locations(chat: *mut dc_chat_t) -> libc::c_int {
     if chat.is_null() {
@@ -2808,6 +3953,22 @@ pub struct MessageWrapper {
     ffi_msg.message.get_text().unwrap_or_default().strdup()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_get_full_text(msg: *mut dc_msg_t) -> *mut libc::c_char {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_get_full_text()");
+        return "".strdup();
+    }
+    let ffi_msg = &*msg;
+    let ctx = &*ffi_msg.context;
+
+    block_on(ffi_msg.message.get_full_text(ctx))
+        .log_err(ctx, "Failed to get full text")
+        .unwrap_or_default()
+        .unwrap_or_default()
+        .strdup()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_msg_get_subject(msg: *mut dc_msg_t) -> *mut libc::c_char {
     if msg.is_null() {
@@ -2909,6 +4070,16 @@ pub struct MessageWrapper {
     ffi_msg.message.get_showpadlock() as libc::c_int
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_is_signed_only(msg: *mut dc_msg_t) -> libc::c_int {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_is_signed_only()");
+        return 0;
+    }
+    let ffi_msg = &*msg;
+    ffi_msg.message.is_signed_only() as libc::c_int
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_msg_is_bot(msg: *mut dc_msg_t) -> libc::c_int {
     if msg.is_null() {
@@ -2939,6 +4110,56 @@ pub struct MessageWrapper {
     ffi_msg.message.get_ephemeral_timestamp()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_get_ephemeral_remaining_secs(msg: *mut dc_msg_t) -> i64 {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_get_ephemeral_remaining_secs()");
+        return 0;
+    }
+    let ffi_msg = &*msg;
+    ffi_msg.message.get_ephemeral_remaining_secs()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_is_view_once(msg: *mut dc_msg_t) -> libc::c_int {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_is_view_once()");
+        return 0;
+    }
+    let ffi_msg = &*msg;
+    ffi_msg.message.is_view_once().into()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_is_starred(msg: *mut dc_msg_t) -> libc::c_int {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_is_starred()");
+        return 0;
+    }
+    let ffi_msg = &*msg;
+    ffi_msg.message.is_starred().into()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_is_from_self_alias(msg: *mut dc_msg_t) -> libc::c_int {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_is_from_self_alias()");
+        return 0;
+    }
+    let ffi_msg = &*msg;
+    ffi_msg.message.is_from_self_alias().into()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_set_view_once(msg: *mut dc_msg_t, view_once: libc::c_int) {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_set_view_once()");
+        return;
+    }
+    let ffi_msg = &mut *msg;
+    ffi_msg.message.set_view_once(view_once != 0)
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_msg_get_summary(
     msg: *mut dc_msg_t,
@@ -3143,6 +4364,18 @@ pub struct MessageWrapper {
     ffi_msg.message.set_html(to_opt_string_lossy(html))
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_msg_set_subject(msg: *mut dc_msg_t, subject: *const libc::c_char) {
+    if msg.is_null() {
+        eprintln!("ignoring careless call to dc_msg_set_subject()");
+        return;
+    }
+    let ffi_msg = &mut *msg;
+    ffi_msg
+        .message
+        .set_subject(to_opt_string_lossy(subject).unwrap_or_default())
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_msg_set_override_sender_name(
     msg: *mut dc_msg_t,
@@ -3448,6 +4681,38 @@ pub struct ContactWrapper {
     ffi_contact.contact.is_blocked() as libc::c_int
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dc_contact_get_load_remote_content(
+    contact: *mut dc_contact_t,
+) -> libc::c_int {
+    if contact.is_null() {
+        eprintln!("ignoring careless call to dc_contact_get_load_remote_content()");
+        return 0;
+    }
+    let ffi_contact = &*contact;
+    ffi_contact.contact.get_load_remote_content() as libc::c_int
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_contact_is_plain_email(contact: *mut dc_contact_t) -> libc::c_int {
+    if contact.is_null() {
+        eprintln!("ignoring careless call to dc_contact_is_plain_email()");
+        return 0;
+    }
+    let ffi_contact = &*contact;
+    ffi_contact.contact.is_plain_email() as libc::c_int
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dc_contact_is_self_alias(contact: *mut dc_contact_t) -> libc::c_int {
+    if contact.is_null() {
+        eprintln!("ignoring careless call to dc_contact_is_self_alias()");
+        return 0;
+    }
+    let ffi_contact = &*contact;
+    ffi_contact.contact.is_self_alias() as libc::c_int
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn dc_contact_is_verified(contact: *mut dc_contact_t) -> libc::c_int {
     if contact.is_null() {
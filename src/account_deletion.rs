@@ -0,0 +1,211 @@
+//! # Account deletion
+//!
+//! [`Context::delete_account`] wipes all local data of an account. Optionally, the
+//! messages in the DeltaChat folder and the messages sent by the user are deleted from
+//! the server before, using the usual `DeleteMsgOnImap` jobs. The progress is reported
+//! with `DeleteAccountProgress` events.
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, ensure, Result};
+use async_std::fs;
+use async_std::task;
+
+use crate::config::Config;
+use crate::constants::DC_CONTACT_ID_SELF;
+use crate::context::Context;
+use crate::ephemeral;
+use crate::events::EventType;
+use crate::job::{self, Action, Job};
+use crate::message::MsgId;
+use crate::param::Params;
+
+/// Maximum time to wait for the server data to be deleted.
+const SERVER_CLEANUP_TIMEOUT: Duration = Duration::from_secs(300);
+
+impl Context {
+    /// Deletes the account.
+    ///
+    /// If `also_remove_server_data` is set, the messages in the DeltaChat folder and
+    /// the messages sent by the user are deleted from the server first. This requires
+    /// IO, which is started if needed. If the server data cannot be deleted, an error
+    /// is returned and no local data is deleted.
+    ///
+    /// Afterwards, IO is stopped and the database and all blobs are removed, so the
+    /// context cannot be used anymore. When using [`crate::accounts::Accounts`], remove
+    /// the account using `remove_account` afterwards.
+    pub async fn delete_account(&self, also_remove_server_data: bool) -> Result<()> {
+        self.emit_event(EventType::DeleteAccountProgress(10));
+        if also_remove_server_data {
+            if let Err(err) = self.remove_server_data().await {
+                self.emit_event(EventType::DeleteAccountProgress(0));
+                return Err(err);
+            }
+        }
+        self.emit_event(EventType::DeleteAccountProgress(900));
+
+        self.stop_io().await;
+        ephemeral::stop_ephemeral_task(self).await;
+        self.sql.close().await;
+        let dbfile = self.get_dbfile().to_path_buf();
+        let blobdir = self.get_blobdir().to_path_buf();
+        let walfile = Context::derive_walfile(&dbfile);
+        fs::remove_dir_all(&blobdir).await.ok();
+        fs::remove_file(&walfile).await.ok();
+        fs::remove_file(&dbfile).await?;
+        info!(self, "Account deleted.");
+
+        self.emit_event(EventType::DeleteAccountProgress(1000));
+        Ok(())
+    }
+
+    /// Deletes the messages in the DeltaChat folder and the messages sent by the user
+    /// from the server and waits until they are deleted.
+    async fn remove_server_data(&self) -> Result<()> {
+        ensure!(
+            self.is_configured().await?,
+            "Account is not configured, cannot remove server data"
+        );
+        let mvbox = self
+            .get_config(Config::ConfiguredMvboxFolder)
+            .await?
+            .unwrap_or_default();
+
+        // Every part gets its own job: the jobs of all but the last part of a message
+        // only unlink their part, the last one deletes the message from the server.
+        let msg_ids = self
+            .sql
+            .query_map(
+                "SELECT id FROM msgs \
+                 WHERE server_uid != 0 \
+                 AND rfc724_mid != '' \
+                 AND (server_folder=? OR from_id=?)",
+                paramsv![mvbox, DC_CONTACT_ID_SELF],
+                |row| row.get::<_, MsgId>(0),
+                |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+            )
+            .await?;
+        let total = msg_ids.len();
+        info!(self, "Deleting {} messages from the server.", total);
+        if total == 0 {
+            return Ok(());
+        }
+
+        let mut pending: HashSet<MsgId> = msg_ids.iter().copied().collect();
+        self.sql
+            .transaction(move |transaction| {
+                for msg_id in msg_ids {
+                    Job::new(Action::DeleteMsgOnImap, msg_id.to_u32(), Params::new(), 0)
+                        .insert(transaction)?;
+                }
+                Ok(())
+            })
+            .await?;
+        self.start_io().await;
+        job::interrupt(self, Action::DeleteMsgOnImap).await;
+
+        let start = Instant::now();
+        loop {
+            // A message is deleted from the server once its job removed the row
+            // or its server UID. A message which still has a UID but no job anymore
+            // failed to be deleted.
+            let linked = self.linked_msgs().await?;
+            let mut failed = 0;
+            pending.retain(|msg_id| match linked.get(msg_id) {
+                Some(true) => true,
+                Some(false) => {
+                    failed += 1;
+                    true
+                }
+                None => false,
+            });
+            let done = total - pending.len();
+            self.emit_event(EventType::DeleteAccountProgress(10 + 880 * done / total));
+            if pending.is_empty() {
+                return Ok(());
+            }
+            if failed == pending.len() {
+                bail!("Failed to delete {} messages from the server", failed);
+            }
+            if start.elapsed() > SERVER_CLEANUP_TIMEOUT {
+                bail!(
+                    "Timeout while deleting server data, {} messages left",
+                    pending.len()
+                );
+            }
+            task::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    /// Returns the messages with a server UID, mapped to whether
+    /// a `DeleteMsgOnImap` job is pending for them.
+    async fn linked_msgs(&self) -> Result<HashMap<MsgId, bool>> {
+        self.sql
+            .query_map(
+                "SELECT id, id IN (SELECT foreign_id FROM jobs WHERE action=?) \
+                 FROM msgs WHERE server_uid != 0",
+                paramsv![Action::DeleteMsgOnImap],
+                |row| Ok((row.get::<_, MsgId>(0)?, row.get::<_, bool>(1)?)),
+                |rows| {
+                    rows.collect::<Result<HashMap<_, _>, _>>()
+                        .map_err(Into::into)
+                },
+            )
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestContext;
+
+    #[async_std::test]
+    async fn test_delete_account() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let dbfile = t.get_dbfile().to_path_buf();
+        let blobdir = t.get_blobdir().to_path_buf();
+        assert!(dbfile.exists().await);
+        assert!(blobdir.exists().await);
+
+        t.delete_account(false).await?;
+        assert!(!dbfile.exists().await);
+        assert!(!blobdir.exists().await);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_linked_msgs() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let msg_id = t
+            .sql
+            .insert(
+                "INSERT INTO msgs (chat_id, rfc724_mid, server_folder, server_uid) \
+                 VALUES (10, 'abc@example.org', 'INBOX', 1)",
+                paramsv![],
+            )
+            .await?;
+        let msg_id = MsgId::new(msg_id as u32);
+
+        // A message with a server UID but without a job failed to be deleted.
+        assert_eq!(t.linked_msgs().await?.get(&msg_id), Some(&false));
+
+        Job::new(Action::DeleteMsgOnImap, msg_id.to_u32(), Params::new(), 0)
+            .save(&t)
+            .await?;
+        assert_eq!(t.linked_msgs().await?.get(&msg_id), Some(&true));
+
+        msg_id.unlink(&t).await?;
+        assert_eq!(t.linked_msgs().await?.get(&msg_id), None);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_remove_server_data_unconfigured() -> Result<()> {
+        let t = TestContext::new().await;
+        assert!(t.delete_account(true).await.is_err());
+        assert!(t.get_dbfile().exists().await);
+        Ok(())
+    }
+}
@@ -11,6 +11,7 @@
 use serde::{Deserialize, Serialize};
 
 use crate::context::Context;
+use crate::ephemeral;
 use crate::events::Event;
 
 /// Account manager, that can handle multiple accounts in a single place.
@@ -103,6 +104,7 @@ pub async fn remove_account(&self, id: u32) -> Result<()> {
         ensure!(ctx.is_some(), "no account with this id: {}", id);
         let ctx = ctx.unwrap();
         ctx.stop_io().await;
+        ephemeral::stop_ephemeral_task(&ctx).await;
         drop(ctx);
 
         if let Some(cfg) = self.config.get_account(id).await {
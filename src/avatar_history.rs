@@ -0,0 +1,167 @@
+//! # Avatar history
+//!
+//! When the avatar of a group or a contact changes, the previous avatar is kept
+//! together with the time it was replaced, so old messages can be shown with the
+//! avatar that was valid when they were sent. The files are kept in the blob directory
+//! by housekeeping as long as they are referenced from the history.
+//! The history is removed together with its chat or contact.
+
+use anyhow::Result;
+use async_std::path::PathBuf;
+
+use crate::chat::{self, Chat, ChatId};
+use crate::constants::Chattype;
+use crate::context::Context;
+use crate::dc_tools::{dc_get_abs_path, time};
+
+/// A previous avatar of a chat or contact.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AvatarHistoryEntry {
+    /// Path to the avatar image.
+    pub path: PathBuf,
+
+    /// Timestamp at which the avatar was replaced or removed.
+    pub valid_until: i64,
+}
+
+/// Owner of an avatar.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum AvatarOwner {
+    Chat(ChatId),
+    Contact(u32),
+}
+
+/// Adds the avatar `old` to the history of `owner` if it is replaced by `new`.
+pub(crate) async fn save_replaced_avatar(
+    context: &Context,
+    owner: AvatarOwner,
+    old: Option<&str>,
+    new: Option<&str>,
+) -> Result<()> {
+    let old = match old {
+        Some(old) if !old.is_empty() && Some(old) != new => old,
+        _ => return Ok(()),
+    };
+    let (chat_id, contact_id) = match owner {
+        AvatarOwner::Chat(chat_id) => (chat_id, 0),
+        AvatarOwner::Contact(contact_id) => (ChatId::new(0), contact_id),
+    };
+    context
+        .sql
+        .execute(
+            "INSERT INTO avatar_history (chat_id, contact_id, avatar, valid_until)
+             VALUES (?, ?, ?, ?);",
+            paramsv![chat_id, contact_id, old, time()],
+        )
+        .await?;
+    Ok(())
+}
+
+impl ChatId {
+    /// Returns the previous avatars of the chat, oldest first.
+    ///
+    /// For one-to-one chats, these are the previous avatars of the contact.
+    pub async fn get_avatar_history(self, context: &Context) -> Result<Vec<AvatarHistoryEntry>> {
+        let chat = Chat::load_from_db(context, self).await?;
+        let (chat_id, contact_id) = if chat.typ == Chattype::Single {
+            let contacts = chat::get_chat_contacts(context, self).await?;
+            match contacts.first() {
+                Some(contact_id) => (ChatId::new(0), *contact_id),
+                None => return Ok(Vec::new()),
+            }
+        } else {
+            (self, 0)
+        };
+
+        let entries = context
+            .sql
+            .query_map(
+                "SELECT avatar, valid_until FROM avatar_history
+                 WHERE chat_id=? AND contact_id=?
+                 ORDER BY valid_until, id;",
+                paramsv![chat_id, contact_id],
+                |row| {
+                    let avatar: String = row.get(0)?;
+                    let valid_until: i64 = row.get(1)?;
+                    Ok((avatar, valid_until))
+                },
+                |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+            )
+            .await?
+            .into_iter()
+            .map(|(avatar, valid_until)| AvatarHistoryEntry {
+                path: dc_get_abs_path(context, avatar),
+                valid_until,
+            })
+            .collect();
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::indexing_slicing)]
+
+    use super::*;
+    use crate::chat::{create_group_chat, ProtectionStatus};
+    use crate::contact::Contact;
+    use crate::test_utils::TestContext;
+
+    #[async_std::test]
+    async fn test_avatar_history() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat_id = create_group_chat(&t, ProtectionStatus::Unprotected, "foo").await?;
+        assert!(chat_id.get_avatar_history(&t).await?.is_empty());
+
+        let owner = AvatarOwner::Chat(chat_id);
+        save_replaced_avatar(&t, owner, None, Some("$BLOBDIR/first.png")).await?;
+        save_replaced_avatar(
+            &t,
+            owner,
+            Some("$BLOBDIR/first.png"),
+            Some("$BLOBDIR/first.png"),
+        )
+        .await?;
+        assert!(chat_id.get_avatar_history(&t).await?.is_empty());
+
+        save_replaced_avatar(
+            &t,
+            owner,
+            Some("$BLOBDIR/first.png"),
+            Some("$BLOBDIR/second.png"),
+        )
+        .await?;
+        save_replaced_avatar(&t, owner, Some("$BLOBDIR/second.png"), None).await?;
+        let history = chat_id.get_avatar_history(&t).await?;
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].path, t.get_blobdir().join("first.png"));
+        assert_eq!(history[1].path, t.get_blobdir().join("second.png"));
+        assert!(history[0].valid_until <= history[1].valid_until);
+
+        // One-to-one chats show the history of the contact.
+        let chat = t.create_chat_with_contact("Bob", "bob@example.net").await;
+        let contact_id = chat::get_chat_contacts(&t, chat.id).await?[0];
+        save_replaced_avatar(
+            &t,
+            AvatarOwner::Contact(contact_id),
+            Some("$BLOBDIR/bob.png"),
+            None,
+        )
+        .await?;
+        let history = chat.id.get_avatar_history(&t).await?;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].path, t.get_blobdir().join("bob.png"));
+
+        // The history is removed together with the chat or contact.
+        chat_id.delete(&t).await?;
+        chat.id.delete(&t).await?;
+        Contact::delete(&t, contact_id).await?;
+        assert_eq!(
+            t.sql
+                .count("SELECT COUNT(*) FROM avatar_history", paramsv![])
+                .await?,
+            0
+        );
+        Ok(())
+    }
+}
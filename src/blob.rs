@@ -76,6 +76,13 @@ pub async fn create(
         // (the executor does not handle blocking operation in Drop correctly,
         // see <https://github.com/async-rs/async-std/issues/900>)
         let _ = file.flush().await;
+        BlobObject::sync_new_file(context, &file, &name)
+            .await
+            .map_err(|err| BlobError::WriteFailure {
+                blobdir: blobdir.to_path_buf(),
+                blobname: name.clone(),
+                cause: err,
+            })?;
 
         let blob = BlobObject {
             blobdir,
@@ -85,6 +92,26 @@ pub async fn create(
         Ok(blob)
     }
 
+    /// Syncs a newly written blob file to disk according to `Config::BlobSync`.
+    async fn sync_new_file(context: &Context, file: &fs::File, name: &str) -> anyhow::Result<()> {
+        let policy =
+            BlobSync::from_i32(context.get_config_int(Config::BlobSync).await?).unwrap_or_default();
+        match policy {
+            BlobSync::Never => {}
+            BlobSync::Always => file.sync_all().await?,
+            BlobSync::Batch => {
+                let mut queue = context.blob_sync_queue.lock().await;
+                queue.push(context.get_blobdir().join(name));
+                if queue.len() >= BLOB_SYNC_BATCH_SIZE {
+                    let paths = std::mem::take(&mut *queue);
+                    drop(queue);
+                    sync_files(context.get_blobdir().to_path_buf(), paths).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
     // Creates a new file, returning a tuple of the name and the handle.
     async fn create_new_file(
         dir: &Path,
@@ -167,6 +194,13 @@ pub async fn create_and_copy(
 
         // workaround, see create() for details
         let _ = dst_file.flush().await;
+        BlobObject::sync_new_file(context, &dst_file, &name)
+            .await
+            .map_err(|err| BlobError::WriteFailure {
+                blobdir: context.get_blobdir().to_path_buf(),
+                blobname: name.clone(),
+                cause: err,
+            })?;
 
         let blob = BlobObject {
             blobdir: context.get_blobdir(),
@@ -575,6 +609,57 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     }
 }
 
+/// When blob files are synced to disk, see `Config::BlobSync`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum BlobSync {
+    /// Leave writing blob files to disk to the operating system.
+    Never = 0,
+
+    /// Sync each blob file after writing it.
+    Always = 1,
+
+    /// Sync blob files in batches, eg. after fetching messages.
+    Batch = 2,
+}
+
+impl Default for BlobSync {
+    fn default() -> Self {
+        BlobSync::Never
+    }
+}
+
+/// Number of blob files written before they are synced with [`BlobSync::Batch`].
+const BLOB_SYNC_BATCH_SIZE: usize = 32;
+
+/// Syncs the blob files written since the last sync with [`BlobSync::Batch`].
+pub(crate) async fn sync_pending_blobs(context: &Context) -> anyhow::Result<()> {
+    let paths = std::mem::take(&mut *context.blob_sync_queue.lock().await);
+    if paths.is_empty() {
+        return Ok(());
+    }
+    sync_files(context.get_blobdir().to_path_buf(), paths).await
+}
+
+/// Syncs the files and the directory containing them on a blocking thread.
+async fn sync_files(blobdir: PathBuf, paths: Vec<PathBuf>) -> anyhow::Result<()> {
+    async_std::task::spawn_blocking(move || {
+        for path in paths {
+            // The file may be removed already, eg. if the message was deleted.
+            if let Ok(file) = std::fs::File::open(&path) {
+                file.sync_all()?;
+            }
+        }
+        // Make sure the new directory entries are written as well.
+        #[cfg(unix)]
+        std::fs::File::open(&blobdir)?.sync_all()?;
+        #[cfg(not(unix))]
+        drop(blobdir);
+        Ok(())
+    })
+    .await
+}
+
 /// Errors for the [BlobObject].
 #[derive(Debug, Error)]
 pub enum BlobError {
@@ -635,6 +720,33 @@ async fn test_create() {
         assert_eq!(blob.to_abs_path(), t.get_blobdir().join("foo"));
     }
 
+    #[async_std::test]
+    async fn test_blob_sync() -> anyhow::Result<()> {
+        let t = TestContext::new().await;
+        BlobObject::create(&t, "foo", b"hello").await?;
+        assert!(t.blob_sync_queue.lock().await.is_empty());
+
+        t.set_config(Config::BlobSync, Some("1")).await?;
+        BlobObject::create(&t, "foo", b"hello").await?;
+        assert!(t.blob_sync_queue.lock().await.is_empty());
+
+        t.set_config(Config::BlobSync, Some("2")).await?;
+        BlobObject::create(&t, "foo", b"hello").await?;
+        let blob = BlobObject::create(&t, "bar", b"world").await?;
+        assert_eq!(t.blob_sync_queue.lock().await.len(), 2);
+
+        // Removed files are skipped.
+        fs::remove_file(blob.to_abs_path()).await?;
+        sync_pending_blobs(&t).await?;
+        assert!(t.blob_sync_queue.lock().await.is_empty());
+
+        for i in 0..BLOB_SYNC_BATCH_SIZE {
+            BlobObject::create(&t, &format!("file{}", i), b"data").await?;
+        }
+        assert!(t.blob_sync_queue.lock().await.is_empty());
+        Ok(())
+    }
+
     #[async_std::test]
     async fn test_lowercase_ext() {
         let t = TestContext::new().await;
@@ -6,11 +6,14 @@
 
 use anyhow::{bail, ensure, format_err, Context as _, Result};
 use async_std::path::{Path, PathBuf};
+use chrono::Timelike;
 use deltachat_derive::{FromSql, ToSql};
 use itertools::Itertools;
+use num_traits::FromPrimitive;
 use serde::{Deserialize, Serialize};
 
 use crate::aheader::EncryptPreference;
+use crate::avatar_history::{self, AvatarOwner};
 use crate::blob::{BlobError, BlobObject};
 use crate::color::str_to_color;
 use crate::config::Config;
@@ -24,19 +27,27 @@
 use crate::context::Context;
 use crate::dc_tools::{
     dc_create_id, dc_create_outgoing_rfc724_mid, dc_create_smeared_timestamp,
-    dc_create_smeared_timestamps, dc_get_abs_path, dc_gm2local_offset, improve_single_line_input,
-    remove_subject_prefix, time, IsNoneOrEmpty,
+    dc_create_smeared_timestamps, dc_get_abs_path, dc_gm2local_offset, dc_truncate,
+    improve_single_line_input, remove_subject_prefix, time, IsNoneOrEmpty,
 };
+use crate::deletion;
 use crate::ephemeral::{delete_expired_messages, schedule_ephemeral_task, Timer as EphemeralTimer};
 use crate::events::EventType;
 use crate::html::new_html_mimepart;
+use crate::imap;
 use crate::job::{self, Action};
+use crate::log::LogExt;
+use crate::media;
 use crate::message::{self, Message, MessageState, MsgId};
+use crate::mimefactory::MimeFactory;
 use crate::mimeparser::SystemMessage;
 use crate::param::{Param, Params};
 use crate::peerstate::{Peerstate, PeerstateVerifiedStatus};
 use crate::stock_str;
 
+/// Time in seconds for which the previous group secret is accepted after re-keying.
+const GROUP_SECRET_GRACE_PERIOD: i64 = 2 * 24 * 60 * 60;
+
 /// An chat item, such as a message or a marker.
 #[derive(Debug, Copy, Clone)]
 pub enum ChatItem {
@@ -83,6 +94,26 @@ fn default() -> Self {
     }
 }
 
+/// Whether read receipts are requested and sent in a chat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum MdnPolicy {
+    /// Follow `Config::MdnsEnabled`, read receipts are never sent to mailing lists.
+    Default = 0,
+
+    /// Request and send read receipts regardless of `Config::MdnsEnabled`.
+    Enabled = 1,
+
+    /// Neither request nor send read receipts.
+    Disabled = 2,
+}
+
+impl Default for MdnPolicy {
+    fn default() -> Self {
+        MdnPolicy::Default
+    }
+}
+
 /// Chat ID, including reserved IDs.
 ///
 /// Some chat IDs are reserved to identify special chat types.  This
@@ -176,6 +207,7 @@ pub async fn create_for_contact(context: &Context, contact_id: u32) -> Result<Se
                 if chat.blocked != Blocked::Not {
                     chat.id.unblock(context).await?;
                 }
+                deletion::cancel_chat_deletion(context, chat.id).await?;
                 chat.id
             }
             None => {
@@ -402,6 +434,112 @@ pub async fn set_protection(self, context: &Context, protect: ProtectionStatus)
             .await
     }
 
+    /// Sets a prefix that is added to the subjects of outgoing messages, eg. `[project]`.
+    ///
+    /// The prefix is not added to subjects set explicitly using `Message::set_subject`
+    /// or subjects already containing the prefix.
+    /// Pass `None` or an empty string to remove the prefix.
+    pub async fn set_subject_prefix(self, context: &Context, prefix: Option<&str>) -> Result<()> {
+        ensure!(!self.is_special(), "set subject prefix: invalid chat-id.");
+
+        let mut chat = Chat::load_from_db(context, self).await?;
+        match prefix.map(str::trim) {
+            Some(prefix) if !prefix.is_empty() => {
+                chat.param.set(Param::SubjectPrefix, prefix);
+            }
+            _ => {
+                chat.param.remove(Param::SubjectPrefix);
+            }
+        }
+        chat.update_param(context).await?;
+        context.emit_event(EventType::ChatModified(self));
+        Ok(())
+    }
+
+    /// Sets the read receipt policy of the chat.
+    ///
+    /// This allows eg. sending read receipts only to close contacts
+    /// while `Config::MdnsEnabled` is disabled.
+    pub async fn set_mdn_policy(self, context: &Context, policy: MdnPolicy) -> Result<()> {
+        ensure!(!self.is_special(), "set mdn policy: invalid chat-id.");
+
+        let mut chat = Chat::load_from_db(context, self).await?;
+        match policy {
+            MdnPolicy::Default => chat.param.remove(Param::MdnPolicy),
+            _ => chat.param.set_int(Param::MdnPolicy, policy as i32),
+        };
+        chat.update_param(context).await?;
+        context.emit_event(EventType::ChatModified(self));
+        Ok(())
+    }
+
+    /// Sets the notification profile of the chat, `None` resets it to the default.
+    ///
+    /// The profile is passed to the UI with `IncomingMsg` events for this chat.
+    pub async fn set_notification_profile(
+        self,
+        context: &Context,
+        profile: Option<&NotificationProfile>,
+    ) -> Result<()> {
+        ensure!(!self.is_special(), "Invalid chat ID");
+        let mut chat = Chat::load_from_db(context, self).await?;
+        if let Some(profile) = profile {
+            chat.param
+                .set(Param::NotificationProfile, serde_json::to_string(profile)?);
+        } else {
+            chat.param.remove(Param::NotificationProfile);
+        }
+        chat.update_param(context).await?;
+        context.emit_event(EventType::ChatModified(self));
+        Ok(())
+    }
+
+    /// Returns the notification profile of the chat, `None` if the default is used.
+    pub async fn get_notification_profile(
+        self,
+        context: &Context,
+    ) -> Result<Option<NotificationProfile>> {
+        let chat = Chat::load_from_db(context, self).await?;
+        let profile = chat
+            .param
+            .get(Param::NotificationProfile)
+            .map(serde_json::from_str)
+            .transpose()?;
+        Ok(profile)
+    }
+
+    /// Sets whether the raw mime-headers of incoming messages in this chat are saved,
+    /// even if `Config::SaveMimeHeaders` is not set.
+    ///
+    /// This allows exporting chats with message sources without storing the sources of
+    /// all other chats.
+    pub async fn set_save_mime(self, context: &Context, save: bool) -> Result<()> {
+        ensure!(!self.is_special(), "Invalid chat ID");
+        let mut chat = Chat::load_from_db(context, self).await?;
+        if save {
+            chat.param.set_int(Param::SaveMime, 1);
+        } else {
+            chat.param.remove(Param::SaveMime);
+        }
+        chat.update_param(context).await?;
+        context.emit_event(EventType::ChatModified(self));
+        Ok(())
+    }
+
+    /// Returns whether the raw mime-headers of incoming messages in this chat are saved,
+    /// see [`ChatId::set_save_mime`].
+    pub async fn get_save_mime(self, context: &Context) -> Result<bool> {
+        let chat = Chat::load_from_db(context, self).await?;
+        Ok(chat.param.get_bool(Param::SaveMime).unwrap_or_default())
+    }
+
+    /// Returns the chat as JSON, see [ChatInfo].
+    pub async fn to_json(self, context: &Context) -> Result<String> {
+        let chat = Chat::load_from_db(context, self).await?;
+        let info = chat.get_info(context).await?;
+        Ok(serde_json::to_string(&info)?)
+    }
+
     /// Archives or unarchives a chat.
     pub async fn set_visibility(self, context: &Context, visibility: ChatVisibility) -> Result<()> {
         ensure!(
@@ -450,12 +588,32 @@ pub async fn unarchive(self, context: &Context) -> Result<()> {
     }
 
     /// Deletes a chat.
+    ///
+    /// If `Config::DeletionUndoWindow` is set, the chat is only hidden
+    /// and deleted irreversibly after the window,
+    /// see `Context::undo_last_deletion`.
     pub async fn delete(self, context: &Context) -> Result<()> {
         ensure!(
             !self.is_special(),
             "bad chat_id, can not be a special chat: {}",
             self
         );
+
+        let window = deletion::undo_window(context).await?;
+        if window > 0 {
+            deletion::defer_chat_deletion(context, self, window).await
+        } else {
+            self.delete_now(context).await
+        }
+    }
+
+    /// Deletes a chat irreversibly.
+    pub(crate) async fn delete_now(self, context: &Context) -> Result<()> {
+        ensure!(
+            !self.is_special(),
+            "bad chat_id, can not be a special chat: {}",
+            self
+        );
         /* Up to 2017-11-02 deleting a group also implied leaving it, see above why we have changed this. */
 
         let chat = Chat::load_from_db(context, self).await?;
@@ -614,8 +772,9 @@ async fn do_set_draft(self, context: &Context, msg: &mut Message) -> Result<()>
                  txt,
                  param,
                  hidden,
-                 mime_in_reply_to)
-         VALUES (?,?,?, ?,?,?,?,?,?);",
+                 mime_in_reply_to,
+                 view_once)
+         VALUES (?,?,?, ?,?,?,?,?,?,?);",
                 paramsv![
                     self,
                     DC_CONTACT_ID_SELF,
@@ -626,6 +785,7 @@ async fn do_set_draft(self, context: &Context, msg: &mut Message) -> Result<()>
                     msg.param.to_string(),
                     1,
                     msg.in_reply_to.as_deref().unwrap_or_default(),
+                    msg.view_once,
                 ],
             )
             .await?;
@@ -1039,6 +1199,176 @@ pub fn get_visibility(&self) -> ChatVisibility {
         self.visibility
     }
 
+    /// Returns the prefix added to subjects of outgoing messages,
+    /// see `ChatId::set_subject_prefix`.
+    pub fn get_subject_prefix(&self) -> Option<&str> {
+        self.param.get(Param::SubjectPrefix)
+    }
+
+    /// Returns the read receipt policy of the chat.
+    pub fn get_mdn_policy(&self) -> MdnPolicy {
+        self.param
+            .get_int(Param::MdnPolicy)
+            .and_then(MdnPolicy::from_i32)
+            .unwrap_or_default()
+    }
+
+    /// Returns true if read receipts are requested in and sent to this chat.
+    pub(crate) async fn mdns_enabled(&self, context: &Context) -> Result<bool> {
+        match self.get_mdn_policy() {
+            MdnPolicy::Enabled => Ok(true),
+            MdnPolicy::Disabled => Ok(false),
+            MdnPolicy::Default => {
+                if self.is_mailing_list() {
+                    Ok(false)
+                } else {
+                    context.get_config_bool(Config::MdnsEnabled).await
+                }
+            }
+        }
+    }
+
+    /// Returns the epoch and the group secret if the group was re-keyed,
+    /// see `Config::GroupRekeying`.
+    pub(crate) fn get_group_secret(&self) -> Option<(i32, &str)> {
+        let epoch = self.param.get_int(Param::GroupEpoch)?;
+        let secret = self.param.get(Param::GroupSecret)?;
+        Some((epoch, secret))
+    }
+
+    /// Returns true if `secret` is the previous group secret of `epoch`
+    /// and its grace period has not passed yet.
+    fn is_prev_group_secret(&self, epoch: i32, secret: Option<&str>) -> bool {
+        let prev = match self.param.get(Param::GroupPrevSecret) {
+            Some(prev) => prev,
+            None => return false,
+        };
+        let mut parts = prev.splitn(3, ':');
+        let prev_epoch = parts.next().and_then(|s| s.parse::<i32>().ok());
+        let valid_until = parts.next().and_then(|s| s.parse::<i64>().ok());
+        let prev_secret = parts.next();
+        prev_epoch == Some(epoch)
+            && valid_until.map_or(false, |until| time() < until)
+            && prev_secret.is_some()
+            && prev_secret == secret
+    }
+
+    /// Keeps `secret` of `epoch` accepted for [`GROUP_SECRET_GRACE_PERIOD`],
+    /// so messages sent before a re-keying was received are not rejected.
+    fn set_prev_group_secret(&mut self, epoch: i32, secret: &str) {
+        self.param.set(
+            Param::GroupPrevSecret,
+            format!(
+                "{}:{}:{}",
+                epoch,
+                time() + GROUP_SECRET_GRACE_PERIOD,
+                secret
+            ),
+        );
+    }
+
+    /// Sets the group secret of `epoch`, keeping the current one as previous secret.
+    fn set_group_secret(&mut self, epoch: i32, secret: &str) {
+        if let Some((prev_epoch, prev_secret)) = self.get_group_secret() {
+            let prev_secret = prev_secret.to_string();
+            self.set_prev_group_secret(prev_epoch, &prev_secret);
+        }
+        self.param.set_int(Param::GroupEpoch, epoch);
+        self.param.set(Param::GroupSecret, secret);
+    }
+
+    /// Generates a new group secret for the next epoch and sends it to the members
+    /// with a hidden rekey message.
+    ///
+    /// Called after a member was removed, so the secret is only sent to the remaining members.
+    pub(crate) async fn rotate_group_secret(&mut self, context: &Context) -> Result<()> {
+        let epoch = self.param.get_int(Param::GroupEpoch).unwrap_or_default() + 1;
+        self.set_group_secret(epoch, &format!("{}{}", dc_create_id(), dc_create_id()));
+        self.update_param(context).await?;
+        info!(context, "Group {} re-keyed to epoch {}", self.id, epoch);
+
+        if self.is_promoted() {
+            let mut msg = Message::new(Viewtype::Text);
+            msg.text = Some("Group key renewed.".to_string());
+            msg.hidden = true;
+            msg.param.set_cmd(SystemMessage::GroupRekeyed);
+            send_msg(context, self.id, &mut msg).await?;
+        }
+        Ok(())
+    }
+
+    /// Checks the group epoch and secret of a received message.
+    ///
+    /// A newer epoch is only taken over from a current member and only with the
+    /// member removal or rekey message starting it. If two members re-key to the same
+    /// epoch concurrently, the lexicographically smallest secret wins on all devices.
+    /// The previous secret is still accepted from current members
+    /// for [`GROUP_SECRET_GRACE_PERIOD`], so messages in flight during a re-keying
+    /// are not lost. Other messages with an older epoch, a wrong secret or a newer epoch
+    /// not meeting these conditions are rejected with an error.
+    pub(crate) async fn apply_group_secret(
+        &mut self,
+        context: &Context,
+        from_id: u32,
+        command: SystemMessage,
+        epoch: Option<&str>,
+        secret: Option<&str>,
+    ) -> Result<()> {
+        let epoch = epoch
+            .and_then(|epoch| epoch.parse::<i32>().ok())
+            .unwrap_or_default();
+        let known_epoch = self.param.get_int(Param::GroupEpoch).unwrap_or_default();
+        if epoch == known_epoch && (epoch == 0 || secret == self.param.get(Param::GroupSecret)) {
+            return Ok(());
+        }
+        let is_member = is_contact_in_chat(context, self.id, from_id).await;
+        if epoch <= known_epoch && is_member && self.is_prev_group_secret(epoch, secret) {
+            return Ok(());
+        }
+        if epoch < known_epoch {
+            bail!("Message uses an outdated group key");
+        }
+
+        let starts_epoch = matches!(
+            command,
+            SystemMessage::MemberRemovedFromGroup | SystemMessage::GroupRekeyed
+        );
+        if epoch == known_epoch {
+            // Two members re-keyed concurrently, the smallest secret wins.
+            let secret = match secret {
+                Some(secret) if starts_epoch && is_member => secret,
+                _ => bail!("Message uses an invalid group key"),
+            };
+            let current = self.param.get(Param::GroupSecret).unwrap_or_default();
+            if secret < current {
+                self.set_group_secret(epoch, secret);
+                info!(
+                    context,
+                    "Group {} re-keyed concurrently, using the smaller secret of epoch {}",
+                    self.id,
+                    epoch
+                );
+            } else {
+                // The sender switches to our secret once it receives our rekey message.
+                self.set_prev_group_secret(epoch, secret);
+            }
+            self.update_param(context).await?;
+            return Ok(());
+        }
+
+        if !starts_epoch {
+            bail!("Message uses a group key that was not announced");
+        }
+        if !is_member {
+            bail!("Group key changed by a non-member");
+        }
+        let secret = secret.context("Message has no group key")?;
+        self.set_group_secret(epoch, secret);
+        self.update_param(context).await?;
+        info!(context, "Group {} re-keyed to epoch {}", self.id, epoch);
+        Ok(())
+    }
+
     /// Returns true if chat is a contact request.
     ///
     /// Messages cannot be sent to such chat and read receipts are not
@@ -1253,10 +1583,10 @@ async fn prepare_msg_raw(
                         mime_in_reply_to,
                         mime_references,
                         mime_modified,
-                        mime_headers,
                         location_id,
                         ephemeral_timer,
-                        ephemeral_timestamp)
+                        ephemeral_timestamp,
+                        view_once)
                         VALUES (?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?);",
                 paramsv![
                     new_rfc724_mid,
@@ -1273,16 +1603,79 @@ async fn prepare_msg_raw(
                     msg.in_reply_to.as_deref().unwrap_or_default(),
                     new_references,
                     new_mime_headers.is_some(),
-                    new_mime_headers.unwrap_or_default(),
                     location_id as i32,
                     ephemeral_timer,
-                    ephemeral_timestamp
+                    ephemeral_timestamp,
+                    msg.view_once
                 ],
             )
             .await?;
+        let msg_id = MsgId::new(u32::try_from(msg_id)?);
+        if let Some(mime_headers) = new_mime_headers {
+            message::set_mime_headers(context, msg_id, mime_headers.as_bytes()).await?;
+        }
         schedule_ephemeral_task(context).await;
 
-        Ok(MsgId::new(u32::try_from(msg_id)?))
+        Ok(msg_id)
+    }
+}
+
+/// Notification settings of a chat.
+///
+/// The core only stores the profile and passes it to the UI with `IncomingMsg` events,
+/// so all UIs of the account notify the same way. The values are defined by the UIs.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotificationProfile {
+    /// Identifier of the notification sound.
+    pub sound: Option<String>,
+
+    /// Key of the vibration pattern.
+    pub vibration: Option<String>,
+
+    /// Priority of the notifications, 0 is the default priority.
+    #[serde(default)]
+    pub priority: i32,
+}
+
+/// Returns true if `minute`, minutes after midnight, is within the quiet hours
+/// from `start` to `end`, which may span midnight.
+fn is_quiet_minute(minute: i32, start: i32, end: i32) -> bool {
+    if start <= end {
+        start <= minute && minute < end
+    } else {
+        minute >= start || minute < end
+    }
+}
+
+/// Returns true if the current local time is within the quiet hours,
+/// see `Config::QuietHoursStart`.
+pub(crate) async fn is_quiet_hours(context: &Context) -> Result<bool> {
+    let start = context.get_config_int(Config::QuietHoursStart).await?;
+    let end = context.get_config_int(Config::QuietHoursEnd).await?;
+    let now = chrono::Local::now();
+    let minute = (now.hour() * 60 + now.minute()) as i32;
+    Ok(is_quiet_minute(minute, start, end))
+}
+
+/// Creates the `IncomingMsg` event for a message
+/// with the notification hints of the chat.
+pub(crate) async fn incoming_msg_event(
+    context: &Context,
+    chat_id: ChatId,
+    msg_id: MsgId,
+) -> EventType {
+    EventType::IncomingMsg {
+        chat_id,
+        msg_id,
+        notification_profile: chat_id
+            .get_notification_profile(context)
+            .await
+            .ok_or_log(context)
+            .flatten(),
+        muted: is_quiet_hours(context)
+            .await
+            .ok_or_log(context)
+            .unwrap_or_default(),
     }
 }
 
@@ -1599,24 +1992,100 @@ pub(crate) fn msgtype_has_file(msgtype: Viewtype) -> bool {
     }
 }
 
+/// Returns the path of the attachment if it is left at its original location
+/// until the message is sent, see [`Config::PassthroughAttachments`].
+async fn get_passthrough_path(context: &Context, msg: &Message) -> Result<Option<PathBuf>> {
+    if msg.viewtype == Viewtype::Image
+        || msg.is_increation()
+        || !context
+            .get_config_bool(Config::PassthroughAttachments)
+            .await?
+    {
+        return Ok(None);
+    }
+    let path = match msg.param.get_path(Param::File, context)? {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    if !path.is_absolute() || path.starts_with(context.get_blobdir()) {
+        return Ok(None);
+    }
+    ensure!(
+        path.is_file().await,
+        "Attachment {} not found",
+        path.display()
+    );
+    Ok(Some(path))
+}
+
+/// Copies an attachment left at its original location by [`get_passthrough_path`]
+/// to the blob directory when the message is rendered for sending,
+/// so that sent messages only reference files inside the blob directory.
+pub(crate) async fn copy_passthrough_attachment(
+    context: &Context,
+    msg: &mut Message,
+) -> Result<()> {
+    let path = match msg.param.get_path(Param::File, context)? {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    if !path.is_absolute() || path.starts_with(context.get_blobdir()) {
+        return Ok(());
+    }
+    let blob = BlobObject::create_and_copy(context, &path).await?;
+    msg.param.set(Param::File, blob.as_name());
+    msg.update_param(context).await;
+    Ok(())
+}
+
+/// Number of characters kept as preview when a long text is sent as attachment.
+const TEXT_OVERFLOW_PREVIEW_LEN: usize = 500;
+
+/// Converts the text of an outgoing message longer than [`Config::MaxTextLength`]
+/// into a text attachment, keeping the beginning of the text as preview.
+async fn overflow_text_to_file(context: &Context, msg: &mut Message) -> Result<()> {
+    let max_len = context.get_config_int(Config::MaxTextLength).await?;
+    let preview = match msg.text.as_ref() {
+        Some(text) if max_len > 0 && text.chars().count() > max_len as usize => {
+            let blob = BlobObject::create(context, "message.txt", text.as_bytes()).await?;
+            msg.param.set(Param::File, blob.as_name());
+            dc_truncate(text, TEXT_OVERFLOW_PREVIEW_LEN.min(max_len as usize)).to_string()
+        }
+        _ => return Ok(()),
+    };
+    msg.viewtype = Viewtype::File;
+    msg.param.set(Param::MimeType, "text/plain");
+    msg.text = Some(preview);
+    Ok(())
+}
+
 async fn prepare_msg_blob(context: &Context, msg: &mut Message) -> Result<()> {
+    if msg.viewtype == Viewtype::Text {
+        overflow_text_to_file(context, msg).await?;
+    }
     if msg.viewtype == Viewtype::Text || msg.viewtype == Viewtype::VideochatInvitation {
         // the caller should check if the message text is empty
     } else if msgtype_has_file(msg.viewtype) {
-        let blob = msg
-            .param
-            .get_blob(Param::File, context, !msg.is_increation())
-            .await?
-            .ok_or_else(|| {
-                format_err!("Attachment missing for message of type #{}", msg.viewtype)
-            })?;
+        let path = if let Some(path) = get_passthrough_path(context, msg).await? {
+            msg.param.set(Param::File, path.to_string_lossy());
+            path
+        } else {
+            let blob = msg
+                .param
+                .get_blob(Param::File, context, !msg.is_increation())
+                .await?
+                .ok_or_else(|| {
+                    format_err!("Attachment missing for message of type #{}", msg.viewtype)
+                })?;
 
-        if msg.viewtype == Viewtype::Image {
-            if let Err(e) = blob.recode_to_image_size(context).await {
-                warn!(context, "Cannot recode image, using original data: {:?}", e);
+            if msg.viewtype == Viewtype::Image {
+                if let Err(e) = blob.recode_to_image_size(context).await {
+                    warn!(context, "Cannot recode image, using original data: {:?}", e);
+                }
             }
-        }
-        msg.param.set(Param::File, blob.as_name());
+            msg.param.set(Param::File, blob.as_name());
+            blob.to_abs_path()
+        };
 
         if msg.viewtype == Viewtype::File || msg.viewtype == Viewtype::Image {
             // Correct the type, take care not to correct already very special
@@ -1625,23 +2094,37 @@ async fn prepare_msg_blob(context: &Context, msg: &mut Message) -> Result<()> {
             // Typical conversions:
             // - from FILE to AUDIO/VIDEO/IMAGE
             // - from FILE/IMAGE to GIF */
-            if let Some((better_type, better_mime)) =
-                message::guess_msgtype_from_suffix(&blob.to_abs_path())
-            {
+            if let Some((better_type, better_mime)) = message::guess_msgtype_from_suffix(&path) {
                 msg.viewtype = better_type;
                 if !msg.param.exists(Param::MimeType) {
                     msg.param.set(Param::MimeType, better_mime);
                 }
             }
         } else if !msg.param.exists(Param::MimeType) {
-            if let Some((_, mime)) = message::guess_msgtype_from_suffix(&blob.to_abs_path()) {
+            if let Some((_, mime)) = message::guess_msgtype_from_suffix(&path) {
                 msg.param.set(Param::MimeType, mime);
             }
         }
+        if matches!(
+            msg.viewtype,
+            Viewtype::Audio | Viewtype::Voice | Viewtype::Video
+        ) && !msg.param.exists(Param::Duration)
+        {
+            let path = path.clone();
+            let duration_ms = async_std::task::spawn_blocking(move || {
+                std::fs::File::open(path)
+                    .ok()
+                    .and_then(|mut file| media::get_duration_ms(&mut file))
+            })
+            .await;
+            if let Some(duration_ms) = duration_ms {
+                msg.param.set_int(Param::Duration, duration_ms as i32);
+            }
+        }
         info!(
             context,
             "Attaching \"{}\" for message type #{}.",
-            blob.to_abs_path().display(),
+            path.display(),
             msg.viewtype
         );
     } else {
@@ -1678,6 +2161,48 @@ async fn prepare_msg_common(
     Ok(msg.id)
 }
 
+/// Estimates the size in bytes of `msg` when sent to `chat_id`.
+///
+/// The message is rendered as it would be sent, including encryption and the
+/// base64-encoding of attachments, but it is neither stored nor sent. This allows the UI
+/// to warn before sending attachments exceeding the message size limit of the provider.
+///
+/// As with [`prepare_msg`], the attachment is copied to the blob directory
+/// and images are recoded; files of messages which are not sent are removed by housekeeping.
+pub async fn estimate_msg_size(context: &Context, chat_id: ChatId, msg: &Message) -> Result<usize> {
+    ensure!(
+        !chat_id.is_special(),
+        "Cannot estimate message size for special chat"
+    );
+    let chat = Chat::load_from_db(context, chat_id).await?;
+    ensure!(chat.can_send(context).await, "cannot send to {}", chat_id);
+
+    let mut msg = msg.clone();
+    msg.id = MsgId::new_unset();
+    msg.chat_id = chat_id;
+    msg.state = MessageState::OutPending;
+    msg.timestamp_sort = time();
+    prepare_msg_blob(context, &mut msg).await?;
+    if msg.rfc724_mid.is_empty() {
+        let from = context
+            .get_config(Config::ConfiguredAddr)
+            .await?
+            .unwrap_or_default();
+        let grpid = match chat.typ {
+            Chattype::Group => Some(chat.grpid.as_str()),
+            _ => None,
+        };
+        msg.rfc724_mid = dc_create_outgoing_rfc724_mid(grpid, &from);
+    }
+
+    let attach_selfavatar = shall_attach_selfavatar(context, chat_id).await?;
+    let rendered = MimeFactory::from_msg(context, &msg, attach_selfavatar)
+        .await?
+        .render(context)
+        .await?;
+    Ok(rendered.message.len())
+}
+
 /// Returns whether a contact is in a chat or not.
 pub async fn is_contact_in_chat(context: &Context, chat_id: ChatId, contact_id: u32) -> bool {
     // this function works for group and for normal chats, however, it is more useful
@@ -1962,6 +2487,33 @@ pub async fn get_chat_msgs(
     Ok(items)
 }
 
+/// Returns the first message in a chat sent on or after the timestamp `ts`.
+///
+/// This allows UIs to implement "jump to date" in long chats without loading all
+/// messages. Hidden messages are skipped, the ordering is the same as in [`get_chat_msgs`].
+/// Returns `None` if there is no such message.
+pub async fn get_first_msg_on_or_after(
+    context: &Context,
+    chat_id: ChatId,
+    ts: i64,
+) -> Result<Option<MsgId>> {
+    // uses the index `(chat_id, timestamp)`
+    let msg_id = context
+        .sql
+        .query_get_value(
+            "SELECT id
+               FROM msgs
+              WHERE chat_id=?
+                AND timestamp>=?
+                AND hidden=0
+              ORDER BY timestamp, id
+              LIMIT 1;",
+            paramsv![chat_id, ts],
+        )
+        .await?;
+    Ok(msg_id)
+}
+
 pub(crate) async fn marknoticed_chat_if_older_than(
     context: &Context,
     chat_id: ChatId,
@@ -2171,6 +2723,128 @@ pub async fn create_group_chat(
     Ok(chat_id)
 }
 
+/// Initial setup of a group created with [`create_group_ex`].
+#[derive(Debug, Clone, Default)]
+pub struct GroupSpec {
+    /// Name of the group.
+    pub name: String,
+
+    /// Contact IDs of the initial members, not including ourself.
+    pub members: Vec<u32>,
+
+    /// Path to the group avatar, `None` to create the group without avatar.
+    pub avatar: Option<String>,
+
+    /// Ephemeral timer of the group.
+    pub ephemeral_timer: EphemeralTimer,
+
+    /// Whether the group is protected, all members must be verified then.
+    pub protected: ProtectionStatus,
+}
+
+/// Creates a group with initial members and settings.
+///
+/// Other than calling [`create_group_chat`] followed by [`add_contact_to_chat`],
+/// [`set_chat_profile_image`] etc., the group is set up in a single transaction
+/// and announced to the members with a single message, so other devices never see
+/// a half-finished group. If there are no members, the group is not promoted and
+/// a draft is set instead, as for [`create_group_chat`].
+pub async fn create_group_ex(context: &Context, spec: GroupSpec) -> Result<ChatId> {
+    let chat_name = improve_single_line_input(&spec.name);
+    ensure!(!chat_name.is_empty(), "Invalid chat name");
+
+    let self_addr = context
+        .get_config(Config::ConfiguredAddr)
+        .await?
+        .unwrap_or_default();
+    let mut members = Vec::new();
+    for contact_id in spec.members.into_iter().unique() {
+        if contact_id == DC_CONTACT_ID_SELF {
+            continue;
+        }
+        ensure!(
+            Contact::real_exists_by_id(context, contact_id).await,
+            "invalid contact_id {} for adding to group",
+            contact_id
+        );
+        let contact = Contact::get_by_id(context, contact_id).await?;
+        if addr_cmp(contact.get_addr(), &self_addr) {
+            continue;
+        }
+        if spec.protected == ProtectionStatus::Protected
+            && contact.is_verified(context).await != VerifiedStatus::BidirectVerified
+        {
+            bail!("{} is not verified.", contact.get_display_name());
+        }
+        members.push(contact_id);
+    }
+
+    let mut param = Params::new();
+    param.set_int(Param::Unpromoted, 1);
+    if let Some(avatar) = spec.avatar.filter(|avatar| !avatar.is_empty()) {
+        let mut image_blob = match BlobObject::from_path(context, Path::new(&avatar)) {
+            Ok(blob) => Ok(blob),
+            Err(BlobError::WrongBlobdir { .. }) => {
+                BlobObject::create_and_copy(context, Path::new(&avatar)).await
+            }
+            Err(err) => Err(err),
+        }?;
+        image_blob.recode_to_avatar_size(context).await?;
+        param.set(Param::ProfileImage, image_blob.as_name());
+    }
+
+    let grpid = dc_create_id();
+    let created_timestamp = dc_create_smeared_timestamp(context).await;
+    let ephemeral_timer = spec.ephemeral_timer;
+    let protected = spec.protected;
+    let has_members = !members.is_empty();
+    let chat_id = {
+        let chat_name = chat_name.clone();
+        context
+            .sql
+            .transaction(move |transaction| {
+                transaction.execute(
+                    "INSERT INTO chats
+                     (type, name, grpid, param, created_timestamp, ephemeral_timer, protected)
+                     VALUES(?, ?, ?, ?, ?, ?, ?);",
+                    paramsv![
+                        Chattype::Group,
+                        chat_name,
+                        grpid,
+                        param.to_string(),
+                        created_timestamp,
+                        ephemeral_timer,
+                        protected
+                    ],
+                )?;
+                let chat_id = ChatId::new(u32::try_from(transaction.last_insert_rowid())?);
+                for contact_id in std::iter::once(DC_CONTACT_ID_SELF).chain(members.into_iter()) {
+                    transaction.execute(
+                        "INSERT INTO chats_contacts (chat_id, contact_id) VALUES(?, ?)",
+                        paramsv![chat_id, contact_id],
+                    )?;
+                }
+                Ok(chat_id)
+            })
+            .await?
+    };
+
+    let text = stock_str::new_group_draft(context, &chat_name).await;
+    let mut msg = Message::new(Viewtype::Text);
+    msg.set_text(Some(text));
+    if has_members {
+        send_msg(context, chat_id, &mut msg).await?;
+    } else {
+        chat_id.set_draft_raw(context, &mut msg).await?;
+    }
+
+    context.emit_event(EventType::MsgsChanged {
+        msg_id: MsgId::new(0),
+        chat_id: ChatId::new(0),
+    });
+    Ok(chat_id)
+}
+
 /// add a contact to the chats_contact table
 pub(crate) async fn add_to_chat_contacts_table(
     context: &Context,
@@ -2520,6 +3194,17 @@ pub async fn remove_contact_from_chat(
                 // check/encryption logic.
                 success = remove_from_chat_contacts_table(context, chat_id, contact_id).await;
                 context.emit_event(EventType::ChatModified(chat_id));
+
+                // The member removal itself is still sent with the old group secret,
+                // the new one is only sent to the remaining members.
+                if success
+                    && chat.is_protected()
+                    && contact_id != DC_CONTACT_ID_SELF
+                    && context.get_config_bool(Config::GroupRekeying).await?
+                {
+                    let mut chat = chat;
+                    chat.rotate_group_secret(context).await?;
+                }
             }
         }
     }
@@ -2531,6 +3216,53 @@ pub async fn remove_contact_from_chat(
     Ok(())
 }
 
+/// Downloads the history of a chat from the server again,
+/// eg. after the messages were deleted from the device because of `Config::DeleteDeviceAfter`.
+///
+/// The DeltaChat and Inbox folders are searched in the background for messages of the chat
+/// that are not stored on the device anymore, at most `limit` messages are downloaded
+/// from each folder. `EventType::MsgsChanged` is emitted when done.
+///
+/// Only messages removed from the device by `Config::DeleteDeviceAfter` are downloaded again,
+/// messages deleted by the user or by ephemeral timers stay deleted.
+pub async fn redownload_history(context: &Context, chat_id: ChatId, limit: u32) -> Result<()> {
+    ensure!(!chat_id.is_special(), "Cannot redownload special chat");
+    ensure!(limit > 0, "Limit must be positive");
+    let chat = Chat::load_from_db(context, chat_id).await?;
+    let search_command = match chat.typ {
+        Chattype::Group => format!(
+            "OR HEADER Chat-Group-ID {} HEADER References {}",
+            imap::quote_search_string(&chat.grpid)?,
+            imap::quote_search_string(&format!("Gr.{}.", chat.grpid))?
+        ),
+        Chattype::Mailinglist => {
+            format!("HEADER List-Id {}", imap::quote_search_string(&chat.grpid)?)
+        }
+        Chattype::Single => {
+            let contact_id = get_chat_contacts(context, chat_id)
+                .await?
+                .into_iter()
+                .next()
+                .context("1:1 chat has no contact")?;
+            let contact = Contact::get_by_id(context, contact_id).await?;
+            let addr = imap::quote_search_string(contact.get_addr())?;
+            // Group messages with the contact belong to the group chats.
+            format!("OR FROM {} TO {} NOT HEADER Chat-Group-ID \"\"", addr, addr)
+        }
+        Chattype::Undefined => bail!("Cannot redownload chat of undefined type"),
+    };
+
+    let mut param = Params::new();
+    param.set(Param::Arg, search_command);
+    param.set_int(Param::Arg2, limit as i32);
+    job::add(
+        context,
+        job::Job::new(Action::SearchServer, chat_id.to_u32(), param, 0),
+    )
+    .await;
+    Ok(())
+}
+
 async fn set_group_explicitly_left(context: &Context, grpid: &str) -> Result<()> {
     if !is_group_explicitly_left(context, grpid).await? {
         context
@@ -2649,6 +3381,7 @@ pub async fn set_chat_profile_image(
         );
         bail!("Failed to set profile image");
     }
+    let old_image = chat.param.get(Param::ProfileImage).map(|s| s.to_string());
     let mut msg = Message::new(Viewtype::Text);
     msg.param
         .set_int(Param::Cmd, SystemMessage::GroupImageChanged as i32);
@@ -2671,6 +3404,13 @@ pub async fn set_chat_profile_image(
         msg.param.set(Param::Arg, image_blob.as_name());
         msg.text = Some(stock_str::msg_grp_img_changed(context, DC_CONTACT_ID_SELF as u32).await);
     }
+    avatar_history::save_replaced_avatar(
+        context,
+        AvatarOwner::Chat(chat_id),
+        old_image.as_deref(),
+        chat.param.get(Param::ProfileImage),
+    )
+    .await?;
     chat.update_param(context).await?;
     if chat.is_promoted() && !chat.is_mailing_list() {
         msg.id = send_msg(context, chat_id, &mut msg).await?;
@@ -2690,7 +3430,6 @@ pub async fn forward_msgs(context: &Context, msg_ids: &[MsgId], chat_id: ChatId)
     ensure!(!msg_ids.is_empty(), "empty msgs_ids: nothing to forward");
     ensure!(!chat_id.is_special(), "can not forward to special chat");
 
-    let mut created_chats: Vec<ChatId> = Vec::new();
     let mut created_msgs: Vec<MsgId> = Vec::new();
     let mut curr_timestamp: i64;
 
@@ -2764,16 +3503,17 @@ pub async fn forward_msgs(context: &Context, msg_ids: &[MsgId], chat_id: ChatId)
                     job::add(context, send_job).await;
                 }
             }
-            created_chats.push(chat_id);
             created_msgs.push(new_msg_id);
         }
     }
-    for (chat_id, msg_id) in created_chats.iter().zip(created_msgs.iter()) {
-        context.emit_event(EventType::MsgsChanged {
-            chat_id: *chat_id,
-            msg_id: *msg_id,
-        });
-    }
+
+    // Emit a single event, several forwarded messages are reloaded together.
+    let msg_id = match created_msgs.as_slice() {
+        [] => return Ok(()),
+        [msg_id] => *msg_id,
+        _ => MsgId::new(0),
+    };
+    context.emit_event(EventType::MsgsChanged { chat_id, msg_id });
     Ok(())
 }
 
@@ -2877,6 +3617,7 @@ pub async fn add_device_msg_with_importance(
             }
         }
 
+        let ephemeral_timer = chat_id.get_ephemeral_timer(context).await?;
         let row_id = context
             .sql
             .insert(
@@ -2890,8 +3631,9 @@ pub async fn add_device_msg_with_importance(
             type,state,
             txt,
             param,
-            rfc724_mid)
-            VALUES (?,?,?,?,?,?,?,?,?,?,?);",
+            rfc724_mid,
+            ephemeral_timer)
+            VALUES (?,?,?,?,?,?,?,?,?,?,?,?);",
                 paramsv![
                     chat_id,
                     DC_CONTACT_ID_DEVICE,
@@ -2904,6 +3646,7 @@ pub async fn add_device_msg_with_importance(
                     msg.text.as_ref().cloned().unwrap_or_default(),
                     msg.param.to_string(),
                     rfc724_mid,
+                    ephemeral_timer,
                 ],
             )
             .await?;
@@ -2923,7 +3666,7 @@ pub async fn add_device_msg_with_importance(
 
     if !msg_id.is_unset() {
         if important {
-            context.emit_event(EventType::IncomingMsg { chat_id, msg_id });
+            context.emit_event(incoming_msg_event(context, chat_id, msg_id).await);
         } else {
             context.emit_event(EventType::MsgsChanged { chat_id, msg_id });
         }
@@ -4214,4 +4957,402 @@ async fn test_can_send_group() -> Result<()> {
         );
         Ok(())
     }
+
+    #[async_std::test]
+    async fn test_create_group_ex() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        let bob = TestContext::new_bob().await;
+        let bob_id = Contact::create(&alice, "Bob", "bob@example.net").await?;
+        let timer = EphemeralTimer::Enabled { duration: 3600 };
+        let chat_id = create_group_ex(
+            &alice,
+            GroupSpec {
+                name: "foo".to_string(),
+                members: vec![bob_id, bob_id, DC_CONTACT_ID_SELF],
+                ephemeral_timer: timer,
+                ..Default::default()
+            },
+        )
+        .await?;
+        let chat = Chat::load_from_db(&alice, chat_id).await?;
+        assert!(chat.is_promoted());
+        assert_eq!(chat.get_name(), "foo");
+        assert_eq!(chat_id.get_ephemeral_timer(&alice).await?, timer);
+        assert_eq!(get_chat_contacts(&alice, chat_id).await?.len(), 2);
+        assert_eq!(get_chat_msgs(&alice, chat_id, 0, None).await?.len(), 1);
+
+        // Bob sees the complete group with the first message.
+        bob.recv_msg(&alice.pop_sent_msg().await).await;
+        let bob_chat = Chat::load_from_db(&bob, bob.get_last_msg().await.chat_id).await?;
+        assert_eq!(bob_chat.get_name(), "foo");
+        assert_eq!(bob_chat.id.get_ephemeral_timer(&bob).await?, timer);
+        assert_eq!(get_chat_contacts(&bob, bob_chat.id).await?.len(), 2);
+
+        // Without members, the group is not promoted.
+        let chat_id = create_group_ex(
+            &alice,
+            GroupSpec {
+                name: "bar".to_string(),
+                ..Default::default()
+            },
+        )
+        .await?;
+        assert!(!Chat::load_from_db(&alice, chat_id).await?.is_promoted());
+        assert!(chat_id.get_draft(&alice).await?.is_some());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_mdn_policy() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat_id = create_group_chat(&t, ProtectionStatus::Unprotected, "foo").await?;
+        let chat = Chat::load_from_db(&t, chat_id).await?;
+        assert_eq!(chat.get_mdn_policy(), MdnPolicy::Default);
+        assert!(chat.mdns_enabled(&t).await?);
+
+        t.set_config(Config::MdnsEnabled, Some("0")).await?;
+        assert!(!chat.mdns_enabled(&t).await?);
+        chat_id.set_mdn_policy(&t, MdnPolicy::Enabled).await?;
+        let chat = Chat::load_from_db(&t, chat_id).await?;
+        assert_eq!(chat.get_mdn_policy(), MdnPolicy::Enabled);
+        assert!(chat.mdns_enabled(&t).await?);
+
+        t.set_config(Config::MdnsEnabled, Some("1")).await?;
+        chat_id.set_mdn_policy(&t, MdnPolicy::Disabled).await?;
+        let chat = Chat::load_from_db(&t, chat_id).await?;
+        assert!(!chat.mdns_enabled(&t).await?);
+
+        chat_id.set_mdn_policy(&t, MdnPolicy::Default).await?;
+        let chat = Chat::load_from_db(&t, chat_id).await?;
+        assert_eq!(chat.get_mdn_policy(), MdnPolicy::Default);
+        assert!(chat.mdns_enabled(&t).await?);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_group_secret() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat_id = create_group_chat(&t, ProtectionStatus::Unprotected, "foo").await?;
+        let mut chat = Chat::load_from_db(&t, chat_id).await?;
+        assert_eq!(chat.get_group_secret(), None);
+
+        let bob = Contact::create(&t, "bob", "bob@example.net").await?;
+        let claire = Contact::create(&t, "claire", "claire@example.org").await?;
+        let dave = Contact::create(&t, "dave", "dave@example.org").await?;
+        add_contact_to_chat(&t, chat_id, bob).await;
+        add_contact_to_chat(&t, chat_id, claire).await;
+        add_contact_to_chat(&t, chat_id, dave).await;
+        let text = SystemMessage::Unknown;
+        let rekey = SystemMessage::GroupRekeyed;
+
+        // Groups that were never re-keyed accept all messages.
+        chat.apply_group_secret(&t, bob, text, None, None).await?;
+
+        chat.rotate_group_secret(&t).await?;
+        let mut chat = Chat::load_from_db(&t, chat_id).await?;
+        let (epoch, secret) = chat.get_group_secret().unwrap();
+        assert_eq!(epoch, 1);
+        let secret = secret.to_string();
+
+        chat.apply_group_secret(&t, bob, text, Some("1"), Some(&secret))
+            .await?;
+        assert!(chat
+            .apply_group_secret(&t, bob, text, None, None)
+            .await
+            .is_err());
+        assert!(chat
+            .apply_group_secret(&t, bob, text, Some("1"), Some("wrong"))
+            .await
+            .is_err());
+
+        // A newer secret is not taken over from ordinary messages.
+        assert!(chat
+            .apply_group_secret(&t, bob, text, Some("2"), Some("newsecret"))
+            .await
+            .is_err());
+
+        // A removed member cannot re-key the group to get back in.
+        remove_contact_from_chat(&t, chat_id, claire).await?;
+        let mut chat = Chat::load_from_db(&t, chat_id).await?;
+        assert!(chat
+            .apply_group_secret(&t, claire, rekey, Some("2"), Some("evil"))
+            .await
+            .is_err());
+        assert_eq!(chat.get_group_secret(), Some((1, secret.as_str())));
+
+        // A newer secret from a rekey message of a member is taken over.
+        chat.apply_group_secret(&t, bob, rekey, Some("2"), Some("newsecret"))
+            .await?;
+        let mut chat = Chat::load_from_db(&t, chat_id).await?;
+        assert_eq!(chat.get_group_secret(), Some((2, "newsecret")));
+
+        // Messages in flight with the previous secret are accepted from members
+        // during the grace period.
+        chat.apply_group_secret(&t, bob, text, Some("1"), Some(&secret))
+            .await?;
+        assert!(chat
+            .apply_group_secret(&t, claire, text, Some("1"), Some(&secret))
+            .await
+            .is_err());
+        chat.param.set(
+            Param::GroupPrevSecret,
+            format!("1:{}:{}", time() - 1, secret),
+        );
+        assert!(chat
+            .apply_group_secret(&t, bob, text, Some("1"), Some(&secret))
+            .await
+            .is_err());
+
+        // Concurrent re-keying to the same epoch: the smallest secret wins,
+        // the other one is accepted as previous secret.
+        chat.apply_group_secret(&t, bob, rekey, Some("3"), Some("bbb"))
+            .await?;
+        chat.apply_group_secret(&t, dave, rekey, Some("3"), Some("aaa"))
+            .await?;
+        assert_eq!(chat.get_group_secret(), Some((3, "aaa")));
+        chat.apply_group_secret(&t, bob, text, Some("3"), Some("bbb"))
+            .await?;
+        chat.apply_group_secret(&t, dave, rekey, Some("3"), Some("ccc"))
+            .await?;
+        let chat = Chat::load_from_db(&t, chat_id).await?;
+        assert_eq!(chat.get_group_secret(), Some((3, "aaa")));
+        assert!(chat.is_prev_group_secret(3, Some("ccc")));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_redownload_history() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat_id = create_group_chat(&t, ProtectionStatus::Unprotected, "foo").await?;
+        let grpid = Chat::load_from_db(&t, chat_id).await?.grpid;
+        assert!(redownload_history(&t, chat_id, 0).await.is_err());
+        redownload_history(&t, chat_id, 50).await?;
+
+        let param: Params = t
+            .sql
+            .query_get_value::<String>(
+                "SELECT param FROM jobs WHERE action=? AND foreign_id=?",
+                paramsv![Action::SearchServer, chat_id],
+            )
+            .await?
+            .unwrap()
+            .parse()?;
+        assert_eq!(
+            param.get(Param::Arg),
+            Some(
+                format!(
+                    "OR HEADER Chat-Group-ID \"{}\" HEADER References \"Gr.{}.\"",
+                    grpid, grpid
+                )
+                .as_str()
+            )
+        );
+        assert_eq!(param.get_int(Param::Arg2), Some(50));
+
+        let chat = t.create_chat_with_contact("Bob", "bob@example.net").await;
+        redownload_history(&t, chat.id, 10).await?;
+        let param: Params = t
+            .sql
+            .query_get_value::<String>(
+                "SELECT param FROM jobs WHERE action=? AND foreign_id=?",
+                paramsv![Action::SearchServer, chat.id],
+            )
+            .await?
+            .unwrap()
+            .parse()?;
+        assert_eq!(
+            param.get(Param::Arg),
+            Some(
+                "OR FROM \"bob@example.net\" TO \"bob@example.net\" NOT HEADER Chat-Group-ID \"\""
+            )
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_get_first_msg_on_or_after() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat = t.get_self_chat().await;
+
+        let msg1 = t.send_text(chat.id, "first").await.sender_msg_id;
+        let msg2 = t.send_text(chat.id, "second").await.sender_msg_id;
+        let ts1 = Message::load_from_db(&t, msg1).await?.get_timestamp();
+        let ts2 = Message::load_from_db(&t, msg2).await?.get_timestamp();
+        assert!(ts2 > ts1);
+
+        assert_eq!(get_first_msg_on_or_after(&t, chat.id, 0).await?, Some(msg1));
+        assert_eq!(
+            get_first_msg_on_or_after(&t, chat.id, ts1).await?,
+            Some(msg1)
+        );
+        assert_eq!(
+            get_first_msg_on_or_after(&t, chat.id, ts1 + 1).await?,
+            Some(msg2)
+        );
+        assert_eq!(get_first_msg_on_or_after(&t, chat.id, ts2 + 1).await?, None);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_notification_profile() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        let bob = TestContext::new_bob().await;
+        let alice_chat = alice.create_chat(&bob).await;
+        assert_eq!(alice_chat.id.get_notification_profile(&alice).await?, None);
+
+        let profile = NotificationProfile {
+            sound: Some("bell".to_string()),
+            vibration: None,
+            priority: 1,
+        };
+        alice_chat
+            .id
+            .set_notification_profile(&alice, Some(&profile))
+            .await?;
+        assert_eq!(
+            alice_chat.id.get_notification_profile(&alice).await?,
+            Some(profile.clone())
+        );
+
+        let sent = bob.send_text(bob.create_chat(&alice).await.id, "hi").await;
+        alice.recv_msg(&sent).await;
+        loop {
+            if let EventType::IncomingMsg {
+                notification_profile,
+                ..
+            } = alice.evtracker.recv().await?
+            {
+                assert_eq!(notification_profile, Some(profile));
+                break;
+            }
+        }
+
+        alice_chat.id.set_notification_profile(&alice, None).await?;
+        assert_eq!(alice_chat.id.get_notification_profile(&alice).await?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_quiet_minute() {
+        // No quiet hours.
+        assert!(!is_quiet_minute(0, 0, 0));
+        assert!(!is_quiet_minute(600, 600, 600));
+
+        // 12:00 to 14:00.
+        assert!(!is_quiet_minute(719, 720, 840));
+        assert!(is_quiet_minute(720, 720, 840));
+        assert!(!is_quiet_minute(840, 720, 840));
+
+        // 22:00 to 07:00, spanning midnight.
+        assert!(is_quiet_minute(1380, 1320, 420));
+        assert!(is_quiet_minute(0, 1320, 420));
+        assert!(is_quiet_minute(419, 1320, 420));
+        assert!(!is_quiet_minute(420, 1320, 420));
+        assert!(!is_quiet_minute(1319, 1320, 420));
+    }
+
+    #[async_std::test]
+    async fn test_incoming_msg_event_quiet_hours() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat_id = t.get_self_chat().await.id;
+        let msg_id = MsgId::new(10);
+        assert_eq!(
+            incoming_msg_event(&t, chat_id, msg_id).await,
+            EventType::IncomingMsg {
+                chat_id,
+                msg_id,
+                notification_profile: None,
+                muted: false
+            }
+        );
+
+        // Quiet hours over the whole day.
+        t.set_config(Config::QuietHoursStart, Some("0")).await?;
+        t.set_config(Config::QuietHoursEnd, Some("1440")).await?;
+        assert!(is_quiet_hours(&t).await?);
+        assert_eq!(
+            incoming_msg_event(&t, chat_id, msg_id).await,
+            EventType::IncomingMsg {
+                chat_id,
+                msg_id,
+                notification_profile: None,
+                muted: true
+            }
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_passthrough_attachments() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        let bob = TestContext::new_bob().await;
+        let chat = alice.create_chat(&bob).await;
+        alice
+            .set_config_bool(Config::PassthroughAttachments, true)
+            .await?;
+
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("video.mp4");
+        File::create(&file).await?.write_all(&[0x42; 1000]).await?;
+        let mut msg = Message::new(Viewtype::File);
+        msg.set_file(file.to_str().unwrap(), None);
+
+        // The file is not copied to the blob directory when the message is prepared.
+        let msg_id = prepare_msg(&alice, chat.id, &mut msg).await?;
+        let prepared = Message::load_from_db(&alice, msg_id).await?;
+        assert_eq!(prepared.get_viewtype(), Viewtype::Video);
+        assert_eq!(prepared.get_file(&alice), Some(file.clone().into()));
+        assert!(!alice.get_blobdir().join("video.mp4").exists().await);
+
+        // The file is copied when the message is sent.
+        send_msg(&alice, chat.id, &mut msg).await?;
+        let sent = alice.pop_sent_msg().await;
+        let msg = Message::load_from_db(&alice, sent.sender_msg_id).await?;
+        let blob = msg.get_file(&alice).unwrap();
+        assert_eq!(blob.parent(), Some(alice.get_blobdir()));
+        assert_eq!(
+            async_std::fs::read(blob).await?,
+            async_std::fs::read(&file).await?
+        );
+
+        bob.recv_msg(&sent).await;
+        let msg = bob.get_last_msg().await;
+        assert_eq!(msg.get_viewtype(), Viewtype::Video);
+        assert_eq!(msg.get_filebytes(&bob).await, 1000);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_estimate_msg_size() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        let bob = TestContext::new_bob().await;
+        let chat = alice.create_chat(&bob).await;
+
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("hi".to_string()));
+        let estimate = estimate_msg_size(&alice, chat.id, &msg).await?;
+        assert_eq!(chat.id.get_msg_cnt(&alice).await?, 0);
+
+        let sent = alice.send_msg(chat.id, &mut msg).await;
+        let size = sent.payload().len();
+        assert!(estimate * 9 / 10 < size && size < estimate * 11 / 10);
+
+        // Attachments are base64-encoded.
+        let file = alice.get_blobdir().join("data.bin");
+        File::create(&file)
+            .await?
+            .write_all(&[0x42; 30_000])
+            .await?;
+        let mut msg = Message::new(Viewtype::File);
+        msg.set_file(file.to_str().unwrap(), None);
+        let estimate = estimate_msg_size(&alice, chat.id, &msg).await?;
+        assert!(estimate > 40_000);
+
+        assert!(
+            estimate_msg_size(&alice, ChatId::new(DC_CHAT_ID_TRASH), &msg)
+                .await
+                .is_err()
+        );
+        Ok(())
+    }
 }
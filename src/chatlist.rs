@@ -6,13 +6,14 @@
 use crate::constants::{
     Blocked, Chattype, DC_CHAT_ID_ALLDONE_HINT, DC_CHAT_ID_ARCHIVED_LINK, DC_CONTACT_ID_DEVICE,
     DC_CONTACT_ID_SELF, DC_CONTACT_ID_UNDEFINED, DC_GCL_ADD_ALLDONE_HINT, DC_GCL_ARCHIVED_ONLY,
-    DC_GCL_FOR_FORWARDING, DC_GCL_NO_SPECIALS,
+    DC_GCL_CONTACT_REQUESTS_ONLY, DC_GCL_FOR_FORWARDING, DC_GCL_NO_CONTACT_REQUESTS,
+    DC_GCL_NO_SPECIALS,
 };
 use crate::contact::Contact;
 use crate::context::Context;
 use crate::ephemeral::delete_expired_messages;
 use crate::lot::Lot;
-use crate::message::{Message, MessageState, MsgId};
+use crate::message::{Message, MsgId};
 use crate::stock_str;
 
 /// An object representing a single chatlist in memory.
@@ -75,6 +76,11 @@ impl Chatlist {
     /// - if the flag DC_GCL_NO_SPECIALS is set, archive link is not added
     ///   to the list (may be used eg. for selecting chats on forwarding, the flag is
     ///   not needed when DC_GCL_ARCHIVED_ONLY is already set)
+    /// - if the flag DC_GCL_CONTACT_REQUESTS_ONLY is set, only contact requests are
+    ///   returned, eg. to show them in a separate inbox; archived chats and
+    ///   the archive link are not included then
+    /// - if the flag DC_GCL_NO_CONTACT_REQUESTS is set, contact requests are not
+    ///   added to the normal chatlist
     /// - if the flag DC_GCL_ADD_ALLDONE_HINT is set, DC_CHAT_ID_ALLDONE_HINT
     ///   is added as needed.
     /// `query`: An optional query for filtering the list. Only chats matching this query
@@ -91,6 +97,8 @@ pub async fn try_load(
         let flag_for_forwarding = 0 != listflags & DC_GCL_FOR_FORWARDING;
         let flag_no_specials = 0 != listflags & DC_GCL_NO_SPECIALS;
         let flag_add_alldone_hint = 0 != listflags & DC_GCL_ADD_ALLDONE_HINT;
+        let flag_contact_requests_only = 0 != listflags & DC_GCL_CONTACT_REQUESTS_ONLY;
+        let flag_no_contact_requests = 0 != listflags & DC_GCL_NO_CONTACT_REQUESTS;
 
         // Note that we do not emit DC_EVENT_MSGS_MODIFIED here even if some
         // messages get deleted to avoid reloading the same chatlist.
@@ -119,35 +127,23 @@ pub async fn try_load(
             ChatId::new(0)
         };
 
-        // select with left join and minimum:
-        //
-        // - the inner select must use `hidden` and _not_ `m.hidden`
-        //   which would refer the outer select and take a lot of time
-        // - `GROUP BY` is needed several messages may have the same
-        //   timestamp
-        // - the list starts with the newest chats
+        // The last message of each chat is maintained in the `chats` table
+        // by triggers, see migration 85, so no lookup in `msgs` is needed.
+        // The list starts with the newest chats.
         //
         // The query shows messages from blocked contacts in
         // groups. Otherwise it would be hard to follow conversations.
         let mut ids = if let Some(query_contact_id) = query_contact_id {
             // show chats shared with a given contact
             context.sql.query_map(
-                "SELECT c.id, m.id
+                "SELECT c.id, NULLIF(c.last_msg_id, 0)
                  FROM chats c
-                 LEFT JOIN msgs m
-                        ON c.id=m.chat_id
-                       AND m.id=(
-                               SELECT id
-                                 FROM msgs
-                                WHERE chat_id=c.id
-                                  AND (hidden=0 OR state=?1)
-                                  ORDER BY timestamp DESC, id DESC LIMIT 1)
                  WHERE c.id>9
+                   AND c.id NOT IN (SELECT chat_id FROM pending_deletions)
                    AND c.blocked!=1
-                   AND c.id IN(SELECT chat_id FROM chats_contacts WHERE contact_id=?2)
-                 GROUP BY c.id
-                 ORDER BY c.archived=?3 DESC, IFNULL(m.timestamp,c.created_timestamp) DESC, m.id DESC;",
-                paramsv![MessageState::OutDraft, query_contact_id as i32, ChatVisibility::Pinned],
+                   AND c.id IN(SELECT chat_id FROM chats_contacts WHERE contact_id=?1)
+                 ORDER BY c.archived=?2 DESC, CASE WHEN c.last_msg_id=0 THEN c.created_timestamp ELSE c.last_msg_timestamp END DESC, c.last_msg_id DESC;",
+                paramsv![query_contact_id as i32, ChatVisibility::Pinned],
                 process_row,
                 process_rows,
             ).await?
@@ -159,22 +155,31 @@ pub async fn try_load(
             context
                 .sql
                 .query_map(
-                    "SELECT c.id, m.id
+                    "SELECT c.id, NULLIF(c.last_msg_id, 0)
                  FROM chats c
-                 LEFT JOIN msgs m
-                        ON c.id=m.chat_id
-                       AND m.id=(
-                               SELECT id
-                                 FROM msgs
-                                WHERE chat_id=c.id
-                                  AND (hidden=0 OR state=?)
-                                  ORDER BY timestamp DESC, id DESC LIMIT 1)
                  WHERE c.id>9
+                   AND c.id NOT IN (SELECT chat_id FROM pending_deletions)
                    AND c.blocked!=1
                    AND c.archived=1
-                 GROUP BY c.id
-                 ORDER BY IFNULL(m.timestamp,c.created_timestamp) DESC, m.id DESC;",
-                    paramsv![MessageState::OutDraft],
+                 ORDER BY CASE WHEN c.last_msg_id=0 THEN c.created_timestamp ELSE c.last_msg_timestamp END DESC, c.last_msg_id DESC;",
+                    paramsv![],
+                    process_row,
+                    process_rows,
+                )
+                .await?
+        } else if flag_contact_requests_only {
+            // show contact requests
+            context
+                .sql
+                .query_map(
+                    "SELECT c.id, NULLIF(c.last_msg_id, 0)
+                 FROM chats c
+                 WHERE c.id>9
+                   AND c.id NOT IN (SELECT chat_id FROM pending_deletions)
+                   AND c.blocked=?
+                   AND NOT c.archived=?
+                 ORDER BY CASE WHEN c.last_msg_id=0 THEN c.created_timestamp ELSE c.last_msg_timestamp END DESC, c.last_msg_id DESC;",
+                    paramsv![Blocked::Request, ChatVisibility::Archived],
                     process_row,
                     process_rows,
                 )
@@ -193,22 +198,14 @@ pub async fn try_load(
             context
                 .sql
                 .query_map(
-                    "SELECT c.id, m.id
+                    "SELECT c.id, NULLIF(c.last_msg_id, 0)
                  FROM chats c
-                 LEFT JOIN msgs m
-                        ON c.id=m.chat_id
-                       AND m.id=(
-                               SELECT id
-                                 FROM msgs
-                                WHERE chat_id=c.id
-                                  AND (hidden=0 OR state=?1)
-                                  ORDER BY timestamp DESC, id DESC LIMIT 1)
-                 WHERE c.id>9 AND c.id!=?2
+                 WHERE c.id>9 AND c.id!=?1
+                   AND c.id NOT IN (SELECT chat_id FROM pending_deletions)
                    AND c.blocked!=1
-                   AND c.name LIKE ?3
-                 GROUP BY c.id
-                 ORDER BY IFNULL(m.timestamp,c.created_timestamp) DESC, m.id DESC;",
-                    paramsv![MessageState::OutDraft, skip_id, str_like_cmd],
+                   AND c.name LIKE ?2
+                 ORDER BY CASE WHEN c.last_msg_id=0 THEN c.created_timestamp ELSE c.last_msg_timestamp END DESC, c.last_msg_id DESC;",
+                    paramsv![skip_id, str_like_cmd],
                     process_row,
                     process_rows,
                 )
@@ -223,22 +220,14 @@ pub async fn try_load(
                 ChatId::new(0)
             };
             let ids = context.sql.query_map(
-                "SELECT c.id, m.id
+                "SELECT c.id, NULLIF(c.last_msg_id, 0)
                  FROM chats c
-                 LEFT JOIN msgs m
-                        ON c.id=m.chat_id
-                       AND m.id=(
-                               SELECT id
-                                 FROM msgs
-                                WHERE chat_id=c.id
-                                  AND (hidden=0 OR state=?1)
-                                  ORDER BY timestamp DESC, id DESC LIMIT 1)
-                 WHERE c.id>9 AND c.id!=?2
-                   AND (c.blocked=0 OR (c.blocked=2 AND NOT ?3))
-                   AND NOT c.archived=?4
-                 GROUP BY c.id
-                 ORDER BY c.id=?5 DESC, c.archived=?6 DESC, IFNULL(m.timestamp,c.created_timestamp) DESC, m.id DESC;",
-                paramsv![MessageState::OutDraft, skip_id, flag_for_forwarding, ChatVisibility::Archived, sort_id_up, ChatVisibility::Pinned],
+                 WHERE c.id>9 AND c.id!=?1
+                   AND c.id NOT IN (SELECT chat_id FROM pending_deletions)
+                   AND (c.blocked=0 OR (c.blocked=2 AND NOT ?2 AND NOT ?6))
+                   AND NOT c.archived=?3
+                 ORDER BY c.id=?4 DESC, c.archived=?5 DESC, CASE WHEN c.last_msg_id=0 THEN c.created_timestamp ELSE c.last_msg_timestamp END DESC, c.last_msg_id DESC;",
+                paramsv![skip_id, flag_for_forwarding, ChatVisibility::Archived, sort_id_up, ChatVisibility::Pinned, flag_no_contact_requests],
                 process_row,
                 process_rows,
             ).await?;
@@ -379,7 +368,9 @@ pub async fn dc_get_archived_cnt(context: &Context) -> Result<usize> {
     let count = context
         .sql
         .count(
-            "SELECT COUNT(*) FROM chats WHERE blocked!=? AND archived=?;",
+            "SELECT COUNT(*) FROM chats
+             WHERE blocked!=? AND archived=?
+               AND id NOT IN (SELECT chat_id FROM pending_deletions);",
             paramsv![Blocked::Manually, ChatVisibility::Archived],
         )
         .await?;
@@ -393,6 +384,7 @@ mod tests {
     use crate::chat::{create_group_chat, get_chat_contacts, ProtectionStatus};
     use crate::constants::Viewtype;
     use crate::dc_receive_imf::dc_receive_imf;
+    use crate::message::{self, MessageState};
     use crate::stock_str::StockMessage;
     use crate::test_utils::TestContext;
 
@@ -442,6 +434,69 @@ async fn test_try_load() {
         assert_eq!(chats.len(), 1);
     }
 
+    #[async_std::test]
+    async fn test_chat_summary_columns() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat_id = create_group_chat(&t, ProtectionStatus::Unprotected, "a chat").await?;
+        async fn get_summary(t: &TestContext, chat_id: ChatId) -> Result<MsgId> {
+            t.sql
+                .query_row(
+                    "SELECT last_msg_id FROM chats WHERE id=?",
+                    paramsv![chat_id],
+                    |row| row.get(0),
+                )
+                .await
+        }
+        let initial = get_summary(&t, chat_id).await?;
+
+        let first = t.send_text(chat_id, "first").await.sender_msg_id;
+        let second = t.send_text(chat_id, "second").await.sender_msg_id;
+        assert_eq!(get_summary(&t, chat_id).await?, second);
+        let chats = Chatlist::try_load(&t, 0, None, None).await?;
+        assert_eq!(chats.get_msg_id(0)?, Some(second));
+
+        message::delete_msgs(&t, &[second]).await;
+        assert_eq!(get_summary(&t, chat_id).await?, first);
+        message::delete_msgs(&t, &[first]).await;
+        assert_eq!(get_summary(&t, chat_id).await?, initial);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_contact_requests() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        let bob = TestContext::new_bob().await;
+        let alice_chat = alice.create_chat(&bob).await;
+        let sent = alice.send_text(alice_chat.id, "hi").await;
+        bob.recv_msg(&sent).await;
+        let msg = bob.get_last_msg().await;
+        let chat = Chat::load_from_db(&bob, msg.chat_id).await?;
+        assert!(chat.is_contact_request());
+
+        let chats = Chatlist::try_load(&bob, 0, None, None).await?;
+        assert_eq!(chats.len(), 1);
+        let chats = Chatlist::try_load(&bob, DC_GCL_NO_CONTACT_REQUESTS, None, None).await?;
+        assert_eq!(chats.len(), 0);
+        let chats = Chatlist::try_load(&bob, DC_GCL_CONTACT_REQUESTS_ONLY, None, None).await?;
+        assert_eq!(chats.len(), 1);
+        assert_eq!(chats.get_chat_id(0), chat.id);
+
+        // Messages are not marked as seen and no read receipts are sent until accepted.
+        crate::message::markseen_msgs(&bob, vec![msg.id]).await?;
+        let msg = Message::load_from_db(&bob, msg.id).await?;
+        assert_eq!(msg.state, MessageState::InFresh);
+
+        chat.id.accept(&bob).await?;
+        let chats = Chatlist::try_load(&bob, DC_GCL_CONTACT_REQUESTS_ONLY, None, None).await?;
+        assert_eq!(chats.len(), 0);
+        let chats = Chatlist::try_load(&bob, DC_GCL_NO_CONTACT_REQUESTS, None, None).await?;
+        assert_eq!(chats.len(), 1);
+        crate::message::markseen_msgs(&bob, vec![msg.id]).await?;
+        let msg = Message::load_from_db(&bob, msg.id).await?;
+        assert_eq!(msg.state, MessageState::InSeen);
+        Ok(())
+    }
+
     #[async_std::test]
     async fn test_sort_self_talk_up_on_forward() {
         let t = TestContext::new().await;
@@ -6,13 +6,16 @@
 
 use crate::blob::BlobObject;
 use crate::chat::ChatId;
+use crate::color::str_to_color;
 use crate::constants::DC_VERSION_STR;
 use crate::context::Context;
-use crate::dc_tools::{dc_get_abs_path, improve_single_line_input};
+use crate::dc_tools::{dc_create_id, dc_get_abs_path, improve_single_line_input};
+use crate::ephemeral;
 use crate::events::EventType;
 use crate::job;
 use crate::message::MsgId;
 use crate::mimefactory::RECOMMENDED_FILE_SIZE;
+use crate::param::Params;
 use crate::provider::{get_provider_by_id, Provider};
 use crate::stock_str;
 
@@ -52,6 +55,9 @@ pub enum Config {
     Selfstatus,
     Selfavatar,
 
+    /// Accent color of the account as 24-bit RGB, see `Context::get_self_color`.
+    SelfColor,
+
     #[strum(props(default = "0"))]
     BccSelf,
 
@@ -82,6 +88,27 @@ pub enum Config {
     #[strum(props(default = "0"))] // also change MediaQuality.default() on changes
     MediaQuality,
 
+    /// If set to "1", attachments outside the blob directory are left at their original
+    /// location when the message is prepared and only copied to the blob directory
+    /// when the message is sent.
+    /// Images are still copied on preparation as they are recoded.
+    #[strum(props(default = "0"))]
+    PassthroughAttachments,
+
+    /// When blob files are synced to disk, see `BlobSync`.
+    /// By default, this is left to the operating system.
+    #[strum(props(default = "0"))]
+    BlobSync,
+
+    /// Maximum length of message texts in characters.
+    ///
+    /// Longer outgoing texts are sent as text attachment with the beginning of the text
+    /// as preview. Longer incoming texts are truncated, the full text is stored in a blob
+    /// and available via `Message::get_full_text`.
+    /// Equals to 0 by default, which means the length is not limited.
+    #[strum(props(default = "0"))]
+    MaxTextLength,
+
     /// If set to "1", on the first time `start_io()` is called after configuring,
     /// the newest existing messages are fetched.
     /// Existing recipients are added to the contact database regardless of this setting.
@@ -102,6 +129,20 @@ pub enum Config {
     #[strum(props(default = "0"))]
     DeleteServerAfter,
 
+    /// Timer in seconds after which outgoing messages, eg. BCC-self copies,
+    /// are deleted from the server.
+    ///
+    /// Equals to 0 by default, which means `Config::DeleteServerAfter` is used
+    /// for outgoing messages as well. Value 1 is treated as "delete at once".
+    #[strum(props(default = "0"))]
+    DeleteServerAfterSelf,
+
+    /// If set to "1", `DeleteServerAfter` deletes incoming messages from the server
+    /// only after they were marked as seen, so other devices can still fetch unread
+    /// messages. Ephemeral messages are not affected.
+    #[strum(props(default = "0"))]
+    DeleteServerOnlySeen,
+
     /// Timer in seconds after which the message is deleted from the
     /// device.
     ///
@@ -110,7 +151,146 @@ pub enum Config {
     #[strum(props(default = "0"))]
     DeleteDeviceAfter,
 
+    /// Timer in seconds after which messages in the device chat are deleted.
+    ///
+    /// `DeleteDeviceAfter` does not apply to the device chat,
+    /// so old device messages are kept forever by default.
+    /// Equals to 0 by default, which means the messages are never deleted.
+    #[strum(props(default = "0"))]
+    DeleteDeviceChatAfter,
+
+    /// If set to "1", `DeleteDeviceAfter` also deletes messages
+    /// in the saved messages chat, which is skipped by default.
+    #[strum(props(default = "0"))]
+    DeleteDeviceAfterIncludesSelfChat,
+
+    /// If set to "1", starred messages are neither deleted by ephemeral timers
+    /// nor by `DeleteDeviceAfter`.
+    #[strum(props(default = "0"))]
+    ProtectStarredFromDeletion,
+
+    /// Time in seconds after which messages expired by their ephemeral timer are deleted,
+    /// so that the user can still keep them using `MsgId::cancel_expiry`.
+    /// Equals to 0 by default, which means the messages are deleted once they expire.
+    #[strum(props(default = "0"))]
+    EphemeralGracePeriod,
+
+    /// Time in seconds during which outgoing messages are still sent after their ephemeral
+    /// timer expired, eg. if they could not be sent because of a short network outage.
+    /// Messages not sent until the timer and this grace period are over are marked as failed.
+    /// Equals to 0 by default, which means the messages fail once their timer expires.
+    #[strum(props(default = "0"))]
+    UndeliveredGracePeriod,
+
+    /// If set to "1", `DeleteDeviceAfter` and `DeleteServerAfter` are synchronized
+    /// with the other devices of the user having this option set.
+    #[strum(props(default = "0"))]
+    SyncDeviceSettings,
+
+    /// Database queries taking longer than this number of milliseconds
+    /// are logged as warnings along with their query plan.
+    /// Equals to 0 by default, which means slow queries are not logged.
+    #[strum(props(default = "0"))]
+    SlowQueryThreshold,
+
+    /// If set to "1", OpenPGP keys of new contacts not using Autocrypt are looked up in
+    /// the Web Key Directory of their email provider, allowing to encrypt messages to them.
+    #[strum(props(default = "0"))]
+    WkdLookup,
+
+    /// If set to "1", the own public key is published on keys.openpgp.org,
+    /// so correspondents using classic OpenPGP software can find it.
+    #[strum(props(default = "0"))]
+    PublishKey,
+
+    /// Timer in seconds after which info messages, eg. about group changes,
+    /// are deleted from the device.
+    ///
+    /// The latest message of each kind of group change is kept in each chat.
+    /// Equals to 0 by default, which means info messages are never deleted.
+    #[strum(props(default = "0"))]
+    DeleteInfoMsgsAfter,
+
+    /// If set to "1", a new group secret is generated when a member is removed
+    /// from a protected group.
+    ///
+    /// Messages to a protected group are encrypted to the keys of the current members only,
+    /// but a removed member still knows the group ID and may keep sending messages
+    /// to the group. The group secret is sent to the remaining members in the encrypted
+    /// part of the following messages; once members know a secret, messages with an
+    /// older or without a secret are rejected.
+    #[strum(props(default = "0"))]
+    GroupRekeying,
+
+    /// Time in seconds during which deletions of messages and chats by the user
+    /// can be reverted using `Context::undo_last_deletion`.
+    ///
+    /// Equals to 0 by default, which means deletions are irreversible immediately.
+    #[strum(props(default = "0"))]
+    DeletionUndoWindow,
+
     SaveMimeHeaders,
+
+    /// If set to "1", remote content as images and stylesheets is kept
+    /// in the HTML returned by `MsgId::get_html()`.
+    ///
+    /// Disabled by default as remote content may be used to track when and where
+    /// a message is read.
+    #[strum(props(default = "0"))]
+    ShowRemoteContent,
+
+    /// If set to "1", classic emails are grouped into chats by their `References:` chain,
+    /// even if the parent message is not in the database
+    /// or the reply is sent to self only.
+    ///
+    /// This keeps long email threads with changing subjects and recipients,
+    /// eg. support tickets, in one chat.
+    #[strum(props(default = "0"))]
+    GroupThreadsByReferences,
+
+    /// Start of the quiet hours in minutes after midnight, local time.
+    ///
+    /// `IncomingMsg` events emitted during the quiet hours are marked as muted.
+    /// The quiet hours end at `Config::QuietHoursEnd` and may span midnight;
+    /// if start and end are equal, which is the default, there are no quiet hours.
+    #[strum(props(default = "0"))]
+    QuietHoursStart,
+
+    /// End of the quiet hours in minutes after midnight, local time,
+    /// see `Config::QuietHoursStart`.
+    #[strum(props(default = "0"))]
+    QuietHoursEnd,
+
+    /// Comma-separated list of optional headers which are not added to outgoing messages,
+    /// to reduce fingerprinting in privacy-conscious deployments.
+    ///
+    /// Only `Chat-Version`, `Chat-Disposition-Notification-To` and `Auto-Submitted`
+    /// can be omitted, other headers are required and always sent. Note that without
+    /// `Chat-Version`, other Delta Chat clients treat messages as classic emails.
+    /// `User-Agent` and `X-Mailer` headers are never sent.
+    OmitHeaders,
+
+    /// If set to "1", the `Date` header of outgoing messages is rounded down to the minute.
+    ///
+    /// The `Date` header is always sent in UTC, so it does not leak the time zone.
+    #[strum(props(default = "0"))]
+    CoarseDate,
+
+    /// Directory automatic backups are written to, see `AutoBackupInterval`.
+    AutoBackupDir,
+
+    /// Interval in seconds at which a backup is written to `AutoBackupDir`
+    /// while IO is running.
+    ///
+    /// Equals to 0 by default, which means no automatic backups are written.
+    #[strum(props(default = "0"))]
+    AutoBackupInterval,
+
+    /// Number of backups kept in `AutoBackupDir`, older backups are deleted
+    /// after an automatic backup was written. 0 keeps all backups.
+    #[strum(props(default = "3"))]
+    AutoBackupKeep,
+
     ConfiguredAddr,
     ConfiguredMailServer,
     ConfiguredMailUser,
@@ -156,6 +336,23 @@ pub enum Config {
     /// Timestamp of the last time housekeeping was run
     LastHousekeeping,
 
+    /// Timestamp of the last upload of the own key to the keyserver
+    #[strum(props(default = "0"))]
+    LastKeyPublish,
+
+    /// Fingerprint of the own key whose address verification was requested
+    /// from the keyserver and is not confirmed yet
+    KeyVerificationPending,
+
+    /// Timestamp of the last automatic backup
+    #[strum(props(default = "0"))]
+    LastAutoBackup,
+
+    /// Timestamp of the last search for trashed messages deleted from the server
+    /// by another client, see `Imap::clear_vanished_trash_uids`
+    #[strum(props(default = "0"))]
+    LastVanishedTrashCheck,
+
     /// To how many seconds to debounce scan_all_folders. Used mainly in tests, to disable debouncing completely.
     #[strum(props(default = "60"))]
     ScanAllFoldersDebounceSecs,
@@ -213,6 +410,32 @@ pub async fn get_config_bool(&self, key: Config) -> Result<bool> {
         Ok(self.get_config_int(key).await? != 0)
     }
 
+    /// Returns the accent color of the account as 24-bit RGB,
+    /// eg. to color-code accounts identically in multi-account UIs.
+    ///
+    /// The color is generated from the address as for contacts when it is first requested
+    /// and then stored in `Config::SelfColor`, so it stays the same if the address changes.
+    pub async fn get_self_color(&self) -> Result<u32> {
+        if let Some(color) = self
+            .get_config(Config::SelfColor)
+            .await?
+            .and_then(|color| color.parse().ok())
+        {
+            return Ok(color);
+        }
+        let seed = match self.get_config(Config::ConfiguredAddr).await? {
+            Some(addr) => addr,
+            None => match self.get_config(Config::Addr).await? {
+                Some(addr) => addr,
+                None => dc_create_id(),
+            },
+        };
+        let color = str_to_color(&seed);
+        self.set_config(Config::SelfColor, Some(&color.to_string()))
+            .await?;
+        Ok(color)
+    }
+
     /// Gets configured "delete_server_after" value.
     ///
     /// `None` means never delete the message, `Some(0)` means delete
@@ -225,6 +448,18 @@ pub async fn get_config_delete_server_after(&self) -> Result<Option<i64>> {
         }
     }
 
+    /// Gets the "delete_server_after" value used for outgoing messages.
+    ///
+    /// Falls back to `Context::get_config_delete_server_after`
+    /// if "delete_server_after_self" is not set.
+    pub async fn get_config_delete_server_after_self(&self) -> Result<Option<i64>> {
+        match self.get_config_int(Config::DeleteServerAfterSelf).await? {
+            0 => self.get_config_delete_server_after().await,
+            1 => Ok(Some(0)),
+            x => Ok(Some(x as i64)),
+        }
+    }
+
     /// Gets the configured provider, as saved in the `configured_provider` value.
     ///
     /// The provider is determined by `get_provider_info()` during configuration and then saved
@@ -293,22 +528,49 @@ pub async fn set_config(&self, key: Config, value: Option<&str>) -> Result<()> {
                     msg_id: MsgId::new(0),
                     chat_id: ChatId::new(0),
                 });
+                if let Err(err) = ephemeral::sync_device_settings(self).await {
+                    warn!(self, "Failed to sync device settings: {}", err);
+                }
                 ret
             }
+            Config::SlowQueryThreshold => {
+                self.sql.set_raw_config(key, value).await?;
+                self.sql.update_slow_query_log(self).await
+            }
             Config::Displayname => {
                 let value = value.map(improve_single_line_input);
                 self.sql.set_raw_config(key, value.as_deref()).await?;
                 Ok(())
             }
-            Config::DeleteServerAfter => {
+            Config::DeleteServerAfter | Config::DeleteServerAfterSelf => {
                 let ret = self
                     .sql
                     .set_raw_config(key, value)
                     .await
                     .map_err(Into::into);
                 job::schedule_resync(self).await;
+                if key == Config::DeleteServerAfter {
+                    if let Err(err) = ephemeral::sync_device_settings(self).await {
+                        warn!(self, "Failed to sync device settings: {}", err);
+                    }
+                }
                 ret
             }
+            Config::PublishKey => {
+                self.sql.set_raw_config(key, value).await?;
+                if self.get_config_bool(Config::PublishKey).await? {
+                    // Publish the key with the next housekeeping.
+                    self.sql
+                        .set_raw_config(Config::LastKeyPublish, None)
+                        .await?;
+                    job::add(
+                        self,
+                        job::Job::new(job::Action::Housekeeping, 0, Params::new(), 0),
+                    )
+                    .await;
+                }
+                Ok(())
+            }
             _ => {
                 self.sql.set_raw_config(key, value).await?;
                 Ok(())
@@ -345,6 +607,21 @@ mod tests {
     use crate::test_utils::TestContext;
     use num_traits::FromPrimitive;
 
+    #[async_std::test]
+    async fn test_self_color() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let self_contact =
+            crate::contact::Contact::get_by_id(&t, constants::DC_CONTACT_ID_SELF).await?;
+        let color = t.get_self_color().await?;
+        assert_eq!(color, self_contact.get_color());
+
+        // The color does not change with the address.
+        t.set_config(Config::ConfiguredAddr, Some("other@example.org"))
+            .await?;
+        assert_eq!(t.get_self_color().await?, color);
+        Ok(())
+    }
+
     #[test]
     fn test_to_string() {
         assert_eq!(Config::MailServer.to_string(), "mail_server");
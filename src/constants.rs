@@ -107,6 +107,8 @@ fn default() -> Self {
 pub const DC_GCL_NO_SPECIALS: usize = 0x02;
 pub const DC_GCL_ADD_ALLDONE_HINT: usize = 0x04;
 pub const DC_GCL_FOR_FORWARDING: usize = 0x08;
+pub const DC_GCL_CONTACT_REQUESTS_ONLY: usize = 0x10;
+pub const DC_GCL_NO_CONTACT_REQUESTS: usize = 0x20;
 
 pub const DC_GCM_ADDDAYMARKER: u32 = 0x01;
 pub const DC_GCM_INFO_ONLY: u32 = 0x02;
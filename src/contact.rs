@@ -10,6 +10,7 @@
 use regex::Regex;
 
 use crate::aheader::EncryptPreference;
+use crate::avatar_history::{self, AvatarOwner};
 use crate::chat::ChatId;
 use crate::color::str_to_color;
 use crate::config::Config;
@@ -20,13 +21,13 @@
 use crate::context::Context;
 use crate::dc_tools::{dc_get_abs_path, improve_single_line_input, EmailAddress};
 use crate::events::EventType;
-use crate::key::{DcKey, SignedPublicKey};
+use crate::key::{DcKey, KeySource, SignedPublicKey};
 use crate::login_param::LoginParam;
 use crate::message::MessageState;
 use crate::mimeparser::AvatarAction;
 use crate::param::{Param, Params};
-use crate::peerstate::{Peerstate, PeerstateVerifiedStatus};
-use crate::{chat, stock_str};
+use crate::peerstate::{Peerstate, PeerstateVerifiedStatus, ToSave};
+use crate::{chat, job, stock_str};
 
 /// An object representing a single contact in memory.
 ///
@@ -272,6 +273,13 @@ pub async fn create(context: &Context, name: &str, addr: &str) -> Result<u32> {
         if blocked {
             Contact::unblock(context, contact_id).await?;
         }
+        if sth_modified == Modifier::Created && context.get_config_bool(Config::WkdLookup).await? {
+            job::add(
+                context,
+                job::Job::new(job::Action::WkdLookup, contact_id, Params::new(), 0),
+            )
+            .await;
+        }
 
         Ok(contact_id)
     }
@@ -802,11 +810,11 @@ pub async fn get_encrinfo(context: &Context, contact_id: u32) -> Result<String>
                     EncryptPreference::Reset => stock_str::encr_none(context).await,
                 };
 
-                ret += &format!(
-                    "{}\n{}:",
-                    stock_message,
-                    stock_str::finger_prints(context).await
-                );
+                ret += &stock_message;
+                if contact.has_pinned_key(&peerstate) {
+                    ret += &format!("\n{}", stock_str::key_imported_manually(context).await);
+                }
+                ret += &format!("\n{}:", stock_str::finger_prints(context).await);
 
                 let fingerprint_self = SignedPublicKey::load_self(context)
                     .await?
@@ -913,6 +921,163 @@ pub async fn update_param(&self, context: &Context) -> Result<()> {
         Ok(())
     }
 
+    /// Returns true if remote content as images in HTML messages
+    /// is loaded for messages from this contact.
+    ///
+    /// See `Contact::set_load_remote_content` for details.
+    pub fn get_load_remote_content(&self) -> bool {
+        self.param
+            .get_bool(Param::LoadRemoteContent)
+            .unwrap_or_default()
+    }
+
+    /// Allows or disallows loading remote content for messages from this contact.
+    ///
+    /// By default, remote content is removed from HTML messages
+    /// as it may be used to track whether and when a message was read.
+    /// For trusted senders, eg. newsletters the user subscribed to,
+    /// the UI may offer to always load remote content.
+    /// `Config::ShowRemoteContent` enables remote content for all contacts.
+    pub async fn set_load_remote_content(&mut self, context: &Context, allow: bool) -> Result<()> {
+        if allow {
+            self.param.set_int(Param::LoadRemoteContent, 1);
+        } else {
+            self.param.remove(Param::LoadRemoteContent);
+        }
+        self.update_param(context).await
+    }
+
+    /// Returns true if messages to this contact are sent as classic email.
+    ///
+    /// See `Contact::set_plain_email` for details.
+    pub fn is_plain_email(&self) -> bool {
+        self.param.get_bool(Param::PlainEmail).unwrap_or_default()
+    }
+
+    /// Enables or disables the classic email mode for this contact.
+    ///
+    /// In classic email mode, messages in the 1:1 chat with the contact
+    /// are sent without chat-specific headers and without Autocrypt-Gossip,
+    /// and replies contain the quoted message below the text,
+    /// so that the conversation looks like normal email for correspondents not using Delta Chat.
+    pub async fn set_plain_email(&mut self, context: &Context, plain_email: bool) -> Result<()> {
+        if plain_email {
+            self.param.set_int(Param::PlainEmail, 1);
+        } else {
+            self.param.remove(Param::PlainEmail);
+        }
+        self.update_param(context).await
+    }
+
+    /// Returns true if messages to this contact are signed but not encrypted.
+    ///
+    /// See `Contact::set_sign_only` for details.
+    pub fn is_sign_only(&self) -> bool {
+        self.param.get_bool(Param::SignOnly).unwrap_or_default()
+    }
+
+    /// Enables or disables the signed-only mode for this contact.
+    ///
+    /// In signed-only mode, messages in the 1:1 chat with the contact are not encrypted,
+    /// but sent as PGP/MIME signed messages, so that correspondents who cannot decrypt
+    /// can still check the authenticity of the messages.
+    /// Messages in protected chats are always encrypted.
+    pub async fn set_sign_only(&mut self, context: &Context, sign_only: bool) -> Result<()> {
+        if sign_only {
+            self.param.set_int(Param::SignOnly, 1);
+        } else {
+            self.param.remove(Param::SignOnly);
+        }
+        self.update_param(context).await
+    }
+
+    /// Returns true if the contact is the user on another account.
+    ///
+    /// See `Contact::set_self_alias` for details.
+    pub fn is_self_alias(&self) -> bool {
+        self.param.get_bool(Param::SelfAlias).unwrap_or_default()
+    }
+
+    /// Marks the contact as the user on another account, eg. while migrating to a new address.
+    ///
+    /// Messages from this contact in groups are not counted as fresh messages
+    /// and do not cause notifications. The UI should render them like outgoing messages,
+    /// see `Message::is_from_self_alias`.
+    pub async fn set_self_alias(&mut self, context: &Context, self_alias: bool) -> Result<()> {
+        ensure!(
+            self.id > DC_CONTACT_ID_LAST_SPECIAL,
+            "Can not mark special contact as self alias"
+        );
+        if self_alias {
+            self.param.set_int(Param::SelfAlias, 1);
+        } else {
+            self.param.remove(Param::SelfAlias);
+        }
+        self.update_param(context).await
+    }
+
+    /// Imports an OpenPGP public key for a contact not using Autocrypt.
+    ///
+    /// The key is used to encrypt messages to the contact and to check signatures of
+    /// messages from the contact, until the contact announces another key using Autocrypt.
+    /// `asc` is the ASCII-armored key.
+    ///
+    /// If the contact is verified, only the verified key can be imported,
+    /// a different key must not silently replace it.
+    pub async fn import_public_key(context: &Context, contact_id: u32, asc: &str) -> Result<()> {
+        ensure!(
+            contact_id > DC_CONTACT_ID_LAST_SPECIAL,
+            "Can not import key for special contact"
+        );
+        let mut contact = Contact::load_from_db(context, contact_id).await?;
+        let (key, _headers) = SignedPublicKey::from_asc(asc)?;
+        key.verify()?;
+        let fingerprint = key.fingerprint();
+
+        match Peerstate::from_addr(context, &contact.addr).await? {
+            Some(mut peerstate) => {
+                if let Some(verified) = &peerstate.verified_key_fingerprint {
+                    ensure!(
+                        *verified == fingerprint,
+                        "{} is verified with key {}, refusing to import key {}",
+                        contact.addr,
+                        verified.hex(),
+                        fingerprint.hex()
+                    );
+                }
+                peerstate.public_key = Some(key);
+                peerstate.public_key_fingerprint = Some(fingerprint.clone());
+                peerstate.prefer_encrypt = EncryptPreference::Mutual;
+                peerstate.to_save = Some(ToSave::All);
+                peerstate.save_to_db(&context.sql, false).await?;
+            }
+            None => {
+                Peerstate::from_pinned_key(&contact.addr, key)
+                    .save_to_db(&context.sql, true)
+                    .await?;
+            }
+        }
+        contact.param.set(Param::PinnedKey, fingerprint.hex());
+        contact.update_param(context).await?;
+
+        context.emit_event(EventType::ContactKeyImported {
+            contact_id,
+            source: KeySource::Manual,
+        });
+        Ok(())
+    }
+
+    /// Returns true if the key used for the peer is the one imported manually.
+    pub(crate) fn has_pinned_key(&self, peerstate: &Peerstate) -> bool {
+        match (
+            self.param.get(Param::PinnedKey),
+            &peerstate.public_key_fingerprint,
+        ) {
+            (Some(pinned), Some(fingerprint)) => pinned == fingerprint.hex(),
+            _ => false,
+        }
+    }
+
     /// Updates `status` column in the database.
     pub async fn update_status(&self, context: &Context) -> Result<()> {
         context
@@ -1225,6 +1390,10 @@ pub(crate) async fn set_profile_image(
     was_encrypted: bool,
 ) -> Result<()> {
     let mut contact = Contact::load_from_db(context, contact_id).await?;
+    let old_image = contact
+        .param
+        .get(Param::ProfileImage)
+        .map(|s| s.to_string());
     let changed = match profile_image {
         AvatarAction::Change(profile_image) => {
             if contact_id == DC_CONTACT_ID_SELF {
@@ -1254,6 +1423,13 @@ pub(crate) async fn set_profile_image(
         }
     };
     if changed {
+        avatar_history::save_replaced_avatar(
+            context,
+            AvatarOwner::Contact(contact_id),
+            old_image.as_deref(),
+            contact.param.get(Param::ProfileImage),
+        )
+        .await?;
         contact.update_param(context).await?;
         context.emit_event(EventType::ContactsChanged(Some(contact_id)));
     }
@@ -1908,6 +2084,63 @@ async fn test_contact_get_encrinfo() -> Result<()> {
         Ok(())
     }
 
+    #[async_std::test]
+    async fn test_import_public_key() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        let (contact_bob_id, _modified) =
+            Contact::add_or_lookup(&alice, "Bob", "bob@example.net", Origin::ManuallyCreated)
+                .await?;
+        assert!(
+            Contact::import_public_key(&alice, contact_bob_id, "not a key")
+                .await
+                .is_err()
+        );
+
+        let bob_key = crate::test_utils::bob_keypair().public;
+        Contact::import_public_key(&alice, contact_bob_id, &bob_key.to_asc(None)).await?;
+
+        let peerstate = Peerstate::from_addr(&alice, "bob@example.net")
+            .await?
+            .unwrap();
+        assert_eq!(peerstate.public_key, Some(bob_key.clone()));
+        assert_eq!(peerstate.prefer_encrypt, EncryptPreference::Mutual);
+        let contact = Contact::load_from_db(&alice, contact_bob_id).await?;
+        assert!(contact.has_pinned_key(&peerstate));
+
+        // A different key is not imported for a verified contact.
+        let mut peerstate = peerstate;
+        peerstate.verified_key = Some(bob_key.clone());
+        peerstate.verified_key_fingerprint = Some(bob_key.fingerprint());
+        peerstate.to_save = Some(ToSave::All);
+        peerstate.save_to_db(&alice.sql, false).await?;
+        let other_key = crate::test_utils::alice_keypair().public;
+        assert!(
+            Contact::import_public_key(&alice, contact_bob_id, &other_key.to_asc(None))
+                .await
+                .is_err()
+        );
+        let peerstate = Peerstate::from_addr(&alice, "bob@example.net")
+            .await?
+            .unwrap();
+        assert_eq!(peerstate.public_key, Some(bob_key.clone()));
+
+        // Importing the verified key again is fine.
+        Contact::import_public_key(&alice, contact_bob_id, &bob_key.to_asc(None)).await?;
+
+        let encrinfo = Contact::get_encrinfo(&alice, contact_bob_id).await?;
+        assert!(encrinfo.starts_with(
+            "End-to-end encryption preferred.\nThe key of this contact was imported manually.\n"
+        ));
+
+        // messages to bob are encrypted
+        let chat = alice
+            .create_chat_with_contact("Bob", "bob@example.net")
+            .await;
+        let sent = alice.send_text(chat.id, "hi").await;
+        assert!(sent.payload().contains("-----BEGIN PGP MESSAGE-----"));
+        Ok(())
+    }
+
     /// Tests that status is synchronized when sending encrypted BCC-self messages and not
     /// synchronized when the message is not encrypted.
     #[async_std::test]
@@ -2,29 +2,41 @@
 
 use std::collections::{BTreeMap, HashMap};
 use std::ffi::OsString;
+use std::future::Future;
 use std::ops::Deref;
-use std::time::{Instant, SystemTime};
+use std::sync::Weak;
+use std::time::{Duration, Instant, SystemTime};
 
-use anyhow::{bail, ensure, Result};
+use anyhow::{bail, ensure, Context as _, Result};
 use async_std::{
     channel::{self, Receiver, Sender},
     path::{Path, PathBuf},
     sync::{Arc, Mutex, RwLock},
     task,
 };
+use serde::Serialize;
 
-use crate::chat::{get_chat_cnt, ChatId};
+use crate::chat::{get_chat_cnt, Chat, ChatId};
 use crate::config::Config;
-use crate::constants::DC_VERSION_STR;
+use crate::constants::{DC_CHAT_ID_LAST_SPECIAL, DC_CONTACT_ID_SELF, DC_VERSION_STR};
 use crate::contact::Contact;
+use crate::dblock::DbLock;
 use crate::dc_tools::{duration_to_str, time};
-use crate::events::{Event, EventEmitter, EventType, Events};
+use crate::ephemeral::{self, ClockReference, EphemeralTask};
+use crate::events::{Event, EventEmitter, EventJson, EventType, Events};
+use crate::imap::{self, Imap};
+use crate::job;
 use crate::key::{DcKey, SignedPublicKey};
 use crate::login_param::LoginParam;
-use crate::message::{self, MessageState, MsgId};
+use crate::message::{self, Message, MessageState, MsgId, SUMMARY_CHARACTERS};
+use crate::network_policy::NetworkPolicy;
+use crate::param::{Param, Params};
+use crate::preview::PreviewGenerator;
+use crate::render_pool::RenderPool;
 use crate::scheduler::Scheduler;
 use crate::securejoin::Bob;
 use crate::sql::Sql;
+use crate::webhook::Webhook;
 
 #[derive(Clone, Debug)]
 pub struct Context {
@@ -56,14 +68,36 @@ pub struct InnerContext {
     pub(crate) oauth2_mutex: Mutex<()>,
     /// Mutex to prevent a race condition when a "your pw is wrong" warning is sent, resulting in multiple messeges being sent.
     pub(crate) wrong_pw_warning_mutex: Mutex<()>,
+    /// Mutex held while a bulk operation runs in maintenance mode.
+    pub(crate) maintenance_mutex: Mutex<()>,
     pub(crate) translated_stockstrings: RwLock<HashMap<usize, String>>,
     pub(crate) events: Events,
 
     pub(crate) scheduler: RwLock<Scheduler>,
-    pub(crate) ephemeral_task: RwLock<Option<task::JoinHandle<()>>>,
+    pub(crate) ephemeral_task: RwLock<Option<EphemeralTask>>,
+    /// Reference to detect changes of the system clock, see [`crate::ephemeral`].
+    pub(crate) ephemeral_clock: Mutex<Option<ClockReference>>,
+
+    /// Generator for document previews registered by the UI.
+    pub(crate) preview_generator: RwLock<Option<Arc<dyn PreviewGenerator>>>,
 
     pub(crate) last_full_folder_scan: Mutex<Option<Instant>>,
 
+    /// App state set by the UI, see [`Context::maintenance_hint`].
+    pub(crate) hint: RwLock<Hint>,
+
+    /// Network policy set by the UI, see [`crate::network_policy`].
+    pub(crate) network_policy: RwLock<NetworkPolicy>,
+
+    /// Lock of the database file, see [`crate::dblock`].
+    db_lock: DbLock,
+
+    /// Slots for rendering outgoing messages, see [`crate::render_pool`].
+    pub(crate) render_pool: RenderPool,
+
+    /// Blob files not synced to disk yet, see [`crate::blob::BlobSync`].
+    pub(crate) blob_sync_queue: Mutex<Vec<PathBuf>>,
+
     /// ID for this `Context` in the current process.
     ///
     /// This allows for multiple `Context`s open in a single process where each context can
@@ -73,6 +107,58 @@ pub struct InnerContext {
     creation_time: SystemTime,
 }
 
+/// State of the app passed to [`Context::maintenance_hint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum Hint {
+    /// The app is in the foreground, this is the default.
+    AppForegrounded = 0,
+
+    /// The app is in the background.
+    AppBackgrounded = 1,
+
+    /// The device is in a power saving mode, eg. doze mode or low battery.
+    LowPower = 2,
+}
+
+impl Default for Hint {
+    fn default() -> Self {
+        Hint::AppForegrounded
+    }
+}
+
+impl Hint {
+    /// Time after which IMAP IDLE is restarted to keep the connection alive.
+    pub(crate) fn idle_timeout(self) -> Duration {
+        match self {
+            Hint::AppForegrounded => Duration::from_secs(23 * 60),
+            Hint::AppBackgrounded | Hint::LowPower => Duration::from_secs(28 * 60),
+        }
+    }
+
+    /// Interval in which folders are polled if the server does not support IDLE.
+    pub(crate) fn fake_idle_interval(self) -> Duration {
+        match self {
+            Hint::AppForegrounded => Duration::from_secs(60),
+            Hint::AppBackgrounded => Duration::from_secs(5 * 60),
+            Hint::LowPower => Duration::from_secs(15 * 60),
+        }
+    }
+
+    /// Number of IMAP jobs executed in a row before fetching new messages in between.
+    pub(crate) fn job_batch_size(self) -> usize {
+        match self {
+            Hint::AppForegrounded => 20,
+            Hint::AppBackgrounded | Hint::LowPower => 100,
+        }
+    }
+
+    /// Whether housekeeping may run now; it is postponed while in power saving mode.
+    pub(crate) fn allows_housekeeping(self) -> bool {
+        self != Hint::LowPower
+    }
+}
+
 #[derive(Debug)]
 pub struct RunningState {
     pub ongoing_running: bool,
@@ -98,7 +184,27 @@ pub fn get_info() -> BTreeMap<&'static str, String> {
 
 impl Context {
     /// Creates new context.
+    ///
+    /// Fails with [`DbLockError::AlreadyInUse`](crate::dblock::DbLockError::AlreadyInUse)
+    /// if the database is in use by another context.
     pub async fn new(os_name: String, dbfile: PathBuf, id: u32) -> Result<Context> {
+        Context::new_inner(os_name, dbfile, id, false).await
+    }
+
+    /// Creates new context, taking over the database if it is in use by another context.
+    ///
+    /// The other context stops IO and cannot start it again until this context is dropped,
+    /// see [`Context::is_db_owner`] and [`EventType::DbOwnerChanged`].
+    pub async fn new_with_takeover(os_name: String, dbfile: PathBuf, id: u32) -> Result<Context> {
+        Context::new_inner(os_name, dbfile, id, true).await
+    }
+
+    async fn new_inner(
+        os_name: String,
+        dbfile: PathBuf,
+        id: u32,
+        takeover: bool,
+    ) -> Result<Context> {
         // pretty_env_logger::try_init_timed().ok();
 
         let mut blob_fname = OsString::new();
@@ -108,7 +214,7 @@ pub async fn new(os_name: String, dbfile: PathBuf, id: u32) -> Result<Context> {
         if !blobdir.exists().await {
             async_std::fs::create_dir_all(&blobdir).await?;
         }
-        Context::with_blobdir(os_name, dbfile, blobdir, id).await
+        Context::open(os_name, dbfile, blobdir, id, takeover).await
     }
 
     pub(crate) async fn with_blobdir(
@@ -116,12 +222,23 @@ pub(crate) async fn with_blobdir(
         dbfile: PathBuf,
         blobdir: PathBuf,
         id: u32,
+    ) -> Result<Context> {
+        Context::open(os_name, dbfile, blobdir, id, false).await
+    }
+
+    async fn open(
+        os_name: String,
+        dbfile: PathBuf,
+        blobdir: PathBuf,
+        id: u32,
+        takeover: bool,
     ) -> Result<Context> {
         ensure!(
             blobdir.is_dir().await,
             "Blobdir does not exist: {}",
             blobdir.display()
         );
+        let db_lock = DbLock::acquire(&dbfile, takeover).await?;
 
         let inner = InnerContext {
             id,
@@ -135,29 +252,66 @@ pub(crate) async fn with_blobdir(
             generating_key_mutex: Mutex::new(()),
             oauth2_mutex: Mutex::new(()),
             wrong_pw_warning_mutex: Mutex::new(()),
+            maintenance_mutex: Mutex::new(()),
             translated_stockstrings: RwLock::new(HashMap::new()),
             events: Events::default(),
             scheduler: RwLock::new(Scheduler::Stopped),
             ephemeral_task: RwLock::new(None),
+            ephemeral_clock: Mutex::new(None),
+            preview_generator: RwLock::new(None),
             creation_time: std::time::SystemTime::now(),
             last_full_folder_scan: Mutex::new(None),
+            hint: RwLock::new(Hint::default()),
+            network_policy: RwLock::new(NetworkPolicy::default()),
+            db_lock,
+            render_pool: RenderPool::default(),
+            blob_sync_queue: Mutex::new(Vec::new()),
         };
 
         let ctx = Context {
             inner: Arc::new(inner),
         };
         ctx.sql.open(&ctx, &ctx.dbfile, false).await?;
+        // The owner token was stored in the database by older versions.
+        ctx.sql.set_raw_config("db_owner_token", None).await?;
+        ephemeral::start_ephemeral_task(&ctx).await;
+        task::spawn(watch_db_owner(
+            Arc::downgrade(&ctx.inner),
+            ctx.db_lock.owner_changes(),
+        ));
 
         Ok(ctx)
     }
 
+    /// Returns false if the database has been taken over by another context,
+    /// see [`Context::new_with_takeover`].
+    pub async fn is_db_owner(&self) -> Result<bool> {
+        Ok(self.db_lock.is_owner().await)
+    }
+
     /// Starts the IO scheduler.
+    ///
+    /// IO is not started if the database has been taken over by another context.
     pub async fn start_io(&self) {
         info!(self, "starting IO");
         if self.inner.is_io_running().await {
             info!(self, "IO is already running");
             return;
         }
+        match self.is_db_owner().await {
+            Ok(true) => {}
+            Ok(false) => {
+                warn!(
+                    self,
+                    "Not starting IO, database is in use by another context"
+                );
+                return;
+            }
+            Err(err) => {
+                error!(self, "Failed to check database owner: {}", err);
+                return;
+            }
+        }
 
         {
             let l = &mut *self.inner.scheduler.write().await;
@@ -174,6 +328,58 @@ pub async fn stop_io(&self) {
         self.inner.stop_io().await;
     }
 
+    /// Tells the scheduler about the state of the app.
+    ///
+    /// Instead of stopping and starting IO when the app goes to the background,
+    /// UIs should pass this hint. The scheduler then adjusts IDLE keepalive and
+    /// polling intervals, executes more jobs in a row and postpones housekeeping
+    /// while in power saving mode. When the app comes to the foreground,
+    /// new messages are fetched immediately.
+    pub async fn maintenance_hint(&self, hint: Hint) {
+        let old_hint = std::mem::replace(&mut *self.hint.write().await, hint);
+        info!(self, "maintenance hint {:?}", hint);
+        if old_hint != hint && hint == Hint::AppForegrounded {
+            self.maybe_network().await;
+        }
+    }
+
+    /// Returns the hint passed to [`Context::maintenance_hint`].
+    pub(crate) async fn get_hint(&self) -> Hint {
+        *self.hint.read().await
+    }
+
+    /// Runs a heavy bulk operation in maintenance mode.
+    ///
+    /// IO and the ephemeral message task are paused while `f` runs, so the operation
+    /// does not compete with fetching and sending for the database. Events are not
+    /// coalesced during maintenance, see `Context::set_event_coalescing`. Only one maintenance
+    /// operation runs at a time, calls must not be nested. Afterwards IO is started
+    /// again if it was running before, also if `f` fails.
+    ///
+    /// Must not be called from the IO tasks, e.g. from jobs.
+    pub async fn with_maintenance<F, Fut, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(Context) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let _guard = self.maintenance_mutex.lock().await;
+        let io_was_running = self.inner.is_io_running().await;
+
+        info!(self, "entering maintenance mode");
+        self.inner.stop_io().await;
+        ephemeral::stop_ephemeral_task(self).await;
+        self.events.set_coalescing_enabled(false);
+        let res = f(self.clone()).await;
+        self.events.set_coalescing_enabled(true);
+        info!(self, "leaving maintenance mode");
+
+        if io_was_running {
+            self.start_io().await;
+        }
+        ephemeral::start_ephemeral_task(self).await;
+        res
+    }
+
     /// Returns a reference to the underlying SQL instance.
     ///
     /// Warning: this is only here for testing, not part of the public API.
@@ -200,6 +406,44 @@ pub fn emit_event(&self, event: EventType) {
         });
     }
 
+    /// Sets the interval in which events of the given type are coalesced.
+    ///
+    /// Events of this type are then emitted at most once per interval and chat
+    /// or contact, events in between are merged into a single one emitted later.
+    /// A zero interval disables coalescing, which is the default.
+    ///
+    /// Only `MsgsChanged`, `MsgsNoticed`, `ChatModified`, `ContactsChanged` and
+    /// `ConnectivityChanged` events can be coalesced.
+    pub fn set_event_coalescing(&self, event_id: i32, interval: Duration) -> Result<()> {
+        self.events.set_coalescing(event_id, interval)
+    }
+
+    /// Delivers all events emitted by the context also to the given webhook,
+    /// see [`crate::webhook`] for details. `None` disables the webhook.
+    pub fn set_event_webhook(&self, webhook: Option<Webhook>) {
+        self.events.set_webhook(webhook);
+    }
+
+    /// Waits up to `timeout` for the next event and returns it serialized as JSON.
+    ///
+    /// This is meant for bindings which cannot hold an [`EventEmitter`], eg. across an FFI
+    /// boundary. The JSON object contains the `context_id`, the `event_id` as used in the
+    /// `DC_EVENT_*` constants and the serialized [`EventType`] as `event`. Events are
+    /// delivered here in addition to the event emitters, starting with the first call.
+    ///
+    /// Returns `None` on timeout or if interrupted by [`Context::interrupt_event_polling`].
+    pub async fn get_next_event(&self, timeout: Duration) -> Option<String> {
+        let event = self.events.poll(timeout).await?;
+        serde_json::to_string(&EventJson::from(&event)).ok()
+    }
+
+    /// Makes a pending [`Context::get_next_event`] return `None` immediately.
+    ///
+    /// If no call is pending, the next call returns immediately.
+    pub fn interrupt_event_polling(&self) {
+        self.events.interrupt_polling();
+    }
+
     /// Returns a receiver for emitted events.
     ///
     /// Multiple emitters can be created, but note that in this case each emitted event will
@@ -350,6 +594,12 @@ pub async fn get_info(&self) -> Result<BTreeMap<&'static str, String>> {
                 .await?
                 .unwrap_or_else(|| "<unset>".to_string()),
         );
+        res.insert(
+            "self_color",
+            self.get_config(Config::SelfColor)
+                .await?
+                .unwrap_or_else(|| "<unset>".to_string()),
+        );
         res.insert("is_configured", is_configured.to_string());
         res.insert("entered_account_settings", l.to_string());
         res.insert("used_account_settings", l2.to_string());
@@ -391,18 +641,140 @@ pub async fn get_info(&self) -> Result<BTreeMap<&'static str, String>> {
             "media_quality",
             self.get_config_int(Config::MediaQuality).await?.to_string(),
         );
+        res.insert(
+            "passthrough_attachments",
+            self.get_config_bool(Config::PassthroughAttachments)
+                .await?
+                .to_string(),
+        );
+        res.insert(
+            "blob_sync",
+            self.get_config_int(Config::BlobSync).await?.to_string(),
+        );
+        res.insert(
+            "max_text_length",
+            self.get_config_int(Config::MaxTextLength)
+                .await?
+                .to_string(),
+        );
         res.insert(
             "delete_device_after",
             self.get_config_int(Config::DeleteDeviceAfter)
                 .await?
                 .to_string(),
         );
+        res.insert(
+            "delete_device_chat_after",
+            self.get_config_int(Config::DeleteDeviceChatAfter)
+                .await?
+                .to_string(),
+        );
+        res.insert(
+            "delete_device_after_includes_self_chat",
+            self.get_config_bool(Config::DeleteDeviceAfterIncludesSelfChat)
+                .await?
+                .to_string(),
+        );
+        res.insert(
+            "protect_starred_from_deletion",
+            self.get_config_bool(Config::ProtectStarredFromDeletion)
+                .await?
+                .to_string(),
+        );
+        res.insert(
+            "ephemeral_grace_period",
+            self.get_config_int(Config::EphemeralGracePeriod)
+                .await?
+                .to_string(),
+        );
+        res.insert(
+            "undelivered_grace_period",
+            self.get_config_int(Config::UndeliveredGracePeriod)
+                .await?
+                .to_string(),
+        );
+        res.insert(
+            "sync_device_settings",
+            self.get_config_bool(Config::SyncDeviceSettings)
+                .await?
+                .to_string(),
+        );
+        res.insert(
+            "slow_query_threshold",
+            self.get_config_int(Config::SlowQueryThreshold)
+                .await?
+                .to_string(),
+        );
+        res.insert(
+            "delete_info_msgs_after",
+            self.get_config_int(Config::DeleteInfoMsgsAfter)
+                .await?
+                .to_string(),
+        );
+        res.insert(
+            "group_rekeying",
+            self.get_config_bool(Config::GroupRekeying)
+                .await?
+                .to_string(),
+        );
+        res.insert(
+            "quiet_hours_start",
+            self.get_config_int(Config::QuietHoursStart)
+                .await?
+                .to_string(),
+        );
+        res.insert(
+            "quiet_hours_end",
+            self.get_config_int(Config::QuietHoursEnd)
+                .await?
+                .to_string(),
+        );
+        res.insert(
+            "omit_headers",
+            self.get_config(Config::OmitHeaders)
+                .await?
+                .unwrap_or_default(),
+        );
+        res.insert(
+            "coarse_date",
+            self.get_config_bool(Config::CoarseDate).await?.to_string(),
+        );
+        res.insert(
+            "auto_backup_interval",
+            self.get_config_int(Config::AutoBackupInterval)
+                .await?
+                .to_string(),
+        );
+        res.insert(
+            "auto_backup_keep",
+            self.get_config_int(Config::AutoBackupKeep)
+                .await?
+                .to_string(),
+        );
+        res.insert(
+            "last_auto_backup",
+            self.get_config_int(Config::LastAutoBackup)
+                .await?
+                .to_string(),
+        );
         res.insert(
             "delete_server_after",
             self.get_config_int(Config::DeleteServerAfter)
                 .await?
                 .to_string(),
         );
+        res.insert(
+            "delete_server_after_self",
+            self.get_config_int(Config::DeleteServerAfterSelf)
+                .await?
+                .to_string(),
+        );
+        res.insert(
+            "delete_server_only_seen",
+            self.get_config_bool(Config::DeleteServerOnlySeen)
+                .await?
+                .to_string(),
+        );
         res.insert(
             "last_housekeeping",
             self.get_config_int(Config::LastHousekeeping)
@@ -461,6 +833,23 @@ pub async fn get_fresh_msgs(&self) -> Result<Vec<MsgId>> {
         Ok(list)
     }
 
+    /// Searches messages on the server that are no longer stored on the device,
+    /// eg. because of `Config::DeleteDeviceAfter`, and downloads them again.
+    ///
+    /// The DeltaChat and Inbox folders are searched in the background,
+    /// `EventType::MsgsChanged` is emitted when the search is done;
+    /// the downloaded messages can then be found using `Context::search_msgs`.
+    /// The messages are deleted from the device again after `Config::DeleteDeviceAfter`.
+    pub async fn search_server(&self, query: &str) -> Result<()> {
+        let query = query.trim();
+        ensure!(!query.is_empty(), "Search query is empty");
+
+        let mut param = Params::new();
+        param.set(Param::Arg, imap::build_text_search(query)?);
+        job::add(self, job::Job::new(job::Action::SearchServer, 0, param, 0)).await;
+        Ok(())
+    }
+
     /// Searches for messages containing the query string.
     ///
     /// If `chat_id` is provided this searches only for messages in this chat, if `chat_id`
@@ -496,9 +885,10 @@ pub async fn search_msgs(&self, chat_id: Option<ChatId>, query: &str) -> Result<
                  WHERE m.chat_id=?
                    AND m.hidden=0
                    AND ct.blocked=0
-                   AND txt LIKE ?
+                   AND (txt LIKE ?
+                        OR m.id IN (SELECT msg_id FROM msgs_notes WHERE note LIKE ?))
                  ORDER BY m.timestamp,m.id;",
-                paramsv![chat_id, str_like_in_text],
+                paramsv![chat_id, str_like_in_text, str_like_in_text],
             )
             .await?
         } else {
@@ -523,9 +913,10 @@ pub async fn search_msgs(&self, chat_id: Option<ChatId>, query: &str) -> Result<
                    AND m.hidden=0
                    AND c.blocked=0
                    AND ct.blocked=0
-                   AND m.txt LIKE ?
+                   AND (m.txt LIKE ?
+                        OR m.id IN (SELECT msg_id FROM msgs_notes WHERE note LIKE ?))
                  ORDER BY m.id DESC LIMIT 1000",
-                paramsv![str_like_in_text],
+                paramsv![str_like_in_text, str_like_in_text],
             )
             .await?
         };
@@ -568,6 +959,88 @@ pub fn derive_walfile(dbfile: &PathBuf) -> PathBuf {
     }
 }
 
+/// Maximum time [`fetch_one_notification`] may take.
+const NOTIFICATION_FETCH_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Maximum size in bytes of a message downloaded by [`fetch_one_notification`].
+const NOTIFICATION_MAX_MSG_SIZE: u32 = 512 * 1024;
+
+/// Information needed to show a notification for a new message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NotificationPayload {
+    pub chat_id: ChatId,
+    pub chat_name: String,
+    pub msg_id: MsgId,
+
+    /// Display name of the sender.
+    pub sender: String,
+
+    /// Summary of the message text.
+    pub snippet: String,
+}
+
+/// Downloads a single new message and returns what is needed to show a notification for it.
+///
+/// This is meant for notification extensions, eg. on iOS, which run in a separate
+/// process with a tight time and memory budget: the scheduler is not started, only the
+/// message with the UID `uid_hint` or otherwise the newest new message in `folder` is
+/// downloaded and received as usual, so the main app does not download it again.
+/// Messages larger than 512 KiB are not downloaded and the whole operation is cancelled
+/// after 20 seconds.
+///
+/// Returns `None` if there is no new message or it should not be notified about,
+/// eg. because it was sent by ourselves or to a muted chat.
+pub async fn fetch_one_notification(
+    context: &Context,
+    folder: &str,
+    uid_hint: Option<u32>,
+) -> Result<Option<NotificationPayload>> {
+    let (_interrupt_sender, interrupt_receiver) = channel::bounded(1);
+    let mut imap = Imap::new_configured(context, interrupt_receiver).await?;
+    let res = async_std::future::timeout(
+        NOTIFICATION_FETCH_TIMEOUT,
+        imap.fetch_single_msg(context, folder, uid_hint, NOTIFICATION_MAX_MSG_SIZE),
+    )
+    .await;
+    imap.disconnect(context).await;
+    let uid = match res.context("timeout fetching message for notification")?? {
+        Some(uid) => uid,
+        None => return Ok(None),
+    };
+
+    let msg_id: Option<MsgId> = context
+        .sql
+        .query_get_value(
+            "SELECT id FROM msgs
+             WHERE server_folder=? AND server_uid=? AND chat_id>? AND hidden=0 AND from_id!=?;",
+            paramsv![folder, uid, DC_CHAT_ID_LAST_SPECIAL, DC_CONTACT_ID_SELF],
+        )
+        .await?;
+    let msg_id = match msg_id {
+        Some(msg_id) => msg_id,
+        None => return Ok(None),
+    };
+    let msg = Message::load_from_db(context, msg_id).await?;
+    let chat = Chat::load_from_db(context, msg.chat_id).await?;
+    if chat.is_muted() {
+        return Ok(None);
+    }
+    let sender = match msg.get_override_sender_name() {
+        Some(name) => name,
+        None => Contact::load_from_db(context, msg.from_id)
+            .await?
+            .get_display_name()
+            .to_string(),
+    };
+    Ok(Some(NotificationPayload {
+        chat_id: chat.id,
+        chat_name: chat.get_name().to_string(),
+        msg_id,
+        sender,
+        snippet: msg.get_summarytext(context, SUMMARY_CHARACTERS).await,
+    }))
+}
+
 impl InnerContext {
     async fn is_io_running(&self) -> bool {
         self.scheduler.read().await.is_running()
@@ -584,10 +1057,32 @@ async fn stop_io(&self) {
                 lock.stop(token).await;
             }
         }
+    }
+}
 
-        if let Some(ephemeral_task) = self.ephemeral_task.write().await.take() {
-            ephemeral_task.cancel().await;
+/// Stops IO when the database is taken over by another context
+/// and emits [`EventType::DbOwnerChanged`] on ownership changes.
+///
+/// Only holds a weak reference, so the context can be dropped meanwhile,
+/// which also closes `owner_changes`.
+async fn watch_db_owner(inner: Weak<InnerContext>, owner_changes: Receiver<bool>) {
+    while let Ok(owner) = owner_changes.recv().await {
+        let context = match inner.upgrade() {
+            Some(inner) => Context { inner },
+            None => break,
+        };
+        if owner {
+            info!(context, "Database lock acquired again");
+            ephemeral::start_ephemeral_task(&context).await;
+        } else {
+            warn!(
+                context,
+                "Database was taken over by another context, stopping IO"
+            );
+            context.stop_io().await;
+            ephemeral::stop_ephemeral_task(&context).await;
         }
+        context.emit_event(EventType::DbOwnerChanged(owner));
     }
 }
 
@@ -783,6 +1278,18 @@ async fn test_blobdir_exists() {
         assert!(blobdir.is_dir());
     }
 
+    #[async_std::test]
+    async fn test_ephemeral_task_started_on_open() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let dbfile = tmp.path().join("db.sqlite");
+        let context = Context::new("FakeOS".into(), dbfile.into(), 1).await?;
+        assert!(context.ephemeral_task.read().await.is_some());
+
+        context.with_maintenance(|_| async { Ok(()) }).await?;
+        assert!(context.ephemeral_task.read().await.is_some());
+        Ok(())
+    }
+
     #[async_std::test]
     async fn test_wrong_blogdir() {
         let tmp = tempfile::tempdir().unwrap();
@@ -806,6 +1313,35 @@ async fn test_sqlite_parent_not_exists() {
         assert!(dbfile2.is_file());
     }
 
+    #[async_std::test]
+    async fn test_fetch_one_notification_unconfigured() {
+        let t = TestContext::new().await;
+        assert!(fetch_one_notification(&t, "INBOX", None).await.is_err());
+    }
+
+    #[async_std::test]
+    async fn test_db_takeover() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let dbfile: PathBuf = tmp.path().join("db.sqlite").into();
+        let ctx = Context::new("FakeOS".into(), dbfile.clone(), 1).await?;
+        assert!(ctx.is_db_owner().await?);
+
+        let err = Context::new("FakeOS".into(), dbfile.clone(), 2)
+            .await
+            .unwrap_err();
+        assert!(err.downcast_ref::<crate::dblock::DbLockError>().is_some());
+
+        let ctx2 = Context::new_with_takeover("FakeOS".into(), dbfile.clone(), 2).await?;
+        assert!(ctx2.is_db_owner().await?);
+        assert!(!ctx.is_db_owner().await?);
+        ctx.start_io().await;
+        assert!(!ctx.is_io_running().await);
+
+        drop(ctx2);
+        Context::new("FakeOS".into(), dbfile, 3).await?;
+        Ok(())
+    }
+
     #[async_std::test]
     async fn test_with_empty_blobdir() {
         let tmp = tempfile::tempdir().unwrap();
@@ -978,4 +1514,40 @@ async fn test_limit_search_msgs() -> Result<()> {
 
         Ok(())
     }
+
+    #[async_std::test]
+    async fn test_with_maintenance() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let id = t
+            .with_maintenance(|ctx| async move { Ok(ctx.get_id()) })
+            .await?;
+        assert_eq!(id, t.get_id());
+
+        let res = t
+            .with_maintenance(|_| async { Err::<(), _>(anyhow::format_err!("failed")) })
+            .await;
+        assert!(res.is_err());
+
+        // Maintenance mode is left also on errors.
+        assert!(t.maintenance_mutex.try_lock().is_some());
+        assert!(!t.inner.is_io_running().await);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_maintenance_hint() {
+        let t = TestContext::new().await;
+        assert_eq!(t.get_hint().await, Hint::AppForegrounded);
+        assert!(t.get_hint().await.allows_housekeeping());
+
+        t.maintenance_hint(Hint::LowPower).await;
+        let hint = t.get_hint().await;
+        assert_eq!(hint, Hint::LowPower);
+        assert!(!hint.allows_housekeeping());
+        assert!(hint.fake_idle_interval() > Hint::AppForegrounded.fake_idle_interval());
+        assert!(hint.idle_timeout() < Duration::from_secs(29 * 60));
+
+        t.maintenance_hint(Hint::AppForegrounded).await;
+        assert_eq!(t.get_hint().await, Hint::AppForegrounded);
+    }
 }
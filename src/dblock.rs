@@ -0,0 +1,320 @@
+//! # Database lock
+//!
+//! Opening the same database from two contexts, eg. from the main app and a
+//! notification extension process, leads to undefined behaviour as both would fetch
+//! and send messages. Each context therefore writes an advisory lock file next to the
+//! database containing a random owner token, its process ID and a timestamp which is
+//! refreshed periodically. Another context refuses to open the database with
+//! [`DbLockError::AlreadyInUse`] while the lock is fresh, unless it takes the database
+//! over. The lock file is created exclusively, so two contexts cannot both create it,
+//! and the previous owner notices that the database has been taken over when the lock
+//! file does not contain its token anymore. Once the lock file is removed or stale again,
+//! eg. because the context which took the database over exited, the previous owner
+//! acquires the lock again. Changes of the ownership are reported through
+//! [`DbLock::owner_changes`].
+
+use std::time::Duration;
+
+use anyhow::Result;
+use async_std::channel::{self, Receiver, Sender};
+use async_std::fs::{self, File, OpenOptions};
+use async_std::io::{self, SeekFrom};
+use async_std::path::{Path, PathBuf};
+use async_std::prelude::*;
+use async_std::task;
+
+use crate::dc_tools::{dc_create_id, time};
+
+/// Interval in which the owner refreshes the lock file.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Time in seconds after which a lock which is not refreshed anymore is considered stale,
+/// eg. because the owning process crashed.
+const STALE_AFTER: i64 = 30;
+
+/// Number of attempts to create the lock file after removing a stale one.
+const CREATE_ATTEMPTS: usize = 3;
+
+/// Error returned when opening a database which is in use by another context.
+#[derive(Debug, thiserror::Error)]
+pub enum DbLockError {
+    #[error("Database is already in use by process {pid}")]
+    AlreadyInUse { pid: u32 },
+}
+
+/// Contents of a lock file.
+#[derive(Debug)]
+struct LockInfo {
+    token: String,
+    pid: u32,
+    timestamp: i64,
+}
+
+/// Lock of a database held by a context.
+///
+/// The lock file is removed when the lock is dropped, unless the database
+/// has been taken over by another context meanwhile.
+#[derive(Debug)]
+pub(crate) struct DbLock {
+    path: PathBuf,
+    token: String,
+
+    /// Receives `false` when the database is taken over and `true` when it is owned again.
+    owner_changes: Receiver<bool>,
+}
+
+impl DbLock {
+    /// Locks the database `dbfile`.
+    ///
+    /// Fails with [`DbLockError::AlreadyInUse`] if the database is locked by another
+    /// context, unless `takeover` is set.
+    pub(crate) async fn acquire(dbfile: &Path, takeover: bool) -> Result<Self> {
+        let mut path = dbfile.as_os_str().to_owned();
+        path.push(".lock");
+        let path = PathBuf::from(path);
+
+        let token = dc_create_id();
+        let mut attempts = 0;
+        let file = loop {
+            match create_lock(&path, &token).await {
+                Ok(file) => break file,
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    attempts += 1;
+                    if !takeover {
+                        if let Some(pid) = fresh_lock_owner(&path).await {
+                            return Err(DbLockError::AlreadyInUse { pid }.into());
+                        }
+                    }
+                    if attempts >= CREATE_ATTEMPTS {
+                        return Err(err.into());
+                    }
+                    // The lock is stale or taken over. If another context creates a new
+                    // lock in between, it notices that it lost the lock on the next check.
+                    fs::remove_file(&path).await.ok();
+                }
+                Err(err) => return Err(err.into()),
+            }
+        };
+
+        let (sender, owner_changes) = channel::unbounded();
+        task::spawn(refresh_lock(path.clone(), token.clone(), file, sender));
+        Ok(Self {
+            path,
+            token,
+            owner_changes,
+        })
+    }
+
+    /// Returns the owner token of the lock.
+    #[cfg(test)]
+    pub(crate) fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Returns false if the database has been taken over by another context.
+    pub(crate) async fn is_owner(&self) -> bool {
+        read_lock(&self.path)
+            .await
+            .map_or(false, |info| info.token == self.token)
+    }
+
+    /// Returns a receiver of ownership changes, `false` when the database has been
+    /// taken over by another context and `true` when the lock is acquired again.
+    ///
+    /// The channel is closed when the lock is dropped.
+    pub(crate) fn owner_changes(&self) -> Receiver<bool> {
+        self.owner_changes.clone()
+    }
+}
+
+impl Drop for DbLock {
+    fn drop(&mut self) {
+        // Stop refreshing first, so the lock file is not created again.
+        self.owner_changes.close();
+        let owned = std::fs::read_to_string(&self.path)
+            .ok()
+            .and_then(|content| parse_lock(&content))
+            .map_or(false, |info| info.token == self.token);
+        if owned {
+            std::fs::remove_file(&self.path).ok();
+        }
+    }
+}
+
+fn parse_lock(content: &str) -> Option<LockInfo> {
+    let mut parts = content.split_whitespace();
+    let token = parts.next()?.to_string();
+    let pid = parts.next()?.parse().ok()?;
+    let timestamp = parts.next()?.parse().ok()?;
+    Some(LockInfo {
+        token,
+        pid,
+        timestamp,
+    })
+}
+
+async fn read_lock(path: &Path) -> Option<LockInfo> {
+    let content = fs::read_to_string(path).await.ok()?;
+    parse_lock(&content)
+}
+
+fn lock_content(token: &str) -> String {
+    format!("{} {} {}\n", token, std::process::id(), time())
+}
+
+/// Creates the lock file, failing if it already exists.
+async fn create_lock(path: &Path, token: &str) -> io::Result<File> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .await?;
+    file.write_all(lock_content(token).as_bytes()).await?;
+    file.sync_all().await?;
+    Ok(file)
+}
+
+/// Rewrites the lock file through the handle it was created with,
+/// so a lock file created by another context in the meantime is never overwritten.
+async fn write_lock(file: &mut File, token: &str) -> io::Result<()> {
+    let content = lock_content(token);
+    file.seek(SeekFrom::Start(0)).await?;
+    file.write_all(content.as_bytes()).await?;
+    file.set_len(content.len() as u64).await?;
+    file.sync_all().await
+}
+
+/// Returns the process ID of the owner if the lock file at `path` is fresh.
+///
+/// A lock file which cannot be parsed may still be being written by its creator,
+/// so it is only considered stale after [`STALE_AFTER`] seconds; its owner is unknown.
+async fn fresh_lock_owner(path: &Path) -> Option<u32> {
+    match read_lock(path).await {
+        Some(info) => {
+            if time() - info.timestamp < STALE_AFTER {
+                Some(info.pid)
+            } else {
+                None
+            }
+        }
+        None => {
+            let age = fs::metadata(path)
+                .await
+                .ok()?
+                .modified()
+                .ok()?
+                .elapsed()
+                .unwrap_or_default();
+            if age.as_secs() < STALE_AFTER as u64 {
+                Some(0)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// Refreshes the lock file until the lock is dropped.
+///
+/// If the database has been taken over, the lock is acquired again
+/// as soon as the lock file of the other context is removed or stale.
+/// Ownership changes are sent to `owner_changes`.
+async fn refresh_lock(path: PathBuf, token: String, file: File, owner_changes: Sender<bool>) {
+    let mut file = Some(file);
+    let mut owner = true;
+    loop {
+        task::sleep(REFRESH_INTERVAL).await;
+        if owner_changes.is_closed() {
+            break;
+        }
+        let is_owner = check_lock(&path, &token, &mut file).await;
+        if is_owner != owner {
+            owner = is_owner;
+            if owner_changes.send(owner).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// Refreshes the lock file if the lock is owned, or acquires the lock again
+/// if the lock file is missing or stale. Returns true if the lock is owned.
+async fn check_lock(path: &Path, token: &str, file: &mut Option<File>) -> bool {
+    match read_lock(path).await {
+        Some(info) if info.token == token => match file.as_mut() {
+            Some(file) => write_lock(file, token).await.is_ok(),
+            None => false,
+        },
+        _ => {
+            if fresh_lock_owner(path).await.is_some() {
+                return false;
+            }
+            fs::remove_file(path).await.ok();
+            match create_lock(path, token).await {
+                Ok(new_file) => {
+                    *file = Some(new_file);
+                    true
+                }
+                Err(_) => false,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[async_std::test]
+    async fn test_db_lock() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dbfile: PathBuf = dir.path().join("db.sqlite").into();
+        let lockfile: PathBuf = dir.path().join("db.sqlite.lock").into();
+
+        let lock = DbLock::acquire(&dbfile, false).await?;
+        assert!(lockfile.exists().await);
+        let err = DbLock::acquire(&dbfile, false).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<DbLockError>(),
+            Some(DbLockError::AlreadyInUse { .. })
+        ));
+
+        let takeover = DbLock::acquire(&dbfile, true).await?;
+        assert_ne!(lock.token(), takeover.token());
+        assert!(takeover.is_owner().await);
+        assert!(!lock.is_owner().await);
+        drop(lock);
+        assert!(lockfile.exists().await);
+        drop(takeover);
+        assert!(!lockfile.exists().await);
+
+        // A lock file which is still being written is in use.
+        fs::write(&lockfile, "").await?;
+        assert!(DbLock::acquire(&dbfile, false).await.is_err());
+
+        // Stale locks are ignored.
+        fs::write(&lockfile, format!("stale 1 {}", time() - STALE_AFTER)).await?;
+        let lock = DbLock::acquire(&dbfile, false).await?;
+        assert!(lock.is_owner().await);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_db_lock_reacquire() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dbfile: PathBuf = dir.path().join("db.sqlite").into();
+
+        let lock = DbLock::acquire(&dbfile, false).await?;
+        let takeover = DbLock::acquire(&dbfile, true).await?;
+        let mut file = None;
+        assert!(!check_lock(&lock.path, &lock.token, &mut file).await);
+        assert!(!lock.is_owner().await);
+
+        // The lock is acquired again once the other context exits.
+        drop(takeover);
+        assert!(check_lock(&lock.path, &lock.token, &mut file).await);
+        assert!(lock.is_owner().await);
+        assert!(check_lock(&lock.path, &lock.token, &mut file).await);
+        Ok(())
+    }
+}
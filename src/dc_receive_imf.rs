@@ -8,21 +8,28 @@
 use regex::Regex;
 use sha2::{Digest, Sha256};
 
+use crate::avatar_history::{self, AvatarOwner};
+use crate::blob::BlobObject;
 use crate::chat::{self, Chat, ChatId, ChatIdBlocked, ProtectionStatus};
 use crate::config::Config;
 use crate::constants::{
-    Blocked, Chattype, ShowEmails, Viewtype, DC_CHAT_ID_TRASH, DC_CONTACT_ID_LAST_SPECIAL,
-    DC_CONTACT_ID_SELF,
+    Blocked, Chattype, ShowEmails, Viewtype, DC_CHAT_ID_LAST_SPECIAL, DC_CHAT_ID_TRASH,
+    DC_CONTACT_ID_LAST_SPECIAL, DC_CONTACT_ID_SELF,
 };
 use crate::contact::{addr_cmp, normalize_name, Contact, Origin, VerifiedStatus};
 use crate::context::Context;
 use crate::dc_tools::{
-    dc_create_smeared_timestamp, dc_extract_grpid_from_rfc724_mid, dc_smeared_time, time,
+    dc_create_smeared_timestamp, dc_extract_grpid_from_rfc724_mid, dc_smeared_time, dc_truncate,
+    time,
+};
+use crate::deletion;
+use crate::ephemeral::{
+    self, stock_ephemeral_timer_changed, Timer as EphemeralTimer, TimerStart as EphemeralTimerStart,
 };
-use crate::ephemeral::{stock_ephemeral_timer_changed, Timer as EphemeralTimer};
 use crate::events::EventType;
 use crate::headerdef::{HeaderDef, HeaderDefMap};
 use crate::job::{self, Action};
+use crate::keyserver;
 use crate::log::LogExt;
 use crate::message::{self, rfc724_mid_exists, Message, MessageState, MessengerMessage, MsgId};
 use crate::mimeparser::{
@@ -31,6 +38,7 @@
 use crate::param::{Param, Params};
 use crate::peerstate::{Peerstate, PeerstateKeyType, PeerstateVerifiedStatus};
 use crate::securejoin::{self, handle_securejoin_handshake, observe_securejoin_on_other_device};
+use crate::stats;
 use crate::stock_str;
 use crate::{contact, location};
 
@@ -146,6 +154,11 @@ pub(crate) async fn dc_receive_imf_inner(
         from_field_to_contact_id(context, &mime_parser.from, prevent_rename).await?;
 
     let incoming = from_id != DC_CONTACT_ID_SELF;
+    if incoming {
+        keyserver::handle_verification_mail(context, &mime_parser)
+            .await
+            .ok_or_log(context);
+    }
 
     let mut to_ids = ContactIds::new();
 
@@ -195,6 +208,27 @@ pub(crate) async fn dc_receive_imf_inner(
         MsgId::new_unset()
     };
 
+    if mime_parser.is_system_message == SystemMessage::EphemeralTimerChanged
+        && !chat_id.is_special()
+        && !insert_msg_id.is_unset()
+    {
+        ephemeral::trash_superseded_timer_changes(context, chat_id, from_id, insert_msg_id)
+            .await
+            .ok_or_log(context);
+    }
+
+    if incoming
+        && !fetching_existing_messages
+        && !chat_id.is_trash()
+        && mime_parser.mdn_reports.is_empty()
+    {
+        stats::record_received(context, mime_parser.was_encrypted())
+            .await
+            .ok_or_log(context);
+        #[cfg(feature = "metrics")]
+        crate::metrics::MSGS_RECEIVED.inc();
+    }
+
     if mime_parser.location_kml.is_some() || mime_parser.message_kml.is_some() {
         save_locations(
             context,
@@ -243,10 +277,21 @@ pub(crate) async fn dc_receive_imf_inner(
     }
 
     // Get user-configured server deletion
-    let delete_server_after = context.get_config_delete_server_after().await?;
+    let delete_server_after = if incoming {
+        context.get_config_delete_server_after().await?
+    } else {
+        context.get_config_delete_server_after_self().await?
+    };
+
+    // Unseen messages may be kept for other devices.
+    let delete_at_once = delete_server_after == Some(0)
+        && !(incoming
+            && context
+                .get_config_bool(Config::DeleteServerOnlySeen)
+                .await?);
 
     if !created_db_entries.is_empty() {
-        if needs_delete_job || delete_server_after == Some(0) {
+        if needs_delete_job || delete_at_once {
             for db_entry in &created_db_entries {
                 job::add(
                     context,
@@ -290,7 +335,9 @@ pub(crate) async fn dc_receive_imf_inner(
         for (chat_id, msg_id) in created_db_entries {
             let event = match create_event_to_send {
                 CreateEvent::MsgsChanged => EventType::MsgsChanged { msg_id, chat_id },
-                CreateEvent::IncomingMsg => EventType::IncomingMsg { msg_id, chat_id },
+                CreateEvent::IncomingMsg => {
+                    chat::incoming_msg_event(context, chat_id, msg_id).await
+                }
             };
             context.emit_event(event);
         }
@@ -632,6 +679,22 @@ async fn add_parts(
             chat_id = DC_CHAT_ID_TRASH;
             info!(context, "Message is probably spam (TRASH)");
         }
+
+        // Messages sent to groups by the user from another account are not fresh.
+        if !chat_id.is_special()
+            && Contact::load_from_db(context, from_id)
+                .await?
+                .is_self_alias()
+            && Chat::load_from_db(context, chat_id).await?.typ == Chattype::Group
+        {
+            info!(context, "Message from self alias {}.", from_id);
+            if state == MessageState::InFresh || state == MessageState::InNoticed {
+                state = MessageState::InSeen;
+            }
+            for part in mime_parser.parts.iter_mut() {
+                part.param.set_int(Param::SelfAlias, 1);
+            }
+        }
     } else {
         // Outgoing
 
@@ -661,6 +724,15 @@ async fn add_parts(
             }
         }
 
+        if mime_parser.is_system_message == SystemMessage::DeviceSettingsChanged {
+            *hidden = true;
+            if let Err(err) =
+                ephemeral::apply_synced_device_settings(context, mime_parser, *sent_timestamp).await
+            {
+                warn!(context, "Failed to apply device settings: {}", err);
+            }
+        }
+
         // If the message is outgoing AND there is no Received header AND it's not in the sentbox,
         // then ignore the email.
         //
@@ -784,9 +856,25 @@ async fn add_parts(
     } else {
         EphemeralTimer::Disabled
     };
+    let ephemeral_timer_start = match mime_parser.get(HeaderDef::EphemeralTimerStart) {
+        Some(value) if value == "delivery" => EphemeralTimerStart::Delivery,
+        _ => EphemeralTimerStart::Read,
+    };
+    let view_once = mime_parser
+        .get(HeaderDef::ChatViewOnce)
+        .map_or(false, |value| value == "1");
 
     let location_kml_is = mime_parser.location_kml.is_some();
 
+    // Rekey messages only carry the new group secret, which is applied below.
+    if mime_parser.is_system_message == SystemMessage::GroupRekeyed {
+        *hidden = true;
+    }
+
+    // Set if the timer change of the message was not applied,
+    // because the policy of the chat rejected it or it waits for approval.
+    let mut timer_not_applied = false;
+
     // Apply ephemeral timer changes to the chat.
     //
     // Only non-hidden timers are applied now. Timers from hidden
@@ -801,14 +889,59 @@ async fn add_parts(
             || parent.unwrap().ephemeral_timer != ephemeral_timer)
         && chat_id.get_ephemeral_timer(context).await? != ephemeral_timer
     {
-        if let Err(err) = chat_id
-            .inner_set_ephemeral_timer(context, ephemeral_timer)
+        let timer_change = chat_id
+            .get_ephemeral_timer_policy(context)
+            .await
+            .unwrap_or_default()
+            .check(context, from_id, ephemeral_timer)
+            .await;
+        if timer_change == ephemeral::TimerChange::Approve {
+            info!(
+                context,
+                "Ephemeral timer change in chat {} by contact {} needs approval", chat_id, from_id
+            );
+            timer_not_applied = true;
+            if chat_id
+                .set_pending_ephemeral_timer(context, ephemeral_timer)
+                .await?
+            {
+                let name = Contact::load_from_db(context, from_id)
+                    .await
+                    .map(|contact| contact.get_name_n_addr())
+                    .unwrap_or_default();
+                chat::add_info_msg_with_cmd(
+                    context,
+                    chat_id,
+                    stock_str::msg_ephemeral_timer_change_pending(context, name).await,
+                    SystemMessage::EphemeralTimerChangePending,
+                )
+                .await?;
+            }
+        } else if timer_change == ephemeral::TimerChange::Reject {
+            info!(
+                context,
+                "Rejecting ephemeral timer change in chat {} by contact {}", chat_id, from_id
+            );
+            timer_not_applied = true;
+            let name = Contact::load_from_db(context, from_id)
+                .await
+                .map(|contact| contact.get_name_n_addr())
+                .unwrap_or_default();
+            chat::add_info_msg(
+                context,
+                chat_id,
+                stock_str::msg_ephemeral_timer_change_rejected(context, name).await,
+            )
+            .await;
+        } else if let Err(err) = chat_id
+            .inner_set_ephemeral_timer(context, ephemeral_timer, from_id, true)
             .await
         {
             warn!(
                 context,
                 "failed to modify timer for chat {}: {}", chat_id, err
             );
+            timer_not_applied = true;
         } else if mime_parser.is_system_message != SystemMessage::EphemeralTimerChanged {
             chat::add_info_msg(
                 context,
@@ -819,11 +952,30 @@ async fn add_parts(
         }
     }
 
+    // Apply the start of the ephemeral timer, which is sent along with the timer,
+    // if the timer itself is applied.
+    if !*hidden
+        && !is_mdn
+        && !chat_id.is_special()
+        && ephemeral_timer != EphemeralTimer::Disabled
+        && chat_id.get_ephemeral_timer(context).await? == ephemeral_timer
+        && chat_id.get_ephemeral_timer_start(context).await? != ephemeral_timer_start
+    {
+        chat_id
+            .set_ephemeral_timer_start(context, ephemeral_timer_start)
+            .await?;
+    }
+
     if mime_parser.is_system_message == SystemMessage::EphemeralTimerChanged {
-        set_better_msg(
-            mime_parser,
-            stock_ephemeral_timer_changed(context, ephemeral_timer, from_id).await,
-        );
+        if timer_not_applied {
+            // The info message about the rejection or pending approval is shown instead.
+            *hidden = true;
+        } else {
+            set_better_msg(
+                mime_parser,
+                stock_ephemeral_timer_changed(context, ephemeral_timer, from_id).await,
+            );
+        }
 
         // Do not delete the system message itself.
         //
@@ -836,7 +988,7 @@ async fn add_parts(
 
     // if a chat is protected, check additional properties
     if !chat_id.is_special() {
-        let chat = Chat::load_from_db(context, chat_id).await?;
+        let mut chat = Chat::load_from_db(context, chat_id).await?;
         let new_status = match mime_parser.is_system_message {
             SystemMessage::ChatProtectionEnabled => Some(ProtectionStatus::Protected),
             SystemMessage::ChatProtectionDisabled => Some(ProtectionStatus::Unprotected),
@@ -849,6 +1001,23 @@ async fn add_parts(
                 warn!(context, "verification problem: {}", err);
                 let s = format!("{}. See 'Info' for more details", err);
                 mime_parser.repl_msg_by_error(s);
+            } else if let Err(err) = chat
+                .apply_group_secret(
+                    context,
+                    from_id,
+                    mime_parser.is_system_message,
+                    mime_parser
+                        .get(HeaderDef::ChatGroupEpoch)
+                        .map(|s| s.as_str()),
+                    mime_parser
+                        .get(HeaderDef::ChatGroupSecret)
+                        .map(|s| s.as_str()),
+                )
+                .await
+            {
+                warn!(context, "group key problem: {}", err);
+                let s = format!("{}. See 'Info' for more details", err);
+                mime_parser.repl_msg_by_error(s);
             } else {
                 // change chat protection only when verification check passes
                 if let Some(new_status) = new_status {
@@ -895,7 +1064,8 @@ async fn add_parts(
 
     // if the mime-headers should be saved, find out its size
     // (the mime-header ends with an empty line)
-    let save_mime_headers = context.get_config_bool(Config::SaveMimeHeaders).await?;
+    let save_mime_headers = context.get_config_bool(Config::SaveMimeHeaders).await?
+        || (!chat_id.is_special() && chat_id.get_save_mime(context).await?);
 
     let mime_in_reply_to = mime_parser
         .get(HeaderDef::InReplyTo)
@@ -905,6 +1075,7 @@ async fn add_parts(
         .get(HeaderDef::References)
         .cloned()
         .unwrap_or_default();
+    let thread_root = get_thread_root(mime_parser).unwrap_or_default();
 
     // fine, so far.  now, split the message into simple parts usable as "short messages"
     // and add them to the database (mails sent by other messenger clients should result
@@ -925,11 +1096,12 @@ async fn add_parts(
     let mut save_mime_modified = mime_parser.is_mime_modified;
 
     let mime_headers = if save_mime_headers || save_mime_modified {
-        if mime_parser.was_encrypted() && !mime_parser.decoded_data.is_empty() {
-            mime_parser.decoded_data.clone()
+        let mime_headers = if mime_parser.was_encrypted() && !mime_parser.decoded_data.is_empty() {
+            mime_parser.decoded_data.as_slice()
         } else {
-            imf_raw.to_vec()
-        }
+            imf_raw
+        };
+        message::compress_mime_headers(mime_headers)?
     } else {
         Vec::new()
     };
@@ -939,35 +1111,29 @@ async fn add_parts(
     let chat_id = chat_id;
 
     let mut is_hidden = is_hidden;
-    let mut ids = Vec::with_capacity(parts.len());
 
-    let conn = context.sql.get_conn().await?;
+    let max_text_len = context.get_config_int(Config::MaxTextLength).await?;
+    if max_text_len > 0 && !chat_id.is_trash() {
+        let max_text_len = max_text_len as usize;
+        for part in &mut parts {
+            if part.msg.chars().count() > max_text_len {
+                let blob =
+                    BlobObject::create(context, "full-text.txt", part.msg.as_bytes()).await?;
+                part.param.set(Param::FullText, blob.as_name());
+                part.msg = dc_truncate(&part.msg, max_text_len).to_string();
+                part.msg_raw = part
+                    .msg_raw
+                    .as_ref()
+                    .map(|msg_raw| dc_truncate(msg_raw, max_text_len).to_string());
+            }
+        }
+    }
 
+    // The rows are prepared first and inserted in a single transaction,
+    // which runs on the blocking writer thread.
+    let mut rows = Vec::with_capacity(parts.len());
     for part in &mut parts {
         let mut txt_raw = "".to_string();
-        let mut stmt = conn.prepare_cached(
-            r#"
-INSERT INTO msgs
-  (
-    rfc724_mid, server_folder, server_uid, chat_id, 
-    from_id, to_id, timestamp, timestamp_sent, 
-    timestamp_rcvd, type, state, msgrmsg, 
-    txt, subject, txt_raw, param, 
-    bytes, hidden, mime_headers, mime_in_reply_to,
-    mime_references, mime_modified, error, ephemeral_timer,
-    ephemeral_timestamp
-  )
-  VALUES (
-    ?, ?, ?, ?,
-    ?, ?, ?, ?,
-    ?, ?, ?, ?,
-    ?, ?, ?, ?,
-    ?, ?, ?, ?,
-    ?, ?, ?, ?,
-    ?
-  );
-"#,
-        )?;
 
         let is_location_kml =
             location_kml_is && icnt == 1 && (part.msg == "-location-" || part.msg.is_empty());
@@ -993,7 +1159,8 @@ async fn add_parts(
             part.param.set_int(Param::Cmd, is_system_message as i32);
         }
 
-        let ephemeral_timestamp = if in_fresh {
+        let ephemeral_timestamp = if in_fresh && ephemeral_timer_start == EphemeralTimerStart::Read
+        {
             0
         } else {
             match ephemeral_timer {
@@ -1006,7 +1173,7 @@ async fn add_parts(
         // also change `MsgId::trash()` and `delete_expired_messages()`
         let trash = chat_id.is_trash();
 
-        stmt.execute(paramsv![
+        let row = paramsv![
             rfc724_mid,
             server_folder,
             server_uid as i32,
@@ -1030,27 +1197,67 @@ async fn add_parts(
             },
             part.bytes as isize,
             is_hidden,
-            if (save_mime_headers || mime_modified) && !trash {
-                mime_headers.clone()
-            } else {
-                Vec::new()
-            },
             mime_in_reply_to,
             mime_references,
+            thread_root,
             mime_modified,
             part.error.take().unwrap_or_default(),
             ephemeral_timer,
-            ephemeral_timestamp
-        ])?;
-        let row_id = conn.last_insert_rowid();
-
-        drop(stmt);
-        ids.push(MsgId::new(u32::try_from(row_id)?));
+            ephemeral_timestamp,
+            view_once
+        ];
+        let save_mime = (save_mime_headers || mime_modified) && !trash;
+        rows.push((row, save_mime));
     }
-    drop(conn);
+
+    let ids = context
+        .sql
+        .transaction(move |transaction| {
+            let mut ids = Vec::with_capacity(rows.len());
+            for (row, save_mime) in rows {
+                transaction
+                    .prepare_cached(
+                        r#"
+INSERT INTO msgs
+  (
+    rfc724_mid, server_folder, server_uid, chat_id, 
+    from_id, to_id, timestamp, timestamp_sent, 
+    timestamp_rcvd, type, state, msgrmsg, 
+    txt, subject, txt_raw, param, 
+    bytes, hidden, mime_in_reply_to, mime_references,
+    thread_root, mime_modified, error, ephemeral_timer,
+    ephemeral_timestamp, view_once
+  )
+  VALUES (
+    ?, ?, ?, ?,
+    ?, ?, ?, ?,
+    ?, ?, ?, ?,
+    ?, ?, ?, ?,
+    ?, ?, ?, ?,
+    ?, ?, ?, ?,
+    ?, ?
+  );
+"#,
+                    )?
+                    .execute(row)?;
+                let row_id = transaction.last_insert_rowid();
+                if save_mime {
+                    transaction.execute(
+                        "INSERT INTO msgs_mime_headers (msg_id, mime_headers) VALUES (?, ?);",
+                        paramsv![row_id, mime_headers],
+                    )?;
+                }
+                ids.push(MsgId::new(u32::try_from(row_id)?));
+            }
+            Ok(ids)
+        })
+        .await?;
 
     if !is_hidden {
         chat_id.unarchive(context).await?;
+        // Messages arriving while the deletion of the chat can be undone
+        // would be deleted along with the chat, which is therefore kept.
+        deletion::cancel_chat_deletion(context, chat_id).await?;
     }
 
     *hidden = is_hidden;
@@ -1206,6 +1413,11 @@ async fn lookup_chat_by_reply(
 
     // If this was a private message just to self, it was probably a private reply.
     // It should not go into the group then, but into the private chat.
+    // If threads of classic emails are grouped by references, the reply stays in the thread.
+    let group_by_references = !mime_parser.has_chat_version()
+        && context
+            .get_config_bool(Config::GroupThreadsByReferences)
+            .await?;
 
     if let Some(parent) = parent {
         let parent_chat = Chat::load_from_db(context, parent.chat_id).await?;
@@ -1231,7 +1443,10 @@ async fn lookup_chat_by_reply(
             return Ok((ChatId::new(0), Blocked::Not));
         }
 
-        if is_probably_private_reply(context, to_ids, mime_parser, parent_chat.id, from_id).await? {
+        if !group_by_references
+            && is_probably_private_reply(context, to_ids, mime_parser, parent_chat.id, from_id)
+                .await?
+        {
             return Ok((ChatId::new(0), Blocked::Not));
         }
 
@@ -1242,9 +1457,92 @@ async fn lookup_chat_by_reply(
         return Ok((parent_chat.id, parent_chat.blocked));
     }
 
+    if group_by_references {
+        if let Some((chat_id, blocked)) = lookup_chat_by_thread(context, mime_parser).await? {
+            info!(
+                context,
+                "Assigning message to {} as it belongs to the same thread", chat_id
+            );
+            return Ok((chat_id, blocked));
+        }
+    }
+
     Ok((ChatId::new(0), Blocked::Not))
 }
 
+/// Returns the message-id of the root of the email thread `mime_parser` belongs to.
+///
+/// The root is the first entry of `References:`, or `In-Reply-To:` for MUAs not setting
+/// `References:`.
+fn get_thread_root(mime_parser: &MimeMessage) -> Option<String> {
+    mime_parser
+        .get(HeaderDef::References)
+        .or_else(|| mime_parser.get(HeaderDef::InReplyTo))
+        .and_then(|field| parse_message_ids(field).into_iter().next())
+}
+
+/// Looks up the chat of an email thread.
+///
+/// The messages referenced by `References:` and `In-Reply-To:` are looked up first,
+/// starting with the most recent one. If none of them was received, a message with
+/// the same thread root is looked up, so this also works if no ancestor of the message
+/// is in the database but other messages of the thread are.
+async fn lookup_chat_by_thread(
+    context: &Context,
+    mime_parser: &MimeMessage,
+) -> Result<Option<(ChatId, Blocked)>> {
+    let root = match get_thread_root(mime_parser) {
+        Some(root) => root,
+        None => return Ok(None),
+    };
+
+    let mut references = Vec::new();
+    for header in &[HeaderDef::References, HeaderDef::InReplyTo] {
+        if let Some(field) = mime_parser.get(*header) {
+            references.extend(parse_message_ids(field));
+        }
+    }
+    references.dedup();
+
+    let select_chat = |row: &rusqlite::Row| -> rusqlite::Result<(ChatId, Blocked)> {
+        let chat_id: ChatId = row.get(0)?;
+        let blocked: Option<Blocked> = row.get(1)?;
+        Ok((chat_id, blocked.unwrap_or(Blocked::Not)))
+    };
+    for rfc724_mid in references.into_iter().rev() {
+        let chat = context
+            .sql
+            .query_row_optional(
+                "SELECT m.chat_id, c.blocked
+                 FROM msgs m
+                 INNER JOIN chats c ON c.id=m.chat_id
+                 WHERE m.rfc724_mid=? AND m.chat_id>?
+                 ORDER BY m.id DESC
+                 LIMIT 1;",
+                paramsv![rfc724_mid, DC_CHAT_ID_LAST_SPECIAL],
+                select_chat,
+            )
+            .await?;
+        if chat.is_some() {
+            return Ok(chat);
+        }
+    }
+
+    context
+        .sql
+        .query_row_optional(
+            "SELECT m.chat_id, c.blocked
+             FROM msgs m
+             INNER JOIN chats c ON c.id=m.chat_id
+             WHERE m.thread_root=? AND m.chat_id>?
+             ORDER BY m.timestamp DESC, m.id DESC
+             LIMIT 1;",
+            paramsv![root, DC_CHAT_ID_LAST_SPECIAL],
+            select_chat,
+        )
+        .await
+}
+
 /// If this method returns true, the message shall be assigned to the 1:1 chat with the sender.
 /// If it returns false, it shall be assigned to the parent chat.
 async fn is_probably_private_reply(
@@ -1532,6 +1830,7 @@ async fn create_or_lookup_group(
     if let Some(avatar_action) = &mime_parser.group_avatar {
         info!(context, "group-avatar change for {}", chat_id);
         if let Ok(mut chat) = Chat::load_from_db(context, chat_id).await {
+            let old_image = chat.param.get(Param::ProfileImage).map(|s| s.to_string());
             match avatar_action {
                 AvatarAction::Change(profile_image) => {
                     chat.param.set(Param::ProfileImage, profile_image);
@@ -1540,6 +1839,13 @@ async fn create_or_lookup_group(
                     chat.param.remove(Param::ProfileImage);
                 }
             };
+            avatar_history::save_replaced_avatar(
+                context,
+                AvatarOwner::Chat(chat_id),
+                old_image.as_deref(),
+                chat.param.get(Param::ProfileImage),
+            )
+            .await?;
             chat.update_param(context).await?;
             send_EVENT_CHAT_MODIFIED = true;
         }
@@ -2831,6 +3137,62 @@ async fn test_parse_ndn_group_msg() {
         assert_eq!(last_msg.from_id, DC_CONTACT_ID_INFO);
     }
 
+    #[async_std::test]
+    async fn test_self_alias() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let (contact_id, _) =
+            Contact::add_or_lookup(&t, "", "alice@other.org", Origin::ManuallyCreated).await?;
+        let mut contact = Contact::load_from_db(&t, contact_id).await?;
+        contact.set_self_alias(&t, true).await?;
+        assert!(Contact::load_from_db(&t, contact_id).await?.is_self_alias());
+
+        dc_receive_imf(
+            &t,
+            b"From: bob@example.net\n\
+                 To: alice@example.com, alice@other.org\n\
+                 Subject: foo\n\
+                 Message-ID: <first@example.net>\n\
+                 Chat-Version: 1.0\n\
+                 Chat-Group-ID: abcde\n\
+                 Chat-Group-Name: foo\n\
+                 Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+                 \n\
+                 hello\n",
+            "INBOX",
+            1,
+            false,
+        )
+        .await?;
+        let msg = t.get_last_msg().await;
+        assert_eq!(msg.state, MessageState::InFresh);
+        assert!(!msg.is_from_self_alias());
+
+        dc_receive_imf(
+            &t,
+            b"From: alice@other.org\n\
+                 To: alice@example.com, bob@example.net\n\
+                 Subject: foo\n\
+                 Message-ID: <second@other.org>\n\
+                 In-Reply-To: <first@example.net>\n\
+                 Chat-Version: 1.0\n\
+                 Chat-Group-ID: abcde\n\
+                 Chat-Group-Name: foo\n\
+                 Date: Sun, 22 Mar 2020 22:38:57 +0000\n\
+                 \n\
+                 hello from my other account\n",
+            "INBOX",
+            2,
+            false,
+        )
+        .await?;
+        let msg = t.get_last_msg().await;
+        assert_eq!(msg.from_id, contact_id);
+        assert_eq!(msg.state, MessageState::InSeen);
+        assert!(msg.is_from_self_alias());
+        assert_eq!(msg.chat_id.get_fresh_msg_cnt(&t).await?, 1);
+        Ok(())
+    }
+
     async fn load_imf_email(context: &Context, imf_raw: &[u8]) -> Message {
         context
             .set_config(Config::ShowEmails, Some("2"))
@@ -3661,6 +4023,29 @@ async fn test_save_mime_headers_on() -> anyhow::Result<()> {
         Ok(())
     }
 
+    #[async_std::test]
+    async fn test_save_mime_per_chat() -> anyhow::Result<()> {
+        let alice = TestContext::new_alice().await;
+        let bob = TestContext::new_bob().await;
+        let chat_alice = alice.create_chat(&bob).await;
+        let chat_bob = bob.create_chat(&alice).await;
+        assert!(!chat_bob.id.get_save_mime(&bob).await?);
+
+        bob.recv_msg(&alice.send_text(chat_alice.id, "before").await)
+            .await;
+        let msg = bob.get_last_msg().await;
+        assert!(message::get_mime_headers(&bob, msg.id).await?.is_empty());
+
+        chat_bob.id.set_save_mime(&bob, true).await?;
+        assert!(chat_bob.id.get_save_mime(&bob).await?);
+        bob.recv_msg(&alice.send_text(chat_alice.id, "after").await)
+            .await;
+        let msg = bob.get_last_msg().await;
+        let mime = message::get_mime_headers(&bob, msg.id).await?;
+        assert!(String::from_utf8_lossy(&mime).contains("From:"));
+        Ok(())
+    }
+
     async fn create_test_alias(
         chat_request: bool,
         group_request: bool,
@@ -3857,6 +4242,62 @@ async fn test_alias_answer_from_dc() {
         check_alias_reply(bob_answer, false, false).await;
     }
 
+    /// Receives two messages of a thread, whose earlier messages were not sent to alice.
+    async fn receive_ticket_thread(t: &TestContext) -> Result<(ChatId, ChatId)> {
+        t.set_config(Config::ShowEmails, Some("2")).await?;
+        dc_receive_imf(
+            t,
+            b"From: Customer <customer@example.org>\n\
+            To: alice@example.com, support@example.org\n\
+            Subject: Re: Printer broken\n\
+            Message-ID: <ticket-3@example.org>\n\
+            In-Reply-To: <ticket-2@example.org>\n\
+            References: <ticket-1@example.org> <ticket-2@example.org>\n\
+            Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+            \n\
+            My printer is still broken\n",
+            "INBOX",
+            1,
+            false,
+        )
+        .await?;
+        let first_chat_id = t.get_last_msg().await.chat_id;
+
+        dc_receive_imf(
+            t,
+            b"From: Agent <agent@example.org>\n\
+            To: alice@example.com, customer@example.org, vendor@example.net\n\
+            Subject: [#123] Replacement ordered\n\
+            Message-ID: <ticket-5@example.org>\n\
+            In-Reply-To: <ticket-4@example.org>\n\
+            References: <ticket-1@example.org> <ticket-4@example.org>\n\
+            Date: Mon, 23 Mar 2020 22:37:57 +0000\n\
+            \n\
+            A replacement is on its way\n",
+            "INBOX",
+            2,
+            false,
+        )
+        .await?;
+        let second_chat_id = t.get_last_msg().await.chat_id;
+        Ok((first_chat_id, second_chat_id))
+    }
+
+    #[async_std::test]
+    async fn test_group_threads_by_references() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let (first_chat_id, second_chat_id) = receive_ticket_thread(&t).await?;
+        assert_ne!(first_chat_id, second_chat_id);
+
+        let t = TestContext::new_alice().await;
+        t.set_config_bool(Config::GroupThreadsByReferences, true)
+            .await?;
+        let (first_chat_id, second_chat_id) = receive_ticket_thread(&t).await?;
+        assert!(!first_chat_id.is_special());
+        assert_eq!(first_chat_id, second_chat_id);
+        Ok(())
+    }
+
     #[async_std::test]
     async fn test_dont_assign_to_trash_by_parent() {
         let t = TestContext::new_alice().await;
@@ -0,0 +1,318 @@
+//! # Undoable deletion of messages and chats
+//!
+//! If `Config::DeletionUndoWindow` is set, messages and chats deleted by the user
+//! are only hidden at first. Until the undo window passes, the last deletion can be
+//! reverted using [`Context::undo_last_deletion`]. Afterwards, the messages and chats
+//! are deleted irreversibly by housekeeping.
+//!
+//! This is independent of the trash chat, which only keeps the Message-IDs of
+//! irreversibly deleted messages until they are deleted on the server.
+
+use anyhow::Result;
+
+use crate::chat::ChatId;
+use crate::config::Config;
+use crate::context::Context;
+use crate::dc_tools::time;
+use crate::events::EventType;
+use crate::job::{self, Action};
+use crate::log::LogExt;
+use crate::message::{self, MsgId};
+use crate::param::Params;
+
+/// Returns the undo window in seconds, 0 if deletions cannot be undone.
+pub(crate) async fn undo_window(context: &Context) -> Result<i64> {
+    Ok(context
+        .get_config_i64(Config::DeletionUndoWindow)
+        .await?
+        .max(0))
+}
+
+async fn next_batch(context: &Context) -> Result<i64> {
+    Ok(context
+        .sql
+        .query_get_value(
+            "SELECT IFNULL(MAX(batch), 0) + 1 FROM pending_deletions;",
+            paramsv![],
+        )
+        .await?
+        .unwrap_or(1))
+}
+
+/// Hides messages and schedules their deletion after the undo window.
+pub(crate) async fn defer_msgs_deletion(
+    context: &Context,
+    msg_ids: &[MsgId],
+    window: i64,
+) -> Result<()> {
+    let batch = next_batch(context).await?;
+    let delete_after = time() + window;
+    let msg_ids = msg_ids.to_vec();
+    context
+        .sql
+        .transaction(move |transaction| {
+            for msg_id in msg_ids {
+                transaction.execute(
+                    "INSERT INTO pending_deletions (batch, delete_after, msg_id, orig_state)
+                     SELECT ?, ?, id, hidden FROM msgs WHERE id=?;",
+                    params![batch, delete_after, msg_id],
+                )?;
+                transaction.execute("UPDATE msgs SET hidden=1 WHERE id=?;", params![msg_id])?;
+            }
+            Ok(())
+        })
+        .await?;
+
+    context.emit_event(EventType::MsgsChanged {
+        chat_id: ChatId::new(0),
+        msg_id: MsgId::new(0),
+    });
+    schedule_finalization(context, window).await;
+    Ok(())
+}
+
+/// Hides a chat and schedules its deletion after the undo window.
+///
+/// The chat is only hidden from the chatlist by its entry in `pending_deletions`,
+/// so messages arriving meanwhile are still added to it, see [`cancel_chat_deletion`].
+pub(crate) async fn defer_chat_deletion(
+    context: &Context,
+    chat_id: ChatId,
+    window: i64,
+) -> Result<()> {
+    let batch = next_batch(context).await?;
+    let delete_after = time() + window;
+    context
+        .sql
+        .execute(
+            "INSERT INTO pending_deletions (batch, delete_after, chat_id) VALUES (?, ?, ?);",
+            paramsv![batch, delete_after, chat_id],
+        )
+        .await?;
+
+    context.emit_event(EventType::MsgsChanged {
+        chat_id: ChatId::new(0),
+        msg_id: MsgId::new(0),
+    });
+    schedule_finalization(context, window).await;
+    Ok(())
+}
+
+/// Cancels the pending deletion of a chat, eg. because a new message arrived
+/// or the user started a new chat with the contact.
+///
+/// Returns true if a deletion was cancelled.
+pub(crate) async fn cancel_chat_deletion(context: &Context, chat_id: ChatId) -> Result<bool> {
+    let cancelled = context
+        .sql
+        .execute(
+            "DELETE FROM pending_deletions WHERE chat_id=?;",
+            paramsv![chat_id],
+        )
+        .await?;
+    Ok(cancelled > 0)
+}
+
+async fn schedule_finalization(context: &Context, delay: i64) {
+    job::add(
+        context,
+        job::Job::new(Action::Housekeeping, 0, Params::new(), delay + 1),
+    )
+    .await;
+}
+
+/// Irreversibly deletes messages and chats whose undo window has passed.
+///
+/// Called from housekeeping.
+pub(crate) async fn finalize_pending_deletions(context: &Context) -> Result<()> {
+    let now = time();
+    let pending = context
+        .sql
+        .query_map(
+            "SELECT msg_id, chat_id FROM pending_deletions WHERE delete_after<=?;",
+            paramsv![now],
+            |row| {
+                let msg_id: MsgId = row.get(0)?;
+                let chat_id: ChatId = row.get(1)?;
+                Ok((msg_id, chat_id))
+            },
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+    context
+        .sql
+        .execute(
+            "DELETE FROM pending_deletions WHERE delete_after<=?;",
+            paramsv![now],
+        )
+        .await?;
+
+    let msg_ids: Vec<MsgId> = pending
+        .iter()
+        .map(|(msg_id, _)| *msg_id)
+        .filter(|msg_id| !msg_id.is_unset())
+        .collect();
+    if !msg_ids.is_empty() {
+        info!(context, "Deleting {} messages irreversibly", msg_ids.len());
+        message::delete_msgs_now(context, &msg_ids).await;
+    }
+
+    for chat_id in pending
+        .iter()
+        .map(|(_, chat_id)| *chat_id)
+        .filter(|chat_id| !chat_id.is_unset())
+    {
+        info!(context, "Deleting chat {} irreversibly", chat_id);
+        chat_id.delete_now(context).await.ok_or_log(context);
+    }
+
+    let next = context
+        .sql
+        .query_get_value::<i64>(
+            "SELECT delete_after FROM pending_deletions ORDER BY delete_after LIMIT 1;",
+            paramsv![],
+        )
+        .await?;
+    if let Some(next) = next {
+        schedule_finalization(context, next - now).await;
+    }
+    Ok(())
+}
+
+impl Context {
+    /// Reverts the last deletion of messages or of a chat.
+    ///
+    /// Only possible if `Config::DeletionUndoWindow` was set at the time of deletion
+    /// and the window has not passed yet.
+    /// Returns false if there is no deletion to revert.
+    pub async fn undo_last_deletion(&self) -> Result<bool> {
+        let batch = self
+            .sql
+            .query_get_value::<i64>(
+                "SELECT batch FROM pending_deletions WHERE delete_after>?
+                 ORDER BY batch DESC LIMIT 1;",
+                paramsv![time()],
+            )
+            .await?;
+        let batch = match batch {
+            Some(batch) => batch,
+            None => return Ok(false),
+        };
+
+        self.sql
+            .transaction(move |transaction| {
+                transaction.execute(
+                    "UPDATE msgs SET hidden=(SELECT orig_state FROM pending_deletions p
+                                             WHERE p.msg_id=msgs.id AND p.batch=?1)
+                     WHERE id IN (SELECT msg_id FROM pending_deletions WHERE batch=?1);",
+                    params![batch],
+                )?;
+                transaction.execute(
+                    "DELETE FROM pending_deletions WHERE batch=?;",
+                    params![batch],
+                )?;
+                Ok(())
+            })
+            .await?;
+
+        self.emit_event(EventType::MsgsChanged {
+            chat_id: ChatId::new(0),
+            msg_id: MsgId::new(0),
+        });
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::chat::{get_chat_msgs, Chat};
+    use crate::chatlist::Chatlist;
+    use crate::constants::Blocked;
+    use crate::test_utils::TestContext;
+
+    #[async_std::test]
+    async fn test_undo_msgs_deletion() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        t.set_config(Config::DeletionUndoWindow, Some("3600"))
+            .await?;
+        let chat = t.create_chat_with_contact("bob", "bob@example.net").await;
+        let msg_id = t.send_text(chat.id, "hi").await.sender_msg_id;
+        assert_eq!(get_chat_msgs(&t, chat.id, 0, None).await?.len(), 1);
+
+        message::delete_msgs(&t, &[msg_id]).await;
+        assert_eq!(get_chat_msgs(&t, chat.id, 0, None).await?.len(), 0);
+
+        assert!(t.undo_last_deletion().await?);
+        assert_eq!(get_chat_msgs(&t, chat.id, 0, None).await?.len(), 1);
+        assert!(!t.undo_last_deletion().await?);
+
+        // after the undo window, the message is deleted irreversibly
+        message::delete_msgs(&t, &[msg_id]).await;
+        t.sql
+            .execute("UPDATE pending_deletions SET delete_after=0;", paramsv![])
+            .await?;
+        assert!(!t.undo_last_deletion().await?);
+        finalize_pending_deletions(&t).await?;
+        let msg = message::Message::load_from_db(&t, msg_id).await?;
+        assert!(msg.chat_id.is_trash());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_undo_chat_deletion() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        t.set_config(Config::DeletionUndoWindow, Some("3600"))
+            .await?;
+        let chat = t.create_chat_with_contact("bob", "bob@example.net").await;
+        t.send_text(chat.id, "hi").await;
+        assert_eq!(Chatlist::try_load(&t, 0, None, None).await?.len(), 1);
+
+        chat.id.delete(&t).await?;
+        assert_eq!(Chatlist::try_load(&t, 0, None, None).await?.len(), 0);
+
+        assert!(t.undo_last_deletion().await?);
+        assert_eq!(Chatlist::try_load(&t, 0, None, None).await?.len(), 1);
+        assert_eq!(Chat::load_from_db(&t, chat.id).await?.blocked, Blocked::Not);
+
+        chat.id.delete(&t).await?;
+        t.sql
+            .execute("UPDATE pending_deletions SET delete_after=0;", paramsv![])
+            .await?;
+        finalize_pending_deletions(&t).await?;
+        assert!(Chat::load_from_db(&t, chat.id).await.is_err());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_msg_during_chat_deletion_window() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        let bob = TestContext::new_bob().await;
+        alice
+            .set_config(Config::DeletionUndoWindow, Some("3600"))
+            .await?;
+        let chat = alice.create_chat(&bob).await;
+        alice.send_text(chat.id, "hi").await;
+        chat.id.delete(&alice).await?;
+        assert_eq!(Chatlist::try_load(&alice, 0, None, None).await?.len(), 0);
+
+        // A message arriving during the undo window cancels the deletion.
+        let bob_chat = bob.create_chat(&alice).await;
+        let sent = bob.send_text(bob_chat.id, "are you there?").await;
+        alice.recv_msg(&sent).await;
+        let msg = alice.get_last_msg().await;
+        assert_eq!(msg.chat_id, chat.id);
+        assert_eq!(Chatlist::try_load(&alice, 0, None, None).await?.len(), 1);
+
+        alice
+            .sql
+            .execute("UPDATE pending_deletions SET delete_after=0;", paramsv![])
+            .await?;
+        finalize_pending_deletions(&alice).await?;
+        let msg = message::Message::load_from_db(&alice, msg.id).await?;
+        assert_eq!(msg.chat_id, chat.id);
+        assert!(!alice.undo_last_deletion().await?);
+        Ok(())
+    }
+}
@@ -8,6 +8,7 @@
 
 use crate::aheader::{Aheader, EncryptPreference};
 use crate::config::Config;
+use crate::contact::{Contact, Origin};
 use crate::context::Context;
 use crate::headerdef::HeaderDef;
 use crate::headerdef::HeaderDefMap;
@@ -135,6 +136,12 @@ pub async fn encrypt(
 
         Ok(ctext)
     }
+
+    /// Creates a detached signature of the passed in raw `mail_to_sign`.
+    pub async fn sign(&self, context: &Context, mail_to_sign: Vec<u8>) -> Result<String> {
+        let sign_key = SignedSecretKey::load_self(context).await?;
+        pgp::pk_sign_detached(mail_to_sign, sign_key).await
+    }
 }
 
 /// Tries to decrypt a message, but only if it is structured as an
@@ -197,9 +204,12 @@ pub async fn try_decrypt(
 
     if let Some(mut peerstate) = peerstate {
         // If message is not encrypted and it is not a read receipt, degrade encryption.
+        // Keys imported manually are kept in use, the user explicitly wants to encrypt
+        // to the contact.
         if out_mail.is_none()
             && message_time > peerstate.last_seen_autocrypt
             && !contains_report(mail)
+            && !has_pinned_key(context, &peerstate).await?
         {
             peerstate.degrade_encryption(message_time);
             peerstate.save_to_db(&context.sql, false).await?;
@@ -209,6 +219,133 @@ pub async fn try_decrypt(
     Ok((out_mail, signatures))
 }
 
+async fn has_pinned_key(context: &Context, peerstate: &Peerstate) -> Result<bool> {
+    match Contact::lookup_id_by_addr(context, &peerstate.addr, Origin::Unknown).await? {
+        Some(contact_id) => Ok(Contact::load_from_db(context, contact_id)
+            .await?
+            .has_pinned_key(peerstate)),
+        None => Ok(false),
+    }
+}
+
+/// Validates the signature of a message that is signed, but not encrypted.
+///
+/// Returns the set of valid signature fingerprints of the sender's key,
+/// which is empty if the message is not a PGP/MIME signed message
+/// or the signature is not valid.
+pub async fn validate_signed_only(
+    context: &Context,
+    mail: &ParsedMail<'_>,
+) -> Result<HashSet<Fingerprint>> {
+    let (content, signature) = match get_signed_content(mail)? {
+        Some(res) => res,
+        None => return Ok(HashSet::default()),
+    };
+
+    let from = mail
+        .headers
+        .get_header(HeaderDef::From_)
+        .and_then(|from_addr| mailparse::addrparse_header(from_addr).ok())
+        .and_then(|from| from.extract_single_info())
+        .map(|from| from.addr)
+        .unwrap_or_default();
+
+    let mut public_keyring_for_validate: Keyring<SignedPublicKey> = Keyring::new();
+    if let Some(peerstate) = Peerstate::from_addr(context, &from).await? {
+        if let Some(key) = peerstate.public_key {
+            public_keyring_for_validate.add(key);
+        } else if let Some(key) = peerstate.gossip_key {
+            public_keyring_for_validate.add(key);
+        }
+    }
+    if public_keyring_for_validate.is_empty() {
+        info!(context, "No key to validate signed message from {}", from);
+        return Ok(HashSet::default());
+    }
+
+    pgp::pk_validate_detached(content, signature, public_keyring_for_validate).await
+}
+
+/// Returns the signed content and the signature of a PGP/MIME signed message.
+///
+/// The signed content is the first body part exactly as transmitted, with line
+/// endings converted to CRLF, see [RFC 3156].
+///
+/// Returns `None` if the message is not a PGP/MIME signed message.
+///
+/// [RFC 3156]: https://www.rfc-editor.org/info/rfc3156
+fn get_signed_content(mail: &ParsedMail<'_>) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+    if mail.ctype.mimetype != "multipart/signed"
+        || mail
+            .ctype
+            .params
+            .get("protocol")
+            .map(|protocol| protocol.to_lowercase())
+            != Some("application/pgp-signature".to_string())
+    {
+        return Ok(None);
+    }
+    let signature_part = match &mail.subparts[..] {
+        [_, second_part] if second_part.ctype.mimetype == "application/pgp-signature" => {
+            second_part
+        }
+        _ => return Ok(None),
+    };
+    let delimiter = match mail.ctype.params.get("boundary") {
+        Some(boundary) => format!("--{}", boundary).into_bytes(),
+        None => return Ok(None),
+    };
+
+    let body = mail.get_body_raw()?;
+    let start = match find_subslice(&body, &delimiter) {
+        Some(pos) => pos + delimiter.len(),
+        None => return Ok(None),
+    };
+    // The content starts after the line break ending the delimiter line.
+    let start = match body
+        .get(start..)
+        .and_then(|rest| find_subslice(rest, b"\n"))
+    {
+        Some(pos) => start + pos + 1,
+        None => return Ok(None),
+    };
+    let mut end = match body
+        .get(start..)
+        .and_then(|rest| find_subslice(rest, &delimiter))
+    {
+        Some(pos) => start + pos,
+        None => return Ok(None),
+    };
+    // The line break before the next delimiter belongs to the delimiter.
+    if body.get(start..end).map_or(false, |c| c.ends_with(b"\r\n")) {
+        end -= 2;
+    } else if body.get(start..end).map_or(false, |c| c.ends_with(b"\n")) {
+        end -= 1;
+    }
+    let content = match body.get(start..end) {
+        Some(content) => content,
+        None => return Ok(None),
+    };
+
+    let mut canonical = Vec::with_capacity(content.len());
+    let mut prev = 0;
+    for &byte in content {
+        if byte == b'\n' && prev != b'\r' {
+            canonical.push(b'\r');
+        }
+        canonical.push(byte);
+        prev = byte;
+    }
+
+    Ok(Some((canonical, signature_part.get_body_raw()?)))
+}
+
+fn find_subslice(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
 /// Returns a reference to the encrypted payload of a valid PGP/MIME message.
 ///
 /// Returns `None` if the message is not a valid PGP/MIME message.
@@ -596,4 +733,35 @@ fn test_mixed_up_mime() -> Result<()> {
 
         Ok(())
     }
+
+    #[test]
+    fn test_get_signed_content() -> Result<()> {
+        let raw = b"From: alice@example.org\n\
+                    To: bob@example.net\n\
+                    Content-Type: multipart/signed; micalg=pgp-sha256;\n \
+                    protocol=\"application/pgp-signature\"; boundary=\"XYZ\"\n\
+                    \n\
+                    --XYZ\n\
+                    Content-Type: text/plain\n\
+                    \n\
+                    Hello\n\
+                    --XYZ\n\
+                    Content-Type: application/pgp-signature; name=\"signature.asc\"\n\
+                    \n\
+                    SIGNATURE\n\
+                    --XYZ--\n";
+        let mail = mailparse::parse_mail(raw)?;
+        let (content, signature) = get_signed_content(&mail)?.unwrap();
+        assert_eq!(content, b"Content-Type: text/plain\r\n\r\nHello".to_vec());
+        assert!(String::from_utf8_lossy(&signature).contains("SIGNATURE"));
+
+        let unsigned = b"From: alice@example.org\n\
+                         To: bob@example.net\n\
+                         Content-Type: text/plain\n\
+                         \n\
+                         Hello\n";
+        let mail = mailparse::parse_mail(unsigned)?;
+        assert!(get_signed_content(&mail)?.is_none());
+        Ok(())
+    }
 }
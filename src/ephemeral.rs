@@ -18,19 +18,71 @@
 //! message is automatically sent to update the setting for all
 //! participants. This allows changing the setting for a chat like any
 //! group chat setting, e.g. name and avatar, without the need to
-//! write an actual message.
+//! write an actual message. In 1:1 chats the timer can also be changed
+//! silently with [`ChatId::set_ephemeral_timer_silently`]; the new setting
+//! is then propagated with the next message sent to the chat.
+//!
+//! With [`TimerPolicy`], timer changes received from other chat members
+//! can be rejected, or applied only after the user approved them with
+//! [`ChatId::approve_ephemeral_timer_change`].
+//!
+//! By default, the timer of a received message starts when it is read.
+//! With [`ChatId::set_ephemeral_timer_start`], the timer starts when the
+//! message is received instead, so messages disappear even if they are never
+//! displayed. This setting is sent in the `Ephemeral-Timer-Start` header
+//! along with the timer and applied in the same way.
+//!
+//! With [`ChatId::set_ephemeral_expiry_mode`], a chat can be set to
+//! [`ExpiryMode::AttachmentOnly`]. Expired messages then only lose their
+//! attachment and raw text; the message text is kept in the chat while the
+//! server copy is deleted as usual.
+//!
+//! Deleted messages are replaced by an info message in the chat telling
+//! how many messages disappeared. Consecutive deletions increase the count
+//! of the same info message.
+//!
+//! If `ephemeral_grace_period` is set, messages are deleted only after
+//! this time has passed since their timer expired. Until then, the UI can
+//! offer to keep the message using [`MsgId::cancel_expiry`].
+//!
+//! The timer of an outgoing message starts when it is created. If the
+//! message could not be sent until its timer expired, sending is cancelled,
+//! the message is marked as failed and deleted like any expired message.
+//!
+//! ## View-once messages
+//!
+//! Independently of the chat timer, a single message can be sent as
+//! view-once message with [`Message::set_view_once`]. It carries a
+//! `Chat-View-Once` header and is deleted by the recipients as soon as
+//! it is marked as seen. The copy of the sender is kept.
+//!
+//! ## Starred messages
+//!
+//! If `protect_starred_from_deletion` is set, starred messages are
+//! neither deleted by ephemeral timers nor by `delete_device_after`.
+//! A `MsgPreserved` event is emitted once when such a message is kept.
+//! The server copies are still deleted.
 //!
 //! ## Device settings
 //!
 //! In addition to per-chat ephemeral message setting, each device has
 //! two global user-configured settings that complement per-chat
 //! settings: `delete_device_after` and `delete_server_after`. These
-//! settings are not synchronized among devices and apply to all
-//! messages known to the device, including messages sent or received
-//! before configuring the setting.
+//! settings apply to all messages known to the device, including
+//! messages sent or received before configuring the setting.
+//!
+//! By default, the settings are not synchronized among devices. If
+//! `sync_device_settings` is set, changing them sends a hidden,
+//! encrypted message to the self-chat carrying both settings. Other
+//! devices with the option set apply the settings unless they were
+//! changed there after the message was sent.
 //!
 //! `delete_device_after` configures the maximum time device is
-//! storing the messages locally. `delete_server_after` configures the
+//! storing the messages locally; messages downloaded again, e.g. by
+//! `Context::search_server`, are kept for this time after downloading.
+//! Messages in the saved messages chat are only deleted if
+//! `delete_device_after_includes_self_chat` is set.
+//! `delete_server_after` configures the
 //! time after which device will delete the messages it knows about
 //! from the server.
 //!
@@ -46,35 +98,61 @@
 //! information, it is deleted from the database, leaving no trace of
 //! the message.
 //!
+//! If the server copy of a trashed message is deleted out-of-band,
+//! e.g. by another client, housekeeping detects that its UID no
+//! longer exists and removes the database entry as well. Remaining
+//! stale entries can be removed with [`purge_stale_trash`].
+//!
 //! ## When messages are deleted
 //!
-//! Local deletion happens when the chatlist or chat is loaded. A
-//! `MsgsChanged` event is emitted when a message deletion is due, to
-//! make UI reload displayed messages and cause actual deletion.
+//! A background task started when the context is opened deletes messages
+//! when they expire, whether IO is running or not, and emits a `MsgsChanged` event to make UI reload displayed
+//! messages. The task is woken up whenever a timer is started and
+//! checks the database at least every minute, so changes of the system
+//! clock do not delay deletion for long. Additionally, local deletion
+//! happens when the chatlist or chat is loaded.
+//!
+//! Before deleting messages, the system clock is compared to the
+//! monotonic clock. If the system clock went back while the context
+//! was open, the ephemeral timestamps are moved back as well, otherwise
+//! messages would be kept until the clock catches up again.
 //!
 //! Server deletion happens by generating IMAP deletion jobs based on
 //! the database entries which are expired either according to their
 //! ephemeral message timers or global `delete_server_after` setting.
+//! Expired messages are collected into a single job which deletes them
+//! with one IMAP command per folder; messages which cannot be deleted
+//! this way get a job of their own.
 
-use std::convert::{TryFrom, TryInto};
+use std::collections::HashSet;
+use std::convert::TryFrom;
 use std::num::ParseIntError;
 use std::str::FromStr;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
 
-use anyhow::{ensure, Context as _, Result};
+use anyhow::{bail, ensure, Context as _, Result};
+use async_std::channel::{self, Receiver, Sender};
+use async_std::future;
 use async_std::task;
+use num_traits::FromPrimitive;
 use serde::{Deserialize, Serialize};
 
-use crate::chat::{send_msg, ChatId};
+use crate::chat::{self, send_msg, Chat, ChatId};
+use crate::config::Config;
 use crate::constants::{
-    Viewtype, DC_CHAT_ID_LAST_SPECIAL, DC_CHAT_ID_TRASH, DC_CONTACT_ID_DEVICE, DC_CONTACT_ID_SELF,
+    Chattype, Viewtype, DC_CHAT_ID_LAST_SPECIAL, DC_CHAT_ID_TRASH, DC_CONTACT_ID_DEVICE,
+    DC_CONTACT_ID_INFO, DC_CONTACT_ID_SELF,
 };
-use crate::context::Context;
-use crate::dc_tools::time;
+use crate::contact::{Contact, VerifiedStatus};
+use crate::context::{Context, InnerContext};
+use crate::dc_tools::{dc_delete_file, dc_get_filebytes, time};
 use crate::events::EventType;
+use crate::headerdef::HeaderDef;
 use crate::job;
-use crate::message::{Message, MessageState, MsgId};
-use crate::mimeparser::SystemMessage;
+use crate::message::{self, Message, MessageState, MsgId};
+use crate::mimeparser::{MimeMessage, SystemMessage};
+use crate::param::{Param, Params};
 use crate::stock_str;
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
@@ -98,6 +176,148 @@ pub fn from_u32(duration: u32) -> Self {
             Self::Enabled { duration }
         }
     }
+
+    /// Returns the timer values offered to the user, shortest first.
+    pub fn presets() -> &'static [Timer] {
+        &[
+            Self::Disabled,
+            Self::Enabled { duration: 5 * 60 },
+            Self::Enabled { duration: 60 * 60 },
+            Self::Enabled {
+                duration: 24 * 60 * 60,
+            },
+            Self::Enabled {
+                duration: 7 * 24 * 60 * 60,
+            },
+            Self::Enabled {
+                duration: 4 * 7 * 24 * 60 * 60,
+            },
+        ]
+    }
+}
+
+/// Restricts which ephemeral timer changes received from other chat members are applied.
+///
+/// Without restrictions, any member can disable the timer for everyone in the chat.
+/// Timer changes made on own other devices are always applied.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct TimerPolicy {
+    /// Only apply timer changes sent by verified contacts.
+    pub verified_only: bool,
+
+    /// Do not apply timer changes disabling the timer
+    /// or setting it to more than this number of seconds.
+    pub max_duration: Option<u32>,
+
+    /// Apply timer changes sent by contacts which are not verified
+    /// only after approval with [`ChatId::approve_ephemeral_timer_change`].
+    pub approve_unverified: bool,
+}
+
+/// How a received ephemeral timer change is handled, see [`TimerPolicy::check`].
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub(crate) enum TimerChange {
+    /// The change is applied.
+    Apply,
+
+    /// The change is not applied.
+    Reject,
+
+    /// The change is applied after approval by the user.
+    Approve,
+}
+
+impl TimerPolicy {
+    /// Returns how a timer change to `timer` sent by `from_id` is handled.
+    pub(crate) async fn check(self, context: &Context, from_id: u32, timer: Timer) -> TimerChange {
+        if from_id == DC_CONTACT_ID_SELF {
+            return TimerChange::Apply;
+        }
+        if let Some(max_duration) = self.max_duration {
+            match timer {
+                Timer::Enabled { duration } if duration <= max_duration => {}
+                _ => return TimerChange::Reject,
+            }
+        }
+        if !self.verified_only && !self.approve_unverified {
+            return TimerChange::Apply;
+        }
+        let verified = match Contact::load_from_db(context, from_id).await {
+            Ok(contact) => contact.is_verified(context).await == VerifiedStatus::BidirectVerified,
+            Err(_) => false,
+        };
+        if verified {
+            TimerChange::Apply
+        } else if self.verified_only {
+            TimerChange::Reject
+        } else {
+            TimerChange::Approve
+        }
+    }
+}
+
+/// Messages a changed ephemeral timer applies to.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Scope {
+    /// Only messages sent or received after the change.
+    NewMessages,
+
+    /// Also all messages already existing in the chat.
+    IncludingHistory,
+}
+
+/// When the ephemeral timer of a message starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum TimerStart {
+    /// When the message is read, ie. marked as seen.
+    Read = 0,
+
+    /// When the message is received, even if it is never read.
+    Delivery = 1,
+}
+
+impl Default for TimerStart {
+    fn default() -> Self {
+        TimerStart::Read
+    }
+}
+
+/// What is deleted when the ephemeral timer of a message expires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum ExpiryMode {
+    /// The whole message.
+    Full = 0,
+
+    /// Only the attachment, the message text is kept.
+    AttachmentOnly = 1,
+}
+
+impl Default for ExpiryMode {
+    fn default() -> Self {
+        ExpiryMode::Full
+    }
+}
+
+/// Statistics about the messages of a chat whose ephemeral timer is running,
+/// see [`ChatId::get_ephemeral_stats`].
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct EphemeralStats {
+    /// Number of messages whose ephemeral timer is running.
+    pub count: usize,
+
+    /// Timestamp at which the first of these messages expires.
+    pub first_expiry: Option<i64>,
+
+    /// Timestamp at which the last of these messages expires.
+    pub last_expiry: Option<i64>,
+
+    /// Number of bytes deleted when the messages expire.
+    ///
+    /// Counts the message texts and attachments,
+    /// only the attachments if the chat uses [`ExpiryMode::AttachmentOnly`].
+    pub bytes: u64,
 }
 
 impl Default for Timer {
@@ -158,14 +378,238 @@ pub async fn get_ephemeral_timer(self, context: &Context) -> Result<Timer> {
         Ok(timer.unwrap_or_default())
     }
 
+    /// Returns the policy restricting timer changes received from other chat members.
+    pub async fn get_ephemeral_timer_policy(self, context: &Context) -> Result<TimerPolicy> {
+        let chat = Chat::load_from_db(context, self).await?;
+        Ok(TimerPolicy {
+            verified_only: chat
+                .param
+                .get_bool(Param::EphemeralVerifiedOnly)
+                .unwrap_or_default(),
+            max_duration: chat
+                .param
+                .get(Param::EphemeralMaxTimer)
+                .and_then(|max| max.parse().ok()),
+            approve_unverified: chat
+                .param
+                .get_bool(Param::EphemeralApproveUnverified)
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Sets the policy restricting timer changes received from other chat members.
+    ///
+    /// The policy is local to this device and not synchronized to other chat members.
+    pub async fn set_ephemeral_timer_policy(
+        self,
+        context: &Context,
+        policy: TimerPolicy,
+    ) -> Result<()> {
+        ensure!(!self.is_special(), "Invalid chat ID");
+
+        let mut chat = Chat::load_from_db(context, self).await?;
+        if policy.verified_only {
+            chat.param.set_int(Param::EphemeralVerifiedOnly, 1);
+        } else {
+            chat.param.remove(Param::EphemeralVerifiedOnly);
+        }
+        match policy.max_duration {
+            Some(max_duration) => chat
+                .param
+                .set(Param::EphemeralMaxTimer, max_duration.to_string()),
+            None => chat.param.remove(Param::EphemeralMaxTimer),
+        };
+        if policy.approve_unverified {
+            chat.param.set_int(Param::EphemeralApproveUnverified, 1);
+        } else {
+            chat.param.remove(Param::EphemeralApproveUnverified);
+        }
+        chat.update_param(context).await?;
+        context.emit_event(EventType::ChatModified(self));
+        Ok(())
+    }
+
+    /// Returns the timer change waiting for approval, see [`TimerPolicy::approve_unverified`].
+    pub async fn get_pending_ephemeral_timer(self, context: &Context) -> Result<Option<Timer>> {
+        let chat = Chat::load_from_db(context, self).await?;
+        Ok(chat
+            .param
+            .get(Param::EphemeralPendingTimer)
+            .and_then(|timer| timer.parse().ok()))
+    }
+
+    /// Remembers a received timer change until it is approved or declined.
+    ///
+    /// Returns false if the same change is waiting for approval already.
+    pub(crate) async fn set_pending_ephemeral_timer(
+        self,
+        context: &Context,
+        timer: Timer,
+    ) -> Result<bool> {
+        let mut chat = Chat::load_from_db(context, self).await?;
+        let timer = timer.to_string();
+        if chat.param.get(Param::EphemeralPendingTimer) == Some(timer.as_str()) {
+            return Ok(false);
+        }
+        chat.param.set(Param::EphemeralPendingTimer, timer);
+        chat.update_param(context).await?;
+        context.emit_event(EventType::ChatModified(self));
+        Ok(true)
+    }
+
+    /// Approves or declines the timer change waiting for approval.
+    ///
+    /// An approved change is applied locally like a change made by the user,
+    /// but not sent to the other chat members.
+    pub async fn approve_ephemeral_timer_change(
+        self,
+        context: &Context,
+        approve: bool,
+    ) -> Result<()> {
+        let timer = match self.get_pending_ephemeral_timer(context).await? {
+            Some(timer) => timer,
+            None => bail!(
+                "No ephemeral timer change waiting for approval in chat {}",
+                self
+            ),
+        };
+        let mut chat = Chat::load_from_db(context, self).await?;
+        chat.param.remove(Param::EphemeralPendingTimer);
+        chat.update_param(context).await?;
+        if approve {
+            self.inner_set_ephemeral_timer(context, timer, DC_CONTACT_ID_SELF, false)
+                .await?;
+            chat::add_info_msg(
+                context,
+                self,
+                stock_ephemeral_timer_changed(context, timer, DC_CONTACT_ID_SELF).await,
+            )
+            .await;
+        }
+        context.emit_event(EventType::ChatModified(self));
+        Ok(())
+    }
+
+    /// Returns when the ephemeral timer of messages in the chat starts.
+    pub async fn get_ephemeral_timer_start(self, context: &Context) -> Result<TimerStart> {
+        let chat = Chat::load_from_db(context, self).await?;
+        Ok(chat
+            .param
+            .get_int(Param::EphemeralTimerStart)
+            .and_then(TimerStart::from_i32)
+            .unwrap_or_default())
+    }
+
+    /// Sets when the ephemeral timer of messages in the chat starts.
+    ///
+    /// The setting is sent along with the ephemeral timer
+    /// and applied by the other chat members when receiving the next message.
+    pub async fn set_ephemeral_timer_start(
+        self,
+        context: &Context,
+        start: TimerStart,
+    ) -> Result<()> {
+        ensure!(!self.is_special(), "Invalid chat ID");
+
+        let mut chat = Chat::load_from_db(context, self).await?;
+        match start {
+            TimerStart::Read => chat.param.remove(Param::EphemeralTimerStart),
+            TimerStart::Delivery => chat.param.set_int(Param::EphemeralTimerStart, start as i32),
+        };
+        chat.update_param(context).await?;
+        context.emit_event(EventType::ChatModified(self));
+        Ok(())
+    }
+
+    /// Returns what is deleted when the ephemeral timer of messages in the chat expires.
+    pub async fn get_ephemeral_expiry_mode(self, context: &Context) -> Result<ExpiryMode> {
+        let chat = Chat::load_from_db(context, self).await?;
+        Ok(chat
+            .param
+            .get_int(Param::EphemeralExpiryMode)
+            .and_then(ExpiryMode::from_i32)
+            .unwrap_or_default())
+    }
+
+    /// Sets what is deleted when the ephemeral timer of messages in the chat expires.
+    ///
+    /// The setting is local to this device and not synchronized to other chat members.
+    pub async fn set_ephemeral_expiry_mode(
+        self,
+        context: &Context,
+        mode: ExpiryMode,
+    ) -> Result<()> {
+        ensure!(!self.is_special(), "Invalid chat ID");
+
+        let mut chat = Chat::load_from_db(context, self).await?;
+        match mode {
+            ExpiryMode::Full => chat.param.remove(Param::EphemeralExpiryMode),
+            ExpiryMode::AttachmentOnly => {
+                chat.param.set_int(Param::EphemeralExpiryMode, mode as i32)
+            }
+        };
+        chat.update_param(context).await?;
+        context.emit_event(EventType::ChatModified(self));
+        Ok(())
+    }
+
+    /// Returns statistics about the messages of the chat whose ephemeral timer is running.
+    ///
+    /// Starred messages are not counted if they are protected from deletion.
+    pub async fn get_ephemeral_stats(self, context: &Context) -> Result<EphemeralStats> {
+        let protect_starred = context
+            .get_config_bool(Config::ProtectStarredFromDeletion)
+            .await?;
+        let attachment_only =
+            self.get_ephemeral_expiry_mode(context).await? == ExpiryMode::AttachmentOnly;
+        let rows = context
+            .sql
+            .query_map(
+                r#"
+    SELECT ephemeral_timestamp, txt, param
+    FROM msgs
+    WHERE chat_id=?
+      AND ephemeral_timestamp != 0
+      AND (starred=0 OR NOT ?);
+    "#,
+                paramsv![self, protect_starred],
+                |row| {
+                    let timestamp: i64 = row.get(0)?;
+                    let text: String = row.get(1)?;
+                    let param: String = row.get(2)?;
+                    Ok((timestamp, text, param))
+                },
+                |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+            )
+            .await?;
+
+        let mut stats = EphemeralStats::default();
+        for (timestamp, text, param) in rows {
+            stats.count += 1;
+            stats.first_expiry = Some(stats.first_expiry.map_or(timestamp, |t| t.min(timestamp)));
+            stats.last_expiry = Some(stats.last_expiry.map_or(timestamp, |t| t.max(timestamp)));
+            if !attachment_only {
+                stats.bytes += text.len() as u64;
+            }
+            let param: Params = param.parse().unwrap_or_default();
+            if let Ok(Some(path)) = param.get_path(Param::File, context) {
+                stats.bytes += dc_get_filebytes(context, &path).await;
+            }
+        }
+        Ok(stats)
+    }
+
     /// Set ephemeral timer value without sending a message.
     ///
     /// Used when a message arrives indicating that someone else has
     /// changed the timer value for a chat.
+    /// `contact_id` and `remote` are passed to the emitted event.
     pub(crate) async fn inner_set_ephemeral_timer(
         self,
         context: &Context,
         timer: Timer,
+        contact_id: u32,
+        remote: bool,
     ) -> Result<()> {
         ensure!(!self.is_special(), "Invalid chat ID");
 
@@ -182,6 +626,8 @@ pub(crate) async fn inner_set_ephemeral_timer(
         context.emit_event(EventType::ChatEphemeralTimerModified {
             chat_id: self,
             timer,
+            contact_id,
+            remote,
         });
         Ok(())
     }
@@ -193,9 +639,16 @@ pub async fn set_ephemeral_timer(self, context: &Context, timer: Timer) -> Resul
         if timer == self.get_ephemeral_timer(context).await? {
             return Ok(());
         }
-        self.inner_set_ephemeral_timer(context, timer).await?;
+        self.inner_set_ephemeral_timer(context, timer, DC_CONTACT_ID_SELF, false)
+            .await?;
+        let text = stock_ephemeral_timer_changed(context, timer, DC_CONTACT_ID_SELF).await;
+        if self.is_device_talk(context).await? {
+            // Nobody to inform about the change, messages cannot be sent to the device chat.
+            chat::add_info_msg(context, self, text).await;
+            return Ok(());
+        }
         let mut msg = Message::new(Viewtype::Text);
-        msg.text = Some(stock_ephemeral_timer_changed(context, timer, DC_CONTACT_ID_SELF).await);
+        msg.text = Some(text);
         msg.param.set_cmd(SystemMessage::EphemeralTimerChanged);
         if let Err(err) = send_msg(context, self, &mut msg).await {
             error!(
@@ -205,6 +658,138 @@ pub async fn set_ephemeral_timer(self, context: &Context, timer: Timer) -> Resul
         }
         Ok(())
     }
+
+    /// Set ephemeral message timer value of a 1:1 chat without sending a message
+    /// about the change.
+    ///
+    /// The new timer is sent to the contact with the next message in the chat
+    /// and applied by the contact when receiving it.
+    pub async fn set_ephemeral_timer_silently(self, context: &Context, timer: Timer) -> Result<()> {
+        let chat = Chat::load_from_db(context, self).await?;
+        ensure!(
+            chat.typ == Chattype::Single,
+            "Ephemeral timer can only be changed silently in 1:1 chats"
+        );
+        if timer == self.get_ephemeral_timer(context).await? {
+            return Ok(());
+        }
+        self.inner_set_ephemeral_timer(context, timer, DC_CONTACT_ID_SELF, false)
+            .await
+    }
+
+    /// Set ephemeral message timer value in seconds, optionally applying it
+    /// to the messages already existing in the chat.
+    ///
+    /// With [`Scope::IncludingHistory`], the timer of already seen messages starts
+    /// immediately and unseen messages get the timer once they are seen.
+    /// Timers already running on existing messages are only shortened, never extended.
+    pub async fn set_ephemeral_timer_with_scope(
+        self,
+        context: &Context,
+        timer: Timer,
+        scope: Scope,
+    ) -> Result<()> {
+        self.set_ephemeral_timer(context, timer).await?;
+        if scope == Scope::NewMessages {
+            return Ok(());
+        }
+        let duration = match timer {
+            Timer::Enabled { duration } => duration,
+            Timer::Disabled => return Ok(()),
+        };
+
+        context
+            .sql
+            .execute(
+                "UPDATE msgs SET ephemeral_timer=? \
+                 WHERE chat_id=? AND (ephemeral_timer=0 OR ephemeral_timer>?) AND state!=?",
+                paramsv![duration, self, duration, MessageState::OutDraft],
+            )
+            .await?;
+        let ephemeral_timestamp = time() + i64::from(duration);
+        context
+            .sql
+            .execute(
+                "UPDATE msgs SET ephemeral_timestamp=? \
+                 WHERE chat_id=? AND ephemeral_timestamp>?",
+                paramsv![ephemeral_timestamp, self, ephemeral_timestamp],
+            )
+            .await?;
+        start_ephemeral_timers(context).await?;
+        schedule_ephemeral_task(context).await;
+
+        context.emit_event(EventType::MsgsChanged {
+            chat_id: self,
+            msg_id: MsgId::new(0),
+        });
+        Ok(())
+    }
+}
+
+/// Time window in seconds in which consecutive timer changes by the same contact
+/// are coalesced into a single message.
+const TIMER_CHANGE_COALESCING_WINDOW: i64 = 10 * 60;
+
+/// Trashes timer change messages of `from_id` superseded by the timer change message `msg_id`.
+///
+/// Only timer changes directly preceding `msg_id` in the chat and sent within
+/// [`TIMER_CHANGE_COALESCING_WINDOW`] are trashed, so quickly toggling the timer
+/// leaves a single message in the chat.
+pub(crate) async fn trash_superseded_timer_changes(
+    context: &Context,
+    chat_id: ChatId,
+    from_id: u32,
+    msg_id: MsgId,
+) -> Result<()> {
+    let msg = Message::load_from_db(context, msg_id).await?;
+    let preceding = context
+        .sql
+        .query_map(
+            "SELECT id, from_id, param FROM msgs
+             WHERE chat_id=? AND id!=? AND hidden=0 AND timestamp<=? AND timestamp>=?
+             ORDER BY timestamp DESC, id DESC;",
+            paramsv![
+                chat_id,
+                msg_id,
+                msg.timestamp_sort,
+                msg.timestamp_sort - TIMER_CHANGE_COALESCING_WINDOW
+            ],
+            |row| {
+                let id: MsgId = row.get(0)?;
+                let from_id: u32 = row.get(1)?;
+                let param: String = row.get(2)?;
+                Ok((id, from_id, param))
+            },
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+
+    let mut superseded = Vec::new();
+    for (id, id_from, param) in preceding {
+        let param: Params = param.parse().unwrap_or_default();
+        if id_from != from_id || param.get_cmd() != SystemMessage::EphemeralTimerChanged {
+            break;
+        }
+        superseded.push(id);
+    }
+    if superseded.is_empty() {
+        return Ok(());
+    }
+
+    info!(
+        context,
+        "Trashing {} superseded timer changes in chat {}",
+        superseded.len(),
+        chat_id
+    );
+    for id in superseded {
+        id.trash(context).await?;
+    }
+    context.emit_event(EventType::MsgsChanged {
+        chat_id,
+        msg_id: MsgId::new(0),
+    });
+    Ok(())
 }
 
 /// Returns a stock message saying that ephemeral timer is changed to `timer` by `from_id`.
@@ -277,9 +862,28 @@ pub(crate) async fn ephemeral_timer(self, context: &Context) -> anyhow::Result<T
     }
 
     /// Starts ephemeral message timer for the message if it is not started yet.
+    ///
+    /// If the timer of the chat starts on delivery,
+    /// the timer is computed from the time the message was received.
     pub(crate) async fn start_ephemeral_timer(self, context: &Context) -> anyhow::Result<()> {
         if let Timer::Enabled { duration } = self.ephemeral_timer(context).await? {
-            let ephemeral_timestamp = time() + i64::from(duration);
+            let (chat_id, timestamp_rcvd) = context
+                .sql
+                .query_row(
+                    "SELECT chat_id, timestamp_rcvd FROM msgs WHERE id=?",
+                    paramsv![self],
+                    |row| Ok((row.get::<_, ChatId>(0)?, row.get::<_, i64>(1)?)),
+                )
+                .await?;
+            let start = if timestamp_rcvd > 0
+                && !chat_id.is_special()
+                && chat_id.get_ephemeral_timer_start(context).await? == TimerStart::Delivery
+            {
+                timestamp_rcvd
+            } else {
+                time()
+            };
+            let ephemeral_timestamp = start + i64::from(duration);
 
             context
                 .sql
@@ -294,231 +898,1117 @@ pub(crate) async fn start_ephemeral_timer(self, context: &Context) -> anyhow::Re
         }
         Ok(())
     }
-}
-
-/// Deletes messages which are expired according to
-/// `delete_device_after` setting or `ephemeral_timestamp` column.
-///
-/// Returns true if any message is deleted, so caller can emit
-/// MsgsChanged event. If nothing has been deleted, returns
-/// false. This function does not emit the MsgsChanged event itself,
-/// because it is also called when chatlist is reloaded, and emitting
-/// MsgsChanged there will cause infinite reload loop.
-pub(crate) async fn delete_expired_messages(context: &Context) -> Result<bool> {
-    let mut updated = context
-        .sql
-        .execute(
-            // If you change which information is removed here, also change MsgId::trash() and
-            // which information dc_receive_imf::add_parts() still adds to the db if the chat_id is TRASH
-            r#"
-UPDATE msgs
-SET 
-  chat_id=?, txt='', subject='', txt_raw='', 
-  mime_headers='', from_id=0, to_id=0, param=''
-WHERE
-  ephemeral_timestamp != 0
-  AND ephemeral_timestamp <= ?
-  AND chat_id != ?
-"#,
-            paramsv![DC_CHAT_ID_TRASH, time(), DC_CHAT_ID_TRASH],
-        )
-        .await
-        .context("update failed")?
-        > 0;
 
-    if let Some(delete_device_after) = context.get_config_delete_device_after().await? {
-        let self_chat_id = ChatId::lookup_by_contact(context, DC_CONTACT_ID_SELF)
-            .await?
-            .unwrap_or_default();
-        let device_chat_id = ChatId::lookup_by_contact(context, DC_CONTACT_ID_DEVICE)
+    /// Stops the ephemeral timer of the message, so that it is not deleted.
+    ///
+    /// This allows the UI to offer keeping an expired message
+    /// during `Config::EphemeralGracePeriod`.
+    /// Fails if the message is deleted already.
+    pub async fn cancel_expiry(self, context: &Context) -> Result<()> {
+        let chat_id: ChatId = context
+            .sql
+            .query_get_value("SELECT chat_id FROM msgs WHERE id=?", paramsv![self])
             .await?
-            .unwrap_or_default();
-
-        let threshold_timestamp = time() - delete_device_after;
-
-        // Delete expired messages
-        //
-        // Only update the rows that have to be updated, to avoid emitting
-        // unnecessary "chat modified" events.
-        let rows_modified = context
+            .with_context(|| format!("message {} not found", self))?;
+        let updated = context
             .sql
             .execute(
-                "UPDATE msgs \
-             SET txt = 'DELETED', chat_id = ? \
-             WHERE timestamp < ? \
-             AND chat_id > ? \
-             AND chat_id != ? \
-             AND chat_id != ?",
-                paramsv![
-                    DC_CHAT_ID_TRASH,
-                    threshold_timestamp,
-                    DC_CHAT_ID_LAST_SPECIAL,
-                    self_chat_id,
-                    device_chat_id
-                ],
+                "UPDATE msgs SET ephemeral_timer=0, ephemeral_timestamp=0 \
+                 WHERE id=? AND chat_id!=?",
+                paramsv![self, DC_CHAT_ID_TRASH],
             )
-            .await
-            .context("deleted update failed")?;
+            .await?;
+        ensure!(updated > 0, "message {} is deleted already", self);
 
-        updated |= rows_modified > 0;
+        context.emit_event(EventType::MsgsChanged {
+            chat_id,
+            msg_id: self,
+        });
+        schedule_ephemeral_task(context).await;
+        Ok(())
     }
+}
 
-    schedule_ephemeral_task(context).await;
-    Ok(updated)
+/// Raw config key of the time of the last change of the device settings.
+const DEVICE_SETTINGS_TIMESTAMP: &str = "device_settings_timestamp";
+
+/// Sends `delete_device_after` and `delete_server_after` to the other devices
+/// of the user after they are changed, if `Config::SyncDeviceSettings` is set.
+pub(crate) async fn sync_device_settings(context: &Context) -> Result<()> {
+    context
+        .sql
+        .set_raw_config_int64(DEVICE_SETTINGS_TIMESTAMP, time())
+        .await?;
+    if !context.get_config_bool(Config::SyncDeviceSettings).await?
+        || !context.is_configured().await?
+    {
+        return Ok(());
+    }
+
+    let chat_id = ChatId::create_for_contact(context, DC_CONTACT_ID_SELF).await?;
+    let mut msg = Message::new(Viewtype::Text);
+    msg.text = Some("Device settings changed.".to_string());
+    msg.hidden = true;
+    msg.param.set_cmd(SystemMessage::DeviceSettingsChanged);
+    msg.param.set(
+        Param::Arg,
+        context
+            .get_config_int(Config::DeleteDeviceAfter)
+            .await?
+            .to_string(),
+    );
+    msg.param.set(
+        Param::Arg2,
+        context
+            .get_config_int(Config::DeleteServerAfter)
+            .await?
+            .to_string(),
+    );
+    send_msg(context, chat_id, &mut msg).await?;
+    Ok(())
 }
 
-/// Schedule a task to emit MsgsChanged event when the next local
-/// deletion happens. Existing task is cancelled to make sure at most
-/// one such task is scheduled at a time.
-///
-/// UI is expected to reload the chatlist or the chat in response to
-/// MsgsChanged event, this will trigger actual deletion.
-///
-/// This takes into account only per-chat timeouts, because global device
-/// timeouts are at least one hour long and deletion is triggered often enough
-/// by user actions.
-pub async fn schedule_ephemeral_task(context: &Context) {
-    let ephemeral_timestamp: Option<i64> = match context
+/// Applies device settings sent by another device of the user,
+/// unless they were changed on this device after `sent_timestamp`.
+pub(crate) async fn apply_synced_device_settings(
+    context: &Context,
+    mime_parser: &MimeMessage,
+    sent_timestamp: i64,
+) -> Result<()> {
+    ensure!(
+        mime_parser.was_encrypted(),
+        "Device settings message is not encrypted"
+    );
+    if !context.get_config_bool(Config::SyncDeviceSettings).await? {
+        return Ok(());
+    }
+    let last_change = context
         .sql
-        .query_get_value(
-            r#"
-    SELECT ephemeral_timestamp
-    FROM msgs
-    WHERE ephemeral_timestamp != 0
-      AND chat_id != ?
-    ORDER BY ephemeral_timestamp ASC
-    LIMIT 1;
-    "#,
-            paramsv![DC_CHAT_ID_TRASH], // Trash contains already deleted messages, skip them
-        )
-        .await
+        .get_raw_config_int64(DEVICE_SETTINGS_TIMESTAMP)
+        .await?
+        .unwrap_or_default();
+    if sent_timestamp <= last_change {
+        info!(
+            context,
+            "Ignoring device settings older than the last change."
+        );
+        return Ok(());
+    }
+
+    for (header, key) in [
+        (HeaderDef::ChatDeleteDeviceAfter, Config::DeleteDeviceAfter),
+        (HeaderDef::ChatDeleteServerAfter, Config::DeleteServerAfter),
+    ]
+    .iter()
     {
-        Err(err) => {
-            warn!(context, "Can't calculate next ephemeral timeout: {}", err);
-            return;
+        if let Some(value) = mime_parser.get(header.clone()) {
+            let value: u32 = value.parse()?;
+            // Not using set_config() as this would send the settings again.
+            context
+                .sql
+                .set_raw_config(key, Some(&value.to_string()))
+                .await?;
         }
-        Ok(ephemeral_timestamp) => ephemeral_timestamp,
+    }
+    context
+        .sql
+        .set_raw_config_int64(DEVICE_SETTINGS_TIMESTAMP, sent_timestamp)
+        .await?;
+    context.emit_event(EventType::MsgsChanged {
+        chat_id: ChatId::new(0),
+        msg_id: MsgId::new(0),
+    });
+    job::schedule_resync(context).await;
+    Ok(())
+}
+
+/// Differences between the system clock and the monotonic clock up to this
+/// number of seconds are not considered as changes of the system clock.
+const CLOCK_TOLERANCE: i64 = 60;
+
+/// System clock at a point of the monotonic clock, see [`reconcile_clock`].
+#[derive(Debug)]
+pub(crate) struct ClockReference {
+    instant: Instant,
+    timestamp: i64,
+}
+
+/// Detects if the system clock went back and moves the ephemeral timestamps
+/// of all messages back by the same amount, so that messages are not kept longer
+/// than their timer says.
+///
+/// The expected time is derived from the monotonic clock, so only jumps
+/// while the context is open are detected. The first check after opening
+/// the context only takes the reference, as the time stored by a previous process
+/// cannot be compared to the monotonic clock.
+///
+/// Jumps forward are not corrected, as they cannot be told apart from the device
+/// being suspended, during which the monotonic clock stops as well.
+async fn reconcile_clock(context: &Context) -> Result<()> {
+    let now = time();
+    let mut clock = context.ephemeral_clock.lock().await;
+    let expected = match &*clock {
+        Some(reference) => reference.timestamp + reference.instant.elapsed().as_secs() as i64,
+        None => now,
     };
 
-    // Cancel existing task, if any
-    if let Some(ephemeral_task) = context.ephemeral_task.write().await.take() {
-        ephemeral_task.cancel().await;
-    }
-
-    if let Some(ephemeral_timestamp) = ephemeral_timestamp {
-        let now = SystemTime::now();
-        let until = UNIX_EPOCH
-            + Duration::from_secs(ephemeral_timestamp.try_into().unwrap_or(u64::MAX))
-            + Duration::from_secs(1);
-
-        if let Ok(duration) = until.duration_since(now) {
-            // Schedule a task, ephemeral_timestamp is in the future
-            let context1 = context.clone();
-            let ephemeral_task = task::spawn(async move {
-                async_std::task::sleep(duration).await;
-                emit_event!(
-                    context1,
-                    EventType::MsgsChanged {
-                        chat_id: ChatId::new(0),
-                        msg_id: MsgId::new(0)
-                    }
-                );
-            });
-            *context.ephemeral_task.write().await = Some(ephemeral_task);
-        } else {
-            // Emit event immediately
-            emit_event!(
-                context,
-                EventType::MsgsChanged {
-                    chat_id: ChatId::new(0),
-                    msg_id: MsgId::new(0)
-                }
-            );
-        }
+    let jump = expected - now;
+    if jump > CLOCK_TOLERANCE {
+        warn!(
+            context,
+            "System clock went back by {} seconds, adjusting ephemeral timers.", jump
+        );
+        context
+            .sql
+            .execute(
+                "UPDATE msgs SET ephemeral_timestamp=MAX(ephemeral_timestamp-?, 1) \
+                 WHERE ephemeral_timestamp!=0",
+                paramsv![jump],
+            )
+            .await?;
     }
+
+    // Keep the reference for a while, so rounding of the elapsed seconds
+    // does not add up when the chatlist is loaded often.
+    let last_check = clock.as_ref().map_or(0, |reference| reference.timestamp);
+    if clock.is_none() || jump > CLOCK_TOLERANCE || now - last_check >= CLOCK_TOLERANCE {
+        *clock = Some(ClockReference {
+            instant: Instant::now(),
+            timestamp: now,
+        });
+    }
+    Ok(())
 }
 
-/// Returns ID of any expired message that should be deleted from the server.
+/// Deletes messages which are expired according to
+/// `delete_device_after` setting or `ephemeral_timestamp` column.
 ///
-/// It looks up the trash chat too, to find messages that are already
-/// deleted locally, but not deleted on the server.
-pub(crate) async fn load_imap_deletion_msgid(context: &Context) -> anyhow::Result<Option<MsgId>> {
-    let now = time();
+/// Returns true if any message is deleted, so caller can emit
+/// MsgsChanged event. If nothing has been deleted, returns
+/// false. This function does not emit the MsgsChanged event itself,
+/// because it is also called when chatlist is reloaded, and emitting
+/// MsgsChanged there will cause infinite reload loop.
+pub(crate) async fn delete_expired_messages(context: &Context) -> Result<bool> {
+    if let Err(err) = reconcile_clock(context).await {
+        warn!(context, "Failed to check system clock: {}", err);
+    }
+    let protect_starred = context
+        .get_config_bool(Config::ProtectStarredFromDeletion)
+        .await?;
+    let ephemeral_threshold = time() - context.get_config_i64(Config::EphemeralGracePeriod).await?;
 
-    let threshold_timestamp = match context.get_config_delete_server_after().await? {
-        None => 0,
-        Some(delete_server_after) => now - delete_server_after,
-    };
+    expire_undelivered_messages(context, ephemeral_threshold, protect_starred).await?;
+    let mut updated =
+        strip_expired_attachments(context, ephemeral_threshold, protect_starred).await?;
 
-    context
+    // Count the messages to be deleted per chat before they are gone,
+    // info messages are not counted.
+    let candidates = context
         .sql
-        .query_row_optional(
-            "SELECT id FROM msgs \
-         WHERE ( \
-         timestamp < ? \
-         OR (ephemeral_timestamp != 0 AND ephemeral_timestamp <= ?) \
-         ) \
-         AND server_uid != 0 \
-         AND NOT id IN (SELECT foreign_id FROM jobs WHERE action = ?)
-         LIMIT 1",
-            paramsv![threshold_timestamp, now, job::Action::DeleteMsgOnImap],
-            |row| {
-                let msg_id: MsgId = row.get(0)?;
-                Ok(msg_id)
-            },
+        .query_map(
+            "SELECT chat_id, param FROM msgs \
+             WHERE ephemeral_timestamp != 0 \
+             AND ephemeral_timestamp <= ? \
+             AND chat_id > ? \
+             AND hidden=0 \
+             AND from_id != ? AND to_id != ? \
+             AND state != ? \
+             AND (starred=0 OR NOT ?)",
+            paramsv![
+                ephemeral_threshold,
+                DC_CHAT_ID_LAST_SPECIAL,
+                DC_CONTACT_ID_INFO,
+                DC_CONTACT_ID_INFO,
+                MessageState::OutPending,
+                protect_starred
+            ],
+            |row| Ok((row.get::<_, ChatId>(0)?, row.get::<_, String>(1)?)),
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+    let mut disappeared: Vec<(ChatId, u32)> = Vec::new();
+    for (chat_id, param) in candidates {
+        if is_info_cmd(get_cmd(&param)) {
+            continue;
+        }
+        match disappeared.iter_mut().find(|(id, _)| *id == chat_id) {
+            Some((_, count)) => *count += 1,
+            None => disappeared.push((chat_id, 1)),
+        }
+    }
+
+    let ephemeral_deleted = context
+        .sql
+        .execute(
+            // If you change which information is removed here, also change MsgId::trash() and
+            // which information dc_receive_imf::add_parts() still adds to the db if the chat_id is TRASH
+            r#"
+UPDATE msgs
+SET 
+  chat_id=?, txt='', subject='', txt_raw='', 
+  mime_headers='', from_id=0, to_id=0, param=''
+WHERE
+  ephemeral_timestamp != 0
+  AND ephemeral_timestamp <= ?
+  AND chat_id != ?
+  AND state != ?
+  AND (starred=0 OR NOT ?)
+"#,
+            paramsv![
+                DC_CHAT_ID_TRASH,
+                ephemeral_threshold,
+                DC_CHAT_ID_TRASH,
+                MessageState::OutPending,
+                protect_starred
+            ],
         )
         .await
+        .context("update failed")?;
+    #[cfg(feature = "metrics")]
+    crate::metrics::EPHEMERAL_DELETIONS.add(ephemeral_deleted as u64);
+    updated |= ephemeral_deleted > 0;
+    for (chat_id, count) in disappeared {
+        add_tombstone(context, chat_id, count).await?;
+    }
+
+    if let Some(delete_device_after) = context.get_config_delete_device_after().await? {
+        let (self_chat_id, device_chat_id) = delete_device_after_excluded_chats(context).await?;
+        let threshold_timestamp = time() - delete_device_after;
+
+        // Delete expired messages
+        //
+        // Only update the rows that have to be updated, to avoid emitting
+        // unnecessary "chat modified" events.
+        let rows_modified = context
+            .sql
+            .execute(
+                "UPDATE msgs \
+             SET txt = 'DELETED', chat_id = ?, device_expired = 1 \
+             WHERE timestamp < ? \
+             AND timestamp_rcvd < ? \
+             AND chat_id > ? \
+             AND chat_id != ? \
+             AND chat_id != ? \
+             AND (starred=0 OR NOT ?)",
+                paramsv![
+                    DC_CHAT_ID_TRASH,
+                    threshold_timestamp,
+                    threshold_timestamp,
+                    DC_CHAT_ID_LAST_SPECIAL,
+                    self_chat_id,
+                    device_chat_id,
+                    protect_starred
+                ],
+            )
+            .await
+            .context("deleted update failed")?;
+
+        updated |= rows_modified > 0;
+    }
+
+    let delete_device_chat_after = context
+        .get_config_i64(Config::DeleteDeviceChatAfter)
+        .await?;
+    if delete_device_chat_after > 0 {
+        if let Some(device_chat_id) =
+            ChatId::lookup_by_contact(context, DC_CONTACT_ID_DEVICE).await?
+        {
+            let rows_modified = context
+                .sql
+                .execute(
+                    "UPDATE msgs \
+                 SET txt = 'DELETED', chat_id = ? \
+                 WHERE timestamp < ? \
+                 AND chat_id = ?",
+                    paramsv![
+                        DC_CHAT_ID_TRASH,
+                        time() - delete_device_chat_after,
+                        device_chat_id
+                    ],
+                )
+                .await
+                .context("device chat update failed")?;
+
+            updated |= rows_modified > 0;
+        }
+    }
+
+    schedule_ephemeral_task(context).await;
+    Ok(updated)
 }
 
-/// Start ephemeral timers for seen messages if they are not started
-/// yet.
+/// Deletes view-once messages which are marked as seen.
 ///
-/// It is possible that timers are not started due to a missing or
-/// failed `MsgId.start_ephemeral_timer()` call, either in the current
-/// or previous version of Delta Chat.
+/// Called when messages are marked as seen. Housekeeping calls it as well
+/// to delete view-once messages which were already seen when they were received.
+/// Returns true if any message is deleted.
+pub(crate) async fn delete_seen_view_once_msgs(context: &Context) -> Result<bool> {
+    let deleted = context
+        .sql
+        .execute(
+            r#"
+UPDATE msgs
+SET 
+  chat_id=?, txt='', subject='', txt_raw='', 
+  mime_headers='', from_id=0, to_id=0, param=''
+WHERE
+  view_once=1
+  AND state=?
+  AND chat_id != ?
+"#,
+            paramsv![DC_CHAT_ID_TRASH, MessageState::InSeen, DC_CHAT_ID_TRASH],
+        )
+        .await
+        .context("view-once update failed")?;
+    Ok(deleted > 0)
+}
+
+/// Stops sending outgoing messages whose ephemeral timer expired before they were sent.
 ///
-/// This function is supposed to be called in the background,
-/// e.g. from housekeeping task.
-pub(crate) async fn start_ephemeral_timers(context: &Context) -> Result<()> {
+/// Timers of pending messages which were not started are started from the time
+/// the message was created. Once the timer and `Config::UndeliveredGracePeriod` are over,
+/// sending is cancelled and the messages are marked as failed; they are deleted
+/// together with the other expired messages. Pending messages are not deleted before.
+async fn expire_undelivered_messages(
+    context: &Context,
+    ephemeral_threshold: i64,
+    protect_starred: bool,
+) -> Result<()> {
+    let ephemeral_threshold = ephemeral_threshold
+        - context
+            .get_config_i64(Config::UndeliveredGracePeriod)
+            .await?;
     context
         .sql
         .execute(
-            "UPDATE msgs \
-    SET ephemeral_timestamp = ? + ephemeral_timer \
-    WHERE ephemeral_timer > 0 \
-    AND ephemeral_timestamp = 0 \
-    AND state NOT IN (?, ?, ?)",
+            "UPDATE msgs SET ephemeral_timestamp = timestamp + ephemeral_timer \
+             WHERE ephemeral_timer > 0 \
+             AND ephemeral_timestamp = 0 \
+             AND state = ? \
+             AND chat_id > ?",
+            paramsv![MessageState::OutPending, DC_CHAT_ID_LAST_SPECIAL],
+        )
+        .await?;
+
+    let msg_ids = context
+        .sql
+        .query_map(
+            "SELECT id FROM msgs \
+             WHERE ephemeral_timestamp != 0 \
+             AND ephemeral_timestamp <= ? \
+             AND state = ? \
+             AND chat_id != ? \
+             AND (starred=0 OR NOT ?)",
             paramsv![
-                time(),
-                MessageState::InFresh,
-                MessageState::InNoticed,
-                MessageState::OutDraft
+                ephemeral_threshold,
+                MessageState::OutPending,
+                DC_CHAT_ID_TRASH,
+                protect_starred
             ],
+            |row| row.get::<_, MsgId>(0),
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
         )
         .await?;
-
+    for msg_id in msg_ids {
+        context
+            .sql
+            .execute(
+                "DELETE FROM jobs WHERE action=? AND foreign_id=?",
+                paramsv![job::Action::SendMsgToSmtp, msg_id],
+            )
+            .await?;
+        message::set_msg_failed(context, msg_id, Some("Message expired before it was sent")).await;
+    }
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::param::Params;
-    use async_std::task::sleep;
+/// Removes the attachments of expired messages in chats using
+/// [`ExpiryMode::AttachmentOnly`], keeping the message text.
+///
+/// The messages are deleted from the server and their ephemeral timer is reset,
+/// so the remaining text is not deleted later. Attachments and stored full texts
+/// inside the blob directory are deleted immediately, see [`delete_stripped_blob`].
+async fn strip_expired_attachments(
+    context: &Context,
+    ephemeral_threshold: i64,
+    protect_starred: bool,
+) -> Result<bool> {
+    let chat_ids = context
+        .sql
+        .query_map(
+            "SELECT DISTINCT chat_id FROM msgs \
+             WHERE ephemeral_timestamp != 0 \
+             AND ephemeral_timestamp <= ? \
+             AND chat_id > ?",
+            paramsv![ephemeral_threshold, DC_CHAT_ID_LAST_SPECIAL],
+            |row| row.get::<_, ChatId>(0),
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
 
-    use super::*;
-    use crate::test_utils::TestContext;
-    use crate::{
-        chat::{self, Chat, ChatItem},
-        dc_tools::IsNoneOrEmpty,
+    let mut stripped = false;
+    for chat_id in chat_ids {
+        if chat_id.get_ephemeral_expiry_mode(context).await? != ExpiryMode::AttachmentOnly {
+            continue;
+        }
+        let msg_ids = context
+            .sql
+            .query_map(
+                "SELECT id FROM msgs \
+                 WHERE chat_id=? \
+                 AND ephemeral_timestamp != 0 \
+                 AND ephemeral_timestamp <= ? \
+                 AND (starred=0 OR NOT ?)",
+                paramsv![chat_id, ephemeral_threshold, protect_starred],
+                |row| row.get::<_, MsgId>(0),
+                |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+            )
+            .await?;
+        for msg_id in msg_ids {
+            let msg = Message::load_from_db(context, msg_id).await?;
+            let mut param = msg.param.clone();
+            param
+                .remove(Param::File)
+                .remove(Param::FullText)
+                .remove(Param::MimeType)
+                .remove(Param::Width)
+                .remove(Param::Height)
+                .remove(Param::Duration);
+            let on_server = msg.server_uid != 0;
+            context
+                .sql
+                .transaction(move |transaction| {
+                    transaction.execute(
+                        "UPDATE msgs \
+                         SET type=?, txt_raw='', param=?, \
+                         ephemeral_timer=0, ephemeral_timestamp=0 \
+                         WHERE id=?",
+                        paramsv![Viewtype::Text, param.to_string(), msg_id],
+                    )?;
+                    transaction.execute(
+                        "DELETE FROM msgs_mime_headers WHERE msg_id=?",
+                        paramsv![msg_id],
+                    )?;
+                    if on_server {
+                        job::Job::new(
+                            job::Action::DeleteMsgOnImap,
+                            msg_id.to_u32(),
+                            Params::new(),
+                            0,
+                        )
+                        .insert(transaction)?;
+                    }
+                    Ok(())
+                })
+                .await?;
+            for key in &[Param::File, Param::FullText] {
+                delete_stripped_blob(context, msg_id, &msg.param, *key).await?;
+            }
+            stripped = true;
+        }
+    }
+    if stripped {
+        job::interrupt(context, job::Action::DeleteMsgOnImap).await;
+    }
+    Ok(stripped)
+}
+
+/// Deletes the file referenced by `key` in the params of the stripped message `msg_id`.
+///
+/// Only files directly inside the blob directory are deleted, attachments of messages
+/// not sent yet may still be at their original location (see [`Config::PassthroughAttachments`]),
+/// these belong to the user.
+/// Blobs still referenced by another message, eg. a forwarded copy, are kept.
+async fn delete_stripped_blob(
+    context: &Context,
+    msg_id: MsgId,
+    param: &Params,
+    key: Param,
+) -> Result<()> {
+    let value = match param.get(key) {
+        Some(value) => value,
+        None => return Ok(()),
     };
+    let path = match param.get_path(key, context) {
+        Ok(Some(path)) => path,
+        Ok(None) | Err(_) => return Ok(()),
+    };
+    if path.parent() != Some(context.get_blobdir()) {
+        return Ok(());
+    }
 
-    #[async_std::test]
-    async fn test_stock_ephemeral_messages() {
-        let context = TestContext::new().await;
+    let referenced = context
+        .sql
+        .query_map(
+            "SELECT param FROM msgs WHERE id!=? AND param LIKE ?",
+            paramsv![msg_id, format!("%{}%", value)],
+            |row| row.get::<_, String>(0),
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?
+        .into_iter()
+        .filter_map(|other| other.parse::<Params>().ok())
+        .any(|other| {
+            other.get(Param::File) == Some(value) || other.get(Param::FullText) == Some(value)
+        });
+    if !referenced {
+        dc_delete_file(context, &path).await;
+    }
+    Ok(())
+}
 
-        assert_eq!(
-            stock_ephemeral_timer_changed(&context, Timer::Disabled, DC_CONTACT_ID_SELF).await,
-            "Message deletion timer is disabled by me."
+/// Tells in an info message that `count` messages of the chat disappeared,
+/// so the deleted messages do not leave silent gaps in the chat.
+///
+/// If the last message of the chat is such an info message already,
+/// ie. no messages were added since the last deletion, its count is increased instead.
+async fn add_tombstone(context: &Context, chat_id: ChatId, count: u32) -> Result<()> {
+    let last_msg_id: Option<MsgId> = context
+        .sql
+        .query_get_value(
+            "SELECT last_msg_id FROM chats WHERE id=?;",
+            paramsv![chat_id],
+        )
+        .await?;
+    if let Some(last_msg_id) = last_msg_id.filter(|id| !id.is_unset()) {
+        let mut msg = Message::load_from_db(context, last_msg_id).await?;
+        if msg.get_info_type() == SystemMessage::MessagesDisappeared {
+            let total = msg.param.get_int(Param::Arg).unwrap_or_default() as u32 + count;
+            msg.param.set_int(Param::Arg, total as i32);
+            context
+                .sql
+                .execute(
+                    "UPDATE msgs SET txt=?, param=? WHERE id=?;",
+                    paramsv![
+                        stock_str::msgs_disappeared(context, total).await,
+                        msg.param.to_string(),
+                        msg.id
+                    ],
+                )
+                .await?;
+            context.emit_event(EventType::MsgsChanged {
+                chat_id,
+                msg_id: msg.id,
+            });
+            return Ok(());
+        }
+    }
+
+    let msg_id = chat::add_info_msg_with_cmd(
+        context,
+        chat_id,
+        stock_str::msgs_disappeared(context, count).await,
+        SystemMessage::MessagesDisappeared,
+    )
+    .await?;
+    let mut msg = Message::load_from_db(context, msg_id).await?;
+    msg.param.set_int(Param::Arg, count as i32);
+    msg.update_param(context).await;
+    Ok(())
+}
+
+/// Returns the self-chat and the device chat, which are not affected by `delete_device_after`.
+///
+/// The self-chat is only returned unless `Config::DeleteDeviceAfterIncludesSelfChat` is set.
+/// Chats which do not exist are returned as zero chat ID, which does not exclude anything.
+async fn delete_device_after_excluded_chats(context: &Context) -> Result<(ChatId, ChatId)> {
+    let self_chat_id = if context
+        .get_config_bool(Config::DeleteDeviceAfterIncludesSelfChat)
+        .await?
+    {
+        ChatId::new(0)
+    } else {
+        ChatId::lookup_by_contact(context, DC_CONTACT_ID_SELF)
+            .await?
+            .unwrap_or_default()
+    };
+    let device_chat_id = ChatId::lookup_by_contact(context, DC_CONTACT_ID_DEVICE)
+        .await?
+        .unwrap_or_default();
+    Ok((self_chat_id, device_chat_id))
+}
+
+/// Emits `MsgPreserved` for starred messages which are due for deletion
+/// but kept because of `Config::ProtectStarredFromDeletion`.
+///
+/// The event is emitted only once per message, reported messages are marked
+/// in the `preserved` column, which is reset when the message is unstarred.
+/// Runs from the ephemeral task, not when the chatlist is loaded.
+pub(crate) async fn report_preserved_msgs(context: &Context) -> Result<()> {
+    if !context
+        .get_config_bool(Config::ProtectStarredFromDeletion)
+        .await?
+    {
+        return Ok(());
+    }
+    let ephemeral_threshold = time() - context.get_config_i64(Config::EphemeralGracePeriod).await?;
+    // Without `delete_device_after`, a threshold of 0 does not match any message.
+    let device_threshold = match context.get_config_delete_device_after().await? {
+        Some(delete_device_after) => time() - delete_device_after,
+        None => 0,
+    };
+    let (self_chat_id, device_chat_id) = delete_device_after_excluded_chats(context).await?;
+
+    let msgs = context
+        .sql
+        .query_map(
+            "SELECT id, chat_id FROM msgs \
+             WHERE starred=1 AND preserved=0 \
+             AND chat_id > ? \
+             AND ((ephemeral_timestamp != 0 AND ephemeral_timestamp <= ?) \
+             OR (timestamp < ? AND timestamp_rcvd < ? \
+             AND chat_id != ? AND chat_id != ?))",
+            paramsv![
+                DC_CHAT_ID_LAST_SPECIAL,
+                ephemeral_threshold,
+                device_threshold,
+                device_threshold,
+                self_chat_id,
+                device_chat_id
+            ],
+            |row| Ok((row.get::<_, MsgId>(0)?, row.get::<_, ChatId>(1)?)),
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+    for (msg_id, chat_id) in msgs {
+        context
+            .sql
+            .execute("UPDATE msgs SET preserved=1 WHERE id=?", paramsv![msg_id])
+            .await?;
+        context.emit_event(EventType::MsgPreserved { chat_id, msg_id });
+    }
+    Ok(())
+}
+
+/// Returns the command of a message with the serialized params `param`,
+/// or `None` if the params cannot be parsed.
+fn get_cmd(param: &str) -> Option<SystemMessage> {
+    param.parse::<Params>().ok().map(|param| param.get_cmd())
+}
+
+/// Returns true if `cmd` marks an info message, see [`Message::is_info`].
+///
+/// Messages with unparsable params are no info messages.
+fn is_info_cmd(cmd: Option<SystemMessage>) -> bool {
+    !matches!(
+        cmd,
+        None | Some(SystemMessage::Unknown) | Some(SystemMessage::AutocryptSetupMessage)
+    )
+}
+
+/// Deletes info messages which are expired according to `delete_info_msgs_after` setting.
+///
+/// In each chat, the latest info message of each kind of system message,
+/// eg. the latest group image change, is kept.
+/// Runs during housekeeping, as it scans all info messages.
+/// Returns true if any message is deleted.
+pub(crate) async fn delete_old_info_msgs(context: &Context) -> Result<bool> {
+    let delete_info_msgs_after = context.get_config_i64(Config::DeleteInfoMsgsAfter).await?;
+    if delete_info_msgs_after <= 0 {
+        return Ok(false);
+    }
+    let threshold_timestamp = time() - delete_info_msgs_after;
+
+    // The `LIKE` conditions only preselect messages with a command,
+    // the params are checked exactly below.
+    let candidates = context
+        .sql
+        .query_map(
+            "SELECT id, chat_id, from_id, timestamp, param FROM msgs \
+             WHERE chat_id > ? \
+             AND (from_id = ? OR param LIKE 'S=%' OR param LIKE '%' || char(10) || 'S=%') \
+             ORDER BY timestamp DESC, id DESC",
+            paramsv![DC_CHAT_ID_LAST_SPECIAL, DC_CONTACT_ID_INFO],
+            |row| {
+                let msg_id: MsgId = row.get(0)?;
+                let chat_id: ChatId = row.get(1)?;
+                let from_id: u32 = row.get(2)?;
+                let timestamp: i64 = row.get(3)?;
+                let param: String = row.get(4)?;
+                Ok((msg_id, chat_id, from_id, timestamp, param))
+            },
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+
+    let mut latest = HashSet::new();
+    let mut expired = Vec::new();
+    for (msg_id, chat_id, from_id, timestamp, param) in candidates {
+        let cmd = match get_cmd(&param) {
+            Some(cmd) => cmd,
+            None => continue,
+        };
+        if from_id != DC_CONTACT_ID_INFO && !is_info_cmd(Some(cmd)) {
+            continue;
+        }
+        // Messages are sorted newest first,
+        // so the first message of each kind is the latest state change.
+        let is_latest = cmd != SystemMessage::Unknown && latest.insert((chat_id, cmd as u32));
+        if !is_latest && timestamp < threshold_timestamp {
+            expired.push(msg_id);
+        }
+    }
+
+    for msg_id in &expired {
+        msg_id.trash(context).await?;
+    }
+    if !expired.is_empty() {
+        info!(context, "Deleted {} old info messages", expired.len());
+    }
+    Ok(!expired.is_empty())
+}
+
+/// Removes messages from the trash chat which are older than `older_than` seconds,
+/// even if they may still have a copy on the server.
+///
+/// Normally, trashed messages are kept until their server copy is deleted. If the
+/// server copy was deleted out-of-band and this was not detected, they stay forever.
+/// Messages still waiting for a server deletion job are not removed.
+/// Returns the number of removed messages.
+pub async fn purge_stale_trash(context: &Context, older_than: i64) -> Result<usize> {
+    let threshold_timestamp = time() - older_than;
+    let purged = context
+        .sql
+        .execute(
+            "DELETE FROM msgs \
+             WHERE chat_id=? \
+             AND timestamp<? \
+             AND id NOT IN (SELECT foreign_id FROM jobs WHERE action=?)",
+            paramsv![
+                DC_CHAT_ID_TRASH,
+                threshold_timestamp,
+                job::Action::DeleteMsgOnImap
+            ],
+        )
+        .await
+        .context("failed to purge stale trash")?;
+    if purged > 0 {
+        info!(context, "Purged {} stale messages from trash", purged);
+    }
+    Ok(purged)
+}
+
+/// Returns the messages whose ephemeral timer is running together with the timestamps
+/// at which they will be deleted, the next deletion first.
+///
+/// Like [`schedule_ephemeral_task`], this considers only per-chat timers.
+pub async fn get_pending_expirations(context: &Context) -> Result<Vec<(MsgId, i64)>> {
+    let protect_starred = context
+        .get_config_bool(Config::ProtectStarredFromDeletion)
+        .await?;
+    context
+        .sql
+        .query_map(
+            r#"
+    SELECT id, ephemeral_timestamp
+    FROM msgs
+    WHERE ephemeral_timestamp != 0
+      AND chat_id != ?
+      AND (starred=0 OR NOT ?)
+    ORDER BY ephemeral_timestamp ASC, id ASC;
+    "#,
+            // Trash contains already deleted messages, skip them
+            paramsv![DC_CHAT_ID_TRASH, protect_starred],
+            |row| Ok((row.get::<_, MsgId>(0)?, row.get::<_, i64>(1)?)),
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await
+}
+
+/// Returns the timestamp at which the ephemeral timer of the next message expires, if any.
+///
+/// If `Config::EphemeralGracePeriod` is set,
+/// the message is deleted after the grace period.
+pub async fn next_expiration_timestamp(context: &Context) -> Result<Option<i64>> {
+    let protect_starred = context
+        .get_config_bool(Config::ProtectStarredFromDeletion)
+        .await?;
+    context
+        .sql
+        .query_get_value(
+            r#"
+    SELECT ephemeral_timestamp
+    FROM msgs
+    WHERE ephemeral_timestamp != 0
+      AND chat_id != ?
+      AND (starred=0 OR NOT ?)
+    ORDER BY ephemeral_timestamp ASC
+    LIMIT 1;
+    "#,
+            // Trash contains already deleted messages, skip them
+            paramsv![DC_CHAT_ID_TRASH, protect_starred],
+        )
+        .await
+}
+
+/// Returns the time at which the next ephemeral timer expires or, if the timer
+/// of a message expired already, the time at which its grace period ends.
+async fn next_ephemeral_task_timestamp(context: &Context) -> Result<Option<i64>> {
+    // Starred messages are not deleted if protected, skip them to avoid scheduling
+    // the task again and again for messages which are already due.
+    let protect_starred = context
+        .get_config_bool(Config::ProtectStarredFromDeletion)
+        .await?;
+    let grace_period = context.get_config_i64(Config::EphemeralGracePeriod).await?;
+    let undelivered_grace_period = context
+        .get_config_i64(Config::UndeliveredGracePeriod)
+        .await?;
+    context
+        .sql
+        .query_get_value(
+            r#"
+    SELECT MIN(CASE WHEN ephemeral_timestamp > ?
+                    THEN ephemeral_timestamp
+                    WHEN state = ?
+                    THEN ephemeral_timestamp + ? + ?
+                    ELSE ephemeral_timestamp + ? END)
+    FROM msgs
+    WHERE ephemeral_timestamp != 0
+      AND chat_id != ?
+      AND (starred=0 OR NOT ?);
+    "#,
+            // Trash contains already deleted messages, skip them
+            paramsv![
+                time(),
+                MessageState::OutPending,
+                grace_period,
+                undelivered_grace_period,
+                grace_period,
+                DC_CHAT_ID_TRASH,
+                protect_starred
+            ],
+        )
+        .await
+}
+
+/// Maximum time the ephemeral task sleeps before checking for expired messages again.
+///
+/// Expiry timestamps are compared to the system clock, so this limits the delay
+/// caused by changes of the clock.
+const EPHEMERAL_TASK_MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// Background task deleting expired messages.
+///
+/// The task is started when the context is opened and runs independently of IO,
+/// so messages expire also while IO is stopped. It is only paused while
+/// the database is closed or used by another context.
+#[derive(Debug)]
+pub(crate) struct EphemeralTask {
+    handle: task::JoinHandle<()>,
+    interrupt: Sender<()>,
+}
+
+impl EphemeralTask {
+    fn start(context: &Context) -> Self {
+        let (interrupt, receiver) = channel::bounded(1);
+        let handle = task::spawn(ephemeral_loop(Arc::downgrade(&context.inner), receiver));
+        Self { handle, interrupt }
+    }
+
+    pub(crate) async fn stop(self) {
+        self.handle.cancel().await;
+    }
+}
+
+/// Starts the ephemeral task unless it is running already.
+pub(crate) async fn start_ephemeral_task(context: &Context) {
+    let mut ephemeral_task = context.ephemeral_task.write().await;
+    if ephemeral_task.is_none() {
+        *ephemeral_task = Some(EphemeralTask::start(context));
+    }
+}
+
+/// Stops the ephemeral task, eg. before the database is closed.
+pub(crate) async fn stop_ephemeral_task(context: &Context) {
+    let ephemeral_task = context.ephemeral_task.write().await.take();
+    if let Some(ephemeral_task) = ephemeral_task {
+        ephemeral_task.stop().await;
+    }
+}
+
+/// Wakes up the ephemeral task, so that it deletes expired messages and
+/// calculates the time of the next deletion again.
+///
+/// Expired messages are also deleted when the chatlist or chat is loaded,
+/// eg. while the task is paused.
+///
+/// This takes into account only per-chat timeouts, because global device
+/// timeouts are at least one hour long and deletion is triggered often enough
+/// by user actions.
+pub async fn schedule_ephemeral_task(context: &Context) {
+    if let Some(ephemeral_task) = &*context.ephemeral_task.read().await {
+        // If the channel is full, the task is woken up already.
+        ephemeral_task.interrupt.try_send(()).ok();
+    }
+}
+
+/// Only holds a weak reference to the context between iterations,
+/// so the loop ends when the context is dropped.
+async fn ephemeral_loop(inner: Weak<InnerContext>, interrupt: Receiver<()>) {
+    let mut due_timestamp = None;
+    loop {
+        let context = match inner.upgrade() {
+            Some(inner) => Context { inner },
+            None => break,
+        };
+        let deleted = delete_expired_messages(&context)
+            .await
+            .unwrap_or_else(|err| {
+                warn!(context, "Failed to delete expired messages: {}", err);
+                false
+            });
+        if let Err(err) = report_preserved_msgs(&context).await {
+            warn!(context, "Failed to report preserved messages: {}", err);
+        }
+        // Deleting messages wakes up the task itself,
+        // the next timestamp is calculated below anyway.
+        while interrupt.try_recv().is_ok() {}
+
+        let timer_expired = due_timestamp.map_or(false, |timestamp| timestamp < time());
+        if deleted || timer_expired {
+            emit_event!(
+                context,
+                EventType::MsgsChanged {
+                    chat_id: ChatId::new(0),
+                    msg_id: MsgId::new(0)
+                }
+            );
+        }
+
+        due_timestamp = next_ephemeral_task_timestamp(&context)
+            .await
+            .unwrap_or_else(|err| {
+                warn!(context, "Can't calculate next ephemeral timeout: {}", err);
+                None
+            });
+        let duration = match due_timestamp {
+            Some(timestamp) => {
+                let seconds = u64::try_from(timestamp + 1 - time()).unwrap_or_default();
+                Duration::from_secs(seconds.max(1)).min(EPHEMERAL_TASK_MAX_SLEEP)
+            }
+            None => EPHEMERAL_TASK_MAX_SLEEP,
+        };
+        drop(context);
+        if let Ok(Err(_)) = future::timeout(duration, interrupt.recv()).await {
+            break;
+        }
+    }
+}
+
+/// Maximum number of messages deleted from the server by a single job.
+const IMAP_DELETION_BATCH_SIZE: u32 = 1000;
+
+/// Returns IDs of expired messages that should be deleted from the server,
+/// at most [`IMAP_DELETION_BATCH_SIZE`].
+///
+/// It looks up the trash chat too, to find messages that are already
+/// deleted locally, but not deleted on the server.
+///
+/// Outgoing messages, eg. BCC-self copies, use `Config::DeleteServerAfterSelf`
+/// if it is set.
+pub(crate) async fn load_imap_deletion_msgids(context: &Context) -> anyhow::Result<Vec<MsgId>> {
+    let now = time();
+
+    let threshold_timestamp = match context.get_config_delete_server_after().await? {
+        None => 0,
+        Some(delete_server_after) => now - delete_server_after,
+    };
+    let self_threshold_timestamp = match context.get_config_delete_server_after_self().await? {
+        None => 0,
+        Some(delete_server_after) => now - delete_server_after,
+    };
+    // Expired messages are kept on the server during the grace period,
+    // so they can still be restored if the user keeps them.
+    let ephemeral_threshold = now - context.get_config_i64(Config::EphemeralGracePeriod).await?;
+    let only_seen = context
+        .get_config_bool(Config::DeleteServerOnlySeen)
+        .await?;
+
+    context
+        .sql
+        .query_map(
+            "SELECT id FROM msgs \
+         WHERE ( \
+         (from_id != ? AND timestamp < ? AND (state = ? OR NOT ?)) \
+         OR (from_id = ? AND timestamp < ?) \
+         OR (ephemeral_timestamp != 0 AND ephemeral_timestamp <= ?) \
+         OR (view_once = 1 AND state = ?) \
+         ) \
+         AND server_uid != 0 \
+         AND NOT id IN (SELECT foreign_id FROM jobs WHERE action = ?)
+         ORDER BY id
+         LIMIT ?",
+            paramsv![
+                DC_CONTACT_ID_SELF,
+                threshold_timestamp,
+                MessageState::InSeen,
+                only_seen,
+                DC_CONTACT_ID_SELF,
+                self_threshold_timestamp,
+                ephemeral_threshold,
+                MessageState::InSeen,
+                job::Action::DeleteMsgOnImap,
+                IMAP_DELETION_BATCH_SIZE
+            ],
+            |row| row.get::<_, MsgId>(0),
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await
+}
+
+/// Start ephemeral timers for seen messages if they are not started
+/// yet.
+///
+/// It is possible that timers are not started due to a missing or
+/// failed `MsgId.start_ephemeral_timer()` call, either in the current
+/// or previous version of Delta Chat.
+///
+/// This function is supposed to be called in the background,
+/// e.g. from housekeeping task.
+///
+/// Returns the number of messages whose timer was started.
+pub(crate) async fn start_ephemeral_timers(context: &Context) -> Result<usize> {
+    let started = context
+        .sql
+        .execute(
+            "UPDATE msgs \
+    SET ephemeral_timestamp = ? + ephemeral_timer \
+    WHERE ephemeral_timer > 0 \
+    AND ephemeral_timestamp = 0 \
+    AND state NOT IN (?, ?, ?)",
+            paramsv![
+                time(),
+                MessageState::InFresh,
+                MessageState::InNoticed,
+                MessageState::OutDraft
+            ],
+        )
+        .await?;
+
+    Ok(started)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::message;
+    use crate::param::Params;
+    use async_std::task::sleep;
+
+    use super::*;
+    use crate::contact::Origin;
+    use crate::test_utils::TestContext;
+    use crate::{
+        chat::{self, Chat, ChatItem},
+        dc_tools::IsNoneOrEmpty,
+    };
+
+    #[async_std::test]
+    async fn test_stock_ephemeral_messages() {
+        let context = TestContext::new().await;
+
+        assert_eq!(
+            stock_ephemeral_timer_changed(&context, Timer::Disabled, DC_CONTACT_ID_SELF).await,
+            "Message deletion timer is disabled by me."
         );
 
         assert_eq!(
@@ -614,144 +2104,1278 @@ async fn test_stock_ephemeral_messages() {
                 },
                 DC_CONTACT_ID_SELF
             )
-            .await,
-            "Message deletion timer is set to 2 days by me."
-        );
+            .await,
+            "Message deletion timer is set to 2 days by me."
+        );
+        assert_eq!(
+            stock_ephemeral_timer_changed(
+                &context,
+                Timer::Enabled {
+                    duration: 7 * 24 * 60 * 60
+                },
+                DC_CONTACT_ID_SELF
+            )
+            .await,
+            "Message deletion timer is set to 1 week by me."
+        );
+        assert_eq!(
+            stock_ephemeral_timer_changed(
+                &context,
+                Timer::Enabled {
+                    duration: 4 * 7 * 24 * 60 * 60
+                },
+                DC_CONTACT_ID_SELF
+            )
+            .await,
+            "Message deletion timer is set to 4 weeks by me."
+        );
+    }
+
+    #[async_std::test]
+    async fn test_ephemeral_timer() -> anyhow::Result<()> {
+        let alice = TestContext::new_alice().await;
+        let bob = TestContext::new_bob().await;
+
+        let chat_alice = alice.create_chat(&bob).await.id;
+        let chat_bob = bob.create_chat(&alice).await.id;
+
+        // Alice sends message to Bob
+        let mut msg = Message::new(Viewtype::Text);
+        chat::prepare_msg(&alice.ctx, chat_alice, &mut msg).await?;
+        chat::send_msg(&alice.ctx, chat_alice, &mut msg).await?;
+        let sent = alice.pop_sent_msg().await;
+        bob.recv_msg(&sent).await;
+
+        // Alice sends second message to Bob, with no timer
+        let mut msg = Message::new(Viewtype::Text);
+        chat::prepare_msg(&alice.ctx, chat_alice, &mut msg).await?;
+        chat::send_msg(&alice.ctx, chat_alice, &mut msg).await?;
+        let sent = alice.pop_sent_msg().await;
+
+        assert_eq!(
+            chat_bob.get_ephemeral_timer(&bob.ctx).await?,
+            Timer::Disabled
+        );
+
+        // Bob sets ephemeral timer and sends a message about timer change
+        chat_bob
+            .set_ephemeral_timer(&bob.ctx, Timer::Enabled { duration: 60 })
+            .await?;
+        let sent_timer_change = bob.pop_sent_msg().await;
+
+        assert_eq!(
+            chat_bob.get_ephemeral_timer(&bob.ctx).await?,
+            Timer::Enabled { duration: 60 }
+        );
+
+        // Bob receives message from Alice.
+        // Alice message has no timer. However, Bob should not disable timer,
+        // because Alice replies to old message.
+        bob.recv_msg(&sent).await;
+
+        assert_eq!(
+            chat_alice.get_ephemeral_timer(&alice.ctx).await?,
+            Timer::Disabled
+        );
+        assert_eq!(
+            chat_bob.get_ephemeral_timer(&bob.ctx).await?,
+            Timer::Enabled { duration: 60 }
+        );
+
+        // Alice receives message from Bob
+        alice.recv_msg(&sent_timer_change).await;
+
+        assert_eq!(
+            chat_alice.get_ephemeral_timer(&alice.ctx).await?,
+            Timer::Enabled { duration: 60 }
+        );
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_get_pending_expirations() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat_id = t.get_self_chat().await.id;
+        assert!(get_pending_expirations(&t).await?.is_empty());
+        assert_eq!(next_expiration_timestamp(&t).await?, None);
+
+        t.send_text(chat_id, "not ephemeral").await;
+        chat_id
+            .set_ephemeral_timer(&t, Timer::Enabled { duration: 3600 })
+            .await?;
+        let msg_id = t.send_text(chat_id, "ephemeral").await.sender_msg_id;
+        let ephemeral_timestamp = Message::load_from_db(&t, msg_id)
+            .await?
+            .get_ephemeral_timestamp();
+        assert!(ephemeral_timestamp > time());
+
+        let pending = get_pending_expirations(&t).await?;
+        assert!(pending.contains(&(msg_id, ephemeral_timestamp)));
+        assert_eq!(
+            next_expiration_timestamp(&t).await?,
+            pending.first().map(|(_, timestamp)| *timestamp)
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_ephemeral_timer_silently() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        let bob = TestContext::new_bob().await;
+
+        let chat_alice = alice.create_chat(&bob).await.id;
+        let chat_bob = bob.create_chat(&alice).await.id;
+
+        chat_alice
+            .set_ephemeral_timer_silently(&alice, Timer::Enabled { duration: 60 })
+            .await?;
+        assert_eq!(
+            chat_alice.get_ephemeral_timer(&alice).await?,
+            Timer::Enabled { duration: 60 }
+        );
+        assert!(chat::get_chat_msgs(&alice, chat_alice, 0, None)
+            .await?
+            .is_empty());
+
+        // The timer is propagated with the next message.
+        let sent = alice.send_text(chat_alice, "hi").await;
+        bob.recv_msg(&sent).await;
+        assert_eq!(
+            chat_bob.get_ephemeral_timer(&bob).await?,
+            Timer::Enabled { duration: 60 }
+        );
+
+        // Groups always get a message about the change.
+        let group_id =
+            chat::create_group_chat(&alice, chat::ProtectionStatus::Unprotected, "foo").await?;
+        assert!(group_id
+            .set_ephemeral_timer_silently(&alice, Timer::Enabled { duration: 60 })
+            .await
+            .is_err());
+        assert_eq!(group_id.get_ephemeral_timer(&alice).await?, Timer::Disabled);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_ephemeral_timer_changes_coalesced() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        let bob = TestContext::new_bob().await;
+
+        let chat_alice = alice.create_chat(&bob).await.id;
+        let chat_bob = bob.create_chat(&alice).await.id;
+
+        // Alice changes the timer three times in a row.
+        for duration in &[60, 3600, 86400] {
+            chat_alice
+                .set_ephemeral_timer(
+                    &alice,
+                    Timer::Enabled {
+                        duration: *duration,
+                    },
+                )
+                .await?;
+            let sent = alice.pop_sent_msg().await;
+            bob.recv_msg(&sent).await;
+        }
+        assert_eq!(
+            chat_bob.get_ephemeral_timer(&bob).await?,
+            Timer::Enabled { duration: 86400 }
+        );
+
+        // Bob only sees the last change.
+        let items = chat::get_chat_msgs(&bob, chat_bob, 0, None).await?;
+        let mut timer_changes = Vec::new();
+        for item in items {
+            if let ChatItem::Message { msg_id } = item {
+                let msg = Message::load_from_db(&bob, msg_id).await?;
+                if msg.get_info_type() == SystemMessage::EphemeralTimerChanged {
+                    timer_changes.push(msg);
+                }
+            }
+        }
+        assert_eq!(timer_changes.len(), 1);
+        let timer_change = timer_changes.first().unwrap();
+        assert_eq!(
+            timer_change.get_text().unwrap(),
+            stock_ephemeral_timer_changed(
+                &bob,
+                Timer::Enabled { duration: 86400 },
+                timer_change.get_from_id()
+            )
+            .await
+        );
+
+        // A message in between ends the coalescing.
+        let sent = alice.send_text(chat_alice, "hi").await;
+        bob.recv_msg(&sent).await;
+        chat_alice
+            .set_ephemeral_timer(&alice, Timer::Disabled)
+            .await?;
+        let sent = alice.pop_sent_msg().await;
+        bob.recv_msg(&sent).await;
+        let msgs = chat::get_chat_msgs(&bob, chat_bob, 0, None).await?;
+        assert_eq!(msgs.len(), 3);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_sync_device_settings() -> Result<()> {
+        let alice1 = TestContext::new_alice().await;
+        let alice2 = TestContext::new_alice().await;
+        alice1
+            .set_config_bool(Config::SyncDeviceSettings, true)
+            .await?;
+        alice2
+            .set_config_bool(Config::SyncDeviceSettings, true)
+            .await?;
+
+        alice1
+            .set_config(Config::DeleteDeviceAfter, Some("3600"))
+            .await?;
+        let sent = alice1.pop_sent_msg().await;
+        let msg = Message::load_from_db(&alice1, sent.sender_msg_id).await?;
+        assert!(msg.hidden);
+        assert!(msg.get_showpadlock());
+
+        alice2.recv_msg(&sent).await;
+        assert_eq!(
+            alice2.get_config_int(Config::DeleteDeviceAfter).await?,
+            3600
+        );
+        assert_eq!(alice2.get_config_int(Config::DeleteServerAfter).await?, 0);
+
+        // Settings changed on the second device later are not overwritten.
+        alice1
+            .set_config(Config::DeleteServerAfter, Some("7200"))
+            .await?;
+        let sent = alice1.pop_sent_msg().await;
+        alice2
+            .sql
+            .set_raw_config_int64(DEVICE_SETTINGS_TIMESTAMP, time() + 100)
+            .await?;
+        alice2.recv_msg(&sent).await;
+        assert_eq!(alice2.get_config_int(Config::DeleteServerAfter).await?, 0);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_reconcile_clock() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat = t.get_self_chat().await;
+        chat.id
+            .set_ephemeral_timer(&t, Timer::Enabled { duration: 86400 })
+            .await?;
+        let msg_id = t
+            .send_text(chat.id, "expiring in a day")
+            .await
+            .sender_msg_id;
+        async fn get_timestamp(t: &TestContext, msg_id: MsgId) -> i64 {
+            Message::load_from_db(t, msg_id)
+                .await
+                .unwrap()
+                .ephemeral_timestamp
+        }
+        let timestamp = get_timestamp(&t, msg_id).await;
+        assert!(timestamp > time());
+
+        // Small differences are ignored.
+        delete_expired_messages(&t).await?;
+        assert_eq!(get_timestamp(&t, msg_id).await, timestamp);
+
+        // The clock went back by two hours while the context was open.
+        *t.ephemeral_clock.lock().await = Some(ClockReference {
+            instant: Instant::now(),
+            timestamp: time() + 7200,
+        });
+        delete_expired_messages(&t).await?;
+        let adjusted = get_timestamp(&t, msg_id).await;
+        assert!((timestamp - 7200 - adjusted).abs() <= 1);
+
+        // Without a reference from this process, timers are not adjusted.
+        *t.ephemeral_clock.lock().await = None;
+        delete_expired_messages(&t).await?;
+        assert_eq!(get_timestamp(&t, msg_id).await, adjusted);
+        assert!(t.ephemeral_clock.lock().await.is_some());
+
+        let msg = Message::load_from_db(&t, msg_id).await?;
+        assert_eq!(msg.chat_id, chat.id);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_ephemeral_task() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        // IO is not started, the task runs anyway.
+        start_ephemeral_task(&t).await;
+        let chat = t.get_self_chat().await;
+        chat.id
+            .set_ephemeral_timer(&t, Timer::Enabled { duration: 1 })
+            .await?;
+        let msg = t.send_text(chat.id, "disappearing after 1s").await;
+
+        // The task deletes the message without the chat being loaded.
+        let mut deleted = false;
+        for _ in 0..50 {
+            sleep(Duration::from_millis(100)).await;
+            let msg = Message::load_from_db(&t, msg.sender_msg_id).await?;
+            if msg.chat_id == DC_CHAT_ID_TRASH {
+                deleted = true;
+                break;
+            }
+        }
+        assert!(deleted);
+
+        stop_ephemeral_task(&t).await;
+        assert!(t.ephemeral_task.read().await.is_none());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_ephemeral_delete_msgs() {
+        let t = TestContext::new_alice().await;
+        let chat = t.get_self_chat().await;
+
+        t.send_text(chat.id, "Saved message, which we delete manually")
+            .await;
+        let msg = t.get_last_msg_in(chat.id).await;
+        msg.id.delete_from_db(&t).await.unwrap();
+        check_msg_was_deleted(&t, &chat, msg.id).await;
+
+        chat.id
+            .set_ephemeral_timer(&t, Timer::Enabled { duration: 1 })
+            .await
+            .unwrap();
+        let msg = t
+            .send_text(chat.id, "Saved message, disappearing after 1s")
+            .await;
+
+        sleep(Duration::from_millis(1100)).await;
+
+        // Check checks that the msg was deleted locally
+        check_msg_was_deleted(&t, &chat, msg.sender_msg_id).await;
+
+        // Check that the msg will be deleted on the server
+        // First of all, set a server_uid so that DC thinks that it's actually possible to delete
+        t.sql
+            .execute(
+                "UPDATE msgs SET server_uid=1 WHERE id=?",
+                paramsv![msg.sender_msg_id],
+            )
+            .await
+            .unwrap();
+        let job = job::load_imap_deletion_job(&t).await.unwrap().unwrap();
+        assert_eq!(job.action, job::Action::DeleteMsgsOnImap);
+        assert_eq!(
+            job.param.get(Param::MsgIds),
+            Some(msg.sender_msg_id.to_u32().to_string().as_str())
+        );
+
+        // Let's assume that deleting the message in a batch fails
+        // and a job for the single message is saved to the db
+        job::Job::new(
+            job::Action::DeleteMsgOnImap,
+            msg.sender_msg_id.to_u32(),
+            Params::new(),
+            0,
+        )
+        .save(&t)
+        .await
+        .unwrap();
+
+        // Make sure that we don't get yet another job when loading from db
+        let job2 = job::load_imap_deletion_job(&t).await.unwrap();
+        assert_eq!(job2, None);
+    }
+
+    #[async_std::test]
+    async fn test_ephemeral_timer_start_on_delivery() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        let bob = TestContext::new_bob().await;
+        let alice_chat = alice.create_chat(&bob).await;
+        let bob_chat = bob.create_chat(&alice).await;
+        assert_eq!(
+            alice_chat.id.get_ephemeral_timer_start(&alice).await?,
+            TimerStart::Read
+        );
+
+        alice_chat
+            .id
+            .set_ephemeral_timer(&alice, Timer::Enabled { duration: 60 })
+            .await?;
+        bob.recv_msg(&alice.pop_sent_msg().await).await;
+        alice_chat
+            .id
+            .set_ephemeral_timer_start(&alice, TimerStart::Delivery)
+            .await?;
+
+        let sent = alice.send_text(alice_chat.id, "disappears unread").await;
+        assert!(sent.payload().contains("Ephemeral-Timer-Start: delivery"));
+        bob.recv_msg(&sent).await;
+        assert_eq!(
+            bob_chat.id.get_ephemeral_timer_start(&bob).await?,
+            TimerStart::Delivery
+        );
+        let msg = bob.get_last_msg().await;
+        assert_eq!(msg.state, MessageState::InFresh);
+        assert!(msg.get_ephemeral_timestamp() > 0);
+        assert!(msg.get_ephemeral_timestamp() <= time() + 60);
+
+        // The setting is switched back with the next message.
+        alice_chat
+            .id
+            .set_ephemeral_timer_start(&alice, TimerStart::Read)
+            .await?;
+        bob.recv_msg(&alice.send_text(alice_chat.id, "hi").await)
+            .await;
+        assert_eq!(
+            bob_chat.id.get_ephemeral_timer_start(&bob).await?,
+            TimerStart::Read
+        );
+        assert_eq!(bob.get_last_msg().await.get_ephemeral_timestamp(), 0);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_view_once() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        let bob = TestContext::new_bob().await;
+        let alice_chat = alice.create_chat(&bob).await;
+        let bob_chat = bob.create_chat(&alice).await;
+
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("view once".to_string()));
+        msg.set_view_once(true);
+        send_msg(&alice, alice_chat.id, &mut msg).await?;
+        let sent = alice.pop_sent_msg().await;
+        assert!(sent.payload().contains("Chat-View-Once: 1"));
+
+        bob.recv_msg(&sent).await;
+        let msg = bob.get_last_msg().await;
+        assert!(msg.is_view_once());
+        assert_eq!(msg.get_ephemeral_timer(), Timer::Disabled);
+
+        // Not deleted before it is seen.
+        assert!(!delete_expired_messages(&bob).await?);
+        assert_eq!(
+            Message::load_from_db(&bob, msg.id).await?.chat_id,
+            bob_chat.id
+        );
+
+        // Deleted when it is marked as seen.
+        message::markseen_msgs(&bob, vec![msg.id]).await?;
+        check_msg_was_deleted(&bob, &bob_chat, msg.id).await;
+        assert!(!delete_seen_view_once_msgs(&bob).await?);
+
+        // The copy of the sender is kept.
+        delete_expired_messages(&alice).await?;
+        let msg = Message::load_from_db(&alice, sent.sender_msg_id).await?;
+        assert_eq!(msg.chat_id, alice_chat.id);
+        assert!(msg.is_view_once());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_protect_starred_from_deletion() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        t.set_config_bool(Config::ProtectStarredFromDeletion, true)
+            .await?;
+        let chat = t.get_self_chat().await;
+        chat.id
+            .set_ephemeral_timer(&t, Timer::Enabled { duration: 1 })
+            .await?;
+        let starred = t.send_text(chat.id, "starred").await;
+        let unstarred = t.send_text(chat.id, "unstarred").await;
+        message::star_msgs(&t, vec![starred.sender_msg_id], true).await?;
+        assert!(Message::load_from_db(&t, starred.sender_msg_id)
+            .await?
+            .is_starred());
+
+        sleep(Duration::from_millis(1100)).await;
+        assert!(delete_expired_messages(&t).await?);
+        let msg = Message::load_from_db(&t, starred.sender_msg_id).await?;
+        assert_eq!(msg.chat_id, chat.id);
+        assert_eq!(msg.get_text(), Some("starred".to_string()));
+        let msg = Message::load_from_db(&t, unstarred.sender_msg_id).await?;
+        assert_eq!(msg.chat_id, DC_CHAT_ID_TRASH);
+        report_preserved_msgs(&t).await?;
+        loop {
+            if let EventType::MsgPreserved { msg_id, .. } = t.evtracker.recv().await? {
+                assert_eq!(msg_id, starred.sender_msg_id);
+                break;
+            }
+        }
+        // The message is marked as reported, so it is not loaded again.
+        let preserved_cnt = "SELECT COUNT(*) FROM msgs WHERE preserved=1";
+        assert_eq!(t.sql.count(preserved_cnt, paramsv![]).await?, 1);
+
+        // Unstarred messages are deleted again.
+        message::star_msgs(&t, vec![starred.sender_msg_id], false).await?;
+        assert_eq!(t.sql.count(preserved_cnt, paramsv![]).await?, 0);
+        assert!(delete_expired_messages(&t).await?);
+        check_msg_was_deleted(&t, &chat, starred.sender_msg_id).await;
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_ephemeral_tombstone() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat = t.get_self_chat().await;
+        chat.id
+            .set_ephemeral_timer(&t, Timer::Enabled { duration: 1 })
+            .await?;
+        t.send_text(chat.id, "one").await;
+        let two = t.send_text(chat.id, "two").await.sender_msg_id;
+
+        // Params merely containing `S=` do not make a message an info message.
+        t.sql
+            .execute(
+                "UPDATE msgs SET param='f=$BLOBDIR/S=1.txt' WHERE id=?",
+                paramsv![two],
+            )
+            .await?;
+
+        sleep(Duration::from_millis(1100)).await;
+        assert!(delete_expired_messages(&t).await?);
+        let tombstone = t.get_last_msg_in(chat.id).await;
+        assert_eq!(
+            tombstone.get_info_type(),
+            SystemMessage::MessagesDisappeared
+        );
+        assert_eq!(
+            tombstone.get_text(),
+            Some("2 messages disappeared.".to_string())
+        );
+
+        // Consecutive deletions are counted in the same info message.
+        t.send_text(chat.id, "three").await;
+        sleep(Duration::from_millis(1100)).await;
+        assert!(delete_expired_messages(&t).await?);
+        let msg = t.get_last_msg_in(chat.id).await;
+        assert_eq!(msg.id, tombstone.id);
+        assert_eq!(msg.get_text(), Some("3 messages disappeared.".to_string()));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_ephemeral_expiry_mode_attachment_only() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat = t.get_self_chat().await;
+        assert_eq!(
+            chat.id.get_ephemeral_expiry_mode(&t).await?,
+            ExpiryMode::Full
+        );
+        chat.id
+            .set_ephemeral_expiry_mode(&t, ExpiryMode::AttachmentOnly)
+            .await?;
+        chat.id
+            .set_ephemeral_timer(&t, Timer::Enabled { duration: 1 })
+            .await?;
+
+        let file = t.get_blobdir().join("secret.txt");
+        async_std::fs::write(&file, b"secret").await?;
+        let mut msg = Message::new(Viewtype::File);
+        msg.set_text(Some("see attachment".to_string()));
+        msg.set_file(file.to_str().unwrap(), None);
+        let sent = t.send_msg(chat.id, &mut msg).await;
+
+        sleep(Duration::from_millis(1100)).await;
+        assert!(delete_expired_messages(&t).await?);
+        let msg = Message::load_from_db(&t, sent.sender_msg_id).await?;
+        assert_eq!(msg.chat_id, chat.id);
+        assert_eq!(msg.get_viewtype(), Viewtype::Text);
+        assert_eq!(msg.get_text(), Some("see attachment".to_string()));
+        assert!(msg.get_file(&t).is_none());
+        assert_eq!(msg.get_ephemeral_timestamp(), 0);
+        assert!(!file.exists().await);
+
+        // The remaining text is not deleted later.
+        assert!(!delete_expired_messages(&t).await?);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_ephemeral_expiry_mode_attachment_only_passthrough() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        t.set_config_bool(Config::PassthroughAttachments, true)
+            .await?;
+        let chat = t.get_self_chat().await;
+        chat.id
+            .set_ephemeral_expiry_mode(&t, ExpiryMode::AttachmentOnly)
+            .await?;
+        chat.id
+            .set_ephemeral_timer(&t, Timer::Enabled { duration: 1 })
+            .await?;
+
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("report.pdf");
+        async_std::fs::write(&file, b"report").await?;
+        let mut msg = Message::new(Viewtype::File);
+        msg.set_file(file.to_str().unwrap(), None);
+        let sent = t.send_msg(chat.id, &mut msg).await;
+        let msg = Message::load_from_db(&t, sent.sender_msg_id).await?;
+        let blob = msg.get_file(&t).unwrap();
+        assert_eq!(blob.parent(), Some(t.get_blobdir()));
+
+        sleep(Duration::from_millis(1100)).await;
+        assert!(delete_expired_messages(&t).await?);
+        let msg = Message::load_from_db(&t, sent.sender_msg_id).await?;
+        assert!(msg.get_file(&t).is_none());
+
+        // Only the copy sent from the blob directory is deleted.
+        assert!(!blob.exists().await);
+        assert!(file.exists());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_get_ephemeral_stats() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat = t.get_self_chat().await;
+        assert_eq!(
+            chat.id.get_ephemeral_stats(&t).await?,
+            EphemeralStats::default()
+        );
+
+        chat.id
+            .set_ephemeral_timer(&t, Timer::Enabled { duration: 3600 })
+            .await?;
+        let first = t.send_text(chat.id, "first").await.sender_msg_id;
+        let second = t.send_text(chat.id, "second").await.sender_msg_id;
+        t.sql
+            .execute(
+                "UPDATE msgs SET ephemeral_timestamp=0 WHERE chat_id=?",
+                paramsv![chat.id],
+            )
+            .await?;
+        for (msg_id, timestamp) in &[(first, 1000), (second, 2000)] {
+            t.sql
+                .execute(
+                    "UPDATE msgs SET ephemeral_timestamp=? WHERE id=?",
+                    paramsv![*timestamp, *msg_id],
+                )
+                .await?;
+        }
+
+        let stats = chat.id.get_ephemeral_stats(&t).await?;
+        assert_eq!(stats.count, 2);
+        assert_eq!(stats.first_expiry, Some(1000));
+        assert_eq!(stats.last_expiry, Some(2000));
+        assert_eq!(stats.bytes, ("first".len() + "second".len()) as u64);
+
+        // Only attachments are deleted, there are none.
+        chat.id
+            .set_ephemeral_expiry_mode(&t, ExpiryMode::AttachmentOnly)
+            .await?;
+        assert_eq!(chat.id.get_ephemeral_stats(&t).await?.bytes, 0);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_ephemeral_grace_period() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        t.set_config(Config::EphemeralGracePeriod, Some("60"))
+            .await?;
+        let chat = t.get_self_chat().await;
+        chat.id
+            .set_ephemeral_timer(&t, Timer::Enabled { duration: 3600 })
+            .await?;
+        let kept = t.send_text(chat.id, "kept").await.sender_msg_id;
+        let deleted = t.send_text(chat.id, "deleted").await.sender_msg_id;
+
+        // Both messages expired, but only one is past the grace period.
+        t.sql
+            .execute(
+                "UPDATE msgs SET ephemeral_timestamp=? WHERE id=?",
+                paramsv![time() - 10, kept],
+            )
+            .await?;
+        t.sql
+            .execute(
+                "UPDATE msgs SET ephemeral_timestamp=? WHERE id=?",
+                paramsv![time() - 100, deleted],
+            )
+            .await?;
+        assert!(delete_expired_messages(&t).await?);
+        assert_eq!(Message::load_from_db(&t, kept).await?.chat_id, chat.id);
+        assert_eq!(
+            Message::load_from_db(&t, deleted).await?.chat_id,
+            DC_CHAT_ID_TRASH
+        );
+
+        kept.cancel_expiry(&t).await?;
+        let msg = Message::load_from_db(&t, kept).await?;
+        assert_eq!(msg.get_ephemeral_timestamp(), 0);
+        assert_eq!(msg.get_ephemeral_timer(), Timer::Disabled);
+        assert!(deleted.cancel_expiry(&t).await.is_err());
+
+        start_ephemeral_timers(&t).await?;
+        assert_eq!(
+            Message::load_from_db(&t, kept)
+                .await?
+                .get_ephemeral_timestamp(),
+            0
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_ephemeral_timer_including_history() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        let bob = TestContext::new_bob().await;
+        let chat = alice.create_chat(&bob).await;
+
+        let seen = alice.send_text(chat.id, "seen").await;
+        let bob_chat = bob.create_chat(&alice).await;
+        alice
+            .recv_msg(&bob.send_text(bob_chat.id, "unseen").await)
+            .await;
+        let unseen = alice.get_last_msg().await;
+        assert_eq!(unseen.state, MessageState::InFresh);
+
+        chat.id
+            .set_ephemeral_timer_with_scope(
+                &alice,
+                Timer::Enabled { duration: 60 },
+                Scope::IncludingHistory,
+            )
+            .await?;
+
+        let seen = Message::load_from_db(&alice, seen.sender_msg_id).await?;
+        assert_eq!(seen.get_ephemeral_timer(), Timer::Enabled { duration: 60 });
+        assert!(seen.get_ephemeral_timestamp() > 0);
+        assert!(seen.get_ephemeral_timestamp() <= time() + 60);
+
+        let unseen = Message::load_from_db(&alice, unseen.id).await?;
         assert_eq!(
-            stock_ephemeral_timer_changed(
-                &context,
-                Timer::Enabled {
-                    duration: 7 * 24 * 60 * 60
+            unseen.get_ephemeral_timer(),
+            Timer::Enabled { duration: 60 }
+        );
+        assert_eq!(unseen.get_ephemeral_timestamp(), 0);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_ephemeral_timer_policy() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        let bob = TestContext::new_bob().await;
+        let alice_chat = alice.create_chat(&bob).await;
+        let bob_chat = bob.create_chat(&alice).await;
+
+        alice_chat
+            .id
+            .set_ephemeral_timer_policy(
+                &alice,
+                TimerPolicy {
+                    verified_only: false,
+                    max_duration: Some(3600),
+                    approve_unverified: false,
                 },
-                DC_CONTACT_ID_SELF
             )
-            .await,
-            "Message deletion timer is set to 1 week by me."
-        );
+            .await?;
         assert_eq!(
-            stock_ephemeral_timer_changed(
-                &context,
-                Timer::Enabled {
-                    duration: 4 * 7 * 24 * 60 * 60
+            alice_chat.id.get_ephemeral_timer_policy(&alice).await?,
+            TimerPolicy {
+                verified_only: false,
+                max_duration: Some(3600),
+                approve_unverified: false,
+            }
+        );
+
+        for (timer, expected) in &[
+            (
+                Timer::Enabled { duration: 60 },
+                Timer::Enabled { duration: 60 },
+            ),
+            (Timer::Disabled, Timer::Enabled { duration: 60 }),
+            (
+                Timer::Enabled { duration: 7200 },
+                Timer::Enabled { duration: 60 },
+            ),
+        ] {
+            bob_chat.id.set_ephemeral_timer(&bob, *timer).await?;
+            alice.recv_msg(&bob.pop_sent_msg().await).await;
+            assert_eq!(alice_chat.id.get_ephemeral_timer(&alice).await?, *expected);
+        }
+        let mut rejected = 0;
+        let mut changed = 0;
+        for item in chat::get_chat_msgs(&alice, alice_chat.id, 0, None).await? {
+            if let ChatItem::Message { msg_id } = item {
+                let msg = Message::load_from_db(&alice, msg_id).await?;
+                let text = msg.get_text().unwrap_or_default();
+                if msg.is_info() && text.contains("rejected") {
+                    rejected += 1;
+                } else if text.contains("is set to") || text.contains("is disabled") {
+                    changed += 1;
+                }
+            }
+        }
+        assert_eq!(rejected, 2);
+        // Rejected changes are not shown as applied.
+        assert_eq!(changed, 1);
+
+        // bob is not verified
+        alice_chat
+            .id
+            .set_ephemeral_timer_policy(
+                &alice,
+                TimerPolicy {
+                    verified_only: true,
+                    max_duration: None,
+                    approve_unverified: false,
                 },
-                DC_CONTACT_ID_SELF
             )
-            .await,
-            "Message deletion timer is set to 4 weeks by me."
+            .await?;
+        bob_chat
+            .id
+            .set_ephemeral_timer(&bob, Timer::Disabled)
+            .await?;
+        alice.recv_msg(&bob.pop_sent_msg().await).await;
+        assert_eq!(
+            alice_chat.id.get_ephemeral_timer(&alice).await?,
+            Timer::Enabled { duration: 60 }
         );
+        Ok(())
     }
 
     #[async_std::test]
-    async fn test_ephemeral_timer() -> anyhow::Result<()> {
+    async fn test_ephemeral_timer_modified_event() -> Result<()> {
         let alice = TestContext::new_alice().await;
         let bob = TestContext::new_bob().await;
+        let alice_chat = alice.create_chat(&bob).await;
+        let bob_chat = bob.create_chat(&alice).await;
 
-        let chat_alice = alice.create_chat(&bob).await.id;
-        let chat_bob = bob.create_chat(&alice).await.id;
+        let timer = Timer::Enabled { duration: 60 };
+        bob_chat.id.set_ephemeral_timer(&bob, timer).await?;
+        loop {
+            if let EventType::ChatEphemeralTimerModified {
+                chat_id,
+                contact_id,
+                remote,
+                ..
+            } = bob.evtracker.recv().await?
+            {
+                assert_eq!(chat_id, bob_chat.id);
+                assert_eq!(contact_id, DC_CONTACT_ID_SELF);
+                assert!(!remote);
+                break;
+            }
+        }
 
-        // Alice sends message to Bob
-        let mut msg = Message::new(Viewtype::Text);
-        chat::prepare_msg(&alice.ctx, chat_alice, &mut msg).await?;
-        chat::send_msg(&alice.ctx, chat_alice, &mut msg).await?;
-        let sent = alice.pop_sent_msg().await;
-        bob.recv_msg(&sent).await;
+        alice.recv_msg(&bob.pop_sent_msg().await).await;
+        let bob_id = Contact::lookup_id_by_addr(&alice, "bob@example.net", Origin::Unknown)
+            .await?
+            .unwrap();
+        loop {
+            if let EventType::ChatEphemeralTimerModified {
+                chat_id,
+                timer: modified_timer,
+                contact_id,
+                remote,
+            } = alice.evtracker.recv().await?
+            {
+                assert_eq!(chat_id, alice_chat.id);
+                assert_eq!(modified_timer, timer);
+                assert_eq!(contact_id, bob_id);
+                assert!(remote);
+                break;
+            }
+        }
+        Ok(())
+    }
 
-        // Alice sends second message to Bob, with no timer
-        let mut msg = Message::new(Viewtype::Text);
-        chat::prepare_msg(&alice.ctx, chat_alice, &mut msg).await?;
-        chat::send_msg(&alice.ctx, chat_alice, &mut msg).await?;
-        let sent = alice.pop_sent_msg().await;
+    #[async_std::test]
+    async fn test_ephemeral_timer_approval() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        let bob = TestContext::new_bob().await;
+        let alice_chat = alice.create_chat(&bob).await;
+        let bob_chat = bob.create_chat(&alice).await;
+        alice_chat
+            .id
+            .set_ephemeral_timer_policy(
+                &alice,
+                TimerPolicy {
+                    verified_only: false,
+                    max_duration: None,
+                    approve_unverified: true,
+                },
+            )
+            .await?;
 
+        let timer = Timer::Enabled { duration: 3600 };
+        bob_chat.id.set_ephemeral_timer(&bob, timer).await?;
+        alice.recv_msg(&bob.pop_sent_msg().await).await;
+        bob.send_text(bob_chat.id, "hi").await;
+        alice.recv_msg(&bob.pop_sent_msg().await).await;
         assert_eq!(
-            chat_bob.get_ephemeral_timer(&bob.ctx).await?,
+            alice_chat.id.get_ephemeral_timer(&alice).await?,
             Timer::Disabled
         );
+        assert_eq!(
+            alice_chat.id.get_pending_ephemeral_timer(&alice).await?,
+            Some(timer)
+        );
 
-        // Bob sets ephemeral timer and sends a message about timer change
-        chat_bob
-            .set_ephemeral_timer(&bob.ctx, Timer::Enabled { duration: 60 })
-            .await?;
-        let sent_timer_change = bob.pop_sent_msg().await;
+        // Only one info message is added for the same change,
+        // the change is not shown as applied.
+        let mut pending = 0;
+        for item in chat::get_chat_msgs(&alice, alice_chat.id, 0, None).await? {
+            if let ChatItem::Message { msg_id } = item {
+                let msg = Message::load_from_db(&alice, msg_id).await?;
+                if msg.get_info_type() == SystemMessage::EphemeralTimerChangePending {
+                    pending += 1;
+                }
+                assert!(!msg.get_text().unwrap_or_default().contains("is set to"));
+            }
+        }
+        assert_eq!(pending, 1);
 
+        alice_chat
+            .id
+            .approve_ephemeral_timer_change(&alice, true)
+            .await?;
+        assert_eq!(alice_chat.id.get_ephemeral_timer(&alice).await?, timer);
         assert_eq!(
-            chat_bob.get_ephemeral_timer(&bob.ctx).await?,
-            Timer::Enabled { duration: 60 }
+            alice_chat.id.get_pending_ephemeral_timer(&alice).await?,
+            None
         );
+        assert!(alice_chat
+            .id
+            .approve_ephemeral_timer_change(&alice, true)
+            .await
+            .is_err());
+        Ok(())
+    }
 
-        // Bob receives message from Alice.
-        // Alice message has no timer. However, Bob should not disable timer,
-        // because Alice replies to old message.
-        bob.recv_msg(&sent).await;
+    #[async_std::test]
+    async fn test_ephemeral_timer_device_chat() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let mut msg = Message::new(Viewtype::Text);
+        msg.text = Some("first".to_string());
+        let first = chat::add_device_msg(&t, None, Some(&mut msg)).await?;
+        let device_chat_id = ChatId::get_for_contact(&t, DC_CONTACT_ID_DEVICE).await?;
 
+        device_chat_id
+            .set_ephemeral_timer(&t, Timer::Enabled { duration: 60 })
+            .await?;
         assert_eq!(
-            chat_alice.get_ephemeral_timer(&alice.ctx).await?,
-            Timer::Disabled
+            device_chat_id.get_ephemeral_timer(&t).await?,
+            Timer::Enabled { duration: 60 }
         );
+        assert!(t.get_last_msg_in(device_chat_id).await.is_info());
         assert_eq!(
-            chat_bob.get_ephemeral_timer(&bob.ctx).await?,
-            Timer::Enabled { duration: 60 }
+            t.sql
+                .count(
+                    "SELECT COUNT(*) FROM jobs WHERE action=?;",
+                    paramsv![job::Action::SendMsgToSmtp],
+                )
+                .await?,
+            0
         );
 
-        // Alice receives message from Bob
-        alice.recv_msg(&sent_timer_change).await;
-
+        let mut msg = Message::new(Viewtype::Text);
+        msg.text = Some("second".to_string());
+        let second = chat::add_device_msg(&t, None, Some(&mut msg)).await?;
+        let second = Message::load_from_db(&t, second).await?;
         assert_eq!(
-            chat_alice.get_ephemeral_timer(&alice.ctx).await?,
+            second.get_ephemeral_timer(),
             Timer::Enabled { duration: 60 }
         );
 
+        // old device messages are deleted if configured
+        t.sql
+            .execute(
+                "UPDATE msgs SET timestamp=? WHERE id=?",
+                paramsv![time() - 7200, first],
+            )
+            .await?;
+        assert!(!delete_expired_messages(&t).await?);
+        t.set_config(Config::DeleteDeviceChatAfter, Some("3600"))
+            .await?;
+        assert!(delete_expired_messages(&t).await?);
+        assert!(Message::load_from_db(&t, first).await?.chat_id.is_trash());
+        assert_eq!(
+            Message::load_from_db(&t, second.id).await?.chat_id,
+            device_chat_id
+        );
         Ok(())
     }
 
     #[async_std::test]
-    async fn test_ephemeral_delete_msgs() {
+    async fn test_delete_device_after_self_chat() -> Result<()> {
         let t = TestContext::new_alice().await;
-        let chat = t.get_self_chat().await;
+        let self_chat = t.get_self_chat().await;
+        let msg_id = t.send_text(self_chat.id, "note").await.sender_msg_id;
+        t.sql
+            .execute(
+                "UPDATE msgs SET timestamp=?, timestamp_rcvd=? WHERE id=?",
+                paramsv![time() - 7200, time() - 7200, msg_id],
+            )
+            .await?;
+        t.set_config(Config::DeleteDeviceAfter, Some("3600"))
+            .await?;
 
-        t.send_text(chat.id, "Saved message, which we delete manually")
-            .await;
-        let msg = t.get_last_msg_in(chat.id).await;
-        msg.id.delete_from_db(&t).await.unwrap();
-        check_msg_was_deleted(&t, &chat, msg.id).await;
+        // The saved messages chat is skipped by default.
+        assert!(!delete_expired_messages(&t).await?);
+        assert_eq!(message::estimate_deletion_cnt(&t, false, 3600).await?, 0);
+        assert_eq!(
+            Message::load_from_db(&t, msg_id).await?.chat_id,
+            self_chat.id
+        );
+
+        t.set_config_bool(Config::DeleteDeviceAfterIncludesSelfChat, true)
+            .await?;
+        assert_eq!(message::estimate_deletion_cnt(&t, false, 3600).await?, 1);
+        assert!(delete_expired_messages(&t).await?);
+        assert!(Message::load_from_db(&t, msg_id).await?.chat_id.is_trash());
+        Ok(())
+    }
 
+    #[async_std::test]
+    async fn test_expire_undelivered_messages() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat = t.create_chat_with_contact("bob", "bob@example.net").await;
         chat.id
-            .set_ephemeral_timer(&t, Timer::Enabled { duration: 1 })
-            .await
-            .unwrap();
-        let msg = t
-            .send_text(chat.id, "Saved message, disappearing after 1s")
-            .await;
+            .set_ephemeral_timer(&t, Timer::Enabled { duration: 60 })
+            .await?;
+        t.pop_sent_msg().await;
 
-        sleep(Duration::from_millis(1100)).await;
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("stuck".to_string()));
+        let msg_id = chat::send_msg(&t, chat.id, &mut msg).await?;
+        assert_eq!(msg_id.get_state(&t).await?, MessageState::OutPending);
 
-        // Check checks that the msg was deleted locally
-        check_msg_was_deleted(&t, &chat, msg.sender_msg_id).await;
+        // The message was created long ago and its timer was never started.
+        t.sql
+            .execute(
+                "UPDATE msgs SET timestamp=?, ephemeral_timestamp=0 WHERE id=?",
+                paramsv![time() - 120, msg_id],
+            )
+            .await?;
 
-        // Check that the msg will be deleted on the server
-        // First of all, set a server_uid so that DC thinks that it's actually possible to delete
+        // During the grace period, the message is still sent.
+        t.set_config(Config::UndeliveredGracePeriod, Some("3600"))
+            .await?;
+        delete_expired_messages(&t).await?;
+        let msg = Message::load_from_db(&t, msg_id).await?;
+        assert_eq!(msg.chat_id, chat.id);
+        assert_eq!(msg.state, MessageState::OutPending);
+
+        t.set_config(Config::UndeliveredGracePeriod, None).await?;
+        assert!(delete_expired_messages(&t).await?);
+        assert!(Message::load_from_db(&t, msg_id).await?.chat_id.is_trash());
+        assert_eq!(
+            t.sql
+                .count(
+                    "SELECT COUNT(*) FROM jobs WHERE action=? AND foreign_id=?",
+                    paramsv![job::Action::SendMsgToSmtp, msg_id],
+                )
+                .await?,
+            0
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_delete_old_info_msgs() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat_id =
+            chat::create_group_chat(&t, chat::ProtectionStatus::Unprotected, "grp").await?;
+        let mut msg_ids = Vec::new();
+        for (text, cmd) in &[
+            ("image 1", SystemMessage::GroupImageChanged),
+            ("name", SystemMessage::GroupNameChanged),
+            ("image 2", SystemMessage::GroupImageChanged),
+            ("plain info", SystemMessage::Unknown),
+        ] {
+            msg_ids.push(chat::add_info_msg_with_cmd(&t, chat_id, text, *cmd).await?);
+        }
+
+        // Normal messages whose params merely contain `S=` are kept.
+        let mut normal_ids = Vec::new();
+        for param in &["f=$BLOBDIR/S=1.txt", "garbage\nS=2"] {
+            let msg_id = t
+                .sql
+                .insert(
+                    "INSERT INTO msgs (chat_id, from_id, to_id, timestamp, type, txt, param) \
+                     VALUES (?, ?, 0, ?, ?, 'normal', ?)",
+                    paramsv![
+                        chat_id,
+                        DC_CONTACT_ID_SELF,
+                        time(),
+                        Viewtype::Text,
+                        param.to_string()
+                    ],
+                )
+                .await?;
+            normal_ids.push(MsgId::new(msg_id as u32));
+        }
         t.sql
             .execute(
-                "UPDATE msgs SET server_uid=1 WHERE id=?",
-                paramsv![msg.sender_msg_id],
+                "UPDATE msgs SET timestamp=timestamp-7200 WHERE chat_id=?",
+                paramsv![chat_id],
             )
-            .await
-            .unwrap();
-        let job = job::load_imap_deletion_job(&t).await.unwrap();
+            .await?;
+
+        assert!(!delete_old_info_msgs(&t).await?);
+        t.set_config(Config::DeleteInfoMsgsAfter, Some("3600"))
+            .await?;
+        // Info messages are not scanned when expired messages are deleted.
+        assert!(!delete_expired_messages(&t).await?);
+        assert!(delete_old_info_msgs(&t).await?);
+
+        let mut kept = Vec::new();
+        for msg_id in msg_ids {
+            let msg = Message::load_from_db(&t, msg_id).await?;
+            if !msg.chat_id.is_trash() {
+                kept.push(msg.get_text().unwrap_or_default());
+            }
+        }
+        assert_eq!(kept, vec!["name".to_string(), "image 2".to_string()]);
+        for msg_id in normal_ids {
+            assert_eq!(Message::load_from_db(&t, msg_id).await?.chat_id, chat_id);
+        }
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_purge_stale_trash() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let now = time();
+        for (rfc724_mid, timestamp) in &[("old@example.org", now - 1000), ("new@example.org", now)]
+        {
+            t.sql
+                .execute(
+                    "INSERT INTO msgs (chat_id, rfc724_mid, server_folder, server_uid, timestamp)
+                     VALUES (?, ?, 'INBOX', 1, ?);",
+                    paramsv![DC_CHAT_ID_TRASH, rfc724_mid, timestamp],
+                )
+                .await?;
+        }
+        let pending = t
+            .sql
+            .insert(
+                "INSERT INTO msgs (chat_id, rfc724_mid, server_folder, server_uid, timestamp)
+                 VALUES (?, 'pending@example.org', 'INBOX', 2, ?);",
+                paramsv![DC_CHAT_ID_TRASH, now - 1000],
+            )
+            .await?;
+        job::Job::new(
+            job::Action::DeleteMsgOnImap,
+            pending as u32,
+            Params::new(),
+            0,
+        )
+        .save(&t)
+        .await?;
+
+        assert_eq!(purge_stale_trash(&t, 500).await?, 1);
+        assert!(message::rfc724_mid_exists(&t, "old@example.org")
+            .await?
+            .is_none());
+        assert!(message::rfc724_mid_exists(&t, "new@example.org")
+            .await?
+            .is_some());
+        assert!(message::rfc724_mid_exists(&t, "pending@example.org")
+            .await?
+            .is_some());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_load_imap_deletion_msgids_self() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let now = time();
+        let incoming = t
+            .sql
+            .insert(
+                "INSERT INTO msgs (chat_id, from_id, rfc724_mid, server_folder, server_uid, timestamp)
+                 VALUES (10, 10, 'incoming@example.org', 'INBOX', 1, ?);",
+                paramsv![now - 2 * 24 * 3600],
+            )
+            .await?;
+        let outgoing = t
+            .sql
+            .insert(
+                "INSERT INTO msgs (chat_id, from_id, rfc724_mid, server_folder, server_uid, timestamp)
+                 VALUES (10, ?, 'outgoing@example.org', 'INBOX', 2, ?);",
+                paramsv![DC_CONTACT_ID_SELF, now - 2 * 24 * 3600],
+            )
+            .await?;
+
+        t.set_config(
+            Config::DeleteServerAfter,
+            Some(&(28 * 24 * 3600).to_string()),
+        )
+        .await?;
+        assert_eq!(load_imap_deletion_msgids(&t).await?, vec![]);
+
+        t.set_config(
+            Config::DeleteServerAfterSelf,
+            Some(&(24 * 3600).to_string()),
+        )
+        .await?;
         assert_eq!(
-            job,
-            Some(job::Job::new(
-                job::Action::DeleteMsgOnImap,
-                msg.sender_msg_id.to_u32(),
-                Params::new(),
-                0,
-            ))
+            load_imap_deletion_msgids(&t).await?,
+            vec![MsgId::new(outgoing as u32)]
         );
-        // Let's assume that executing the job fails on first try and the job is saved to the db
-        job.unwrap().save(&t).await.unwrap();
 
-        // Make sure that we don't get yet another job when loading from db
-        let job2 = job::load_imap_deletion_job(&t).await.unwrap();
-        assert_eq!(job2, None);
+        t.set_config(Config::DeleteServerAfter, Some(&(24 * 3600).to_string()))
+            .await?;
+        t.set_config(
+            Config::DeleteServerAfterSelf,
+            Some(&(28 * 24 * 3600).to_string()),
+        )
+        .await?;
+        assert_eq!(
+            load_imap_deletion_msgids(&t).await?,
+            vec![MsgId::new(incoming as u32)]
+        );
+
+        t.set_config(Config::DeleteServerAfter, Some(&(24 * 3600).to_string()))
+            .await?;
+        t.set_config(
+            Config::DeleteServerAfterSelf,
+            Some(&(24 * 3600).to_string()),
+        )
+        .await?;
+        assert_eq!(
+            load_imap_deletion_msgids(&t).await?,
+            vec![MsgId::new(incoming as u32), MsgId::new(outgoing as u32)]
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_load_imap_deletion_msgids_only_seen() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let incoming = t
+            .sql
+            .insert(
+                "INSERT INTO msgs (chat_id, from_id, rfc724_mid, server_folder, server_uid, timestamp, state)
+                 VALUES (10, 10, 'incoming@example.org', 'INBOX', 1, ?, ?);",
+                paramsv![time() - 2 * 24 * 3600, MessageState::InFresh],
+            )
+            .await?;
+        t.set_config(Config::DeleteServerAfter, Some(&(24 * 3600).to_string()))
+            .await?;
+        t.set_config_bool(Config::DeleteServerOnlySeen, true)
+            .await?;
+        assert_eq!(load_imap_deletion_msgids(&t).await?, vec![]);
+
+        t.sql
+            .execute(
+                "UPDATE msgs SET state=? WHERE id=?;",
+                paramsv![MessageState::InSeen, incoming],
+            )
+            .await?;
+        assert_eq!(
+            load_imap_deletion_msgids(&t).await?,
+            vec![MsgId::new(incoming as u32)]
+        );
+        Ok(())
     }
 
     async fn check_msg_was_deleted(t: &TestContext, chat: &Chat, msg_id: MsgId) {
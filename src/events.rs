@@ -1,49 +1,365 @@
 //! # Events specification
 
+use std::collections::HashMap;
 use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use anyhow::{bail, Result};
 use async_std::channel::{self, Receiver, Sender, TrySendError};
 use async_std::path::PathBuf;
+use async_std::prelude::*;
+use async_std::task;
+use serde::{Serialize, Serializer};
 use strum::EnumProperty;
 
-use crate::chat::ChatId;
+use crate::chat::{ChatId, NotificationProfile};
 use crate::ephemeral::Timer as EphemeralTimer;
+use crate::key::KeySource;
 use crate::message::MsgId;
+use crate::webhook::{Webhook, WebhookQueue};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Events {
+    delivery: Delivery,
+    coalescing: Sender<CoalescingCommand>,
+    coalescing_enabled: Arc<AtomicBool>,
+    /// Whether events are coalesced, by event ID of the coalescable event types.
+    coalesced_types: Arc<HashMap<i32, AtomicBool>>,
+    poll_interrupt_sender: Sender<()>,
+    poll_interrupt_receiver: Receiver<()>,
+}
+
+/// Queues events are delivered to.
+#[derive(Debug, Clone)]
+struct Delivery {
     receiver: Receiver<Event>,
     sender: Sender<Event>,
+    webhook: Arc<Mutex<Option<WebhookQueue>>>,
+    /// Set on the first call to [`Events::poll`].
+    polling: Arc<AtomicBool>,
+    poll_receiver: Receiver<Event>,
+    poll_sender: Sender<Event>,
 }
 
-impl Default for Events {
-    fn default() -> Self {
-        let (sender, receiver) = channel::bounded(1_000);
-
-        Self { receiver, sender }
+impl Delivery {
+    /// Delivers an event to the webhook, if any, to the event emitters
+    /// and to [`Events::poll`] once it is used.
+    fn send(&self, event: Event) {
+        if let Some(webhook) = &*self.webhook.lock().unwrap() {
+            webhook.push(event.clone());
+        }
+        if self.polling.load(Ordering::Relaxed) {
+            push_dropping_oldest(&self.poll_sender, &self.poll_receiver, event.clone());
+        }
+        push_dropping_oldest(&self.sender, &self.receiver, event);
     }
 }
 
-impl Events {
-    pub fn emit(&self, event: Event) {
-        match self.sender.try_send(event) {
-            Ok(()) => {}
-            Err(TrySendError::Full(event)) => {
+/// Pushes an event to a bounded queue, removing the oldest event if the queue is full.
+fn push_dropping_oldest(sender: &Sender<Event>, receiver: &Receiver<Event>, event: Event) {
+    let mut event = event;
+    loop {
+        match sender.try_send(event) {
+            Ok(()) => break,
+            Err(TrySendError::Full(full)) => {
                 // when we are full, we pop remove the oldest event and push on the new one
-                let _ = self.receiver.try_recv();
+                let _ = receiver.try_recv();
 
                 // try again
-                self.emit(event);
+                event = full;
             }
             Err(TrySendError::Closed(_)) => {
                 unreachable!("unable to emit event, channel disconnected");
             }
         }
     }
+}
+
+/// Commands handled by the coalescing task, see [`coalescing_loop`].
+#[derive(Debug)]
+enum CoalescingCommand {
+    /// An event of a type which is coalesced.
+    Event(Event),
+    /// Sets the interval of an event type, a zero interval disables coalescing for the type.
+    SetInterval(i32, Duration),
+    /// Emits all pending events.
+    Flush,
+}
+
+/// State of event coalescing, owned by the coalescing task.
+///
+/// Events of a type with a configured interval are emitted at most once per interval
+/// and chat or contact. Events arriving in between are merged into a single pending
+/// event which is emitted when the interval is over or when IO goes idle.
+#[derive(Debug, Default)]
+struct Coalescing {
+    intervals: HashMap<i32, Duration>,
+    last_emitted: HashMap<CoalescingKey, Instant>,
+    /// Merged events with the time they are due.
+    pending: HashMap<CoalescingKey, (Event, Instant)>,
+}
+
+impl Coalescing {
+    /// Returns the event if it should be emitted now
+    /// or stores it as pending if it is coalesced.
+    fn coalesce(&mut self, event: Event) -> Option<Event> {
+        let key = match coalescing_key(&event.typ) {
+            Some(key) => key,
+            None => return Some(event),
+        };
+        let interval = match self.intervals.get(&key.0) {
+            Some(interval) => *interval,
+            None => return Some(event),
+        };
+
+        if let Some((pending, _)) = self.pending.get_mut(&key) {
+            if let (
+                EventType::MsgsChanged { msg_id, .. },
+                EventType::MsgsChanged {
+                    msg_id: new_msg_id, ..
+                },
+            ) = (&mut pending.typ, &event.typ)
+            {
+                if *msg_id != *new_msg_id {
+                    // several messages changed, let the UI reload the whole chat
+                    *msg_id = MsgId::new(0);
+                }
+            } else {
+                *pending = event;
+            }
+            return None;
+        }
+
+        let now = Instant::now();
+        if let Some(last_emitted) = self.last_emitted.get(&key) {
+            let due = *last_emitted + interval;
+            if now < due {
+                self.pending.insert(key, (event, due));
+                return None;
+            }
+        }
+        self.last_emitted.insert(key, now);
+        Some(event)
+    }
+
+    /// Returns the time the next pending event is due.
+    fn next_due(&self) -> Option<Instant> {
+        self.pending.values().map(|(_, due)| *due).min()
+    }
+
+    /// Removes and returns the pending events due at `now`, all pending events if `all` is set.
+    fn take_due(&mut self, now: Instant, all: bool) -> Vec<Event> {
+        let keys: Vec<CoalescingKey> = self
+            .pending
+            .iter()
+            .filter(|(_, (_, due))| all || *due <= now)
+            .map(|(key, _)| *key)
+            .collect();
+        keys.into_iter()
+            .filter_map(|key| {
+                let (event, _) = self.pending.remove(&key)?;
+                self.last_emitted.insert(key, now);
+                Some(event)
+            })
+            .collect()
+    }
+}
+
+/// Coalesces the events received from `commands` and delivers them,
+/// until all senders of `commands` are dropped.
+///
+/// A single task handles all coalesced events of an [`Events`] instance,
+/// so pending events are emitted without a task or timer per event.
+async fn coalescing_loop(commands: Receiver<CoalescingCommand>, delivery: Delivery) {
+    let mut coalescing = Coalescing::default();
+    loop {
+        let command = match coalescing.next_due() {
+            Some(due) => {
+                let timeout = due.saturating_duration_since(Instant::now());
+                match async_std::future::timeout(timeout, commands.recv()).await {
+                    Ok(Ok(command)) => Some(command),
+                    Ok(Err(_)) => break,
+                    Err(_) => None,
+                }
+            }
+            None => match commands.recv().await {
+                Ok(command) => Some(command),
+                Err(_) => break,
+            },
+        };
+
+        let mut flush = false;
+        match command {
+            Some(CoalescingCommand::Event(event)) => {
+                if let Some(event) = coalescing.coalesce(event) {
+                    delivery.send(event);
+                }
+            }
+            Some(CoalescingCommand::SetInterval(event_id, interval)) => {
+                if interval == Duration::from_secs(0) {
+                    coalescing.intervals.remove(&event_id);
+                } else {
+                    coalescing.intervals.insert(event_id, interval);
+                }
+            }
+            Some(CoalescingCommand::Flush) => flush = true,
+            None => {}
+        }
+        for event in coalescing.take_due(Instant::now(), flush) {
+            delivery.send(event);
+        }
+    }
+}
+
+/// Event ID and chat or contact ID of a coalescable event.
+type CoalescingKey = (i32, u32);
+
+/// Returns the key events are coalesced by, `None` if the event is never coalesced.
+fn coalescing_key(event: &EventType) -> Option<CoalescingKey> {
+    let id = match event {
+        EventType::MsgsChanged { chat_id, .. }
+        | EventType::MsgsNoticed(chat_id)
+        | EventType::ChatModified(chat_id) => chat_id.to_u32(),
+        EventType::ContactsChanged(contact_id) => contact_id.unwrap_or_default(),
+        EventType::ConnectivityChanged => 0,
+        _ => return None,
+    };
+    Some((event.as_id(), id))
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        let (sender, receiver) = channel::bounded(1_000);
+        let (poll_sender, poll_receiver) = channel::bounded(1_000);
+        let (poll_interrupt_sender, poll_interrupt_receiver) = channel::bounded(1);
+        let delivery = Delivery {
+            receiver,
+            sender,
+            webhook: Default::default(),
+            polling: Default::default(),
+            poll_receiver,
+            poll_sender,
+        };
+
+        let (coalescing, commands) = channel::unbounded();
+        task::spawn(coalescing_loop(commands, delivery.clone()));
+
+        let coalescable = [
+            EventType::MsgsChanged {
+                chat_id: ChatId::new(0),
+                msg_id: MsgId::new(0),
+            },
+            EventType::MsgsNoticed(ChatId::new(0)),
+            EventType::ChatModified(ChatId::new(0)),
+            EventType::ContactsChanged(None),
+            EventType::ConnectivityChanged,
+        ];
+        let coalesced_types = coalescable
+            .iter()
+            .map(|event| (event.as_id(), AtomicBool::new(false)))
+            .collect();
+
+        Self {
+            delivery,
+            coalescing,
+            coalescing_enabled: Arc::new(AtomicBool::new(true)),
+            coalesced_types: Arc::new(coalesced_types),
+            poll_interrupt_sender,
+            poll_interrupt_receiver,
+        }
+    }
+}
+
+impl Events {
+    pub fn emit(&self, event: Event) {
+        let coalesced = self.coalescing_enabled.load(Ordering::Relaxed)
+            && self
+                .coalesced_types
+                .get(&event.as_id())
+                .map_or(false, |coalesced| coalesced.load(Ordering::Relaxed));
+        if coalesced {
+            // The task only ends when the last sender is dropped.
+            self.coalescing
+                .try_send(CoalescingCommand::Event(event))
+                .ok();
+        } else {
+            self.delivery.send(event);
+        }
+    }
+
+    /// Emits all pending coalesced events immediately.
+    pub(crate) fn flush(&self) {
+        self.coalescing.try_send(CoalescingCommand::Flush).ok();
+    }
+
+    /// Sets the interval in which events of the given type are coalesced.
+    ///
+    /// Only `MsgsChanged`, `MsgsNoticed` and `ChatModified` events (per chat),
+    /// `ContactsChanged` events (per contact) and `ConnectivityChanged` events
+    /// can be coalesced. A zero interval disables coalescing for the event type,
+    /// which is the default.
+    pub(crate) fn set_coalescing(&self, event_id: i32, interval: Duration) -> Result<()> {
+        let coalesced = match self.coalesced_types.get(&event_id) {
+            Some(coalesced) => coalesced,
+            None => bail!("events with id {} cannot be coalesced", event_id),
+        };
+
+        // The interval is set before further events of the type are sent to the task.
+        self.coalescing
+            .try_send(CoalescingCommand::SetInterval(event_id, interval))
+            .ok();
+        coalesced.store(interval != Duration::from_secs(0), Ordering::Relaxed);
+        if interval == Duration::from_secs(0) {
+            self.flush();
+        }
+        Ok(())
+    }
+
+    /// Enables or disables coalescing of all events.
+    ///
+    /// Pending events are emitted when coalescing is disabled.
+    pub(crate) fn set_coalescing_enabled(&self, enabled: bool) {
+        self.coalescing_enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            self.flush();
+        }
+    }
+
+    /// Starts delivering events to the given webhook in addition to the event emitters,
+    /// replacing the previously set webhook. `None` stops delivering events to webhooks.
+    pub(crate) fn set_webhook(&self, webhook: Option<Webhook>) {
+        *self.delivery.webhook.lock().unwrap() = webhook.map(Webhook::start);
+    }
+
+    /// Waits up to `timeout` for the next event.
+    ///
+    /// Events are queued for polling in addition to the event emitters
+    /// once this was called for the first time.
+    ///
+    /// Returns `None` on timeout or if interrupted by [`Events::interrupt_polling`].
+    pub(crate) async fn poll(&self, timeout: Duration) -> Option<Event> {
+        self.delivery.polling.store(true, Ordering::Relaxed);
+        let event = async { self.delivery.poll_receiver.recv().await.ok() };
+        let interrupt = async {
+            self.poll_interrupt_receiver.recv().await.ok();
+            None
+        };
+        async_std::future::timeout(timeout, event.race(interrupt))
+            .await
+            .unwrap_or_default()
+    }
+
+    /// Makes a pending [`Events::poll`] return immediately,
+    /// or the next one if none is pending.
+    pub(crate) fn interrupt_polling(&self) {
+        self.poll_interrupt_sender.try_send(()).ok();
+    }
 
     /// Retrieve the event emitter.
     pub fn get_emitter(&self) -> EventEmitter {
-        EventEmitter(self.receiver.clone())
+        EventEmitter(self.delivery.receiver.clone())
     }
 }
 
@@ -116,6 +432,34 @@ fn deref(&self) -> &EventType {
     }
 }
 
+/// Serializable representation of an [`Event`].
+#[derive(Debug, Serialize)]
+pub(crate) struct EventJson {
+    /// ID of the context which emitted the event.
+    context_id: u32,
+
+    /// ID of the event as used in the `DC_EVENT_*` constants.
+    event_id: i32,
+
+    /// The event with its fields.
+    event: EventType,
+}
+
+impl From<&Event> for EventJson {
+    fn from(event: &Event) -> Self {
+        Self {
+            context_id: event.id,
+            event_id: event.as_id(),
+            event: event.typ.clone(),
+        }
+    }
+}
+
+#[allow(clippy::ptr_arg)]
+fn serialize_path<S: Serializer>(path: &PathBuf, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&path.to_string_lossy())
+}
+
 impl EventType {
     /// Returns the corresponding Event ID.
     ///
@@ -128,7 +472,10 @@ pub fn as_id(&self) -> i32 {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, EnumProperty)]
+/// Serialized as an object with the name of the variant in `type`
+/// and its fields, if any, in `data`.
+#[derive(Debug, Clone, PartialEq, Eq, EnumProperty, Serialize)]
+#[serde(tag = "type", content = "data")]
 pub enum EventType {
     /// The library-user may write an informational string to the log.
     ///
@@ -207,8 +554,16 @@ pub enum EventType {
     /// when receiving this message.
     ///
     /// There is no extra #DC_EVENT_MSGS_CHANGED event send together with this event.
+    ///
+    /// `notification_profile` is the notification profile of the chat, if set.
+    /// `muted` is true during the quiet hours, see `Config::QuietHoursStart`.
     #[strum(props(id = "2005"))]
-    IncomingMsg { chat_id: ChatId, msg_id: MsgId },
+    IncomingMsg {
+        chat_id: ChatId,
+        msg_id: MsgId,
+        notification_profile: Option<NotificationProfile>,
+        muted: bool,
+    },
 
     /// Messages were seen or noticed.
     /// chat id is always set.
@@ -230,6 +585,12 @@ pub enum EventType {
     #[strum(props(id = "2015"))]
     MsgRead { chat_id: ChatId, msg_id: MsgId },
 
+    /// A starred message was due for deletion by its ephemeral timer or by `delete_device_after`,
+    /// but is kept because of the `protect_starred_from_deletion` setting.
+    /// The event is emitted once per message.
+    #[strum(props(id = "2017"))]
+    MsgPreserved { chat_id: ChatId, msg_id: MsgId },
+
     /// Chat changed.  The name or the image of a chat group was changed or members were added or removed.
     /// Or the verify state of a chat has changed.
     /// See dc_set_chat_name(), dc_set_chat_profile_image(), dc_add_contact_to_chat()
@@ -245,6 +606,13 @@ pub enum EventType {
     ChatEphemeralTimerModified {
         chat_id: ChatId,
         timer: EphemeralTimer,
+
+        /// ID of the contact who changed the timer, `DC_CONTACT_ID_SELF` for own changes.
+        contact_id: u32,
+
+        /// Whether the change was received in a message,
+        /// including messages sent from own other devices.
+        remote: bool,
     },
 
     /// Contact(s) created, renamed, blocked or deleted.
@@ -253,6 +621,13 @@ pub enum EventType {
     #[strum(props(id = "2030"))]
     ContactsChanged(Option<u32>),
 
+    /// A key was imported for a contact from somewhere else than an Autocrypt header.
+    ///
+    /// @param data1 (int) contact_id of the contact.
+    /// @param data2 (int) Source of the key, 1=Web Key Directory, 2=imported manually.
+    #[strum(props(id = "2032"))]
+    ContactKeyImported { contact_id: u32, source: KeySource },
+
     /// Location of one or more contact has changed.
     ///
     /// @param data1 (u32) contact_id of the contact for which the location has changed.
@@ -273,6 +648,12 @@ pub enum EventType {
         comment: Option<String>,
     },
 
+    /// Inform about the progress of an account deletion started by delete_account().
+    ///
+    /// @param data1 (usize) 0=error, 1-999=progress in permille, 1000=success and done
+    #[strum(props(id = "2045"))]
+    DeleteAccountProgress(usize),
+
     /// Inform about the import/export progress started by imex().
     ///
     /// @param data1 (usize) 0=error, 1-999=progress in permille, 1000=success and done
@@ -288,7 +669,29 @@ pub enum EventType {
     ///
     /// @param data2 0
     #[strum(props(id = "2052"))]
-    ImexFileWritten(PathBuf),
+    ImexFileWritten(#[serde(serialize_with = "serialize_path")] PathBuf),
+
+    /// An automatic backup was written, see `Config::AutoBackupDir`.
+    ///
+    /// @param data2 (PathBuf) Path of the backup file.
+    #[strum(props(id = "2053"))]
+    AutoBackupWritten(#[serde(serialize_with = "serialize_path")] PathBuf),
+
+    /// Writing an automatic backup failed.
+    ///
+    /// @param data2 (String) Error message.
+    #[strum(props(id = "2054"))]
+    AutoBackupFailed(String),
+
+    /// The database was taken over by another context or its lock was acquired again,
+    /// see `Context::new_with_takeover`.
+    ///
+    /// IO is stopped when the database is taken over and may be started again
+    /// once the lock is acquired again.
+    ///
+    /// @param data1 (bool) true if the context owns the database again.
+    #[strum(props(id = "2055"))]
+    DbOwnerChanged(bool),
 
     /// Progress information of a secure-join handshake from the view of the inviter
     /// (Alice, the person who shows the QR code).
@@ -323,3 +726,105 @@ pub enum EventType {
     #[strum(props(id = "2100"))]
     ConnectivityChanged,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msgs_changed(chat_id: u32, msg_id: u32) -> Event {
+        Event {
+            id: 1,
+            typ: EventType::MsgsChanged {
+                chat_id: ChatId::new(chat_id),
+                msg_id: MsgId::new(msg_id),
+            },
+        }
+    }
+
+    #[async_std::test]
+    async fn test_coalescing() -> Result<()> {
+        let events = Events::default();
+        let emitter = events.get_emitter();
+        let msgs_changed_id = msgs_changed(0, 0).as_id();
+        events.set_coalescing(msgs_changed_id, Duration::from_secs(60))?;
+        assert!(events.set_coalescing(100, Duration::from_secs(60)).is_err());
+
+        // The first event is emitted immediately, the following ones are merged.
+        events.emit(msgs_changed(10, 1));
+        events.emit(msgs_changed(10, 2));
+        events.emit(msgs_changed(10, 3));
+        events.emit(msgs_changed(11, 4));
+        assert_eq!(emitter.recv().await, Some(msgs_changed(10, 1)));
+        assert_eq!(emitter.recv().await, Some(msgs_changed(11, 4)));
+
+        // Other events are not affected.
+        events.emit(Event {
+            id: 1,
+            typ: EventType::Info("info".to_string()),
+        });
+        assert_eq!(
+            emitter.recv().await.map(|event| event.typ),
+            Some(EventType::Info("info".to_string()))
+        );
+
+        events.flush();
+        assert_eq!(emitter.recv().await, Some(msgs_changed(10, 0)));
+
+        // Disabling coalescing for the event type emits events directly again.
+        events.set_coalescing(msgs_changed_id, Duration::from_secs(0))?;
+        events.emit(msgs_changed(10, 5));
+        assert_eq!(emitter.recv().await, Some(msgs_changed(10, 5)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_event_json() -> Result<()> {
+        let json = serde_json::to_value(&EventJson::from(&msgs_changed(10, 1)))?;
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "context_id": 1,
+                "event_id": 2000,
+                "event": {
+                    "type": "MsgsChanged",
+                    "data": { "chat_id": 10, "msg_id": 1 }
+                }
+            })
+        );
+
+        let event = Event {
+            id: 1,
+            typ: EventType::ImexFileWritten(PathBuf::from("/tmp/backup.tar")),
+        };
+        let json = serde_json::to_value(&EventJson::from(&event))?;
+        assert_eq!(json["event"]["data"], "/tmp/backup.tar");
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_poll() {
+        let events = Events::default();
+        let emitter = events.get_emitter();
+        events.emit(msgs_changed(10, 1));
+        assert_eq!(emitter.recv().await, Some(msgs_changed(10, 1)));
+
+        // Events are queued for polling once polling was started,
+        // in addition to the emitters.
+        assert_eq!(events.poll(Duration::from_millis(10)).await, None);
+        events.emit(msgs_changed(10, 2));
+        assert_eq!(
+            events.poll(Duration::from_secs(10)).await,
+            Some(msgs_changed(10, 2))
+        );
+        assert_eq!(emitter.recv().await, Some(msgs_changed(10, 2)));
+        assert_eq!(events.poll(Duration::from_millis(10)).await, None);
+
+        let poll = {
+            let events = events.clone();
+            task::spawn(async move { events.poll(Duration::from_secs(60)).await })
+        };
+        task::sleep(Duration::from_millis(10)).await;
+        events.interrupt_polling();
+        assert_eq!(poll.await, None);
+    }
+}
@@ -47,10 +47,21 @@ pub enum HeaderDef {
     ChatVoiceMessage,
     ChatGroupMemberRemoved,
     ChatGroupMemberAdded,
+    ChatGroupEpoch,
+    ChatGroupSecret,
     ChatContent,
     ChatDuration,
+
+    /// `delete_device_after` sent to the other devices of the user.
+    ChatDeleteDeviceAfter,
+
+    /// `delete_server_after` sent to the other devices of the user.
+    ChatDeleteServerAfter,
     ChatDispositionNotificationTo,
     ChatWebrtcRoom,
+
+    /// Set to `1` if the message should be deleted after it is displayed once.
+    ChatViewOnce,
     Autocrypt,
     AutocryptSetupMessage,
     SecureJoin,
@@ -60,6 +71,10 @@ pub enum HeaderDef {
     SecureJoinAuth,
     Sender,
     EphemeralTimer,
+
+    /// Set to `delivery` if the ephemeral timer starts when the message is received
+    /// instead of when it is read.
+    EphemeralTimerStart,
     Received,
     _TestHeader,
 }
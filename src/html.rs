@@ -7,12 +7,17 @@
 ///! `MsgId.get_html()` will return HTML -
 ///! this allows nice quoting, handling linebreaks properly etc.
 use futures::future::FutureExt;
+use std::borrow::Cow;
 use std::future::Future;
 use std::pin::Pin;
 
 use anyhow::Result;
 use lettre_email::mime::{self, Mime};
+use once_cell::sync::Lazy;
+use regex::Regex;
 
+use crate::config::Config;
+use crate::contact::Contact;
 use crate::headerdef::{HeaderDef, HeaderDefMap};
 use crate::message::{Message, MsgId};
 use crate::mimeparser::parse_message_id;
@@ -244,6 +249,12 @@ impl MsgId {
     /// this is the case at least when `Message.has_html()` returns true
     /// (we do not save raw mime unconditionally in the database to save space).
     /// The corresponding ffi-function is `dc_get_msg_html()`.
+    ///
+    /// The returned HTML is sanitized: scripts, event handlers and embedded frames are removed.
+    /// Remote content as images or stylesheets is removed as well
+    /// unless `Config::ShowRemoteContent` is enabled
+    /// or loading remote content is allowed for the sender using `Contact::set_load_remote_content`,
+    /// so that the UI can display the HTML without leaking that the message was read.
     pub async fn get_html(self, context: &Context) -> Result<Option<String>> {
         let rawmime = message::get_mime_headers(context, self).await?;
 
@@ -253,13 +264,113 @@ pub async fn get_html(self, context: &Context) -> Result<Option<String>> {
                     warn!(context, "get_html: parser error: {}", err);
                     Ok(None)
                 }
-                Ok(parser) => Ok(Some(parser.html)),
+                Ok(parser) => {
+                    let load_remote = context.get_config_bool(Config::ShowRemoteContent).await?
+                        || self.sender_allows_remote_content(context).await?;
+                    Ok(Some(sanitize_html(&parser.html, load_remote)))
+                }
             }
         } else {
             warn!(context, "get_html: no mime for {}", self);
             Ok(None)
         }
     }
+
+    async fn sender_allows_remote_content(self, context: &Context) -> Result<bool> {
+        let msg = Message::load_from_db(context, self).await?;
+        let contact = Contact::load_from_db(context, msg.from_id).await?;
+        Ok(contact.get_load_remote_content())
+    }
+}
+
+static REMOTE_CSS_URL_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)(url\(\s*["']?\s*(https?:)?//[^)]*\)|@import\s+["']?\s*(https?:)?//[^;]*;?)"#)
+        .unwrap()
+});
+
+/// Attributes containing URLs which are checked by [`filter_attribute`].
+const URL_ATTRIBUTES: &[&str] = &["href", "src", "srcset", "background", "poster"];
+
+/// Returns true if `url` is loaded from the network, also if the scheme is omitted.
+fn is_remote_url(url: &str) -> bool {
+    let url = url.trim_start();
+    url.starts_with("http:") || url.starts_with("https:") || url.starts_with("//")
+}
+
+/// Filters attribute values of tags allowed by [`sanitize_html`].
+///
+/// Only images may be embedded as `data:` URLs. Unless `load_remote` is set,
+/// remote URLs and remote resources in inline styles are removed.
+fn filter_attribute<'u>(
+    element: &str,
+    attribute: &str,
+    value: &'u str,
+    load_remote: bool,
+) -> Option<Cow<'u, str>> {
+    if URL_ATTRIBUTES.contains(&attribute) {
+        let url = value.trim_start().to_lowercase();
+        if url.starts_with("data:")
+            && !(element == "img" && attribute == "src" && url.starts_with("data:image/"))
+        {
+            return None;
+        }
+        // Links are only loaded when the user clicks them.
+        if !load_remote && attribute != "href" && url.split(',').any(is_remote_url) {
+            return None;
+        }
+    }
+    if attribute == "style" && !load_remote {
+        return Some(REMOTE_CSS_URL_RE.replace_all(value, ""));
+    }
+    Some(Cow::Borrowed(value))
+}
+
+/// Removes active and, if `load_remote` is false, remote content from HTML.
+///
+/// Only allowlisted tags, attributes and URL schemes are kept, see [`ammonia`].
+/// The sanitized body is wrapped into a new UTF-8 document.
+/// The UI should display the HTML in a sandbox with JavaScript disabled anyway.
+pub(crate) fn sanitize_html(html: &str, load_remote: bool) -> String {
+    let mut builder = ammonia::Builder::default();
+    builder
+        .add_tags(&["font", "style"])
+        .clean_content_tags(
+            [
+                "script", "noscript", "iframe", "object", "embed", "applet", "title",
+            ]
+            .iter()
+            .copied()
+            .collect(),
+        )
+        .add_generic_attributes(&[
+            "style", "class", "align", "valign", "width", "height", "bgcolor", "border", "dir",
+        ])
+        .add_tag_attributes("font", &["color", "face", "size"])
+        .add_tag_attributes("table", &["cellpadding", "cellspacing", "background"])
+        .add_tag_attributes("td", &["background"])
+        .add_url_schemes(&["data"])
+        .link_rel(None)
+        .attribute_filter(move |element, attribute, value| {
+            filter_attribute(element, attribute, value, load_remote)
+        });
+    if load_remote {
+        builder
+            .add_tags(&["link"])
+            .add_tag_attributes("link", &["rel", "href", "type", "media"]);
+    }
+    let body = builder.clean(html).to_string();
+    let body = if load_remote {
+        body
+    } else {
+        // remote content in <style> blocks
+        REMOTE_CSS_URL_RE.replace_all(&body, "").into_owned()
+    };
+    format!(
+        "<!DOCTYPE html>\n\
+         <html><head><meta http-equiv=\"Content-Type\" content=\"text/html; charset=utf-8\" /></head>\
+         <body>{}</body></html>\n",
+        body
+    )
 }
 
 /// Wraps HTML text into a new text/html mimepart structure.
@@ -277,7 +388,6 @@ mod tests {
     use super::*;
     use crate::chat;
     use crate::chat::forward_msgs;
-    use crate::config::Config;
     use crate::constants::{Viewtype, DC_CONTACT_ID_SELF};
     use crate::dc_receive_imf::dc_receive_imf;
     use crate::message::MessengerMessage;
@@ -572,4 +682,71 @@ async fn test_cp1252_html() -> Result<()> {
         assert!(html.contains("foo bar ä ö ü ß"));
         Ok(())
     }
+
+    #[test]
+    fn test_sanitize_html() {
+        let html = r#"<html><head><script>alert(1)</script><link rel="stylesheet" href="https://example.org/a.css"><style>body { background: url('https://example.org/pixel.png'); }</style></head>
+<body onload="track()"><img src="https://example.org/pixel.gif" width=1><img src="data:image/png;base64,AAAA"><a href="javascript:evil()">x</a><a href="https://delta.chat">link</a><iframe src="https://example.org"></iframe></body></html>"#;
+
+        let sanitized = sanitize_html(html, false);
+        assert!(!sanitized.contains("script"));
+        assert!(!sanitized.contains("alert"));
+        assert!(!sanitized.contains("onload"));
+        assert!(!sanitized.contains("javascript"));
+        assert!(!sanitized.contains("iframe"));
+        assert!(!sanitized.contains("example.org"));
+        assert!(sanitized.contains(r#"<img width="1">"#));
+        assert!(sanitized.contains("data:image/png;base64,AAAA"));
+        assert!(sanitized.contains(r#"<a href="https://delta.chat">link</a>"#));
+        assert!(sanitized.contains("background: ;"));
+
+        let sanitized = sanitize_html(html, true);
+        assert!(!sanitized.contains("script"));
+        assert!(!sanitized.contains("onload"));
+        assert!(sanitized.contains("https://example.org/pixel.gif"));
+        assert!(sanitized.contains("https://example.org/a.css"));
+
+        // Only images may be embedded, formatting of emails is kept.
+        let sanitized = sanitize_html(
+            r#"<a href="data:text/html,evil">x</a><font color="red" onclick="evil()">red</font>"#,
+            false,
+        );
+        assert!(!sanitized.contains("evil"));
+        assert!(sanitized.contains(r#"<a>x</a><font color="red">red</font>"#));
+    }
+
+    #[async_std::test]
+    async fn test_load_remote_content_per_contact() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        t.set_config(Config::ShowEmails, Some("2")).await?;
+        t.set_config_bool(Config::SaveMimeHeaders, true).await?;
+        let chat = t.create_chat_with_contact("", "sender@example.org").await;
+        dc_receive_imf(
+            &t,
+            b"From: sender@example.org\n\
+              To: alice@example.com\n\
+              Subject: newsletter\n\
+              Message-ID: <remote@example.org>\n\
+              Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+              Content-Type: text/html; charset=utf-8\n\
+              \n\
+              <html><body><p>hi</p><img src=\"https://example.org/pixel.gif\"></body></html>\n",
+            "INBOX",
+            1,
+            false,
+        )
+        .await?;
+        let msg = t.get_last_msg_in(chat.get_id()).await;
+        let html = msg.get_id().get_html(&t).await?.unwrap();
+        assert!(!html.contains("https://example.org/pixel.gif"));
+
+        let mut contact = Contact::load_from_db(&t, msg.get_from_id()).await?;
+        assert!(!contact.get_load_remote_content());
+        contact.set_load_remote_content(&t, true).await?;
+        let contact = Contact::load_from_db(&t, msg.get_from_id()).await?;
+        assert!(contact.get_load_remote_content());
+        let html = msg.get_id().get_html(&t).await?.unwrap();
+        assert!(html.contains("https://example.org/pixel.gif"));
+        Ok(())
+    }
 }
@@ -5,7 +5,7 @@
 
 use std::{cmp, cmp::max, collections::BTreeMap};
 
-use anyhow::{bail, format_err, Context as _, Result};
+use anyhow::{bail, ensure, format_err, Context as _, Result};
 use async_imap::{
     error::Result as ImapResult,
     types::{Fetch, Flag, Mailbox, Name, NameAttribute, UnsolicitedResponse},
@@ -15,8 +15,8 @@
 use num_traits::FromPrimitive;
 
 use crate::constants::{
-    Chattype, ShowEmails, Viewtype, DC_FETCH_EXISTING_MSGS_COUNT, DC_FOLDERS_CONFIGURED_VERSION,
-    DC_LP_AUTH_OAUTH2,
+    Chattype, ShowEmails, Viewtype, DC_CHAT_ID_TRASH, DC_FETCH_EXISTING_MSGS_COUNT,
+    DC_FOLDERS_CONFIGURED_VERSION, DC_LP_AUTH_OAUTH2,
 };
 use crate::context::Context;
 use crate::dc_receive_imf::{
@@ -27,8 +27,9 @@
 use crate::headerdef::{HeaderDef, HeaderDefMap};
 use crate::job::{self, Action};
 use crate::login_param::{CertificateChecks, LoginParam, ServerLoginParam};
-use crate::message::{self, update_server_uid, MessageState};
+use crate::message::{self, update_server_uid, MessageState, MsgId};
 use crate::mimeparser;
+use crate::network_policy;
 use crate::oauth2::dc_get_oauth2_access_token;
 use crate::param::Params;
 use crate::provider::Socket;
@@ -65,7 +66,8 @@ pub enum ImapActionResult {
 /// - Chat-Version to check if a message is a chat message
 /// - Autocrypt-Setup-Message to check if a message is an autocrypt setup message,
 ///   not necessarily sent by Delta Chat.
-const PREFETCH_FLAGS: &str = "(UID BODY.PEEK[HEADER.FIELDS (\
+/// - RFC822.SIZE to defer downloading large messages on metered connections.
+const PREFETCH_FLAGS: &str = "(UID RFC822.SIZE BODY.PEEK[HEADER.FIELDS (\
                               MESSAGE-ID \
                               FROM \
                               IN-REPLY-TO REFERENCES \
@@ -390,7 +392,7 @@ pub async fn prepare(&mut self, context: &Context) -> Result<()> {
         Ok(())
     }
 
-    async fn disconnect(&mut self, context: &Context) {
+    pub(crate) async fn disconnect(&mut self, context: &Context) {
         // Close folder if messages should be expunged
         if let Err(err) = self.close_folder(context).await {
             warn!(context, "failed to close folder: {:?}", err);
@@ -419,6 +421,8 @@ pub fn should_reconnect(&self) -> bool {
     pub async fn trigger_reconnect(&mut self, context: &Context) {
         self.connectivity.set_connecting(context).await;
         self.should_reconnect = true;
+        #[cfg(feature = "metrics")]
+        crate::metrics::IMAP_RECONNECTS.inc();
     }
 
     pub async fn fetch(&mut self, context: &Context, watch_folder: &str) -> Result<()> {
@@ -509,6 +513,172 @@ pub(crate) async fn resync_folder_uids(
         Ok(())
     }
 
+    /// Detects trashed messages whose server copy was deleted out-of-band,
+    /// e.g. by another client, and removes their UID from the database.
+    ///
+    /// Such messages are then removed from the database by housekeeping,
+    /// otherwise they would stay in the trash chat forever.
+    /// Returns the number of vanished messages.
+    pub(crate) async fn clear_vanished_trash_uids(
+        &mut self,
+        context: &Context,
+        folder: &str,
+    ) -> Result<usize> {
+        let trashed: Vec<(MsgId, u32)> = context
+            .sql
+            .query_map(
+                "SELECT id, server_uid FROM msgs
+                 WHERE chat_id=? AND server_folder=? AND server_uid!=0;",
+                paramsv![DC_CHAT_ID_TRASH, folder],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+                |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+            )
+            .await?;
+        if trashed.is_empty() {
+            return Ok(0);
+        }
+
+        self.select_folder(context, Some(folder)).await?;
+        let session = if let Some(ref mut session) = &mut self.session {
+            session
+        } else {
+            bail!("IMAP No Connection established");
+        };
+        let existing = session.uid_search("ALL").await?;
+
+        let vanished: Vec<MsgId> = trashed
+            .into_iter()
+            .filter(|(_, uid)| !existing.contains(uid))
+            .map(|(msg_id, _)| msg_id)
+            .collect();
+        for msg_id in &vanished {
+            context
+                .sql
+                .execute("UPDATE msgs SET server_uid=0 WHERE id=?;", paramsv![msg_id])
+                .await?;
+        }
+        if !vanished.is_empty() {
+            info!(
+                context,
+                "{} trashed messages vanished from folder {}",
+                vanished.len(),
+                folder
+            );
+        }
+        Ok(vanished.len())
+    }
+
+    /// Searches `folder` on the server and downloads the matching messages again
+    /// that were already deleted from the device.
+    ///
+    /// Messages that are still stored on the device are not downloaded.
+    /// If `limit` is set, only the latest `limit` matching messages are downloaded.
+    /// Returns the number of downloaded messages.
+    pub(crate) async fn search_and_fetch(
+        &mut self,
+        context: &Context,
+        folder: &str,
+        search_command: &str,
+        limit: Option<usize>,
+    ) -> Result<usize> {
+        self.select_folder(context, Some(folder)).await?;
+        let session = self
+            .session
+            .as_mut()
+            .context("IMAP No Connection established")?;
+        let mut found: Vec<u32> = session
+            .uid_search(search_command)
+            .await?
+            .into_iter()
+            .collect();
+        found.sort_unstable();
+
+        let mut uids = Vec::new();
+        for uid in found.into_iter().rev() {
+            if limit.map_or(false, |limit| uids.len() >= limit) {
+                break;
+            }
+            if remove_redownloadable_tombstone(context, folder, uid).await? {
+                uids.push(uid);
+            }
+        }
+        uids.reverse();
+
+        let count = uids.len();
+        let (_, read_errors) = self.fetch_many_msgs(context, folder, uids, true).await;
+        info!(
+            context,
+            "Downloaded {} messages from \"{}\" again, {} errors.", count, folder, read_errors
+        );
+        Ok(count.saturating_sub(read_errors))
+    }
+
+    /// Downloads a single new message for showing a notification.
+    ///
+    /// If `uid` is not given, the newest message with a UID not below the stored
+    /// `uid_next` of the folder is downloaded. Messages larger than `max_size` bytes are
+    /// not downloaded. Returns the UID of the downloaded message.
+    pub(crate) async fn fetch_single_msg(
+        &mut self,
+        context: &Context,
+        folder: &str,
+        uid: Option<u32>,
+        max_size: u32,
+    ) -> Result<Option<u32>> {
+        self.prepare(context).await?;
+        self.select_folder(context, Some(folder)).await?;
+        let session = self
+            .session
+            .as_mut()
+            .context("IMAP No Connection established")?;
+
+        let uid = match uid {
+            Some(uid) => uid,
+            None => {
+                let uid_next = get_uid_next(context, folder).await?.max(1);
+                let newest = session
+                    .uid_search(format!("UID {}:*", uid_next))
+                    .await?
+                    .into_iter()
+                    .filter(|uid| *uid >= uid_next)
+                    .max();
+                match newest {
+                    Some(uid) => uid,
+                    None => return Ok(None),
+                }
+            }
+        };
+
+        let mut size = None;
+        let mut msgs = session
+            .uid_fetch(uid.to_string(), "(UID RFC822.SIZE)")
+            .await?;
+        while let Some(msg) = msgs.next().await {
+            let msg = msg?;
+            if msg.uid == Some(uid) {
+                size = msg.size;
+            }
+        }
+        drop(msgs);
+        match size {
+            Some(size) if size <= max_size => {}
+            Some(size) => {
+                info!(
+                    context,
+                    "Not downloading message {} of {} bytes for notification", uid, size
+                );
+                return Ok(None);
+            }
+            None => return Ok(None),
+        }
+
+        let (last_uid, read_errors) = self
+            .fetch_many_msgs(context, folder, vec![uid], false)
+            .await;
+        ensure!(read_errors == 0, "failed to download message {}", uid);
+        Ok(last_uid)
+    }
+
     /// Select a folder and take care of uidvalidity changes.
     /// Also, when selecting a folder for the first time, sets the uid_next to the current
     /// mailbox.uid_next so that no old emails are fetched.
@@ -556,6 +726,10 @@ pub(crate) async fn select_with_uidvalidity(
             return Ok(new_emails);
         }
 
+        // UIDs of deferred messages are not valid anymore,
+        // the messages are downloaded again if they are still new.
+        network_policy::remove_deferred(context, folder, None).await?;
+
         if mailbox.exists == 0 {
             info!(context, "Folder \"{}\" is empty.", folder);
 
@@ -626,6 +800,7 @@ pub(crate) async fn fetch_new_messages<S: AsRef<str>>(
         let new_emails = self
             .select_with_uidvalidity(context, folder.as_ref())
             .await?;
+        self.fetch_deferred_msgs(context, folder.as_ref()).await?;
 
         if !new_emails && !fetch_existing_msgs {
             info!(context, "No new emails in folder {}", folder.as_ref());
@@ -670,7 +845,30 @@ pub(crate) async fn fetch_new_messages<S: AsRef<str>>(
             )
             .await
             {
-                uids.push(current_uid);
+                if network_policy::should_defer(context, msg.size).await {
+                    let sender = headers
+                        .get_header_value(HeaderDef::From_)
+                        .unwrap_or_default();
+                    info!(
+                        context,
+                        "Deferring download of message {} in {} on metered connection",
+                        current_uid,
+                        folder
+                    );
+                    network_policy::defer_download(
+                        context,
+                        folder,
+                        current_uid,
+                        msg.size.unwrap_or_default(),
+                        &sender,
+                    )
+                    .await?;
+                    if read_errors == 0 {
+                        largest_uid_skipped = Some(current_uid);
+                    }
+                } else {
+                    uids.push(current_uid);
+                }
             } else if read_errors == 0 {
                 // If there were errors (`read_errors != 0`), stop updating largest_uid_skipped so that uid_next will
                 // not be updated and we will retry prefetching next time
@@ -715,6 +913,28 @@ pub(crate) async fn fetch_new_messages<S: AsRef<str>>(
         Ok(read_cnt > 0)
     }
 
+    /// Downloads messages in the selected `folder` which were deferred
+    /// because of the network policy and are due now.
+    async fn fetch_deferred_msgs(&mut self, context: &Context, folder: &str) -> Result<()> {
+        let uids = network_policy::get_due_downloads(context, folder).await?;
+        if uids.is_empty() {
+            return Ok(());
+        }
+        info!(
+            context,
+            "Downloading {} deferred messages from {}",
+            uids.len(),
+            folder
+        );
+        let (_, error_cnt) = self
+            .fetch_many_msgs(context, folder, uids.clone(), false)
+            .await;
+        if error_cnt == 0 {
+            network_policy::remove_deferred(context, folder, Some(&uids)).await?;
+        }
+        Ok(())
+    }
+
     /// Gets the from, to and bcc addresses from all existing outgoing emails.
     pub async fn get_all_recipients(&mut self, context: &Context) -> Result<Vec<SingleInfo>> {
         if self.session.is_none() {
@@ -944,6 +1164,10 @@ async fn fetch_many_msgs(
             );
         }
 
+        if let Err(err) = crate::blob::sync_pending_blobs(context).await {
+            warn!(context, "Failed to sync blob files: {}", err);
+        }
+
         (last_uid, read_errors)
     }
 
@@ -1241,6 +1465,128 @@ pub async fn delete_msg(
         }
     }
 
+    /// Deletes several messages in `folder`, given as pairs of UID and Message-ID.
+    ///
+    /// As in [`Imap::delete_msg`], the Message-IDs are checked before deleting, but the
+    /// messages are fetched, flagged and expunged using one command per UID set.
+    /// Returns the UIDs of the deleted messages; messages which are not found or have
+    /// a different Message-ID are not deleted.
+    pub(crate) async fn delete_msgs(
+        &mut self,
+        context: &Context,
+        folder: &str,
+        msgs: &[(u32, String)],
+    ) -> Result<Vec<u32>> {
+        self.select_folder(context, Some(folder)).await?;
+        let session = self
+            .session
+            .as_mut()
+            .context("no IMAP session to delete messages")?;
+
+        let mut remote_message_ids = BTreeMap::new();
+        for set in build_sequence_sets(msgs.iter().map(|(uid, _)| *uid).collect()) {
+            let mut list = session.uid_fetch(set, DELETE_CHECK_FLAGS).await?;
+            while let Some(fetch) = list.next().await {
+                let fetch = fetch?;
+                if let Some(uid) = fetch.uid {
+                    if let Ok(message_id) = get_fetch_headers(&fetch)
+                        .and_then(|headers| prefetch_get_message_id(&headers))
+                    {
+                        remote_message_ids.insert(uid, message_id);
+                    }
+                }
+            }
+        }
+
+        let mut uids = Vec::new();
+        for (uid, message_id) in msgs {
+            match remote_message_ids.get(uid) {
+                Some(remote_message_id) if remote_message_id == message_id => uids.push(*uid),
+                Some(remote_message_id) => warn!(
+                    context,
+                    "Cannot delete on IMAP, {}/{}: remote message-id '{}' != '{}'",
+                    folder,
+                    uid,
+                    remote_message_id,
+                    message_id,
+                ),
+                None => info!(
+                    context,
+                    "Cannot delete on IMAP, {}/{}: imap entry gone '{}'", folder, uid, message_id,
+                ),
+            }
+        }
+        if uids.is_empty() {
+            return Ok(uids);
+        }
+
+        for set in build_sequence_sets(uids.clone()) {
+            let mut responses = session.uid_store(&set, "+FLAGS (\\Deleted)").await?;
+            while let Some(response) = responses.next().await {
+                response?;
+            }
+        }
+        emit_event!(
+            context,
+            EventType::ImapMessageDeleted(format!(
+                "{} IMAP messages in {} marked as deleted",
+                uids.len(),
+                folder
+            ))
+        );
+        self.config.selected_folder_needs_expunge = true;
+        self.close_folder(context).await?;
+        Ok(uids)
+    }
+
+    /// Searches the watched folders and the spam folder for a message
+    /// that is not found at its known location anymore,
+    /// eg. because it was moved by a spam filter or in the webmail.
+    ///
+    /// If the message is found, the `server_folder` and `server_uid` columns are updated
+    /// and the new location is returned.
+    pub(crate) async fn relocate_msg(
+        &mut self,
+        context: &Context,
+        message_id: &str,
+    ) -> Result<Option<(String, u32)>> {
+        let mut folders: Vec<String> = Vec::new();
+        for config in &[
+            Config::ConfiguredInboxFolder,
+            Config::ConfiguredMvboxFolder,
+            Config::ConfiguredSentboxFolder,
+            Config::ConfiguredSpamFolder,
+        ] {
+            if let Some(folder) = context.get_config(*config).await? {
+                if !folders.contains(&folder) {
+                    folders.push(folder);
+                }
+            }
+        }
+
+        let search_command = format!("HEADER Message-ID \"{}\"", message_id);
+        for folder in folders {
+            if let Err(err) = self.select_folder(context, Some(&folder)).await {
+                warn!(context, "relocate_msg: cannot select {}: {:?}", folder, err);
+                continue;
+            }
+            let session = self
+                .session
+                .as_mut()
+                .context("IMAP No Connection established")?;
+            let uid = session.uid_search(&search_command).await?.into_iter().max();
+            if let Some(uid) = uid {
+                info!(
+                    context,
+                    "Message {} was relocated to {}/{}", message_id, folder, uid
+                );
+                update_server_uid(context, message_id, &folder, uid).await;
+                return Ok(Some((folder, uid)));
+            }
+        }
+        Ok(None)
+    }
+
     pub async fn ensure_configured_folders(
         &mut self,
         context: &Context,
@@ -1519,7 +1865,7 @@ async fn precheck_imf(
                 "[move] detected bcc-self {} as {}/{}", rfc724_mid, server_folder, server_uid
             );
 
-            let delete_server_after = context.get_config_delete_server_after().await?;
+            let delete_server_after = context.get_config_delete_server_after_self().await?;
 
             if delete_server_after != Some(0) {
                 if msg_id
@@ -1730,6 +2076,58 @@ fn get_fallback_folder(delimiter: &str) -> String {
     format!("INBOX{}DeltaChat", delimiter)
 }
 
+/// Removes the tombstone of a message deleted from the device by `delete_device_after`,
+/// so that the message is downloaded again.
+///
+/// Messages deleted by the user, by an ephemeral timer or after being viewed once,
+/// and messages still to be deleted from the server, are kept deleted.
+/// Returns true if the tombstone was removed.
+pub(crate) async fn remove_redownloadable_tombstone(
+    context: &Context,
+    folder: &str,
+    uid: u32,
+) -> Result<bool> {
+    let removed = context
+        .sql
+        .execute(
+            "DELETE FROM msgs \
+             WHERE chat_id=? AND server_folder=? AND server_uid=? \
+             AND device_expired=1 AND ephemeral_timer=0 AND view_once=0 \
+             AND id NOT IN (SELECT foreign_id FROM jobs WHERE action=?);",
+            paramsv![DC_CHAT_ID_TRASH, folder, uid, Action::DeleteMsgOnImap],
+        )
+        .await?;
+    Ok(removed > 0)
+}
+
+/// Builds an IMAP SEARCH command for messages containing `query`
+/// in the headers or the body.
+pub(crate) fn build_text_search(query: &str) -> Result<String> {
+    if query.is_ascii() {
+        Ok(format!("TEXT {}", quote_search_string(query)?))
+    } else {
+        Ok(format!(
+            "CHARSET UTF-8 TEXT {}",
+            quote_search_string(query)?
+        ))
+    }
+}
+
+/// Quotes a string for use in an IMAP SEARCH command.
+///
+/// Quoted strings cannot contain line breaks, which would end the command,
+/// so strings containing CR or LF are rejected.
+pub(crate) fn quote_search_string(s: &str) -> Result<String> {
+    ensure!(
+        !s.contains(|c| c == '\r' || c == '\n'),
+        "Search string must not contain line breaks"
+    );
+    Ok(format!(
+        "\"{}\"",
+        s.replace('\\', "\\\\").replace('"', "\\\"")
+    ))
+}
+
 /// uid_next is the next unique identifier value from the last time we fetched a folder
 /// See <https://tools.ietf.org/html/rfc3501#section-2.3.1.1>
 /// This function is used to update our uid_next after fetching messages.
@@ -1868,6 +2266,66 @@ fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
 mod tests {
     use super::*;
     use crate::test_utils::TestContext;
+
+    #[test]
+    fn test_build_text_search() {
+        assert_eq!(build_text_search("hello").unwrap(), "TEXT \"hello\"");
+        assert_eq!(
+            build_text_search("say \"hi\" \\o/").unwrap(),
+            "TEXT \"say \\\"hi\\\" \\\\o/\""
+        );
+        assert_eq!(
+            build_text_search("grüße").unwrap(),
+            "CHARSET UTF-8 TEXT \"grüße\""
+        );
+        assert!(build_text_search("hi\r\nA1 DELETE INBOX").is_err());
+        assert!(build_text_search("hi\nthere").is_err());
+    }
+
+    #[async_std::test]
+    async fn test_remove_redownloadable_tombstone() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        for (uid, device_expired, ephemeral_timer, view_once) in &[
+            (1, true, 0, false),
+            (2, false, 0, false),
+            (3, true, 60, false),
+            (4, true, 0, true),
+            (5, true, 0, false),
+        ] {
+            t.sql
+                .execute(
+                    "INSERT INTO msgs (chat_id, rfc724_mid, server_folder, server_uid, \
+                     device_expired, ephemeral_timer, view_once) \
+                     VALUES (?, ?, 'INBOX', ?, ?, ?, ?)",
+                    paramsv![
+                        DC_CHAT_ID_TRASH,
+                        format!("{}@example.org", uid),
+                        *uid,
+                        *device_expired,
+                        *ephemeral_timer,
+                        *view_once
+                    ],
+                )
+                .await?;
+        }
+        let msg_id: u32 = t
+            .sql
+            .query_get_value("SELECT id FROM msgs WHERE server_uid=5", paramsv![])
+            .await?
+            .unwrap();
+        job::Job::new(Action::DeleteMsgOnImap, msg_id, Params::new(), 0)
+            .save(&t)
+            .await?;
+
+        // Only messages removed by `delete_device_after` are downloaded again.
+        assert!(remove_redownloadable_tombstone(&t, "INBOX", 1).await?);
+        assert!(!remove_redownloadable_tombstone(&t, "INBOX", 1).await?);
+        for uid in 2..=5 {
+            assert!(!remove_redownloadable_tombstone(&t, "INBOX", uid).await?);
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_get_folder_meaning_by_name() {
         assert_eq!(get_folder_meaning_by_name("Gesendet"), FolderMeaning::Sent);
@@ -28,7 +28,7 @@ pub async fn idle(
 
         self.select_folder(context, watch_folder.as_deref()).await?;
 
-        let timeout = Duration::from_secs(23 * 60);
+        let timeout = context.get_hint().await.idle_timeout();
         let mut info = Default::default();
 
         if self.server_sent_unsolicited_exists(context) {
@@ -119,9 +119,10 @@ pub(crate) async fn fake_idle(
         };
         info!(context, "IMAP-fake-IDLEing folder={:?}", watch_folder);
 
-        // check every minute if there are new messages
-        // TODO: grow sleep durations / make them more flexible
-        let mut interval = async_std::stream::interval(Duration::from_secs(60));
+        // check every minute if there are new messages,
+        // less often if the app is in the background
+        let mut interval =
+            async_std::stream::interval(context.get_hint().await.fake_idle_interval());
 
         enum Event {
             Tick,
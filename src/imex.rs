@@ -1,6 +1,7 @@
 //! # Import/export module
 
 use std::any::Any;
+use std::collections::BTreeMap;
 use std::ffi::OsStr;
 
 use ::pgp::types::KeyTrait;
@@ -12,26 +13,34 @@
 };
 use async_tar::Archive;
 use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
 
 use crate::blob::BlobObject;
-use crate::chat::{self, delete_and_reset_all_device_msgs, ChatId};
+use crate::chat::{self, delete_and_reset_all_device_msgs, Chat, ChatId, ProtectionStatus};
 use crate::config::Config;
-use crate::constants::{Viewtype, DC_CONTACT_ID_SELF};
+use crate::constants::{
+    Chattype, Viewtype, DC_CHAT_ID_LAST_SPECIAL, DC_CONTACT_ID_LAST_SPECIAL, DC_CONTACT_ID_SELF,
+    DC_VERSION_STR,
+};
+use crate::contact::{Contact, Origin};
 use crate::context::Context;
 use crate::dc_tools::{
     dc_copy_file, dc_create_folder, dc_delete_file, dc_delete_files_in_dir, dc_get_filesuffix_lc,
     dc_open_file_std, dc_read_file, dc_write_file, get_next_backup_path, time, EmailAddress,
 };
 use crate::e2ee;
+use crate::ephemeral;
 use crate::events::EventType;
 use crate::key::{self, DcKey, DcSecretKey, SignedPublicKey, SignedSecretKey};
 use crate::log::LogExt;
-use crate::message::{Message, MsgId};
+use crate::message::{Message, MessageState, MsgId};
 use crate::mimeparser::SystemMessage;
-use crate::param::Param;
+use crate::param::{Param, Params};
 use crate::pgp;
 use crate::sql::{self, Sql};
 use crate::stock_str;
+use crate::storage::{FileSink, StorageSink};
 
 // Name of the database file in the backup.
 const DBFILE_BACKUP_NAME: &str = "dc_database_backup.sqlite";
@@ -80,6 +89,10 @@ pub enum ImexMode {
 /// To cancel an import-/export-progress, drop the future returned by this function.
 pub async fn imex(context: &Context, what: ImexMode, param1: &Path) -> Result<()> {
     let cancel = context.alloc_ongoing().await?;
+    if what == ImexMode::ImportBackup {
+        // The database is replaced during the import.
+        ephemeral::stop_ephemeral_task(context).await;
+    }
 
     let res = async {
         let success = imex_inner(context, what, param1).await;
@@ -105,6 +118,9 @@ pub async fn imex(context: &Context, what: ImexMode, param1: &Path) -> Result<()
     })
     .await;
 
+    if what == ImexMode::ImportBackup {
+        ephemeral::start_ephemeral_task(context).await;
+    }
     context.free_ongoing().await;
 
     res
@@ -662,13 +678,158 @@ async fn import_backup_old(context: &Context, backup_to_import: &Path) -> Result
 /*******************************************************************************
  * Export backup
  ******************************************************************************/
-#[allow(unused)]
 async fn export_backup(context: &Context, dir: &Path) -> Result<()> {
     // get a fine backup file name (the name includes the date so that multiple backup instances are possible)
     let now = time();
     let (temp_path, dest_path) = get_next_backup_path(dir, now).await?;
     let _d = DeleteOnDrop(temp_path.clone());
 
+    info!(
+        context,
+        "Backup '{}' to '{}'.",
+        context.get_dbfile().display(),
+        dest_path.display(),
+    );
+    let sink = FileSink::create(temp_path, dest_path.clone()).await?;
+    let res = write_backup(context, now, Box::new(sink)).await;
+    match &res {
+        Ok(_) => context.emit_event(EventType::ImexFileWritten(dest_path)),
+        Err(e) => error!(context, "backup failed: {}", e),
+    }
+    res
+}
+
+/// Writes a backup to `dir` while the database stays open and IO may keep running,
+/// and returns the path of the backup file.
+///
+/// The database is copied using `VACUUM INTO` instead of closing it, no events are emitted.
+async fn export_backup_online(context: &Context, dir: &Path) -> Result<PathBuf> {
+    let now = time();
+    let (temp_path, dest_path) = get_next_backup_path(dir, now).await?;
+    let _d = DeleteOnDrop(temp_path.clone());
+    let mut db_copy = temp_path.as_os_str().to_owned();
+    db_copy.push(".db");
+    let db_copy = PathBuf::from(db_copy);
+    let _d2 = DeleteOnDrop(db_copy.clone());
+
+    info!(context, "Backup online to '{}'.", dest_path.display());
+    context
+        .sql
+        .set_raw_config_int("backup_time", now as i32)
+        .await?;
+    context
+        .sql
+        .execute(
+            "VACUUM INTO ?;",
+            paramsv![db_copy.to_string_lossy().to_string()],
+        )
+        .await?;
+    let sink = FileSink::create(temp_path, dest_path.clone()).await?;
+    export_backup_inner(context, &db_copy, Box::new(sink), false).await?;
+    Ok(dest_path)
+}
+
+/// Writes an automatic backup to `Config::AutoBackupDir`
+/// if `Config::AutoBackupInterval` passed since the last attempt.
+///
+/// Emits `AutoBackupWritten` or `AutoBackupFailed`.
+/// Called from the `AutoBackup` job while IO is running.
+pub(crate) async fn maybe_auto_backup(context: &Context) {
+    match auto_backup(context).await {
+        Ok(Some(path)) => context.emit_event(EventType::AutoBackupWritten(path)),
+        Ok(None) => {}
+        Err(err) => {
+            warn!(context, "Automatic backup failed: {:#}", err);
+            context.emit_event(EventType::AutoBackupFailed(format!("{:#}", err)));
+        }
+    }
+}
+
+/// Returns the directory to write an automatic backup to if one is due.
+pub(crate) async fn auto_backup_due(context: &Context) -> Result<Option<PathBuf>> {
+    let dir = match context.get_config(Config::AutoBackupDir).await? {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => return Ok(None),
+    };
+    let interval = context.get_config_i64(Config::AutoBackupInterval).await?;
+    if interval <= 0 {
+        return Ok(None);
+    }
+    let now = time();
+    let last_backup = context.get_config_i64(Config::LastAutoBackup).await?;
+    if last_backup <= now && now < last_backup + interval {
+        return Ok(None);
+    }
+    Ok(Some(dir))
+}
+
+/// Writes an automatic backup if it is due and deletes old backups.
+///
+/// A failed attempt is only retried after the interval, like a written backup.
+/// Returns the path of the written backup.
+async fn auto_backup(context: &Context) -> Result<Option<PathBuf>> {
+    let dir = match auto_backup_due(context).await? {
+        Some(dir) => dir,
+        None => return Ok(None),
+    };
+
+    info!(context, "Writing automatic backup to {}", dir.display());
+    context
+        .set_config(Config::LastAutoBackup, Some(&time().to_string()))
+        .await?;
+    e2ee::ensure_secret_key_exists(context)
+        .await
+        .context("Cannot create private key or private key not available")?;
+    dc_create_folder(context, &dir).await?;
+    let path = export_backup_online(context, &dir).await?;
+
+    let keep = context.get_config_int(Config::AutoBackupKeep).await?;
+    if keep > 0 {
+        delete_old_backups(context, &dir, keep as usize).await?;
+    }
+    Ok(Some(path))
+}
+
+/// Deletes all but the `keep` newest backups in `dir`.
+async fn delete_old_backups(context: &Context, dir: &Path, keep: usize) -> Result<()> {
+    let mut names = Vec::new();
+    let mut dir_iter = fs::read_dir(dir).await?;
+    while let Some(dirent) = dir_iter.next().await {
+        let name: String = dirent?.file_name().to_string_lossy().into();
+        if name.starts_with("delta-chat-backup-") && name.ends_with(".tar") {
+            names.push(name);
+        }
+    }
+    // As in has_backup(), the file names sort by the time of the backup.
+    names.sort();
+    let count = names.len().saturating_sub(keep);
+    for name in names.into_iter().take(count) {
+        info!(context, "Deleting old backup {}", name);
+        fs::remove_file(dir.join(name)).await?;
+    }
+    Ok(())
+}
+
+/// Exports a backup to `sink`, eg. to stream it directly to external storage.
+///
+/// Like exporting a backup using [`imex`], this requires IO to be stopped
+/// and cannot run at the same time as other import or export processes.
+/// The backup can be imported using [`ImexMode::ImportBackup`].
+pub async fn export_backup_to_sink(context: &Context, sink: Box<dyn StorageSink>) -> Result<()> {
+    let _cancel = context.alloc_ongoing().await?;
+    let res = async {
+        e2ee::ensure_secret_key_exists(context)
+            .await
+            .context("Cannot create private key or private key not available")?;
+        write_backup(context, time(), sink).await
+    }
+    .await;
+    context.free_ongoing().await;
+    res
+}
+
+/// Writes a backup to `sink` and finalizes the sink on success.
+async fn write_backup(context: &Context, now: i64, sink: Box<dyn StorageSink>) -> Result<()> {
     context
         .sql
         .set_raw_config_int("backup_time", now as i32)
@@ -687,29 +848,14 @@ async fn export_backup(context: &Context, dir: &Path) -> Result<()> {
     );
 
     // we close the database during the export
+    ephemeral::stop_ephemeral_task(context).await;
     context.sql.close().await;
 
-    info!(
-        context,
-        "Backup '{}' to '{}'.",
-        context.get_dbfile().display(),
-        dest_path.display(),
-    );
-
-    let res = export_backup_inner(context, &temp_path).await;
+    let res = export_backup_inner(context, context.get_dbfile(), sink, true).await;
 
     // we re-open the database after export is finished
     context.sql.open(context, context.get_dbfile(), false).await;
-
-    match &res {
-        Ok(_) => {
-            fs::rename(temp_path, &dest_path).await?;
-            context.emit_event(EventType::ImexFileWritten(dest_path));
-        }
-        Err(e) => {
-            error!(context, "backup failed: {}", e);
-        }
-    }
+    ephemeral::start_ephemeral_task(context).await;
 
     res
 }
@@ -722,14 +868,19 @@ fn drop(&mut self) {
     }
 }
 
-async fn export_backup_inner(context: &Context, temp_path: &PathBuf) -> Result<()> {
-    let file = File::create(temp_path).await?;
-
-    let mut builder = async_tar::Builder::new(file);
+/// Writes the database file `dbfile` and the blobs to `sink`,
+/// emitting `ImexProgress` events if `emit_progress` is set.
+async fn export_backup_inner(
+    context: &Context,
+    dbfile: &Path,
+    sink: Box<dyn StorageSink>,
+    emit_progress: bool,
+) -> Result<()> {
+    let mut builder = async_tar::Builder::new(sink);
 
     // append_path_with_name() wants the source path as the first argument, append_dir_all() wants it as the second argument.
     builder
-        .append_path_with_name(context.get_dbfile(), DBFILE_BACKUP_NAME)
+        .append_path_with_name(dbfile, DBFILE_BACKUP_NAME)
         .await?;
 
     let read_dir: Vec<_> = fs::read_dir(context.get_blobdir()).await?.collect().await;
@@ -753,14 +904,14 @@ async fn export_backup_inner(context: &Context, temp_path: &PathBuf) -> Result<(
 
         written_files += 1;
         let progress = 1000 * written_files / count;
-        if progress > 10 && progress < 1000 {
+        if emit_progress && progress > 10 && progress < 1000 {
             // We already emitted ImexProgress(10) above
             emit_event!(context, EventType::ImexProgress(progress));
         }
     }
 
-    builder.finish().await?;
-    Ok(())
+    let sink = builder.into_inner().await?;
+    sink.finalize().await
 }
 
 /*******************************************************************************
@@ -919,6 +1070,610 @@ async fn export_key_to_asc_file<T>(
     res
 }
 
+/// Account settings written by [`export_settings`].
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SettingsExport {
+    config: BTreeMap<String, String>,
+    keypairs: Vec<KeypairExport>,
+    contacts: Vec<ContactExport>,
+    peerstates: Vec<PeerstateExport>,
+    chats: Vec<ChatSettingsExport>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct KeypairExport {
+    addr: String,
+    is_default: bool,
+    private_key: String,
+    public_key: String,
+    created: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ContactExport {
+    addr: String,
+    name: String,
+    blocked: bool,
+}
+
+/// Row of the `acpeerstates` table, keys are base64-encoded.
+#[derive(Debug, Serialize, Deserialize)]
+struct PeerstateExport {
+    addr: String,
+    last_seen: i64,
+    last_seen_autocrypt: i64,
+    prefer_encrypted: i64,
+    public_key: Option<String>,
+    public_key_fingerprint: Option<String>,
+    gossip_timestamp: i64,
+    gossip_key: Option<String>,
+    gossip_key_fingerprint: Option<String>,
+    verified_key: Option<String>,
+    verified_key_fingerprint: Option<String>,
+}
+
+/// Settings of a chat, identified by the group ID or,
+/// for 1:1 chats, by the address of the contact.
+#[derive(Debug, Serialize, Deserialize)]
+struct ChatSettingsExport {
+    grpid: String,
+    addr: String,
+    ephemeral_timer: i64,
+    muted_until: i64,
+
+    // The following fields are missing in files written by older versions.
+    #[serde(default)]
+    protected: ProtectionStatus,
+
+    /// Chat params listed in [`CHAT_SETTINGS_PARAMS`].
+    #[serde(default)]
+    param: String,
+}
+
+impl ChatSettingsExport {
+    fn is_default(&self) -> bool {
+        self.ephemeral_timer == 0
+            && self.muted_until == 0
+            && self.protected == ProtectionStatus::Unprotected
+            && self.param.is_empty()
+    }
+}
+
+/// Chat params which are settings of the user and exported by [`export_settings`].
+const CHAT_SETTINGS_PARAMS: &[Param] = &[
+    Param::SubjectPrefix,
+    Param::EphemeralVerifiedOnly,
+    Param::EphemeralMaxTimer,
+    Param::NotificationProfile,
+    Param::MdnPolicy,
+];
+
+/// Returns true for configuration values that are exported by [`export_settings`].
+///
+/// Passwords, the configured server settings and internal state are not exported,
+/// the account has to be configured with its password on the new device.
+fn is_exported_config(key: Config) -> bool {
+    !key.as_ref().starts_with("configured")
+        && !matches!(
+            key,
+            Config::MailPw
+                | Config::SendPw
+                | Config::Selfavatar
+                | Config::AutoBackupDir
+                | Config::NotifyAboutWrongPw
+                | Config::ScanAllFoldersDebounceSecs
+                | Config::SysVersion
+                | Config::SysMsgsizeMaxRecommended
+                | Config::SysConfigKeys
+                | Config::LastHousekeeping
+                | Config::LastKeyPublish
+                | Config::KeyVerificationPending
+                | Config::LastAutoBackup
+                | Config::LastVanishedTrashCheck
+        )
+}
+
+/// Exports the account settings to the JSON file `path`.
+///
+/// The file contains the configuration, the own keys, contacts with their
+/// Autocrypt and verification states, and per-chat settings as ephemeral timers,
+/// mute durations, protection status and chat properties such as the subject prefix,
+/// but no messages.
+/// It is meant to provision a new device quickly using [`import_settings`],
+/// use [`ImexMode::ExportBackup`] to transfer the message history.
+///
+/// The file contains the private key, so it must be kept secret.
+/// Passwords are not exported.
+pub async fn export_settings(context: &Context, path: &Path) -> Result<()> {
+    let mut export = SettingsExport::default();
+
+    for key in Config::iter().filter(|key| is_exported_config(*key)) {
+        if let Some(value) = context.sql.get_raw_config(key).await? {
+            export.config.insert(key.to_string(), value);
+        }
+    }
+
+    export.keypairs = context
+        .sql
+        .query_map(
+            "SELECT addr, is_default, private_key, public_key, created FROM keypairs;",
+            paramsv![],
+            |row| {
+                let private_key: Vec<u8> = row.get(2)?;
+                let public_key: Vec<u8> = row.get(3)?;
+                Ok(KeypairExport {
+                    addr: row.get(0)?,
+                    is_default: row.get(1)?,
+                    private_key: base64::encode(&private_key),
+                    public_key: base64::encode(&public_key),
+                    created: row.get(4)?,
+                })
+            },
+            |rows| {
+                rows.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+
+    export.contacts = context
+        .sql
+        .query_map(
+            "SELECT addr, name, blocked FROM contacts WHERE id>? AND origin>=?;",
+            paramsv![DC_CONTACT_ID_LAST_SPECIAL, Origin::IncomingReplyTo],
+            |row| {
+                Ok(ContactExport {
+                    addr: row.get(0)?,
+                    name: row.get(1)?,
+                    blocked: row.get::<_, Option<bool>>(2)?.unwrap_or_default(),
+                })
+            },
+            |rows| {
+                rows.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+
+    export.peerstates = context
+        .sql
+        .query_map(
+            "SELECT addr, last_seen, last_seen_autocrypt, prefer_encrypted,
+                    public_key, public_key_fingerprint, gossip_timestamp,
+                    gossip_key, gossip_key_fingerprint,
+                    verified_key, verified_key_fingerprint
+             FROM acpeerstates;",
+            paramsv![],
+            |row| {
+                let encode = |key: Option<Vec<u8>>| key.map(|key| base64::encode(&key));
+                Ok(PeerstateExport {
+                    addr: row.get(0)?,
+                    last_seen: row.get(1)?,
+                    last_seen_autocrypt: row.get(2)?,
+                    prefer_encrypted: row.get(3)?,
+                    public_key: encode(row.get(4)?),
+                    public_key_fingerprint: row.get(5)?,
+                    gossip_timestamp: row.get(6)?,
+                    gossip_key: encode(row.get(7)?),
+                    gossip_key_fingerprint: row.get(8)?,
+                    verified_key: encode(row.get(9)?),
+                    verified_key_fingerprint: row.get(10)?,
+                })
+            },
+            |rows| {
+                rows.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+
+    export.chats = context
+        .sql
+        .query_map(
+            "SELECT c.grpid, c.type,
+                    IFNULL((SELECT ct.addr FROM chats_contacts cc
+                            LEFT JOIN contacts ct ON ct.id=cc.contact_id
+                            WHERE cc.chat_id=c.id AND cc.contact_id>?
+                            LIMIT 1), ''),
+                    IFNULL(c.ephemeral_timer, 0), IFNULL(c.muted_until, 0),
+                    IFNULL(c.protected, 0), c.param
+             FROM chats c
+             WHERE c.id>?;",
+            paramsv![DC_CONTACT_ID_LAST_SPECIAL, DC_CHAT_ID_LAST_SPECIAL],
+            |row| {
+                let typ: Chattype = row.get(1)?;
+                let param: Params = row.get::<_, String>(6)?.parse().unwrap_or_default();
+                let mut settings = Params::new();
+                for key in CHAT_SETTINGS_PARAMS {
+                    if let Some(value) = param.get(*key) {
+                        settings.set(*key, value);
+                    }
+                }
+                Ok(ChatSettingsExport {
+                    grpid: row.get(0)?,
+                    addr: if typ == Chattype::Single {
+                        row.get(2)?
+                    } else {
+                        String::new()
+                    },
+                    ephemeral_timer: row.get(3)?,
+                    muted_until: row.get(4)?,
+                    protected: row.get(5)?,
+                    param: settings.to_string(),
+                })
+            },
+            |rows| {
+                rows.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?
+        .into_iter()
+        .filter(|settings: &ChatSettingsExport| !settings.is_default())
+        .collect();
+
+    let json = serde_json::to_vec_pretty(&export)?;
+    fs::write(path, json)
+        .await
+        .with_context(|| format!("cannot write settings to {}", path.display()))?;
+    context.emit_event(EventType::ImexFileWritten(path.to_path_buf()));
+    Ok(())
+}
+
+/// Imports account settings written by [`export_settings`].
+///
+/// Existing configuration values are overwritten using [`Context::set_config`],
+/// keys, contacts and peerstates are added.
+/// Settings of groups are only applied if the group already exists.
+pub async fn import_settings(context: &Context, path: &Path) -> Result<()> {
+    let json = fs::read(path)
+        .await
+        .with_context(|| format!("cannot read settings from {}", path.display()))?;
+    let import: SettingsExport = serde_json::from_slice(&json).context("invalid settings file")?;
+
+    for (key, value) in &import.config {
+        match key.parse::<Config>() {
+            Ok(config) if is_exported_config(config) => {
+                context.set_config(config, Some(value)).await?;
+            }
+            _ => warn!(context, "import_settings: ignoring key {}", key),
+        }
+    }
+
+    for keypair in import.keypairs {
+        let private_key = base64::decode(&keypair.private_key)?;
+        let public_key = base64::decode(&keypair.public_key)?;
+        if context
+            .sql
+            .exists(
+                "SELECT COUNT(*) FROM keypairs WHERE public_key=?;",
+                paramsv![public_key],
+            )
+            .await?
+        {
+            continue;
+        }
+        if keypair.is_default {
+            context
+                .sql
+                .execute("UPDATE keypairs SET is_default=0;", paramsv![])
+                .await?;
+        }
+        context
+            .sql
+            .execute(
+                "INSERT INTO keypairs (addr, is_default, private_key, public_key, created)
+                 VALUES (?,?,?,?,?);",
+                paramsv![
+                    keypair.addr,
+                    keypair.is_default,
+                    private_key,
+                    public_key,
+                    keypair.created
+                ],
+            )
+            .await?;
+    }
+
+    for contact in import.contacts {
+        let (contact_id, _) = Contact::add_or_lookup(
+            context,
+            &contact.name,
+            &contact.addr,
+            Origin::ManuallyCreated,
+        )
+        .await?;
+        if contact.blocked {
+            Contact::block(context, contact_id).await?;
+        }
+    }
+
+    for peerstate in import.peerstates {
+        let decode = |key: Option<String>| -> Result<Option<Vec<u8>>> {
+            Ok(match key {
+                Some(key) => Some(base64::decode(&key)?),
+                None => None,
+            })
+        };
+        context
+            .sql
+            .execute(
+                "DELETE FROM acpeerstates WHERE addr=?;",
+                paramsv![peerstate.addr],
+            )
+            .await?;
+        context
+            .sql
+            .execute(
+                "INSERT INTO acpeerstates (addr, last_seen, last_seen_autocrypt, prefer_encrypted,
+                    public_key, public_key_fingerprint, gossip_timestamp,
+                    gossip_key, gossip_key_fingerprint,
+                    verified_key, verified_key_fingerprint)
+                 VALUES (?,?,?,?,?,?,?,?,?,?,?);",
+                paramsv![
+                    peerstate.addr,
+                    peerstate.last_seen,
+                    peerstate.last_seen_autocrypt,
+                    peerstate.prefer_encrypted,
+                    decode(peerstate.public_key)?,
+                    peerstate.public_key_fingerprint,
+                    peerstate.gossip_timestamp,
+                    decode(peerstate.gossip_key)?,
+                    peerstate.gossip_key_fingerprint,
+                    decode(peerstate.verified_key)?,
+                    peerstate.verified_key_fingerprint
+                ],
+            )
+            .await?;
+    }
+
+    for settings in import.chats {
+        let chat_id = if !settings.grpid.is_empty() {
+            match chat::get_chat_id_by_grpid(context, &settings.grpid).await {
+                Ok((chat_id, _, _)) => chat_id,
+                Err(_) => continue,
+            }
+        } else if !settings.addr.is_empty() {
+            let (contact_id, _) =
+                Contact::add_or_lookup(context, "", &settings.addr, Origin::ManuallyCreated)
+                    .await?;
+            ChatId::create_for_contact(context, contact_id).await?
+        } else {
+            continue;
+        };
+        context
+            .sql
+            .execute(
+                "UPDATE chats SET ephemeral_timer=?, muted_until=?, protected=? WHERE id=?;",
+                paramsv![
+                    settings.ephemeral_timer,
+                    settings.muted_until,
+                    settings.protected,
+                    chat_id
+                ],
+            )
+            .await?;
+        let param: Params = settings.param.parse().unwrap_or_default();
+        if !param.is_empty() {
+            let mut chat = Chat::load_from_db(context, chat_id).await?;
+            for key in CHAT_SETTINGS_PARAMS {
+                if let Some(value) = param.get(*key) {
+                    chat.param.set(*key, value);
+                }
+            }
+            chat.update_param(context).await?;
+        }
+    }
+
+    context.emit_event(EventType::ChatModified(ChatId::new(0)));
+    Ok(())
+}
+
+/// Account data written by [`export_data_portability`].
+#[derive(Debug, Default, Serialize)]
+struct DataExport {
+    version: String,
+    timestamp: i64,
+    config: BTreeMap<String, String>,
+    contacts: Vec<DataExportContact>,
+    chats: Vec<DataExportChat>,
+    messages: Vec<DataExportMessage>,
+}
+
+#[derive(Debug, Serialize)]
+struct DataExportContact {
+    id: u32,
+    addr: String,
+    name: String,
+    authname: String,
+    blocked: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct DataExportChat {
+    id: u32,
+    #[serde(rename = "type")]
+    typ: Chattype,
+    name: String,
+    archived: bool,
+    members: Vec<u32>,
+}
+
+#[derive(Debug, Serialize)]
+struct DataExportMessage {
+    id: u32,
+    chat_id: u32,
+    from_id: u32,
+    timestamp: i64,
+    timestamp_sent: i64,
+    timestamp_rcvd: i64,
+    viewtype: Viewtype,
+    state: MessageState,
+    rfc724_mid: String,
+    text: String,
+    /// Path of the attachment relative to the export directory.
+    file: Option<String>,
+    /// Private note of the user, see [`MsgId::set_note`].
+    note: Option<String>,
+}
+
+/// Returns true for configuration values that are written by [`export_data_portability`].
+fn is_portable_config(key: Config) -> bool {
+    is_exported_config(key)
+        && !matches!(
+            key,
+            Config::MailPw | Config::SendPw | Config::ConfiguredMailPw | Config::ConfiguredSendPw
+        )
+}
+
+/// Exports the account data to the directory `dir` in a documented format
+/// meant to be read by other software.
+///
+/// Unlike backups, the export cannot be imported again. `dir` must be empty or not exist yet.
+/// The export consists of the file `account.json` and the directory `media` with the
+/// attachments of the messages, named `<message id>-<file name>`.
+/// `account.json` is an object with the fields
+/// - `version`: version of the core that wrote the export,
+/// - `timestamp`: time of the export,
+/// - `config`: the configuration, without passwords,
+/// - `contacts`: objects with the fields `id`, `addr`, `name`, `authname` and `blocked`,
+///   the contact ID 1 is the user,
+/// - `chats`: objects with the fields `id`, `type`, `name`, `archived`
+///   and `members` listing contact IDs,
+/// - `messages`: objects with the fields `id`, `chat_id`, `from_id`, `timestamp`,
+///   `timestamp_sent`, `timestamp_rcvd`, `viewtype`, `state`, `rfc724_mid`, `text`,
+///   `file`, the path of the attachment relative to `dir`,
+///   and `note`, the private note of the user, see [`MsgId::set_note`].
+///
+/// Neither the own keys nor the keys of contacts are exported.
+pub async fn export_data_portability(context: &Context, dir: &Path) -> Result<()> {
+    if dir.exists().await {
+        ensure!(
+            fs::read_dir(dir).await?.next().await.is_none(),
+            "Export directory {} is not empty",
+            dir.display()
+        );
+    }
+    let media_dir = dir.join("media");
+    dc_create_folder(context, &media_dir).await?;
+
+    let mut export = DataExport {
+        version: DC_VERSION_STR.to_string(),
+        timestamp: time(),
+        ..Default::default()
+    };
+
+    for key in Config::iter().filter(|key| is_portable_config(*key)) {
+        if let Some(value) = context.sql.get_raw_config(key).await? {
+            export.config.insert(key.to_string(), value);
+        }
+    }
+
+    export.contacts = context
+        .sql
+        .query_map(
+            "SELECT id, addr, name, authname, blocked FROM contacts WHERE id>? OR id=?;",
+            paramsv![DC_CONTACT_ID_LAST_SPECIAL, DC_CONTACT_ID_SELF],
+            |row| {
+                Ok(DataExportContact {
+                    id: row.get(0)?,
+                    addr: row.get(1)?,
+                    name: row.get(2)?,
+                    authname: row.get(3)?,
+                    blocked: row.get::<_, Option<bool>>(4)?.unwrap_or_default(),
+                })
+            },
+            |rows| {
+                rows.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+
+    let chats = context
+        .sql
+        .query_map(
+            "SELECT id, type, name, archived FROM chats WHERE id>?;",
+            paramsv![DC_CHAT_ID_LAST_SPECIAL],
+            |row| {
+                Ok((
+                    row.get::<_, ChatId>(0)?,
+                    row.get::<_, Chattype>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, i32>(3)?,
+                ))
+            },
+            |rows| {
+                rows.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+    for (chat_id, typ, name, archived) in chats {
+        export.chats.push(DataExportChat {
+            id: chat_id.to_u32(),
+            typ,
+            name,
+            archived: archived == 1,
+            members: chat::get_chat_contacts(context, chat_id).await?,
+        });
+    }
+
+    let messages = context
+        .sql
+        .query_map(
+            "SELECT m.id, m.chat_id, m.from_id, m.timestamp, m.timestamp_sent, m.timestamp_rcvd,
+                    m.type, m.state, m.rfc724_mid, m.txt, m.param, n.note
+             FROM msgs m
+             LEFT JOIN msgs_notes n ON n.msg_id=m.id
+             WHERE m.chat_id>? AND m.hidden=0
+             ORDER BY m.id;",
+            paramsv![DC_CHAT_ID_LAST_SPECIAL],
+            |row| {
+                let msg = DataExportMessage {
+                    id: row.get(0)?,
+                    chat_id: row.get(1)?,
+                    from_id: row.get(2)?,
+                    timestamp: row.get(3)?,
+                    timestamp_sent: row.get(4)?,
+                    timestamp_rcvd: row.get(5)?,
+                    viewtype: row.get(6)?,
+                    state: row.get(7)?,
+                    rfc724_mid: row.get(8)?,
+                    text: row.get(9)?,
+                    file: None,
+                    note: row.get(11)?,
+                };
+                let param: String = row.get(10)?;
+                Ok((msg, param))
+            },
+            |rows| {
+                rows.collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(Into::into)
+            },
+        )
+        .await?;
+    for (mut msg, param) in messages {
+        let param: Params = param.parse().unwrap_or_default();
+        if let Ok(Some(path)) = param.get_path(Param::File, context) {
+            if let Some(name) = path.file_name() {
+                // Attachments sent from their original location may share a file name,
+                // so the message ID makes the name unique.
+                let name = format!("{}-{}", msg.id, name.to_string_lossy());
+                if dc_copy_file(context, &path, &media_dir.join(&name)).await {
+                    msg.file = Some(format!("media/{}", name));
+                }
+            }
+        }
+        export.messages.push(msg);
+    }
+
+    let path = dir.join("account.json");
+    dc_write_file(context, &path, serde_json::to_string(&export)?.as_bytes()).await?;
+    context.emit_event(EventType::ImexFileWritten(path));
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1021,6 +1776,228 @@ async fn test_export_and_import_key() {
         }
     }
 
+    #[derive(Debug)]
+    struct MemorySink {
+        buf: Vec<u8>,
+        finalized: async_std::channel::Sender<Vec<u8>>,
+    }
+
+    impl async_std::io::Write for MemorySink {
+        fn poll_write(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            std::pin::Pin::new(&mut self.buf).poll_write(cx, buf)
+        }
+
+        fn poll_flush(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::pin::Pin::new(&mut self.buf).poll_flush(cx)
+        }
+
+        fn poll_close(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::pin::Pin::new(&mut self.buf).poll_close(cx)
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl StorageSink for MemorySink {
+        async fn finalize(self: Box<Self>) -> Result<()> {
+            self.finalized.send(self.buf).await?;
+            Ok(())
+        }
+    }
+
+    #[async_std::test]
+    async fn test_export_backup_to_sink() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let (sender, receiver) = async_std::channel::bounded(1);
+        let sink = MemorySink {
+            buf: Vec::new(),
+            finalized: sender,
+        };
+        export_backup_to_sink(&t, Box::new(sink)).await?;
+        assert!(t.sql.is_open().await);
+
+        let buf = receiver.try_recv()?;
+        let archive = Archive::new(&buf[..]);
+        let mut entries = archive.entries()?;
+        let mut has_db = false;
+        while let Some(entry) = entries.next().await {
+            if entry?.path()?.file_name() == Some(OsStr::new(DBFILE_BACKUP_NAME)) {
+                has_db = true;
+            }
+        }
+        assert!(has_db);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_auto_backup() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let dir = tempfile::tempdir()?;
+        let dir_path = dir.path().to_str().unwrap();
+        t.set_config(Config::AutoBackupDir, Some(dir_path)).await?;
+
+        // Disabled by default.
+        assert_eq!(auto_backup(&t).await?, None);
+
+        t.set_config(Config::AutoBackupInterval, Some("3600"))
+            .await?;
+        t.set_config(Config::AutoBackupKeep, Some("1")).await?;
+        let first = auto_backup(&t).await?.unwrap();
+        assert!(first.exists().await);
+        assert!(t.sql.is_open().await);
+
+        // The next backup is not due yet.
+        assert_eq!(auto_backup(&t).await?, None);
+
+        t.set_config(Config::LastAutoBackup, Some("0")).await?;
+        let second = auto_backup(&t).await?.unwrap();
+        assert_ne!(first, second);
+        assert!(second.exists().await);
+        assert!(!first.exists().await);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_export_data_portability() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat = t.create_chat_with_contact("bob", "bob@example.net").await;
+        t.send_text(chat.id, "hi bob").await;
+        let file = t.get_blobdir().join("notes.txt");
+        fs::write(&file, b"some notes").await?;
+        let mut msg = Message::new(Viewtype::File);
+        msg.set_file(file.to_str().unwrap(), None);
+        t.send_msg(chat.id, &mut msg).await;
+        t.set_config(Config::MailPw, Some("secret")).await?;
+
+        let tempdir = tempfile::tempdir()?;
+        let dir = Path::new(tempdir.path().to_str().unwrap());
+        export_data_portability(&t, dir).await?;
+
+        let json = fs::read_to_string(dir.join("account.json")).await?;
+        assert!(!json.contains("secret"));
+        let export: serde_json::Value = serde_json::from_str(&json)?;
+        assert_eq!(export["config"]["addr"], "alice@example.com");
+        assert!(export["contacts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|contact| contact["addr"] == "bob@example.net"));
+        assert!(export["chats"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|chat| chat["type"] == "Single" && chat["name"] == "bob"));
+
+        let messages = export["messages"].as_array().unwrap();
+        assert!(messages.iter().any(|msg| msg["text"] == "hi bob"));
+        let file = messages
+            .iter()
+            .find_map(|msg| msg["file"].as_str())
+            .unwrap();
+        assert_eq!(fs::read(dir.join(file)).await?, b"some notes");
+
+        // The directory must be empty.
+        assert!(export_data_portability(&t, dir).await.is_err());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_export_data_portability_same_file_name() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        t.set_config_bool(Config::PassthroughAttachments, true)
+            .await?;
+        let chat = t.create_chat_with_contact("bob", "bob@example.net").await;
+        let first = tempfile::tempdir()?;
+        let second = tempfile::tempdir()?;
+        for (dir, content) in &[(&first, "first"), (&second, "second")] {
+            let file = dir.path().join("notes.txt");
+            fs::write(&file, content).await?;
+            let mut msg = Message::new(Viewtype::File);
+            msg.set_file(file.to_str().unwrap(), None);
+            t.send_msg(chat.id, &mut msg).await;
+        }
+
+        let tempdir = tempfile::tempdir()?;
+        let dir = Path::new(tempdir.path().to_str().unwrap()).join("export");
+        export_data_portability(&t, &dir).await?;
+
+        let json = fs::read_to_string(dir.join("account.json")).await?;
+        let export: serde_json::Value = serde_json::from_str(&json)?;
+        let mut contents = Vec::new();
+        for msg in export["messages"].as_array().unwrap() {
+            if let Some(file) = msg["file"].as_str() {
+                contents.push(fs::read_to_string(dir.join(file)).await?);
+            }
+        }
+        assert_eq!(contents, vec!["first", "second"]);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_export_and_import_settings() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        alice
+            .set_config(Config::Displayname, Some("Alice Exported"))
+            .await?;
+        alice.set_config(Config::MailPw, Some("secret")).await?;
+        let bob = TestContext::new_bob().await;
+        let chat = alice.create_chat(&bob).await;
+        chat::set_muted(&alice, chat.id, chat::MuteDuration::Forever).await?;
+        chat.id.set_subject_prefix(&alice, Some("[test]")).await?;
+        alice.send_text(chat.id, "hi").await;
+
+        let file = alice.get_blobdir().join("settings.json");
+        export_settings(&alice, &file).await?;
+        let json = fs::read_to_string(&file).await?;
+        assert!(!json.contains("secret"));
+        assert!(!json.contains("configured_addr"));
+
+        let t = TestContext::new().await;
+        import_settings(&t, &file).await?;
+        assert!(!t.is_configured().await?);
+        assert_eq!(
+            t.get_config(Config::Displayname).await?,
+            Some("Alice Exported".to_string())
+        );
+        assert_eq!(
+            t.get_config(Config::Addr).await?,
+            alice.get_config(Config::Addr).await?
+        );
+        assert_eq!(
+            SignedPublicKey::load_self(&t).await?,
+            SignedPublicKey::load_self(&alice).await?
+        );
+
+        let contact_id = Contact::lookup_id_by_addr(&t, "bob@example.net", Origin::Unknown)
+            .await?
+            .unwrap();
+        let chat_id = ChatId::create_for_contact(&t, contact_id).await?;
+        let chat = chat::Chat::load_from_db(&t, chat_id).await?;
+        assert!(chat.is_muted());
+        assert_eq!(chat.get_subject_prefix(), Some("[test]"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_chat_settings_export_old_format() -> Result<()> {
+        let settings: ChatSettingsExport = serde_json::from_str(
+            r#"{"grpid": "abc", "addr": "", "ephemeral_timer": 60, "muted_until": 0}"#,
+        )?;
+        assert_eq!(settings.protected, ProtectionStatus::Unprotected);
+        assert!(settings.param.is_empty());
+        assert!(!settings.is_default());
+        Ok(())
+    }
+
     #[test]
     fn test_normalize_setup_code() {
         let norm = normalize_setup_code("123422343234423452346234723482349234");
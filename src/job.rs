@@ -2,6 +2,7 @@
 //!
 //! This module implements a job queue maintained in the SQLite database
 //! and job types.
+use std::collections::BTreeMap;
 use std::future::Future;
 use std::{fmt, time::Duration};
 
@@ -13,14 +14,16 @@
 use rand::{thread_rng, Rng};
 
 use crate::blob::BlobObject;
-use crate::chat::{self, ChatId};
+use crate::chat::{self, Chat, ChatId};
 use crate::config::Config;
 use crate::contact::{normalize_name, Contact, Modifier, Origin};
 use crate::context::Context;
 use crate::dc_tools::{dc_delete_file, dc_read_file, time};
-use crate::ephemeral::load_imap_deletion_msgid;
+use crate::ephemeral::load_imap_deletion_msgids;
 use crate::events::EventType;
 use crate::imap::{Imap, ImapActionResult};
+use crate::imex;
+use crate::keyserver;
 use crate::location;
 use crate::log::LogExt;
 use crate::message::{self, Message, MessageState, MsgId};
@@ -29,6 +32,8 @@
 use crate::scheduler::InterruptInfo;
 use crate::smtp::Smtp;
 use crate::sql;
+use crate::stats;
+use crate::wkd;
 
 // results in ~3 weeks for the last backoff timespan
 const JOB_RETRIES: u32 = 17;
@@ -92,13 +97,18 @@ pub enum Action {
 
     // Jobs in the INBOX-thread, range from DC_IMAP_THREAD..DC_IMAP_THREAD+999
     Housekeeping = 105, // low priority ...
+    AutoBackup = 106,
+    ConfirmKeyVerification = 107,
+    WkdLookup = 108,
     FetchExistingMsgs = 110,
+    SearchServer = 120,
     MarkseenMsgOnImap = 130,
 
     // Moving message is prioritized lower than deletion so we don't
     // bother moving message if it is already scheduled for deletion.
     MoveMsg = 200,
     DeleteMsgOnImap = 210,
+    DeleteMsgsOnImap = 211,
 
     // UID synchronization is high-priority to make sure correct UIDs
     // are used by message moving/deletion.
@@ -125,8 +135,13 @@ fn from(action: Action) -> Thread {
             Unknown => Thread::Unknown,
 
             Housekeeping => Thread::Imap,
+            AutoBackup => Thread::Imap,
+            ConfirmKeyVerification => Thread::Imap,
+            WkdLookup => Thread::Imap,
             FetchExistingMsgs => Thread::Imap,
+            SearchServer => Thread::Imap,
             DeleteMsgOnImap => Thread::Imap,
+            DeleteMsgsOnImap => Thread::Imap,
             ResyncFolders => Thread::Imap,
             MarkseenMsgOnImap => Thread::Imap,
             MoveMsg => Thread::Imap,
@@ -227,6 +242,25 @@ pub(crate) async fn save(self, context: &Context) -> Result<()> {
         Ok(())
     }
 
+    /// Inserts a new job as part of a transaction.
+    ///
+    /// The job is run once the transaction is committed and [`interrupt`] is called.
+    pub(crate) fn insert(&self, transaction: &rusqlite::Transaction<'_>) -> rusqlite::Result<()> {
+        let thread: Thread = self.action.into();
+        transaction.execute(
+            "INSERT INTO jobs (added_timestamp, thread, action, foreign_id, param, desired_timestamp) VALUES (?,?,?,?,?,?);",
+            paramsv![
+                self.added_timestamp,
+                thread,
+                self.action,
+                self.foreign_id,
+                self.param.to_string(),
+                self.desired_timestamp
+            ],
+        )?;
+        Ok(())
+    }
+
     async fn smtp_send<F, Fut>(
         &mut self,
         context: &Context,
@@ -410,6 +444,22 @@ pub(crate) async fn send_msg_to_smtp(&mut self, context: &Context, smtp: &mut Sm
                     return Status::RetryLater;
                 }
             }
+
+            // Sending is cancelled eg. if the ephemeral timer expired before the message was sent,
+            // see `ephemeral::expire_undelivered_messages()`.
+            match MsgId::new(self.foreign_id).get_state(context).await {
+                Ok(MessageState::OutFailed) => {
+                    return Status::Finished(Err(format_err!(
+                        "Not sending Message {} as it failed",
+                        self.foreign_id
+                    )));
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    warn!(context, "failed to check message state: {:?}", err);
+                    return Status::RetryLater;
+                }
+            }
         };
 
         let foreign_id = self.foreign_id;
@@ -471,13 +521,6 @@ async fn get_additional_mdn_jobs(
     }
 
     async fn send_mdn(&mut self, context: &Context, smtp: &mut Smtp) -> Status {
-        let mdns_enabled = job_try!(context.get_config_bool(Config::MdnsEnabled).await);
-        if !mdns_enabled {
-            // User has disabled MDNs after job scheduling but before
-            // execution.
-            return Status::Finished(Err(format_err!("MDNs are disabled")));
-        }
-
         let contact_id = self.foreign_id;
         let contact = job_try!(Contact::load_from_db(context, contact_id).await);
         if contact.is_blocked() {
@@ -493,6 +536,14 @@ async fn send_mdn(&mut self, context: &Context, smtp: &mut Smtp) -> Status {
             )));
         };
 
+        let msg = job_try!(Message::load_from_db(context, msg_id).await);
+        let chat = job_try!(Chat::load_from_db(context, msg.chat_id).await);
+        if !job_try!(chat.mdns_enabled(context).await) {
+            // User has disabled MDNs after job scheduling but before
+            // execution.
+            return Status::Finished(Err(format_err!("MDNs are disabled")));
+        }
+
         // Try to aggregate other SendMdn jobs and send a combined MDN.
         let (additional_job_ids, additional_rfc724_mids) = self
             .get_additional_mdn_jobs(context, contact_id)
@@ -507,7 +558,6 @@ async fn send_mdn(&mut self, context: &Context, smtp: &mut Smtp) -> Status {
             )
         }
 
-        let msg = job_try!(Message::load_from_db(context, msg_id).await);
         let mimefactory =
             job_try!(MimeFactory::from_mdn(context, &msg, additional_rfc724_mids).await);
         let rendered_msg = job_try!(mimefactory.render(context).await);
@@ -568,10 +618,16 @@ async fn move_msg(&mut self, context: &Context, imap: &mut Imap) -> Status {
         };
 
         if let Some(dest_folder) = dest_folder {
-            match imap
+            let mut res = imap
                 .mv(context, server_folder, msg.server_uid, &dest_folder)
-                .await
-            {
+                .await;
+            if res == ImapActionResult::Failed {
+                // The message may have been moved by the provider or another client.
+                if let Ok(Some((folder, uid))) = imap.relocate_msg(context, &msg.rfc724_mid).await {
+                    res = imap.mv(context, &folder, uid, &dest_folder).await;
+                }
+            }
+            match res {
                 ImapActionResult::RetryLater => Status::RetryLater,
                 ImapActionResult::Success => {
                     // Rust-Imap provides no target uid on mv, so just set it to 0, update again when precheck_imf() is called for the moved message
@@ -632,8 +688,25 @@ async fn delete_msg_on_imap(&mut self, context: &Context, imap: &mut Imap) -> St
                     // Message is already deleted on IMAP server.
                     ImapActionResult::AlreadyDone
                 } else {
-                    imap.delete_msg(context, &mid, server_folder, msg.server_uid)
-                        .await
+                    let res = imap
+                        .delete_msg(context, &mid, server_folder, msg.server_uid)
+                        .await;
+                    if res == ImapActionResult::AlreadyDone {
+                        // The message may have been moved by the provider or another client,
+                        // delete it at its new location.
+                        match imap.relocate_msg(context, &mid).await {
+                            Ok(Some((folder, uid))) => {
+                                imap.delete_msg(context, &mid, &folder, uid).await
+                            }
+                            Ok(None) => res,
+                            Err(err) => {
+                                warn!(context, "Cannot relocate message {}: {}", mid, err);
+                                res
+                            }
+                        }
+                    } else {
+                        res
+                    }
                 };
                 match res {
                     ImapActionResult::AlreadyDone | ImapActionResult::Success => {}
@@ -649,27 +722,7 @@ async fn delete_msg_on_imap(&mut self, context: &Context, imap: &mut Imap) -> St
                     }
                 }
             }
-            if msg.chat_id.is_trash() || msg.hidden {
-                // Messages are stored in trash chat only to keep
-                // their server UID and Message-ID. Once message is
-                // deleted from the server, database record can be
-                // removed as well.
-                //
-                // Hidden messages are similar to trashed, but are
-                // related to some chat. We also delete their
-                // database records.
-                job_try!(msg.id.delete_from_db(context).await)
-            } else {
-                // Remove server UID from the database record.
-                //
-                // We have either just removed the message from the
-                // server, in which case UID is not valid anymore, or
-                // we have more refernces to the same server UID, so
-                // we remove UID to reduce the number of messages
-                // pointing to the corresponding UID. Once the counter
-                // reaches zero, we will remove the message.
-                job_try!(msg.id.unlink(context).await);
-            }
+            job_try!(finish_imap_deletion(context, &msg).await);
             Status::Finished(Ok(()))
         } else {
             /* eg. device messages have no Message-ID */
@@ -677,6 +730,81 @@ async fn delete_msg_on_imap(&mut self, context: &Context, imap: &mut Imap) -> St
         }
     }
 
+    /// Deletes several messages on the server.
+    ///
+    /// `Param::MsgIds` is a space-separated list of MsgIds.
+    ///
+    /// Works like [`Job::delete_msg_on_imap`], but the messages are deleted with
+    /// a single UID STORE and EXPUNGE per folder. Messages which cannot be deleted this
+    /// way, eg. because they were moved by another client meanwhile, are handed over
+    /// to `DeleteMsgOnImap` jobs, so the batch job itself is never retried.
+    async fn delete_msgs_on_imap(&mut self, context: &Context, imap: &mut Imap) -> Status {
+        let msg_ids: Vec<MsgId> = self
+            .param
+            .get(Param::MsgIds)
+            .unwrap_or_default()
+            .split_whitespace()
+            .filter_map(|id| id.parse().ok())
+            .map(MsgId::new)
+            .collect();
+
+        if let Err(err) = imap.prepare(context).await {
+            warn!(context, "could not connect: {:?}", err);
+            job_try!(add_single_deletion_jobs(context, &msg_ids, DELETION_RETRY_DELAY).await);
+            return Status::Finished(Ok(()));
+        }
+
+        let mut folders: BTreeMap<String, Vec<Message>> = BTreeMap::new();
+        for msg_id in msg_ids {
+            let msg = match Message::load_from_db(context, msg_id).await {
+                Ok(msg) => msg,
+                Err(err) => {
+                    warn!(context, "Cannot load message {} to delete: {}", msg_id, err);
+                    continue;
+                }
+            };
+            if msg.rfc724_mid.is_empty() {
+                // eg. device messages have no Message-ID
+                continue;
+            }
+            if msg.server_uid == 0 || message::rfc724_mid_cnt(context, &msg.rfc724_mid).await > 1 {
+                // The message is already deleted on the server or is deleted
+                // when the last of its parts is deleted.
+                job_try!(finish_imap_deletion(context, &msg).await);
+            } else if let Some(folder) = msg.server_folder.clone() {
+                folders.entry(folder).or_default().push(msg);
+            }
+        }
+
+        for (folder, msgs) in folders {
+            let uids: Vec<(u32, String)> = msgs
+                .iter()
+                .map(|msg| (msg.server_uid, msg.rfc724_mid.clone()))
+                .collect();
+            match imap.delete_msgs(context, &folder, &uids).await {
+                Ok(deleted) => {
+                    let mut remaining = Vec::new();
+                    for msg in msgs {
+                        if deleted.contains(&msg.server_uid) {
+                            job_try!(finish_imap_deletion(context, &msg).await);
+                        } else {
+                            remaining.push(msg.id);
+                        }
+                    }
+                    job_try!(add_single_deletion_jobs(context, &remaining, 0).await);
+                }
+                Err(err) => {
+                    warn!(context, "Cannot delete messages in {}: {:#}", folder, err);
+                    let msg_ids: Vec<MsgId> = msgs.iter().map(|msg| msg.id).collect();
+                    job_try!(
+                        add_single_deletion_jobs(context, &msg_ids, DELETION_RETRY_DELAY).await
+                    );
+                }
+            }
+        }
+        Status::Finished(Ok(()))
+    }
+
     /// Read the recipients from old emails sent by the user and add them as contacts.
     /// This way, we can already offer them some email addresses they can write to.
     ///
@@ -715,6 +843,36 @@ async fn fetch_existing_msgs(&mut self, context: &Context, imap: &mut Imap) -> S
         Status::Finished(Ok(()))
     }
 
+    /// Searches the DeltaChat and Inbox folders using the IMAP SEARCH command in `Param::Arg`
+    /// and downloads matching messages that were deleted from the device.
+    ///
+    /// `Param::Arg2` optionally limits the number of messages downloaded from each folder.
+    async fn search_server(&mut self, context: &Context, imap: &mut Imap) -> Status {
+        if let Err(err) = imap.prepare(context).await {
+            warn!(context, "could not connect: {:?}", err);
+            return Status::RetryLater;
+        }
+
+        let search_command = self.param.get(Param::Arg).unwrap_or_default();
+        let limit = self.param.get_int(Param::Arg2).map(|limit| limit as usize);
+        let mut count = 0;
+        for config in &[Config::ConfiguredMvboxFolder, Config::ConfiguredInboxFolder] {
+            if let Some(folder) = job_try!(context.get_config(*config).await) {
+                count += job_try!(
+                    imap.search_and_fetch(context, &folder, search_command, limit)
+                        .await
+                );
+            }
+        }
+
+        info!(context, "Server search downloaded {} messages.", count);
+        context.emit_event(EventType::MsgsChanged {
+            chat_id: ChatId::new(0),
+            msg_id: MsgId::new(0),
+        });
+        Status::Finished(Ok(()))
+    }
+
     /// Synchronizes UIDs for sentbox, inbox and mvbox, in this order.
     ///
     /// If a copy of the message is present in multiple folders, mvbox
@@ -748,6 +906,67 @@ async fn resync_folders(&mut self, context: &Context, imap: &mut Imap) -> Status
         Status::Finished(Ok(()))
     }
 
+    /// Detects trashed messages deleted out-of-band on the server before running
+    /// housekeeping, so that housekeeping removes them from the database.
+    ///
+    /// As this lists all messages of the folders on the server, it is done at most
+    /// once per [`VANISHED_TRASH_CHECK_INTERVAL`], while housekeeping runs eg.
+    /// after every deletion.
+    async fn housekeeping(&mut self, context: &Context, imap: &mut Imap) -> Status {
+        let last_check = job_try!(context.get_config_i64(Config::LastVanishedTrashCheck).await);
+        let now = time();
+        if last_check <= now && now < last_check + VANISHED_TRASH_CHECK_INTERVAL {
+            info!(context, "skipping check for vanished trashed messages");
+        } else if let Err(err) = imap.prepare(context).await {
+            warn!(context, "could not connect: {:?}", err);
+        } else {
+            job_try!(
+                context
+                    .set_config(Config::LastVanishedTrashCheck, Some(&now.to_string()))
+                    .await
+            );
+            for folder in &[
+                Config::ConfiguredInboxFolder,
+                Config::ConfiguredMvboxFolder,
+                Config::ConfiguredSentboxFolder,
+            ] {
+                if let Some(folder) = job_try!(context.get_config(*folder).await) {
+                    imap.clear_vanished_trash_uids(context, &folder)
+                        .await
+                        .ok_or_log(context);
+                }
+            }
+        }
+        keyserver::maybe_publish_self_key(context)
+            .await
+            .ok_or_log(context);
+
+        stats::record_storage(context).await.ok_or_log(context);
+        sql::housekeeping(context).await.ok_or_log(context);
+        Status::Finished(Ok(()))
+    }
+
+    async fn confirm_key_verification(&mut self, context: &Context) -> Status {
+        let link = self.param.get(Param::Arg).unwrap_or_default();
+        match keyserver::confirm_verification(context, link).await {
+            Ok(()) => Status::Finished(Ok(())),
+            Err(err) => {
+                warn!(context, "Cannot confirm key verification: {:#}", err);
+                Status::RetryLater
+            }
+        }
+    }
+
+    async fn wkd_lookup(&mut self, context: &Context) -> Status {
+        match wkd::import_key_for_contact(context, self.foreign_id).await {
+            Ok(_) => Status::Finished(Ok(())),
+            Err(err) => {
+                warn!(context, "WKD lookup failed: {:#}", err);
+                Status::RetryLater
+            }
+        }
+    }
+
     async fn markseen_msg_on_imap(&mut self, context: &Context, imap: &mut Imap) -> Status {
         if let Err(err) = imap.prepare(context).await {
             warn!(context, "could not connect: {:?}", err);
@@ -793,7 +1012,8 @@ async fn markseen_msg_on_imap(&mut self, context: &Context, imap: &mut Imap) ->
                 if msg.param.get_bool(Param::WantsMdn).unwrap_or_default()
                     && !msg.is_system_message()
                 {
-                    let mdns_enabled = job_try!(context.get_config_bool(Config::MdnsEnabled).await);
+                    let chat = job_try!(Chat::load_from_db(context, msg.chat_id).await);
+                    let mdns_enabled = job_try!(chat.mdns_enabled(context).await);
                     if mdns_enabled {
                         if let Err(err) = send_mdn(context, &msg).await {
                             warn!(context, "could not send out mdn for {}: {}", msg.id, err);
@@ -824,7 +1044,7 @@ async fn kill_ids(context: &Context, job_ids: &[u32]) -> Result<()> {
     );
     context
         .sql
-        .execute(q, rusqlite::params_from_iter(job_ids))
+        .execute(q, rusqlite::params_from_iter(job_ids.to_vec()))
         .await?;
     Ok(())
 }
@@ -903,6 +1123,7 @@ async fn add_all_recipients_as_contacts(context: &Context, imap: &mut Imap, fold
 /// In order to be processed, must be `add`ded.
 pub async fn send_msg_job(context: &Context, msg_id: MsgId) -> Result<Option<Job>> {
     let mut msg = Message::load_from_db(context, msg_id).await?;
+    chat::copy_passthrough_attachment(context, &mut msg).await?;
     msg.try_calc_and_set_dimensions(context).await.ok();
 
     /* create message */
@@ -929,7 +1150,7 @@ pub async fn send_msg_job(context: &Context, msg_id: MsgId) -> Result<Option<Job
     // Send BCC to self if it is enabled and we are not going to
     // delete it immediately.
     if context.get_config_bool(Config::BccSelf).await?
-        && context.get_config_delete_server_after().await? != Some(0)
+        && context.get_config_delete_server_after_self().await? != Some(0)
         && !recipients
             .iter()
             .any(|x| x.to_lowercase() == lowercase_from)
@@ -947,6 +1168,10 @@ pub async fn send_msg_job(context: &Context, msg_id: MsgId) -> Result<Option<Job
         return Ok(None);
     }
 
+    // Rendering large messages may take a while, meanwhile other messages may be
+    // rendered and handed over to SMTP. Queue the job at the time rendering was
+    // requested, so pending jobs are sent in the order the messages were sent.
+    let queued_timestamp = time();
     let rendered_msg = match mimefactory.render(context).await {
         Ok(res) => Ok(res),
         Err(err) => {
@@ -999,6 +1224,12 @@ pub async fn send_msg_job(context: &Context, msg_id: MsgId) -> Result<Option<Job
         msg.update_param(context).await;
     }
 
+    stats::record_sent(context, rendered_msg.is_encrypted)
+        .await
+        .ok_or_log(context);
+    #[cfg(feature = "metrics")]
+    crate::metrics::MSGS_SENT.inc();
+
     ensure!(!recipients.is_empty(), "no recipients for smtp job set");
     let mut param = Params::new();
     let bytes = &rendered_msg.message;
@@ -1011,7 +1242,9 @@ pub async fn send_msg_job(context: &Context, msg_id: MsgId) -> Result<Option<Job
     msg.subject = rendered_msg.subject.clone();
     msg.update_subject(context).await;
 
-    let job = create(Action::SendMsgToSmtp, msg_id.to_u32(), param, 0)?;
+    let mut job = create(Action::SendMsgToSmtp, msg_id.to_u32(), param, 0)?;
+    job.added_timestamp = queued_timestamp;
+    job.desired_timestamp = queued_timestamp;
 
     Ok(Some(job))
 }
@@ -1022,10 +1255,68 @@ pub(crate) enum Connection<'a> {
 }
 
 pub(crate) async fn load_imap_deletion_job(context: &Context) -> Result<Option<Job>> {
-    let res = load_imap_deletion_msgid(context)
-        .await?
-        .map(|msg_id| Job::new(Action::DeleteMsgOnImap, msg_id.to_u32(), Params::new(), 0));
-    Ok(res)
+    let msg_ids = load_imap_deletion_msgids(context).await?;
+    if msg_ids.is_empty() {
+        return Ok(None);
+    }
+    let mut param = Params::new();
+    param.set(
+        Param::MsgIds,
+        msg_ids.iter().map(|msg_id| msg_id.to_u32()).join(" "),
+    );
+    Ok(Some(Job::new(Action::DeleteMsgsOnImap, 0, param, 0)))
+}
+
+/// Delay in seconds before messages are deleted one by one
+/// if deleting them in a batch failed.
+const DELETION_RETRY_DELAY: i64 = 60;
+
+/// Minimum time in seconds between two searches for trashed messages
+/// deleted from the server by another client.
+const VANISHED_TRASH_CHECK_INTERVAL: i64 = 24 * 60 * 60;
+
+/// Schedules `DeleteMsgOnImap` jobs for messages which could not be deleted in a batch.
+async fn add_single_deletion_jobs(
+    context: &Context,
+    msg_ids: &[MsgId],
+    delay_seconds: i64,
+) -> Result<()> {
+    for msg_id in msg_ids {
+        Job::new(
+            Action::DeleteMsgOnImap,
+            msg_id.to_u32(),
+            Params::new(),
+            delay_seconds,
+        )
+        .save(context)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Updates the database after a message is deleted from the server.
+async fn finish_imap_deletion(context: &Context, msg: &Message) -> Result<()> {
+    if msg.chat_id.is_trash() || msg.hidden {
+        // Messages are stored in trash chat only to keep
+        // their server UID and Message-ID. Once message is
+        // deleted from the server, database record can be
+        // removed as well.
+        //
+        // Hidden messages are similar to trashed, but are
+        // related to some chat. We also delete their
+        // database records.
+        msg.id.delete_from_db(context).await
+    } else {
+        // Remove server UID from the database record.
+        //
+        // We have either just removed the message from the
+        // server, in which case UID is not valid anymore, or
+        // we have more refernces to the same server UID, so
+        // we remove UID to reduce the number of messages
+        // pointing to the corresponding UID. Once the counter
+        // reaches zero, we will remove the message.
+        msg.id.unlink(context).await
+    }
 }
 
 impl<'a> fmt::Display for Connection<'a> {
@@ -1137,14 +1428,19 @@ async fn perform_job_action(
             location::job_maybe_send_locations_ended(context, job).await
         }
         Action::DeleteMsgOnImap => job.delete_msg_on_imap(context, connection.inbox()).await,
+        Action::DeleteMsgsOnImap => job.delete_msgs_on_imap(context, connection.inbox()).await,
         Action::ResyncFolders => job.resync_folders(context, connection.inbox()).await,
         Action::MarkseenMsgOnImap => job.markseen_msg_on_imap(context, connection.inbox()).await,
         Action::MoveMsg => job.move_msg(context, connection.inbox()).await,
         Action::FetchExistingMsgs => job.fetch_existing_msgs(context, connection.inbox()).await,
-        Action::Housekeeping => {
-            sql::housekeeping(context).await.ok_or_log(context);
+        Action::SearchServer => job.search_server(context, connection.inbox()).await,
+        Action::Housekeeping => job.housekeeping(context, connection.inbox()).await,
+        Action::AutoBackup => {
+            imex::maybe_auto_backup(context).await;
             Status::Finished(Ok(()))
         }
+        Action::ConfirmKeyVerification => job.confirm_key_verification(context).await,
+        Action::WkdLookup => job.wkd_lookup(context).await,
     };
 
     info!(context, "Finished immediate try {} of job {}", tries, job);
@@ -1200,33 +1496,48 @@ pub async fn add(context: &Context, job: Job) {
     });
 
     if delay_seconds == 0 {
-        match action {
-            Action::Unknown => unreachable!(),
-            Action::Housekeeping
-            | Action::DeleteMsgOnImap
-            | Action::ResyncFolders
-            | Action::MarkseenMsgOnImap
-            | Action::FetchExistingMsgs
-            | Action::MoveMsg => {
-                info!(context, "interrupt: imap");
-                context
-                    .interrupt_inbox(InterruptInfo::new(false, None))
-                    .await;
-            }
-            Action::MaybeSendLocations
-            | Action::MaybeSendLocationsEnded
-            | Action::SendMdn
-            | Action::SendMsgToSmtp => {
-                info!(context, "interrupt: smtp");
-                context
-                    .interrupt_smtp(InterruptInfo::new(false, None))
-                    .await;
-            }
+        interrupt(context, action).await;
+    }
+}
+
+/// Interrupts the thread running jobs with the given action,
+/// so newly added jobs are run immediately.
+pub(crate) async fn interrupt(context: &Context, action: Action) {
+    match action {
+        Action::Unknown => unreachable!(),
+        Action::Housekeeping
+        | Action::AutoBackup
+        | Action::ConfirmKeyVerification
+        | Action::WkdLookup
+        | Action::DeleteMsgOnImap
+        | Action::DeleteMsgsOnImap
+        | Action::ResyncFolders
+        | Action::MarkseenMsgOnImap
+        | Action::FetchExistingMsgs
+        | Action::SearchServer
+        | Action::MoveMsg => {
+            info!(context, "interrupt: imap");
+            context
+                .interrupt_inbox(InterruptInfo::new(false, None))
+                .await;
+        }
+        Action::MaybeSendLocations
+        | Action::MaybeSendLocationsEnded
+        | Action::SendMdn
+        | Action::SendMsgToSmtp => {
+            info!(context, "interrupt: smtp");
+            context
+                .interrupt_smtp(InterruptInfo::new(false, None))
+                .await;
         }
     }
 }
 
 async fn load_housekeeping_job(context: &Context) -> Option<Job> {
+    if !context.get_hint().await.allows_housekeeping() {
+        return None;
+    }
+
     let last_time = match context.get_config_i64(Config::LastHousekeeping).await {
         Ok(last_time) => last_time,
         Err(err) => {
@@ -1244,6 +1555,24 @@ async fn load_housekeeping_job(context: &Context) -> Option<Job> {
     }
 }
 
+async fn load_auto_backup_job(context: &Context) -> Option<Job> {
+    if !context.get_hint().await.allows_housekeeping() {
+        return None;
+    }
+
+    match imex::auto_backup_due(context).await {
+        Ok(Some(_)) => {
+            kill_action(context, Action::AutoBackup).await;
+            Some(Job::new(Action::AutoBackup, 0, Params::new(), 0))
+        }
+        Ok(None) => None,
+        Err(err) => {
+            warn!(context, "failed to load auto backup config: {:?}", err);
+            None
+        }
+    }
+}
+
 /// Load jobs from the database.
 ///
 /// Load jobs for this "[Thread]", i.e. either load SMTP jobs or load
@@ -1268,6 +1597,17 @@ pub(crate) async fn load_next(
         sleep(Duration::from_millis(500)).await;
     }
 
+    #[cfg(feature = "metrics")]
+    {
+        if let Ok(depth) = context
+            .sql
+            .count("SELECT COUNT(*) FROM jobs;", paramsv![])
+            .await
+        {
+            crate::metrics::JOB_QUEUE_DEPTH.set(context.get_id(), depth as i64);
+        }
+    }
+
     let query;
     let params;
     let t = time();
@@ -1375,8 +1715,10 @@ pub(crate) async fn load_next(
                 }
             } else if let Some(job) = load_imap_deletion_job(context).await.unwrap_or_default() {
                 Some(job)
+            } else if let Some(job) = load_housekeeping_job(context).await {
+                Some(job)
             } else {
-                load_housekeeping_job(context).await
+                load_auto_backup_job(context).await
             }
         }
         Thread::Smtp => job,
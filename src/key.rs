@@ -261,6 +261,17 @@ pub enum KeyPairUse {
     ReadOnly,
 }
 
+/// Origin of a key of a contact not learned using Autocrypt.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, serde::Serialize)]
+#[repr(u32)]
+pub enum KeySource {
+    /// The key was found in the Web Key Directory of the contact's email provider.
+    Wkd = 1,
+
+    /// The key was imported manually using `Contact::import_public_key`.
+    Manual = 2,
+}
+
 /// Store the keypair as an owned keypair for addr in the database.
 ///
 /// This will save the keypair as keys for the given address.  The
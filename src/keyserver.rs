@@ -0,0 +1,323 @@
+//! # Publishing the own key on keys.openpgp.org
+//!
+//! If `Config::PublishKey` is enabled, the public key of the account is uploaded to
+//! [keys.openpgp.org](https://keys.openpgp.org/) so correspondents using classic
+//! OpenPGP software can find it. The keyserver only publishes the address after it is
+//! verified by a link sent to the address; this link is confirmed automatically when
+//! the verification mail for a pending request is received.
+//!
+//! The key is uploaded again periodically from housekeeping, so changes to the key
+//! are published as well.
+
+use std::collections::HashMap;
+
+use anyhow::{ensure, format_err, Context as _, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::contact::addr_cmp;
+use crate::context::Context;
+use crate::dc_tools::time;
+use crate::job::{self, Action, Job};
+use crate::key::{DcKey, SignedPublicKey};
+use crate::mimeparser::MimeMessage;
+use crate::param::{Param, Params};
+
+const KEYSERVER_URL: &str = "https://keys.openpgp.org";
+
+/// Sender of the verification mails.
+const KEYSERVER_ADDR: &str = "keyserver@keys.openpgp.org";
+
+/// Interval in seconds after which the key is uploaded again.
+const REPUBLISH_INTERVAL: i64 = 30 * 24 * 60 * 60;
+
+/// Time in seconds after requesting verification during which the verification mail is accepted.
+const VERIFICATION_TIMEOUT: i64 = 24 * 60 * 60;
+
+static VERIFICATION_LINK: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"https://keys\.openpgp\.org/verify/[^\s<>"]+"#).unwrap());
+
+#[derive(Debug, Serialize)]
+struct UploadRequest {
+    keytext: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct UploadResponse {
+    token: String,
+    status: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+struct VerifyRequest {
+    token: String,
+    addresses: Vec<String>,
+}
+
+async fn post_json<T: Serialize>(url: &str, body: &T) -> Result<surf::Response> {
+    let mut req = surf::post(url).build();
+    req.body_json(body).map_err(|err| format_err!("{}", err))?;
+    let response = surf::Client::new()
+        .send(req)
+        .await
+        .map_err(|err| format_err!("{}", err))?;
+    Ok(response)
+}
+
+/// Uploads the own public key and requests verification of the own address if needed.
+pub(crate) async fn publish_self_key(context: &Context) -> Result<()> {
+    let addr = context
+        .get_config(Config::ConfiguredAddr)
+        .await?
+        .context("not configured")?;
+    let key = SignedPublicKey::load_self(context).await?;
+
+    let upload = UploadRequest {
+        keytext: key.to_asc(None),
+    };
+    let mut response = post_json(&format!("{}/vks/v1/upload", KEYSERVER_URL), &upload).await?;
+    let response: UploadResponse = response
+        .body_json()
+        .await
+        .map_err(|err| format_err!("{}", err))?;
+
+    let status = response
+        .status
+        .iter()
+        .find(|(status_addr, _)| addr_cmp(status_addr, &addr))
+        .map(|(_, status)| status.as_str());
+    match status {
+        Some("published") => info!(context, "Key for {} is published", addr),
+        Some("revoked") => warn!(context, "Key for {} is revoked on keyserver", addr),
+        _ => {
+            info!(context, "Requesting verification of {} on keyserver", addr);
+            let verify = VerifyRequest {
+                token: response.token,
+                addresses: vec![addr],
+            };
+            post_json(&format!("{}/vks/v1/request-verify", KEYSERVER_URL), &verify).await?;
+            context
+                .set_config(
+                    Config::KeyVerificationPending,
+                    Some(&key.fingerprint().hex()),
+                )
+                .await?;
+        }
+    }
+
+    context
+        .set_config(Config::LastKeyPublish, Some(&time().to_string()))
+        .await?;
+    Ok(())
+}
+
+/// Uploads the own public key if `Config::PublishKey` is enabled
+/// and the key was not uploaded recently.
+///
+/// Called from housekeeping.
+pub(crate) async fn maybe_publish_self_key(context: &Context) -> Result<()> {
+    if !context.get_config_bool(Config::PublishKey).await? {
+        return Ok(());
+    }
+    let last_publish = context.get_config_i64(Config::LastKeyPublish).await?;
+    if last_publish + REPUBLISH_INTERVAL > time() {
+        return Ok(());
+    }
+    publish_self_key(context).await
+}
+
+fn verification_link(text: &str) -> Option<&str> {
+    VERIFICATION_LINK.find(text).map(|m| m.as_str())
+}
+
+/// Schedules confirmation of the own address if the message is the verification mail
+/// for a pending verification request.
+///
+/// The mail must come from the keyserver, arrive shortly after the request
+/// and mention the fingerprint of the key the request was made for.
+/// The pending request is consumed, so at most one link is confirmed per request.
+///
+/// Returns true if the message was a verification mail.
+pub(crate) async fn handle_verification_mail(
+    context: &Context,
+    mime_parser: &MimeMessage,
+) -> Result<bool> {
+    if !context.get_config_bool(Config::PublishKey).await? {
+        return Ok(false);
+    }
+    match mime_parser.from.first() {
+        Some(from) if addr_cmp(&from.addr, KEYSERVER_ADDR) => {}
+        _ => return Ok(false),
+    }
+    let fingerprint = match context.get_config(Config::KeyVerificationPending).await? {
+        Some(fingerprint) => fingerprint,
+        None => {
+            warn!(
+                context,
+                "Ignoring keyserver mail without pending verification"
+            );
+            return Ok(false);
+        }
+    };
+    let requested = context.get_config_i64(Config::LastKeyPublish).await?;
+    if requested + VERIFICATION_TIMEOUT < time() {
+        warn!(
+            context,
+            "Ignoring keyserver mail for expired verification request"
+        );
+        context
+            .set_config(Config::KeyVerificationPending, None)
+            .await?;
+        return Ok(false);
+    }
+    let link = match mime_parser.parts.iter().find_map(|part| {
+        if mentions_fingerprint(&part.msg, &fingerprint) {
+            verification_link(&part.msg)
+        } else {
+            None
+        }
+    }) {
+        Some(link) => link,
+        None => return Ok(false),
+    };
+
+    info!(
+        context,
+        "Scheduling confirmation of address verification on keyserver"
+    );
+    context
+        .set_config(Config::KeyVerificationPending, None)
+        .await?;
+    let mut param = Params::new();
+    param.set(Param::Arg, link);
+    job::add(
+        context,
+        Job::new(Action::ConfirmKeyVerification, 0, param, 0),
+    )
+    .await;
+    Ok(true)
+}
+
+/// Returns true if `text` contains the hex `fingerprint`, ignoring case and whitespace.
+fn mentions_fingerprint(text: &str, fingerprint: &str) -> bool {
+    let text: String = text
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase();
+    text.contains(&fingerprint.to_uppercase())
+}
+
+/// Opens the verification link, run from the `ConfirmKeyVerification` job.
+pub(crate) async fn confirm_verification(context: &Context, link: &str) -> Result<()> {
+    ensure!(
+        verification_link(link) == Some(link),
+        "invalid verification link"
+    );
+    info!(context, "Confirming address verification on keyserver");
+    let response = surf::post(link)
+        .await
+        .map_err(|err| format_err!("{}", err))?;
+    ensure!(
+        response.status().is_success(),
+        "keyserver returned {}",
+        response.status()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::TestContext;
+
+    async fn verification_job_cnt(t: &TestContext) -> usize {
+        t.sql
+            .count(
+                "SELECT COUNT(*) FROM jobs WHERE action=?",
+                paramsv![Action::ConfirmKeyVerification],
+            )
+            .await
+            .unwrap()
+    }
+
+    #[async_std::test]
+    async fn test_handle_verification_mail() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        t.set_config_bool(Config::PublishKey, true).await?;
+        let fingerprint = SignedPublicKey::load_self(&t).await?.fingerprint().hex();
+        let raw = format!(
+            "From: keyserver@keys.openpgp.org\n\
+             To: alice@example.com\n\
+             Subject: Verify alice@example.com for your key on keys.openpgp.org\n\
+             Message-ID: <verify@keys.openpgp.org>\n\
+             Date: Sun, 22 Mar 2020 22:37:57 +0000\n\
+             \n\
+             OpenPGP key: {}\n\
+             \n\
+             https://keys.openpgp.org/verify/abcDEF123_-xyz\n",
+            fingerprint
+        );
+
+        // Not requested.
+        let mime_parser = MimeMessage::from_bytes(&t, raw.as_bytes()).await?;
+        assert!(!handle_verification_mail(&t, &mime_parser).await?);
+        assert_eq!(verification_job_cnt(&t).await, 0);
+
+        // Requested for another key.
+        t.set_config(Config::KeyVerificationPending, Some("0123456789ABCDEF"))
+            .await?;
+        t.set_config(Config::LastKeyPublish, Some(&time().to_string()))
+            .await?;
+        assert!(!handle_verification_mail(&t, &mime_parser).await?);
+        assert_eq!(verification_job_cnt(&t).await, 0);
+
+        // Requested, but spoofed sender.
+        t.set_config(Config::KeyVerificationPending, Some(&fingerprint))
+            .await?;
+        let spoofed = raw.replace("keyserver@keys.openpgp.org", "mallory@example.org");
+        let spoofed = MimeMessage::from_bytes(&t, spoofed.as_bytes()).await?;
+        assert!(!handle_verification_mail(&t, &spoofed).await?);
+        assert_eq!(verification_job_cnt(&t).await, 0);
+
+        // Requested, confirmed in a job only once.
+        assert!(handle_verification_mail(&t, &mime_parser).await?);
+        assert_eq!(verification_job_cnt(&t).await, 1);
+        assert!(t
+            .get_config(Config::KeyVerificationPending)
+            .await?
+            .is_none());
+        assert!(!handle_verification_mail(&t, &mime_parser).await?);
+        assert_eq!(verification_job_cnt(&t).await, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mentions_fingerprint() {
+        assert!(mentions_fingerprint(
+            "OpenPGP key: 0123 4567 89ab CDEF",
+            "0123456789ABCDEF"
+        ));
+        assert!(!mentions_fingerprint(
+            "OpenPGP key: 0123",
+            "0123456789ABCDEF"
+        ));
+    }
+
+    #[test]
+    fn test_verification_link() {
+        let text = "Hi,\n\nto let others find this key, click here:\n\n\
+                    https://keys.openpgp.org/verify/abcDEF123_-xyz\n\nThe keys.openpgp.org team";
+        assert_eq!(
+            verification_link(text),
+            Some("https://keys.openpgp.org/verify/abcDEF123_-xyz")
+        );
+        assert_eq!(
+            verification_link("see https://keys.openpgp.org/about"),
+            None
+        );
+    }
+}
@@ -40,7 +40,9 @@ impl<T: rusqlite::ToSql + Send + Sync> ToSql for T {}
 pub(crate) mod events;
 pub use events::*;
 
+mod account_deletion;
 mod aheader;
+pub mod avatar_history;
 mod blob;
 pub mod chat;
 pub mod chatlist;
@@ -49,6 +51,8 @@ impl<T: rusqlite::ToSql + Send + Sync> ToSql for T {}
 pub mod constants;
 pub mod contact;
 pub mod context;
+pub mod dblock;
+mod deletion;
 mod e2ee;
 pub mod ephemeral;
 mod imap;
@@ -59,28 +63,40 @@ impl<T: rusqlite::ToSql + Send + Sync> ToSql for T {}
 mod format_flowed;
 pub mod key;
 mod keyring;
+mod keyserver;
 pub mod location;
 mod login_param;
 pub mod lot;
+mod media;
 pub mod message;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 mod mimefactory;
 pub mod mimeparser;
+pub mod network_policy;
 pub mod oauth2;
 mod param;
 pub mod peerstate;
 pub mod pgp;
 pub mod provider;
 pub mod qr;
+mod render_pool;
+pub mod repair;
 pub mod securejoin;
 mod simplify;
 mod smtp;
+pub mod stats;
 pub mod stock_str;
+pub mod storage;
 mod token;
+pub mod webhook;
+mod wkd;
 #[macro_use]
 mod dehtml;
 mod color;
 pub mod html;
 pub mod plaintext;
+pub mod preview;
 
 pub mod dc_receive_imf;
 pub mod dc_tools;
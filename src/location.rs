@@ -553,49 +553,52 @@ pub async fn save(
 ) -> Result<u32, Error> {
     ensure!(!chat_id.is_special(), "Invalid chat id");
 
-    let mut newest_timestamp = 0;
-    let mut newest_location_id = 0;
-
-    let stmt_insert = "INSERT INTO locations\
-             (timestamp, from_id, chat_id, latitude, longitude, accuracy, independent) \
-             VALUES (?,?,?,?,?,?,?);";
-
-    for location in locations {
-        let &Location {
-            timestamp,
-            latitude,
-            longitude,
-            accuracy,
-            ..
-        } = location;
-
-        let conn = context.sql.get_conn().await?;
-        let mut stmt_test =
-            conn.prepare_cached("SELECT id FROM locations WHERE timestamp=? AND from_id=?")?;
-        let mut stmt_insert = conn.prepare_cached(stmt_insert)?;
-
-        let exists = stmt_test.exists(paramsv![timestamp, contact_id as i32])?;
-
-        if independent || !exists {
-            stmt_insert.execute(paramsv![
-                timestamp,
-                contact_id as i32,
-                chat_id,
-                latitude,
-                longitude,
-                accuracy,
-                independent,
-            ])?;
-
-            if timestamp > newest_timestamp {
-                // okay to drop, as we use cached prepared statements
-                drop(stmt_test);
-                drop(stmt_insert);
-                newest_timestamp = timestamp;
-                newest_location_id = conn.last_insert_rowid();
+    let locations = locations.to_vec();
+    let newest_location_id = context
+        .sql
+        .transaction(move |transaction| {
+            let mut newest_timestamp = 0;
+            let mut newest_location_id = 0;
+
+            let mut stmt_test = transaction
+                .prepare_cached("SELECT id FROM locations WHERE timestamp=? AND from_id=?")?;
+            let mut stmt_insert = transaction.prepare_cached(
+                "INSERT INTO locations\
+                 (timestamp, from_id, chat_id, latitude, longitude, accuracy, independent) \
+                 VALUES (?,?,?,?,?,?,?);",
+            )?;
+
+            for location in locations {
+                let Location {
+                    timestamp,
+                    latitude,
+                    longitude,
+                    accuracy,
+                    ..
+                } = location;
+
+                let exists = stmt_test.exists(paramsv![timestamp, contact_id as i32])?;
+
+                if independent || !exists {
+                    stmt_insert.execute(paramsv![
+                        timestamp,
+                        contact_id as i32,
+                        chat_id,
+                        latitude,
+                        longitude,
+                        accuracy,
+                        independent,
+                    ])?;
+
+                    if timestamp > newest_timestamp {
+                        newest_timestamp = timestamp;
+                        newest_location_id = transaction.last_insert_rowid();
+                    }
+                }
             }
-        }
-    }
+            Ok(newest_location_id)
+        })
+        .await?;
 
     Ok(u32::try_from(newest_location_id)?)
 }
@@ -0,0 +1,201 @@
+//! # Media file metadata
+//!
+//! Minimal parsers to extract the playing duration of audio and video files,
+//! so it is available also if the sending client did not set the `Chat-Duration` header.
+//!
+//! Supported are MP4-based containers (`.mp4`, `.m4a`, `.mov`, ...) and Ogg
+//! (Opus and Vorbis, used for voice messages by most clients).
+
+use std::convert::TryFrom;
+use std::io::{Read, Seek, SeekFrom};
+
+/// Number of bytes searched from the end of an Ogg file for the last page.
+const OGG_TAIL_SIZE: u64 = 64 * 1024;
+
+/// Returns the playing duration of an audio or video file in milliseconds.
+///
+/// Returns `None` if the container format is not supported or the file is damaged.
+pub(crate) fn get_duration_ms<R: Read + Seek>(reader: &mut R) -> Option<u32> {
+    let mut magic = [0u8; 8];
+    reader.seek(SeekFrom::Start(0)).ok()?;
+    reader.read_exact(&mut magic).ok()?;
+
+    let duration_ms = if magic.starts_with(b"OggS") {
+        ogg_duration_ms(reader)?
+    } else if magic.ends_with(b"ftyp") {
+        mp4_duration_ms(reader)?
+    } else {
+        return None;
+    };
+
+    // the same limit as for the `Chat-Duration` header
+    if duration_ms > 0 && duration_ms < 24 * 60 * 60 * 1000 {
+        u32::try_from(duration_ms).ok()
+    } else {
+        None
+    }
+}
+
+fn read_u32_be<R: Read>(reader: &mut R) -> Option<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf).ok()?;
+    Some(u32::from_be_bytes(buf))
+}
+
+fn read_u64_be<R: Read>(reader: &mut R) -> Option<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf).ok()?;
+    Some(u64::from_be_bytes(buf))
+}
+
+/// Searches the boxes between the current position and `end` for a box of type `wanted`.
+///
+/// On success, the reader is positioned at the content of the box
+/// and the end offset of the box is returned.
+fn mp4_find_box<R: Read + Seek>(reader: &mut R, end: u64, wanted: &[u8; 4]) -> Option<u64> {
+    loop {
+        let start = reader.seek(SeekFrom::Current(0)).ok()?;
+        if start + 8 > end {
+            return None;
+        }
+        let size = read_u32_be(reader)?;
+        let mut typ = [0u8; 4];
+        reader.read_exact(&mut typ).ok()?;
+        let box_end = match size {
+            0 => end,
+            1 => start.checked_add(read_u64_be(reader)?)?,
+            size => start + u64::from(size),
+        };
+        if box_end <= start || box_end > end {
+            return None;
+        }
+        if &typ == wanted {
+            return Some(box_end);
+        }
+        reader.seek(SeekFrom::Start(box_end)).ok()?;
+    }
+}
+
+fn mp4_duration_ms<R: Read + Seek>(reader: &mut R) -> Option<u64> {
+    let file_end = reader.seek(SeekFrom::End(0)).ok()?;
+    reader.seek(SeekFrom::Start(0)).ok()?;
+    let moov_end = mp4_find_box(reader, file_end, b"moov")?;
+    mp4_find_box(reader, moov_end, b"mvhd")?;
+
+    let mut version_and_flags = [0u8; 4];
+    reader.read_exact(&mut version_and_flags).ok()?;
+    let (timescale, duration) = if version_and_flags[0] == 1 {
+        reader.seek(SeekFrom::Current(16)).ok()?; // creation and modification time
+        (read_u32_be(reader)?, read_u64_be(reader)?)
+    } else {
+        reader.seek(SeekFrom::Current(8)).ok()?;
+        (read_u32_be(reader)?, u64::from(read_u32_be(reader)?))
+    };
+    if timescale == 0 || duration == u64::MAX || duration == u64::from(u32::MAX) {
+        // duration unknown
+        return None;
+    }
+    duration
+        .checked_mul(1000)
+        .map(|duration| duration / u64::from(timescale))
+}
+
+fn ogg_duration_ms<R: Read + Seek>(reader: &mut R) -> Option<u64> {
+    // The first page contains the identification header of the codec.
+    let mut header = [0u8; 27];
+    reader.seek(SeekFrom::Start(0)).ok()?;
+    reader.read_exact(&mut header).ok()?;
+    let segments = header[26];
+    reader.seek(SeekFrom::Current(i64::from(segments))).ok()?;
+    let mut packet = [0u8; 19];
+    reader.read_exact(&mut packet).ok()?;
+
+    let (sample_rate, pre_skip) = if packet.starts_with(b"OpusHead") {
+        // Opus granule positions are always in 48 kHz
+        (
+            48_000u64,
+            u64::from(u16::from_le_bytes([packet[10], packet[11]])),
+        )
+    } else if packet.starts_with(b"\x01vorbis") {
+        let rate = u32::from_le_bytes([packet[12], packet[13], packet[14], packet[15]]);
+        (u64::from(rate), 0)
+    } else {
+        return None;
+    };
+    if sample_rate == 0 {
+        return None;
+    }
+
+    // The granule position of the last page is the total number of samples.
+    let file_end = reader.seek(SeekFrom::End(0)).ok()?;
+    let tail_start = file_end.saturating_sub(OGG_TAIL_SIZE);
+    reader.seek(SeekFrom::Start(tail_start)).ok()?;
+    let mut tail = Vec::new();
+    reader.read_to_end(&mut tail).ok()?;
+    let last_page = tail.windows(4).rposition(|w| w == b"OggS")?;
+    let granule = tail.get(last_page + 6..last_page + 14)?;
+    let mut granule_bytes = [0u8; 8];
+    granule_bytes.copy_from_slice(granule);
+    let samples = u64::from_le_bytes(granule_bytes).checked_sub(pre_skip)?;
+
+    samples
+        .checked_mul(1000)
+        .map(|samples| samples / sample_rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn mp4_box(typ: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&(content.len() as u32 + 8).to_be_bytes());
+        buf.extend_from_slice(typ);
+        buf.extend_from_slice(content);
+        buf
+    }
+
+    #[test]
+    fn test_mp4_duration() {
+        let mut mvhd = vec![0u8; 12]; // version, flags, creation and modification time
+        mvhd.extend_from_slice(&1000u32.to_be_bytes()); // timescale
+        mvhd.extend_from_slice(&12345u32.to_be_bytes()); // duration
+        mvhd.extend_from_slice(&[0u8; 80]);
+
+        let mut file = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        file.extend(mp4_box(b"free", &[0u8; 16]));
+        file.extend(mp4_box(b"moov", &mp4_box(b"mvhd", &mvhd)));
+
+        assert_eq!(get_duration_ms(&mut Cursor::new(file)), Some(12345));
+    }
+
+    fn ogg_page(granule: u64, packet: &[u8]) -> Vec<u8> {
+        let mut buf = b"OggS\0\0".to_vec();
+        buf.extend_from_slice(&granule.to_le_bytes());
+        buf.extend_from_slice(&[0u8; 12]); // serial, sequence, checksum
+        buf.push(1); // one segment
+        buf.push(packet.len() as u8);
+        buf.extend_from_slice(packet);
+        buf
+    }
+
+    #[test]
+    fn test_ogg_opus_duration() {
+        let mut head = b"OpusHead\x01\x01".to_vec();
+        head.extend_from_slice(&312u16.to_le_bytes()); // pre-skip
+        head.extend_from_slice(&[0u8; 7]);
+
+        let mut file = ogg_page(0, &head);
+        file.extend(ogg_page(0, b"OpusTags"));
+        file.extend(ogg_page(48_000 * 3 + 312, &[0u8; 20]));
+
+        assert_eq!(get_duration_ms(&mut Cursor::new(file)), Some(3000));
+    }
+
+    #[test]
+    fn test_unknown_format() {
+        assert_eq!(get_duration_ms(&mut Cursor::new(b"hello world")), None);
+        assert_eq!(get_duration_ms(&mut Cursor::new(b"")), None);
+    }
+}
@@ -2,12 +2,13 @@
 
 use std::collections::BTreeMap;
 use std::convert::TryInto;
+use std::io::Read;
 
-use anyhow::{ensure, format_err, Result};
+use anyhow::{ensure, format_err, Context as _, Result};
 use async_std::path::{Path, PathBuf};
 use deltachat_derive::{FromSql, ToSql};
+use flate2::read::DeflateDecoder;
 use itertools::Itertools;
-use rusqlite::types::ValueRef;
 use serde::{Deserialize, Serialize};
 
 use crate::chat::{self, Chat, ChatId};
@@ -22,7 +23,8 @@
     dc_get_filebytes, dc_get_filemeta, dc_gm2local_offset, dc_read_file, dc_timestamp_to_str,
     dc_truncate, time,
 };
-use crate::ephemeral::Timer as EphemeralTimer;
+use crate::deletion;
+use crate::ephemeral::{self, schedule_ephemeral_task, Timer as EphemeralTimer};
 use crate::events::EventType;
 use crate::job::{self, Action};
 use crate::log::LogExt;
@@ -34,7 +36,7 @@
 
 // In practice, the user additionally cuts the string themselves
 // pixel-accurate.
-const SUMMARY_CHARACTERS: usize = 160;
+pub(crate) const SUMMARY_CHARACTERS: usize = 160;
 
 /// Message ID, including reserved IDs.
 ///
@@ -77,6 +79,48 @@ pub fn is_unset(self) -> bool {
         self.0 == 0
     }
 
+    /// Returns the private note of the user for the message, see [`MsgId::set_note`].
+    pub async fn get_note(self, context: &Context) -> Result<Option<String>> {
+        context
+            .sql
+            .query_get_value("SELECT note FROM msgs_notes WHERE msg_id=?", paramsv![self])
+            .await
+    }
+
+    /// Sets a private note for the message, an empty note removes it.
+    ///
+    /// Notes are stored on this device only and never sent.
+    /// They are found by [`Context::search_msgs`] and removed when the message is deleted.
+    pub async fn set_note(self, context: &Context, note: &str) -> Result<()> {
+        let chat_id: ChatId = context
+            .sql
+            .query_get_value("SELECT chat_id FROM msgs WHERE id=?", paramsv![self])
+            .await?
+            .with_context(|| format!("message {} not found", self))?;
+        ensure!(!chat_id.is_trash(), "message {} is deleted", self);
+
+        let note = note.trim();
+        if note.is_empty() {
+            context
+                .sql
+                .execute("DELETE FROM msgs_notes WHERE msg_id=?", paramsv![self])
+                .await?;
+        } else {
+            context
+                .sql
+                .execute(
+                    "INSERT OR REPLACE INTO msgs_notes (msg_id, note) VALUES (?, ?)",
+                    paramsv![self, note],
+                )
+                .await?;
+        }
+        context.emit_event(EventType::MsgsChanged {
+            chat_id,
+            msg_id: self,
+        });
+        Ok(())
+    }
+
     /// Returns message state.
     pub async fn get_state(self, context: &Context) -> Result<MessageState> {
         let result = context
@@ -156,21 +200,7 @@ pub async fn trash(self, context: &Context) -> Result<()> {
         let chat_id = DC_CHAT_ID_TRASH;
         context
             .sql
-            .execute(
-                // If you change which information is removed here, also change delete_expired_messages() and
-                // which information dc_receive_imf::add_parts() still adds to the db if the chat_id is TRASH
-                r#"
-UPDATE msgs 
-SET 
-  chat_id=?, txt='', 
-  subject='', txt_raw='', 
-  mime_headers='', 
-  from_id=0, to_id=0, 
-  param='' 
-WHERE id=?;
-"#,
-                paramsv![chat_id, self],
-            )
+            .execute(TRASH_MSG_SQL, paramsv![chat_id, self])
             .await?;
 
         Ok(())
@@ -209,6 +239,13 @@ pub(crate) async fn unlink(self, context: &Context) -> Result<()> {
         Ok(())
     }
 
+    /// Returns the message as JSON, see [MessageSnapshot].
+    pub async fn to_json(self, context: &Context) -> Result<String> {
+        let msg = Message::load_from_db(context, self).await?;
+        let snapshot = msg.get_snapshot(context).await?;
+        Ok(serde_json::to_string(&snapshot)?)
+    }
+
     /// Bad evil escape hatch.
     ///
     /// Avoid using this, eventually types should be cleaned up enough
@@ -307,6 +344,8 @@ pub struct Message {
     pub(crate) timestamp_rcvd: i64,
     pub(crate) ephemeral_timer: EphemeralTimer,
     pub(crate) ephemeral_timestamp: i64,
+    pub(crate) view_once: bool,
+    pub(crate) starred: bool,
     pub(crate) text: Option<String>,
     pub(crate) subject: String,
     pub(crate) rfc724_mid: String,
@@ -353,6 +392,8 @@ pub async fn load_from_db(context: &Context, id: MsgId) -> Result<Message> {
                     "    m.timestamp_rcvd AS timestamp_rcvd,",
                     "    m.ephemeral_timer AS ephemeral_timer,",
                     "    m.ephemeral_timestamp AS ephemeral_timestamp,",
+                    "    m.view_once AS view_once,",
+                    "    m.starred AS starred,",
                     "    m.type AS type,",
                     "    m.state AS state,",
                     "    m.error AS error,",
@@ -404,6 +445,8 @@ pub async fn load_from_db(context: &Context, id: MsgId) -> Result<Message> {
                         timestamp_rcvd: row.get("timestamp_rcvd")?,
                         ephemeral_timer: row.get("ephemeral_timer")?,
                         ephemeral_timestamp: row.get("ephemeral_timestamp")?,
+                        view_once: row.get("view_once")?,
+                        starred: row.get("starred")?,
                         viewtype: row.get("type")?,
                         state: row.get("state")?,
                         error: Some(row.get::<_, String>("error")?)
@@ -546,6 +589,17 @@ pub fn get_text(&self) -> Option<String> {
             .map(|text| dc_truncate(text, DC_MAX_GET_TEXT_LEN).to_string())
     }
 
+    /// Returns the full text of the message.
+    ///
+    /// Unlike [`Message::get_text`], the text is not truncated. Received texts longer than
+    /// [`Config::MaxTextLength`] are loaded from the blob they were stored in.
+    pub async fn get_full_text(&self, context: &Context) -> Result<Option<String>> {
+        match self.param.get_path(Param::FullText, context)? {
+            Some(path) => Ok(Some(async_std::fs::read_to_string(path).await?)),
+            None => Ok(self.text.clone()),
+        }
+    }
+
     pub fn get_subject(&self) -> &str {
         &self.subject
     }
@@ -573,6 +627,11 @@ pub fn get_height(&self) -> i32 {
         self.param.get_int(Param::Height).unwrap_or_default()
     }
 
+    /// Returns the duration of audio and video messages in milliseconds.
+    ///
+    /// The duration is taken from the `Chat-Duration` header if the sender set it,
+    /// otherwise it is extracted from the attached file if the format is known.
+    /// Returns 0 if the duration is unknown.
     pub fn get_duration(&self) -> i32 {
         self.param.get_int(Param::Duration).unwrap_or_default()
     }
@@ -581,6 +640,22 @@ pub fn get_showpadlock(&self) -> bool {
         self.param.get_int(Param::GuaranteeE2ee).unwrap_or_default() != 0
     }
 
+    /// Returns true if the message is not encrypted,
+    /// but signed with a valid signature of the sender.
+    ///
+    /// See `Contact::set_sign_only` for sending such messages.
+    pub fn is_signed_only(&self) -> bool {
+        self.param.get_bool(Param::SignedOnly).unwrap_or_default()
+    }
+
+    /// Returns true if the message was sent to a group by the user from another account,
+    /// see `Contact::set_self_alias`.
+    ///
+    /// Such messages should be rendered like outgoing messages.
+    pub fn is_from_self_alias(&self) -> bool {
+        self.param.get_bool(Param::SelfAlias).unwrap_or_default()
+    }
+
     /// Returns true if message is Auto-Submitted.
     pub fn is_bot(&self) -> bool {
         self.param.get_bool(Param::Bot).unwrap_or_default()
@@ -594,6 +669,37 @@ pub fn get_ephemeral_timestamp(&self) -> i64 {
         self.ephemeral_timestamp
     }
 
+    /// Returns true if the message is deleted after it is displayed once.
+    pub fn is_view_once(&self) -> bool {
+        self.view_once
+    }
+
+    /// Sets whether the message should be deleted after it is displayed once.
+    ///
+    /// The recipients delete the message as soon as it is marked as seen,
+    /// regardless of the ephemeral timer of the chat.
+    /// The copy of the sender is not deleted.
+    pub fn set_view_once(&mut self, view_once: bool) {
+        self.view_once = view_once;
+    }
+
+    /// Returns true if the message is starred, see [`star_msgs`].
+    pub fn is_starred(&self) -> bool {
+        self.starred
+    }
+
+    /// Returns the number of seconds until the ephemeral message is deleted.
+    ///
+    /// If the timer is not started yet, the full timer duration is returned.
+    /// Returns 0 for messages without ephemeral timer.
+    pub fn get_ephemeral_remaining_secs(&self) -> i64 {
+        if self.ephemeral_timestamp != 0 {
+            std::cmp::max(0, self.ephemeral_timestamp - time())
+        } else {
+            i64::from(self.ephemeral_timer.to_u32())
+        }
+    }
+
     pub async fn get_summary(&mut self, context: &Context, chat: Option<&Chat>) -> Lot {
         let mut ret = Lot::new();
 
@@ -814,6 +920,14 @@ pub fn set_file(&mut self, file: impl AsRef<str>, filemime: Option<&str>) {
         }
     }
 
+    /// Sets the subject of an outgoing message.
+    ///
+    /// By default, the subject is derived from the chat, eg. "Re: <last subject>".
+    /// An explicitly set subject is used as is, without prefix.
+    pub fn set_subject(&mut self, subject: String) {
+        self.subject = subject;
+    }
+
     /// Set different sender name for a message.
     /// This overrides the name set by the `set_config()`-option `displayname`.
     pub fn set_override_sender_name(&mut self, name: Option<String>) {
@@ -945,6 +1059,132 @@ pub(crate) async fn update_subject(&self, context: &Context) {
     pub fn error(&self) -> Option<String> {
         self.error.clone()
     }
+
+    /// Returns the current state of the message as a serializable [MessageSnapshot].
+    pub async fn get_snapshot(&self, context: &Context) -> Result<MessageSnapshot> {
+        Ok(MessageSnapshot {
+            id: self.id,
+            chat_id: self.chat_id,
+            from_id: self.from_id,
+            viewtype: self.viewtype as u32,
+            state: self.state as u32,
+            text: self.get_text(),
+            subject: self.subject.clone(),
+            timestamp: self.get_timestamp(),
+            received_timestamp: self.timestamp_rcvd,
+            sort_timestamp: self.timestamp_sort,
+            file: self.get_file(context).map(Into::into),
+            filemime: self.get_filemime(),
+            filename: self.get_filename(),
+            width: self.get_width(),
+            height: self.get_height(),
+            duration: self.get_duration(),
+            showpadlock: self.get_showpadlock(),
+            is_info: self.is_info(),
+            is_forwarded: self.is_forwarded(),
+            is_bot: self.is_bot(),
+            has_location: self.has_location(),
+            override_sender_name: self.get_override_sender_name(),
+            quoted_text: self.quoted_text(),
+            quoted_message_id: self.quoted_message(context).await?.map(|msg| msg.id),
+            ephemeral_timer: self.ephemeral_timer,
+            ephemeral_timestamp: self.ephemeral_timestamp,
+            error: self.error(),
+        })
+    }
+}
+
+/// The current state of a message.
+///
+/// This is the serializable representation of a [Message],
+/// eg. to build JSON APIs on top of the core.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct MessageSnapshot {
+    /// The message ID.
+    pub id: MsgId,
+
+    /// The ID of the chat the message belongs to.
+    pub chat_id: ChatId,
+
+    /// The contact ID of the sender.
+    pub from_id: u32,
+
+    /// The type of the message as a `u32` representation of [Viewtype].
+    ///
+    /// On the C API this number is one of the `DC_MSG_*` constants.
+    pub viewtype: u32,
+
+    /// The state of the message as a `u32` representation of [MessageState].
+    ///
+    /// On the C API this number is one of the `DC_STATE_*` constants.
+    pub state: u32,
+
+    /// The message text.
+    pub text: Option<String>,
+
+    /// The subject of the email the message was sent or received with.
+    pub subject: String,
+
+    /// Time the message was sent, or received if the sending time is unknown.
+    pub timestamp: i64,
+
+    /// Time the message was received, 0 for outgoing messages.
+    pub received_timestamp: i64,
+
+    /// Time used to sort the message in the chat.
+    pub sort_timestamp: i64,
+
+    /// Path to the attachment.
+    pub file: Option<std::path::PathBuf>,
+
+    /// MIME type of the attachment.
+    pub filemime: Option<String>,
+
+    /// Original file name of the attachment.
+    pub filename: Option<String>,
+
+    /// Width of images and videos in pixels, 0 if unknown.
+    pub width: i32,
+
+    /// Height of images and videos in pixels, 0 if unknown.
+    pub height: i32,
+
+    /// Duration of audio and video in milliseconds, 0 if unknown.
+    pub duration: i32,
+
+    /// Whether a padlock should be shown beside the message.
+    pub showpadlock: bool,
+
+    /// Whether the message is an info message.
+    pub is_info: bool,
+
+    /// Whether the message is forwarded.
+    pub is_forwarded: bool,
+
+    /// Whether the message was sent by a bot.
+    pub is_bot: bool,
+
+    /// Whether the message has a location bound to it.
+    pub has_location: bool,
+
+    /// Name to show instead of the sender's name, eg. for mailing lists.
+    pub override_sender_name: Option<String>,
+
+    /// Text of the quote, if the message quotes another one.
+    pub quoted_text: Option<String>,
+
+    /// ID of the quoted message, if it is in the database.
+    pub quoted_message_id: Option<MsgId>,
+
+    /// Ephemeral message timer.
+    pub ephemeral_timer: EphemeralTimer,
+
+    /// Time at which the ephemeral message is deleted, 0 if not started.
+    pub ephemeral_timestamp: i64,
+
+    /// Error status of the message, see [Message::error].
+    pub error: Option<String>,
 }
 
 #[derive(
@@ -1223,6 +1463,8 @@ pub async fn get_msg_info(context: &Context, msg_id: MsgId) -> Result<String> {
         }
     } else if 0 != msg.param.get_int(Param::GuaranteeE2ee).unwrap_or_default() {
         ret += ", Encrypted";
+    } else if msg.is_signed_only() {
+        ret += ", Signed, not encrypted";
     }
 
     ret += "\n";
@@ -1344,70 +1586,168 @@ pub fn guess_msgtype_from_suffix(path: &Path) -> Option<(Viewtype, &str)> {
 /// Get the raw mime-headers of the given message.
 /// Raw headers are saved for incoming messages
 /// only if `dc_set_config(context, "save_mime_headers", "1")`
-/// was called before.
+/// or [`ChatId::set_save_mime`] for the chat was called before.
+///
+/// The headers are stored zstd-compressed in a separate table
+/// and only loaded and decompressed when requested.
+/// Headers stored deflate-compressed by older versions are recompressed
+/// when they are loaded for the first time.
 ///
 /// Returns an empty vector if there are no headers saved for the given message,
 /// e.g. because of save_mime_headers is not set
 /// or the message is not incoming.
 pub async fn get_mime_headers(context: &Context, msg_id: MsgId) -> Result<Vec<u8>> {
-    let headers = context
+    let compressed: Option<Vec<u8>> = context
         .sql
-        .query_row(
-            "SELECT mime_headers FROM msgs WHERE id=?;",
+        .query_get_value(
+            "SELECT mime_headers FROM msgs_mime_headers WHERE msg_id=?;",
             paramsv![msg_id],
-            |row| {
-                row.get(0).or_else(|err| match row.get_ref(0)? {
-                    ValueRef::Null => Ok(Vec::new()),
-                    ValueRef::Text(text) => Ok(text.to_vec()),
-                    ValueRef::Blob(blob) => Ok(blob.to_vec()),
-                    ValueRef::Integer(_) | ValueRef::Real(_) => Err(err),
-                })
-            },
         )
         .await?;
-    Ok(headers)
+    let compressed = match compressed {
+        Some(compressed) => compressed,
+        None => return Ok(Vec::new()),
+    };
+    if compressed.starts_with(ZSTD_MAGIC) {
+        return Ok(zstd::decode_all(compressed.as_slice())?);
+    }
+
+    let mut mime_headers = Vec::new();
+    DeflateDecoder::new(compressed.as_slice()).read_to_end(&mut mime_headers)?;
+    // Not using INSERT OR REPLACE here, the message may have been deleted meanwhile.
+    context
+        .sql
+        .execute(
+            "UPDATE msgs_mime_headers SET mime_headers=? WHERE msg_id=?;",
+            paramsv![compress_mime_headers(&mime_headers)?, msg_id],
+        )
+        .await?;
+    Ok(mime_headers)
+}
+
+/// Stores the raw mime-headers of the given message, see [`get_mime_headers`].
+pub(crate) async fn set_mime_headers(
+    context: &Context,
+    msg_id: MsgId,
+    mime_headers: &[u8],
+) -> Result<()> {
+    let compressed = compress_mime_headers(mime_headers)?;
+    context
+        .sql
+        .execute(
+            "INSERT OR REPLACE INTO msgs_mime_headers (msg_id, mime_headers) VALUES (?, ?);",
+            paramsv![msg_id, compressed],
+        )
+        .await?;
+    Ok(())
 }
 
+/// Magic number at the start of zstd frames,
+/// distinguishes zstd-compressed headers from deflate-compressed ones.
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compresses raw mime-headers for the `msgs_mime_headers` table.
+pub(crate) fn compress_mime_headers(mime_headers: &[u8]) -> Result<Vec<u8>> {
+    // Level 0 selects zstd's default level.
+    Ok(zstd::encode_all(mime_headers, 0)?)
+}
+
+// If you change which information is removed here, also change delete_expired_messages() and
+// which information dc_receive_imf::add_parts() still adds to the db if the chat_id is TRASH
+const TRASH_MSG_SQL: &str = r#"
+UPDATE msgs 
+SET 
+  chat_id=?, txt='', 
+  subject='', txt_raw='', 
+  mime_headers='', 
+  from_id=0, to_id=0, 
+  param='' 
+WHERE id=?;
+"#;
+
+/// Number of messages from which on an immediate deletion runs in maintenance mode.
+const BULK_DELETION_CNT: usize = 100;
+
+/// Deletes messages.
+///
+/// If `Config::DeletionUndoWindow` is set, the messages are only hidden
+/// and deleted irreversibly after the window,
+/// see `Context::undo_last_deletion`.
+/// Deleting many messages at once pauses IO, see `Context::with_maintenance`.
 pub async fn delete_msgs(context: &Context, msg_ids: &[MsgId]) {
-    for msg_id in msg_ids.iter() {
-        if let Ok(msg) = Message::load_from_db(context, *msg_id).await {
-            if msg.location_id > 0 {
-                delete_poi_location(context, msg.location_id).await;
+    match deletion::undo_window(context).await {
+        Ok(window) if window > 0 => {
+            if let Err(err) = deletion::defer_msgs_deletion(context, msg_ids, window).await {
+                error!(context, "Unable to delete messages: {}", err);
             }
         }
-        if let Err(err) = msg_id.trash(context).await {
-            error!(context, "Unable to trash message {}: {}", msg_id, err);
+        _ if msg_ids.len() >= BULK_DELETION_CNT => {
+            let msg_ids = msg_ids.to_vec();
+            let res = context
+                .with_maintenance(|context| async move {
+                    delete_msgs_now(&context, &msg_ids).await;
+                    Ok(())
+                })
+                .await;
+            if let Err(err) = res {
+                error!(context, "Unable to delete messages: {}", err);
+            }
         }
-        job::add(
-            context,
-            job::Job::new(Action::DeleteMsgOnImap, msg_id.to_u32(), Params::new(), 0),
-        )
-        .await;
+        _ => delete_msgs_now(context, msg_ids).await,
     }
+}
 
-    if !msg_ids.is_empty() {
-        context.emit_event(EventType::MsgsChanged {
-            chat_id: ChatId::new(0),
-            msg_id: MsgId::new(0),
-        });
-        job::kill_action(context, Action::Housekeeping).await;
-        job::add(
-            context,
-            job::Job::new(Action::Housekeeping, 0, Params::new(), 10),
-        )
-        .await;
+/// Deletes messages irreversibly.
+///
+/// All messages are trashed in a single transaction
+/// together with adding the jobs deleting them from the server.
+pub(crate) async fn delete_msgs_now(context: &Context, msg_ids: &[MsgId]) {
+    if msg_ids.is_empty() {
+        return;
     }
-}
 
-async fn delete_poi_location(context: &Context, location_id: u32) -> bool {
-    context
+    let mut location_ids = Vec::new();
+    for msg_id in msg_ids.iter() {
+        if let Ok(msg) = Message::load_from_db(context, *msg_id).await {
+            if msg.location_id > 0 {
+                location_ids.push(msg.location_id);
+            }
+        }
+    }
+    let msg_ids = msg_ids.to_vec();
+    let res = context
         .sql
-        .execute(
-            "DELETE FROM locations WHERE independent = 1 AND id=?;",
-            paramsv![location_id as i32],
-        )
-        .await
-        .is_ok()
+        .transaction(move |transaction| {
+            for location_id in location_ids {
+                transaction.execute(
+                    "DELETE FROM locations WHERE independent = 1 AND id=?;",
+                    paramsv![location_id],
+                )?;
+            }
+            for msg_id in msg_ids {
+                transaction.execute(TRASH_MSG_SQL, paramsv![DC_CHAT_ID_TRASH, msg_id])?;
+                job::Job::new(Action::DeleteMsgOnImap, msg_id.to_u32(), Params::new(), 0)
+                    .insert(transaction)?;
+            }
+            Ok(())
+        })
+        .await;
+    if let Err(err) = res {
+        error!(context, "Unable to delete messages: {}", err);
+        return;
+    }
+    job::interrupt(context, Action::DeleteMsgOnImap).await;
+
+    context.emit_event(EventType::MsgsChanged {
+        chat_id: ChatId::new(0),
+        msg_id: MsgId::new(0),
+    });
+    job::kill_action(context, Action::Housekeeping).await;
+    job::add(
+        context,
+        job::Job::new(Action::Housekeeping, 0, Params::new(), 10),
+    )
+    .await;
 }
 
 pub async fn markseen_msgs(context: &Context, msg_ids: Vec<MsgId>) -> Result<()> {
@@ -1421,6 +1761,7 @@ pub async fn markseen_msgs(context: &Context, msg_ids: Vec<MsgId>) -> Result<()>
             "SELECT",
             "    m.chat_id AS chat_id,",
             "    m.state AS state,",
+            "    m.view_once AS view_once,",
             "    c.blocked AS blocked",
             " FROM msgs m LEFT JOIN chats c ON c.id=m.chat_id",
             " WHERE m.id=? AND m.chat_id>9"
@@ -1432,6 +1773,7 @@ pub async fn markseen_msgs(context: &Context, msg_ids: Vec<MsgId>) -> Result<()>
                 Ok((
                     row.get::<_, ChatId>("chat_id")?,
                     row.get::<_, MessageState>("state")?,
+                    row.get::<_, bool>("view_once")?,
                     row.get::<_, Option<Blocked>>("blocked")?
                         .unwrap_or_default(),
                 ))
@@ -1439,8 +1781,9 @@ pub async fn markseen_msgs(context: &Context, msg_ids: Vec<MsgId>) -> Result<()>
             if let Err(rusqlite::Error::QueryReturnedNoRows) = query_res {
                 continue;
             }
-            let (chat_id, state, blocked) = query_res.map_err(Into::<anyhow::Error>::into)?;
-            msgs.push((id, chat_id, state, blocked));
+            let (chat_id, state, view_once, blocked) =
+                query_res.map_err(Into::<anyhow::Error>::into)?;
+            msgs.push((id, chat_id, state, view_once, blocked));
         }
         drop(stmt);
         drop(conn);
@@ -1449,8 +1792,10 @@ pub async fn markseen_msgs(context: &Context, msg_ids: Vec<MsgId>) -> Result<()>
     .await?;
 
     let mut updated_chat_ids = BTreeMap::new();
+    let mut seen_msg_ids = Vec::new();
+    let mut view_once_seen = false;
 
-    for (id, curr_chat_id, curr_state, curr_blocked) in msgs.into_iter() {
+    for (id, curr_chat_id, curr_state, curr_view_once, curr_blocked) in msgs.into_iter() {
         if let Err(err) = id.start_ephemeral_timer(context).await {
             error!(
                 context,
@@ -1462,25 +1807,70 @@ pub async fn markseen_msgs(context: &Context, msg_ids: Vec<MsgId>) -> Result<()>
         if curr_blocked == Blocked::Not
             && (curr_state == MessageState::InFresh || curr_state == MessageState::InNoticed)
         {
-            update_msg_state(context, id, MessageState::InSeen).await;
-            info!(context, "Seen message {}.", id);
-
-            job::add(
-                context,
-                job::Job::new(Action::MarkseenMsgOnImap, id.to_u32(), Params::new(), 0),
-            )
-            .await;
+            seen_msg_ids.push(id);
             updated_chat_ids.insert(curr_chat_id, true);
+            view_once_seen |= curr_view_once;
         }
     }
+    if seen_msg_ids.is_empty() {
+        return Ok(());
+    }
+
+    // Mark all messages as seen and add the jobs marking them as seen on the server
+    // in a single transaction.
+    info!(context, "Seen messages {:?}.", seen_msg_ids);
+    context
+        .sql
+        .transaction(move |transaction| {
+            for id in seen_msg_ids {
+                transaction.execute(
+                    "UPDATE msgs SET state=? WHERE id=?;",
+                    paramsv![MessageState::InSeen, id],
+                )?;
+                job::Job::new(Action::MarkseenMsgOnImap, id.to_u32(), Params::new(), 0)
+                    .insert(transaction)?;
+            }
+            Ok(())
+        })
+        .await?;
+    job::interrupt(context, Action::MarkseenMsgOnImap).await;
 
     for updated_chat_id in updated_chat_ids.keys() {
         context.emit_event(EventType::MsgsNoticed(*updated_chat_id));
     }
+    if view_once_seen && ephemeral::delete_seen_view_once_msgs(context).await? {
+        context.emit_event(EventType::MsgsChanged {
+            chat_id: ChatId::new(0),
+            msg_id: MsgId::new(0),
+        });
+    }
 
     Ok(())
 }
 
+/// Stars or unstars messages.
+///
+/// If `Config::ProtectStarredFromDeletion` is set,
+/// starred messages are not deleted by ephemeral timers or `Config::DeleteDeviceAfter`.
+pub async fn star_msgs(context: &Context, msg_ids: Vec<MsgId>, star: bool) -> Result<()> {
+    for msg_id in msg_ids {
+        let msg = Message::load_from_db(context, msg_id).await?;
+        context
+            .sql
+            .execute(
+                "UPDATE msgs SET starred=?, preserved=(preserved AND ?) WHERE id=?;",
+                paramsv![star, star, msg_id],
+            )
+            .await?;
+        context.emit_event(EventType::MsgsChanged {
+            chat_id: msg.chat_id,
+            msg_id,
+        });
+    }
+    schedule_ephemeral_task(context).await;
+    Ok(())
+}
+
 pub async fn update_msg_state(context: &Context, msg_id: MsgId, state: MessageState) -> bool {
     context
         .sql
@@ -1883,6 +2273,14 @@ pub async fn estimate_deletion_cnt(
             )
             .await?
     } else {
+        let self_chat_id = if context
+            .get_config_bool(Config::DeleteDeviceAfterIncludesSelfChat)
+            .await?
+        {
+            ChatId::new(0)
+        } else {
+            self_chat_id
+        };
         context
             .sql
             .count(
@@ -1994,6 +2392,22 @@ fn test_guess_msgtype_from_suffix() {
         );
     }
 
+    #[test]
+    fn test_get_ephemeral_remaining_secs() {
+        let mut msg = Message::default();
+        assert_eq!(msg.get_ephemeral_remaining_secs(), 0);
+
+        msg.ephemeral_timer = EphemeralTimer::Enabled { duration: 60 };
+        assert_eq!(msg.get_ephemeral_remaining_secs(), 60);
+
+        msg.ephemeral_timestamp = time() + 30;
+        let remaining = msg.get_ephemeral_remaining_secs();
+        assert!(remaining > 0 && remaining <= 30);
+
+        msg.ephemeral_timestamp = time() - 10;
+        assert_eq!(msg.get_ephemeral_remaining_secs(), 0);
+    }
+
     // chat_msg means that the message was sent by Delta Chat
     // The tuples are (folder, mvbox_move, chat_msg, expected_destination)
     const COMBINATIONS_ACCEPTED_CHAT: &[(&str, bool, bool, &str)] = &[
@@ -2647,6 +3061,121 @@ async fn test_set_override_sender_name() {
         assert_ne!(chat.typ, Chattype::Mailinglist);
     }
 
+    #[async_std::test]
+    async fn test_mime_headers_compressed() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let sent = t.send_text(t.get_self_chat().await.id, "hi").await;
+        let msg_id = sent.sender_msg_id;
+        assert!(get_mime_headers(&t, msg_id).await?.is_empty());
+
+        let mime_headers = "Subject: hello\r\n".repeat(100);
+        set_mime_headers(&t, msg_id, mime_headers.as_bytes()).await?;
+        assert_eq!(get_mime_headers(&t, msg_id).await?, mime_headers.as_bytes());
+        let stored: Vec<u8> = t
+            .sql
+            .query_get_value(
+                "SELECT mime_headers FROM msgs_mime_headers WHERE msg_id=?;",
+                paramsv![msg_id],
+            )
+            .await?
+            .unwrap();
+        assert!(stored.starts_with(ZSTD_MAGIC));
+        assert!(stored.len() < mime_headers.len() / 10);
+
+        // Headers stored deflate-compressed by older versions are recompressed on access.
+        let mut encoder =
+            flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, mime_headers.as_bytes())?;
+        t.sql
+            .execute(
+                "UPDATE msgs_mime_headers SET mime_headers=? WHERE msg_id=?;",
+                paramsv![encoder.finish()?, msg_id],
+            )
+            .await?;
+        assert_eq!(get_mime_headers(&t, msg_id).await?, mime_headers.as_bytes());
+        let stored: Vec<u8> = t
+            .sql
+            .query_get_value(
+                "SELECT mime_headers FROM msgs_mime_headers WHERE msg_id=?;",
+                paramsv![msg_id],
+            )
+            .await?
+            .unwrap();
+        assert!(stored.starts_with(ZSTD_MAGIC));
+
+        // Headers are removed when the message is deleted.
+        delete_msgs(&t, &[msg_id]).await;
+        assert!(get_mime_headers(&t, msg_id).await?.is_empty());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_msg_note() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        let chat = alice.create_chat_with_contact("", "bob@example.net").await;
+        let msg_id = alice.send_text(chat.id, "hello").await.sender_msg_id;
+        assert_eq!(msg_id.get_note(&alice).await?, None);
+
+        msg_id.set_note(&alice, "ticket 1234").await?;
+        assert_eq!(
+            msg_id.get_note(&alice).await?,
+            Some("ticket 1234".to_string())
+        );
+        assert_eq!(alice.search_msgs(None, "1234").await?, vec![msg_id]);
+        assert_eq!(
+            alice.search_msgs(Some(chat.id), "ticket").await?,
+            vec![msg_id]
+        );
+
+        msg_id.set_note(&alice, "").await?;
+        assert_eq!(msg_id.get_note(&alice).await?, None);
+        assert!(alice.search_msgs(None, "1234").await?.is_empty());
+
+        // Notes are removed together with the message.
+        msg_id.set_note(&alice, "ticket 1234").await?;
+        delete_msgs(&alice, &[msg_id]).await;
+        assert_eq!(msg_id.get_note(&alice).await?, None);
+        assert!(msg_id.set_note(&alice, "gone").await.is_err());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_max_text_length() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        let bob = TestContext::new_bob().await;
+        let alice_chat = alice.create_chat(&bob).await;
+        let long_text = vec!["word"; 40].join(" ");
+
+        // Long outgoing texts are sent as attachment.
+        alice.set_config(Config::MaxTextLength, Some("100")).await?;
+        let sent = alice.send_text(alice_chat.id, &long_text).await;
+        let msg = Message::load_from_db(&alice, sent.sender_msg_id).await?;
+        assert_eq!(msg.get_viewtype(), Viewtype::File);
+        assert!(msg.get_text().unwrap().len() < long_text.len());
+        bob.recv_msg(&sent).await;
+        let msg = bob.get_last_msg().await;
+        assert_eq!(msg.get_viewtype(), Viewtype::File);
+        assert_eq!(msg.get_filename(), Some("message.txt".to_string()));
+        let file = msg.get_file(&bob).unwrap();
+        assert_eq!(async_std::fs::read_to_string(file).await?, long_text);
+
+        // Long incoming texts are truncated.
+        alice.set_config(Config::MaxTextLength, None).await?;
+        bob.set_config(Config::MaxTextLength, Some("50")).await?;
+        bob.recv_msg(&alice.send_text(alice_chat.id, &long_text).await)
+            .await;
+        let msg = bob.get_last_msg().await;
+        assert_eq!(msg.get_viewtype(), Viewtype::Text);
+        assert!(msg.get_text().unwrap().len() < long_text.len());
+        assert_eq!(msg.get_full_text(&bob).await?, Some(long_text));
+
+        // Short texts are not changed.
+        let msg = bob.send_text(bob.get_self_chat().await.id, "short").await;
+        let msg = Message::load_from_db(&bob, msg.sender_msg_id).await?;
+        assert_eq!(msg.get_full_text(&bob).await?, Some("short".to_string()));
+        Ok(())
+    }
+
     #[async_std::test]
     async fn test_markseen_msgs() -> Result<()> {
         let alice = TestContext::new_alice().await;
@@ -2724,6 +3253,39 @@ async fn test_markseen_msgs() -> Result<()> {
         Ok(())
     }
 
+    #[async_std::test]
+    async fn test_delete_msgs_batch() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        let bob = TestContext::new_bob().await;
+        let alice_chat = alice.create_chat(&bob).await;
+
+        let mut msg_ids = Vec::new();
+        for text in &["one", "two", "three"] {
+            bob.recv_msg(&alice.send_text(alice_chat.id, text).await)
+                .await;
+            msg_ids.push(bob.get_last_msg().await.id);
+        }
+        let bob_chat_id = bob.get_last_msg().await.chat_id;
+
+        delete_msgs(&bob, &msg_ids).await;
+        assert!(chat::get_chat_msgs(&bob, bob_chat_id, 0, None)
+            .await?
+            .is_empty());
+        for msg_id in &msg_ids {
+            let msg = Message::load_from_db(&bob, *msg_id).await?;
+            assert!(msg.chat_id.is_trash());
+        }
+        let jobs = bob
+            .sql
+            .count(
+                "SELECT COUNT(*) FROM jobs WHERE action=?;",
+                paramsv![Action::DeleteMsgOnImap],
+            )
+            .await?;
+        assert_eq!(jobs, 3);
+        Ok(())
+    }
+
     #[async_std::test]
     async fn test_get_state() -> Result<()> {
         let alice = TestContext::new_alice().await;
@@ -2826,4 +3388,29 @@ async fn test_is_bot() -> Result<()> {
 
         Ok(())
     }
+
+    #[async_std::test]
+    async fn test_msg_to_json() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        let bob = TestContext::new_bob().await;
+        let sent = alice
+            .send_text(alice.create_chat(&bob).await.id, "hello")
+            .await;
+        bob.recv_msg(&sent).await;
+        let msg = bob.get_last_msg().await;
+
+        let json = msg.id.to_json(&bob).await?;
+        let snapshot: MessageSnapshot = serde_json::from_str(&json)?;
+        assert_eq!(snapshot, msg.get_snapshot(&bob).await?);
+        assert_eq!(snapshot.id, msg.id);
+        assert_eq!(snapshot.chat_id, msg.chat_id);
+        assert_eq!(snapshot.from_id, msg.from_id);
+        assert_eq!(snapshot.viewtype, Viewtype::Text as u32);
+        assert_eq!(snapshot.text.as_deref(), Some("hello"));
+        assert!(snapshot.quoted_message_id.is_none());
+
+        let chat: chat::ChatInfo = serde_json::from_str(&msg.chat_id.to_json(&bob).await?)?;
+        assert_eq!(chat.id, msg.chat_id);
+        Ok(())
+    }
 }
@@ -0,0 +1,152 @@
+//! # Metrics
+//!
+//! Process-wide counters and gauges for server-side deployments such as bot fleets,
+//! available with the `metrics` feature. [`gather`] returns all metrics in the
+//! Prometheus text exposition format, to be served by the embedding application
+//! eg. on a `/metrics` HTTP endpoint.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+
+/// A monotonically increasing counter.
+#[derive(Debug)]
+pub struct Counter {
+    name: &'static str,
+    help: &'static str,
+    value: AtomicU64,
+}
+
+impl Counter {
+    const fn new(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            value: AtomicU64::new(0),
+        }
+    }
+
+    /// Increments the counter by one.
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    /// Increments the counter by `n`.
+    pub fn add(&self, n: u64) {
+        self.value.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Returns the current value of the counter.
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+/// A value per context which can go up and down.
+#[derive(Debug)]
+pub struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    values: Mutex<BTreeMap<u32, i64>>,
+}
+
+impl Gauge {
+    fn new(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            values: Default::default(),
+        }
+    }
+
+    /// Sets the value of the gauge for the context with the given ID.
+    pub fn set(&self, context_id: u32, value: i64) {
+        self.values.lock().unwrap().insert(context_id, value);
+    }
+
+    /// Returns the value of the gauge for the context with the given ID.
+    pub fn get(&self, context_id: u32) -> Option<i64> {
+        self.values.lock().unwrap().get(&context_id).copied()
+    }
+}
+
+/// Number of messages sent.
+pub static MSGS_SENT: Counter = Counter::new("deltachat_msgs_sent_total", "Messages sent.");
+
+/// Number of messages received.
+pub static MSGS_RECEIVED: Counter =
+    Counter::new("deltachat_msgs_received_total", "Messages received.");
+
+/// Number of IMAP reconnects.
+pub static IMAP_RECONNECTS: Counter =
+    Counter::new("deltachat_imap_reconnects_total", "IMAP reconnects.");
+
+/// Number of times an SQL statement was retried because the database was busy.
+pub static SQL_BUSY_RETRIES: Counter = Counter::new(
+    "deltachat_sql_busy_retries_total",
+    "SQL statements retried because the database was busy.",
+);
+
+/// Number of messages deleted because their ephemeral timer expired.
+pub static EPHEMERAL_DELETIONS: Counter = Counter::new(
+    "deltachat_ephemeral_deletions_total",
+    "Messages deleted because their ephemeral timer expired.",
+);
+
+/// Number of jobs waiting in the job queue, per context.
+pub static JOB_QUEUE_DEPTH: Lazy<Gauge> = Lazy::new(|| {
+    Gauge::new(
+        "deltachat_job_queue_depth",
+        "Jobs waiting in the job queue.",
+    )
+});
+
+/// Returns all metrics in the Prometheus text exposition format.
+pub fn gather() -> String {
+    let mut out = String::new();
+    for counter in &[
+        &MSGS_SENT,
+        &MSGS_RECEIVED,
+        &IMAP_RECONNECTS,
+        &SQL_BUSY_RETRIES,
+        &EPHEMERAL_DELETIONS,
+    ] {
+        writeln!(out, "# HELP {} {}", counter.name, counter.help).ok();
+        writeln!(out, "# TYPE {} counter", counter.name).ok();
+        writeln!(out, "{} {}", counter.name, counter.get()).ok();
+    }
+    let gauge = &*JOB_QUEUE_DEPTH;
+    writeln!(out, "# HELP {} {}", gauge.name, gauge.help).ok();
+    writeln!(out, "# TYPE {} gauge", gauge.name).ok();
+    for (context_id, value) in gauge.values.lock().unwrap().iter() {
+        writeln!(
+            out,
+            "{}{{context=\"{}\"}} {}",
+            gauge.name, context_id, value
+        )
+        .ok();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gather() {
+        MSGS_SENT.inc();
+        EPHEMERAL_DELETIONS.add(2);
+        JOB_QUEUE_DEPTH.set(1000, 3);
+        assert!(MSGS_SENT.get() >= 1);
+        assert_eq!(JOB_QUEUE_DEPTH.get(1000), Some(3));
+
+        let metrics = gather();
+        assert!(metrics.contains("# TYPE deltachat_msgs_sent_total counter\n"));
+        assert!(metrics.contains("# TYPE deltachat_job_queue_depth gauge\n"));
+        assert!(metrics.contains("deltachat_job_queue_depth{context=\"1000\"} 3\n"));
+    }
+}
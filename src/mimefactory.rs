@@ -1,6 +1,7 @@
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 
 use anyhow::{bail, ensure, format_err, Result};
+use async_std::task;
 use chrono::TimeZone;
 use lettre_email::{mime, Address, Header, MimeMultipartType, PartBuilder};
 
@@ -12,11 +13,11 @@
 use crate::context::{get_version_str, Context};
 use crate::dc_tools::IsNoneOrEmpty;
 use crate::dc_tools::{
-    dc_create_outgoing_rfc724_mid, dc_create_smeared_timestamp, dc_get_filebytes,
-    remove_subject_prefix, time,
+    dc_create_id, dc_create_outgoing_rfc724_mid, dc_create_smeared_timestamp, dc_get_filebytes,
+    dc_get_filesuffix_lc, dc_timestamp_to_str, remove_subject_prefix, time,
 };
 use crate::e2ee::EncryptHelper;
-use crate::ephemeral::Timer as EphemeralTimer;
+use crate::ephemeral::{Timer as EphemeralTimer, TimerStart as EphemeralTimerStart};
 use crate::format_flowed::{format_flowed, format_flowed_quote};
 use crate::html::new_html_mimepart;
 use crate::location;
@@ -34,6 +35,13 @@
 pub const RECOMMENDED_FILE_SIZE: u64 = 24 * 1024 * 1024 / 4 * 3;
 const UPPER_LIMIT_FILE_SIZE: u64 = 49 * 1024 * 1024 / 4 * 3;
 
+/// Optional headers which can be omitted using `Config::OmitHeaders`.
+const OMITTABLE_HEADERS: &[&str] = &[
+    "Chat-Version",
+    "Chat-Disposition-Notification-To",
+    "Auto-Submitted",
+];
+
 #[derive(Debug, Clone)]
 pub enum Loaded {
     Message { chat: Chat },
@@ -67,6 +75,12 @@ pub struct MimeFactory<'a> {
     req_mdn: bool,
     last_added_location_id: u32,
     attach_selfavatar: bool,
+
+    /// Send as classic email, see `Contact::set_plain_email`.
+    plain_email: bool,
+
+    /// Sign but do not encrypt, see `Contact::set_sign_only`.
+    sign_only: bool,
 }
 
 /// Result of rendering a message, ready to be submitted to a send job.
@@ -165,13 +179,30 @@ pub async fn from_msg(
                 )
                 .await?;
 
-            if !msg.is_system_message() && context.get_config_bool(Config::MdnsEnabled).await? {
+            if !msg.is_system_message() && chat.mdns_enabled(context).await? {
                 req_mdn = true;
             }
         }
+
+        let (plain_email, sign_only) = if chat.typ == Chattype::Single && !chat.is_self_talk() {
+            let mut plain_email = false;
+            let mut sign_only = false;
+            for contact_id in chat::get_chat_contacts(context, chat.id).await? {
+                let contact = Contact::load_from_db(context, contact_id).await?;
+                plain_email |= contact.is_plain_email();
+                sign_only |= contact.is_sign_only();
+            }
+            (plain_email, sign_only)
+        } else {
+            (false, false)
+        };
+        if plain_email {
+            req_mdn = false;
+        }
+        // Messages which are not stored yet, eg. when estimating the size, have no references.
         let (in_reply_to, references) = context
             .sql
-            .query_row(
+            .query_row_optional(
                 "SELECT mime_in_reply_to, mime_references FROM msgs WHERE id=?",
                 paramsv![msg.id],
                 |row| {
@@ -184,7 +215,8 @@ pub async fn from_msg(
                     ))
                 },
             )
-            .await?;
+            .await?
+            .unwrap_or_default();
 
         let default_str = stock_str::status_line(context).await;
         let factory = MimeFactory {
@@ -204,6 +236,8 @@ pub async fn from_msg(
             req_mdn,
             last_added_location_id: 0,
             attach_selfavatar,
+            plain_email,
+            sign_only,
         };
         Ok(factory)
     }
@@ -248,6 +282,8 @@ pub async fn from_mdn(
             req_mdn: false,
             last_added_location_id: 0,
             attach_selfavatar: false,
+            plain_email: false,
+            sign_only: false,
         };
 
         Ok(res)
@@ -315,10 +351,12 @@ fn should_force_plaintext(&self) -> bool {
                 if chat.is_protected() {
                     false
                 } else {
-                    self.msg
-                        .param
-                        .get_bool(Param::ForcePlaintext)
-                        .unwrap_or_default()
+                    self.sign_only
+                        || self
+                            .msg
+                            .param
+                            .get_bool(Param::ForcePlaintext)
+                            .unwrap_or_default()
                 }
             }
             Loaded::Mdn { .. } => true,
@@ -338,6 +376,7 @@ fn should_skip_autocrypt(&self) -> bool {
 
     async fn should_do_gossip(&self, context: &Context) -> Result<bool> {
         match &self.loaded {
+            Loaded::Message { .. } if self.plain_email => Ok(false),
             Loaded::Message { chat } => {
                 // beside key- and member-changes, force re-gossip every 48 hours
                 let gossiped_timestamp = chat.get_gossiped_timestamp(context).await?;
@@ -351,6 +390,47 @@ async fn should_do_gossip(&self, context: &Context) -> Result<bool> {
         }
     }
 
+    /// Returns the message referenced by the `In-Reply-To` header, if it still exists.
+    async fn parent_message(&self, context: &Context) -> Result<Option<Message>> {
+        let parent_mid = match self.in_reply_to.split_ascii_whitespace().next() {
+            Some(mid) => mid,
+            None => return Ok(None),
+        };
+        if let Some((_, _, msg_id)) = message::rfc724_mid_exists(context, parent_mid).await? {
+            let parent = Message::load_from_db(context, msg_id).await?;
+            if !parent.chat_id.is_trash() {
+                return Ok(Some(parent));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the parent message quoted below the text, as done by classic email clients.
+    ///
+    /// The quote of the parent message is included as well,
+    /// so that the correspondent sees the whole conversation.
+    async fn quoted_history(&self, context: &Context) -> Result<Option<String>> {
+        let parent = match self.parent_message(context).await? {
+            Some(parent) => parent,
+            None => return Ok(None),
+        };
+
+        let mut text = parent.get_text().unwrap_or_default();
+        if let Some(quote) = parent.quoted_text() {
+            text = format!("{}\n\n{}", format_flowed_quote(&quote), text);
+        }
+        if text.is_empty() {
+            return Ok(None);
+        }
+        let author = Contact::load_from_db(context, parent.from_id).await?;
+        Ok(Some(format!(
+            "\r\n\r\nOn {}, {} wrote:\r\n{}",
+            dc_timestamp_to_str(parent.get_timestamp()),
+            author.get_name_n_addr(),
+            format_flowed_quote(&text)
+        )))
+    }
+
     fn grpimage(&self) -> Option<String> {
         match &self.loaded {
             Loaded::Message { chat } => {
@@ -382,7 +462,11 @@ fn grpimage(&self) -> Option<String> {
     }
 
     async fn subject_str(&self, context: &Context) -> anyhow::Result<String> {
-        let quoted_msg_subject = self.msg.quoted_message(context).await?.map(|m| m.subject);
+        let mut quoted_msg_subject = self.msg.quoted_message(context).await?.map(|m| m.subject);
+        if self.plain_email && quoted_msg_subject.is_none_or_empty() {
+            // classic email clients thread by subject, so reply to the subject of the parent
+            quoted_msg_subject = self.parent_message(context).await?.map(|m| m.subject);
+        }
 
         let subject = match self.loaded {
             Loaded::Message { ref chat } => {
@@ -394,32 +478,38 @@ async fn subject_str(&self, context: &Context) -> anyhow::Result<String> {
                     return Ok(self.msg.subject.clone());
                 }
 
-                if chat.typ == Chattype::Group && quoted_msg_subject.is_none_or_empty() {
-                    // If we have a `quoted_msg_subject`, we use the subject of the quoted message
-                    // instead of the group name
-                    let re = if self.in_reply_to.is_empty() {
-                        ""
+                let subject =
+                    if chat.typ == Chattype::Group && quoted_msg_subject.is_none_or_empty() {
+                        // If we have a `quoted_msg_subject`, we use the subject of the quoted message
+                        // instead of the group name
+                        let re = if self.in_reply_to.is_empty() {
+                            ""
+                        } else {
+                            "Re: "
+                        };
+                        format!("{}{}", re, chat.name)
                     } else {
-                        "Re: "
-                    };
-                    return Ok(format!("{}{}", re, chat.name));
-                }
-
-                let parent_subject = if quoted_msg_subject.is_none_or_empty() {
-                    chat.param.get(Param::LastSubject)
-                } else {
-                    quoted_msg_subject.as_deref()
-                };
-
-                if let Some(last_subject) = parent_subject {
-                    format!("Re: {}", remove_subject_prefix(last_subject))
-                } else {
-                    let self_name = match context.get_config(Config::Displayname).await? {
-                        Some(name) => name,
-                        None => context.get_config(Config::Addr).await?.unwrap_or_default(),
+                        let parent_subject = if quoted_msg_subject.is_none_or_empty() {
+                            chat.param.get(Param::LastSubject)
+                        } else {
+                            quoted_msg_subject.as_deref()
+                        };
+
+                        if let Some(last_subject) = parent_subject {
+                            format!("Re: {}", remove_subject_prefix(last_subject))
+                        } else {
+                            let self_name = match context.get_config(Config::Displayname).await? {
+                                Some(name) => name,
+                                None => context.get_config(Config::Addr).await?.unwrap_or_default(),
+                            };
+
+                            stock_str::subject_for_new_contact(context, self_name).await
+                        }
                     };
 
-                    stock_str::subject_for_new_contact(context, self_name).await
+                match chat.get_subject_prefix() {
+                    Some(prefix) if !subject.contains(prefix) => format!("{} {}", prefix, subject),
+                    _ => subject,
                 }
             }
             Loaded::Mdn { .. } => stock_str::read_rcpt(context).await,
@@ -436,6 +526,7 @@ pub fn recipients(&self) -> Vec<String> {
     }
 
     pub async fn render(mut self, context: &Context) -> Result<RenderedEmail> {
+        let _slot = context.render_pool.acquire().await;
         let mut headers: MessageHeaders = Default::default();
 
         let from = Address::new_mailbox_with_name(
@@ -475,8 +566,13 @@ pub async fn render(mut self, context: &Context) -> Result<RenderedEmail> {
                 .push(Header::new("In-Reply-To".into(), self.in_reply_to.clone()));
         }
 
+        let date_timestamp = if context.get_config_bool(Config::CoarseDate).await? {
+            self.timestamp - self.timestamp.rem_euclid(60)
+        } else {
+            self.timestamp
+        };
         let date = chrono::Utc
-            .from_local_datetime(&chrono::NaiveDateTime::from_timestamp(self.timestamp, 0))
+            .from_local_datetime(&chrono::NaiveDateTime::from_timestamp(date_timestamp, 0))
             .unwrap()
             .to_rfc2822();
 
@@ -550,6 +646,19 @@ pub async fn render(mut self, context: &Context) -> Result<RenderedEmail> {
                 "Ephemeral-Timer".to_string(),
                 duration.to_string(),
             ));
+            if self.msg.chat_id.get_ephemeral_timer_start(context).await?
+                == EphemeralTimerStart::Delivery
+            {
+                headers.protected.push(Header::new(
+                    "Ephemeral-Timer-Start".to_string(),
+                    "delivery".to_string(),
+                ));
+            }
+        }
+        if self.msg.view_once {
+            headers
+                .protected
+                .push(Header::new("Chat-View-Once".to_string(), "1".to_string()));
         }
 
         headers.unprotected.push(Header::new(
@@ -573,18 +682,35 @@ pub async fn render(mut self, context: &Context) -> Result<RenderedEmail> {
 
         let mut is_gossiped = false;
 
-        let (main_part, parts) = match self.loaded {
+        let peerstates = self.peerstates_for_recipients(context).await?;
+        let should_encrypt =
+            encrypt_helper.should_encrypt(context, e2ee_guaranteed, &peerstates)?;
+        let is_encrypted = should_encrypt && !force_plaintext;
+
+        // The attachment of unencrypted and unsigned messages is encoded
+        // directly into the rendered message, see `splice_streamed_file()`.
+        let stream_attachment = !is_encrypted && !self.sign_only;
+        let (main_part, parts, streamed_file) = match self.loaded {
             Loaded::Message { .. } => {
-                self.render_message(context, &mut headers, &grpimage)
+                self.render_message(context, &mut headers, &grpimage, stream_attachment)
                     .await?
             }
-            Loaded::Mdn { .. } => (self.render_mdn(context).await?, Vec::new()),
+            Loaded::Mdn { .. } => (self.render_mdn(context).await?, Vec::new(), None),
         };
 
-        let peerstates = self.peerstates_for_recipients(context).await?;
-        let should_encrypt =
-            encrypt_helper.should_encrypt(context, e2ee_guaranteed, &peerstates)?;
-        let is_encrypted = should_encrypt && !force_plaintext;
+        if self.plain_email {
+            headers.protected.retain(|header| !is_chat_header(header));
+            headers.unprotected.retain(|header| !is_chat_header(header));
+            headers.hidden.retain(|header| !is_chat_header(header));
+        }
+
+        let omitted_headers = omitted_headers(context).await?;
+        if !omitted_headers.is_empty() {
+            let keep = |header: &Header| !omitted_headers.contains(&header.name.to_lowercase());
+            headers.protected.retain(keep);
+            headers.unprotected.retain(keep);
+            headers.hidden.retain(keep);
+        }
 
         let message = if parts.is_empty() {
             // Single part, render as regular message.
@@ -693,6 +819,36 @@ pub async fn render(mut self, context: &Context) -> Result<RenderedEmail> {
                     .child(message.build())
             };
 
+            let message = if self.sign_only {
+                // Wrap the message into a PGP/MIME signed message, see RFC 3156.
+                let message = message.build();
+                let signature = encrypt_helper
+                    .sign(context, message.as_string().into_bytes())
+                    .await?;
+
+                PartBuilder::new()
+                    .header((
+                        "Content-Type".to_string(),
+                        "multipart/signed; protocol=\"application/pgp-signature\"; micalg=pgp-sha256"
+                            .to_string(),
+                    ))
+                    .child(message)
+                    .child(
+                        PartBuilder::new()
+                            .content_type(
+                                &"application/pgp-signature; name=\"signature.asc\""
+                                    .parse::<mime::Mime>()
+                                    .unwrap(),
+                            )
+                            .header(("Content-Description", "OpenPGP digital signature"))
+                            .header(("Content-Disposition", "attachment; filename=\"signature.asc\";"))
+                            .body(signature)
+                            .build(),
+                    )
+            } else {
+                message
+            };
+
             // Store protected headers in the outer message.
             headers
                 .protected
@@ -711,8 +867,17 @@ pub async fn render(mut self, context: &Context) -> Result<RenderedEmail> {
             ..
         } = self;
 
+        let message = task::spawn_blocking(move || {
+            let message = outer_message.build().as_string();
+            match streamed_file {
+                Some(file) => splice_streamed_file(message, &file),
+                None => Ok(message.into_bytes()),
+            }
+        })
+        .await?;
+
         Ok(RenderedEmail {
-            message: outer_message.build().as_string().into_bytes(),
+            message,
             // envelope: Envelope::new,
             is_encrypted,
             is_gossiped,
@@ -768,7 +933,8 @@ async fn render_message(
         context: &Context,
         headers: &mut MessageHeaders,
         grpimage: &Option<String>,
-    ) -> Result<(PartBuilder, Vec<PartBuilder>)> {
+        stream_attachment: bool,
+    ) -> Result<(PartBuilder, Vec<PartBuilder>, Option<StreamedFile>)> {
         let chat = match &self.loaded {
             Loaded::Message { chat } => chat,
             Loaded::Mdn { .. } => bail!("Attempt to render MDN as a message"),
@@ -781,6 +947,15 @@ async fn render_message(
             headers
                 .protected
                 .push(Header::new("Chat-Verified".to_string(), "1".to_string()));
+
+            if let Some((epoch, secret)) = chat.get_group_secret() {
+                headers
+                    .protected
+                    .push(Header::new("Chat-Group-Epoch".into(), epoch.to_string()));
+                headers
+                    .protected
+                    .push(Header::new("Chat-Group-Secret".into(), secret.to_string()));
+            }
         }
 
         if chat.typ == Chattype::Group {
@@ -861,6 +1036,26 @@ async fn render_message(
                     "ephemeral-timer-changed".to_string(),
                 ));
             }
+            SystemMessage::GroupRekeyed => {
+                headers.protected.push(Header::new(
+                    "Chat-Content".to_string(),
+                    "group-rekeyed".to_string(),
+                ));
+            }
+            SystemMessage::DeviceSettingsChanged => {
+                headers.protected.push(Header::new(
+                    "Chat-Content".to_string(),
+                    "device-settings-changed".to_string(),
+                ));
+                headers.protected.push(Header::new(
+                    "Chat-Delete-Device-After".to_string(),
+                    self.msg.param.get(Param::Arg).unwrap_or("0").to_string(),
+                ));
+                headers.protected.push(Header::new(
+                    "Chat-Delete-Server-After".to_string(),
+                    self.msg.param.get(Param::Arg2).unwrap_or("0").to_string(),
+                ));
+            }
             SystemMessage::LocationOnly => {
                 // This should prevent automatic replies,
                 // such as non-delivery reports.
@@ -943,7 +1138,8 @@ async fn render_message(
             };
             meta.param.set(Param::File, grpimage);
 
-            let (mail, filename_as_sent) = build_body_file(context, &meta, "group-image").await?;
+            let (mail, filename_as_sent, _) =
+                build_body_file(context, &meta, "group-image", false).await?;
             meta_part = Some(mail);
             headers
                 .protected
@@ -1013,15 +1209,20 @@ async fn render_message(
             }
         };
 
-        let quoted_text = self
-            .msg
-            .quoted_text()
-            .map(|quote| format_flowed_quote(&quote) + "\r\n\r\n");
+        let (quoted_text, quoted_history) = if self.plain_email {
+            (None, self.quoted_history(context).await?)
+        } else {
+            let quoted_text = self
+                .msg
+                .quoted_text()
+                .map(|quote| format_flowed_quote(&quote) + "\r\n\r\n");
+            (quoted_text, None)
+        };
         let flowed_text = format_flowed(final_text);
 
         let footer = &self.selfstatus;
         let message_text = format!(
-            "{}{}{}{}{}{}",
+            "{}{}{}{}{}{}{}",
             fwdhint.unwrap_or_default(),
             quoted_text.unwrap_or_default(),
             escape_message_footer_marks(&flowed_text),
@@ -1031,7 +1232,8 @@ async fn render_message(
                 ""
             },
             if !footer.is_empty() { "-- \r\n" } else { "" },
-            footer
+            footer,
+            quoted_history.unwrap_or_default()
         );
 
         // Message is sent as text/plain, with charset = utf-8
@@ -1062,6 +1264,7 @@ async fn render_message(
         }
 
         // add attachment part
+        let mut streamed_file = None;
         if chat::msgtype_has_file(self.msg.viewtype) {
             if !is_file_size_okay(context, self.msg).await? {
                 bail!(
@@ -1069,8 +1272,10 @@ async fn render_message(
                     RECOMMENDED_FILE_SIZE / 1_000_000,
                 );
             } else {
-                let (file_part, _) = build_body_file(context, self.msg, "").await?;
+                let (file_part, _, file) =
+                    build_body_file(context, self.msg, "", stream_attachment).await?;
                 parts.push(file_part);
+                streamed_file = file;
             }
         }
 
@@ -1106,7 +1311,7 @@ async fn render_message(
             }
         }
 
-        Ok((main_part, parts))
+        Ok((main_part, parts, streamed_file))
     }
 
     /// Render an MDN
@@ -1207,17 +1412,101 @@ fn wrapped_base64_encode(buf: &[u8]) -> String {
         .join("\r\n")
 }
 
+/// Returns the length of the output of [`wrapped_base64_encode`] for `size` bytes of input.
+fn wrapped_base64_len(size: u64) -> u64 {
+    let encoded = (size + 2) / 3 * 4;
+    if encoded == 0 {
+        0
+    } else {
+        encoded + 2 * ((encoded - 1) / 78)
+    }
+}
+
+/// Reads the file at `path` in chunks and writes it to `out` encoded like
+/// [`wrapped_base64_encode`], so that neither the file nor its encoding
+/// has to be kept in memory.
+fn write_wrapped_base64_file(path: &std::path::Path, out: &mut impl std::io::Write) -> Result<()> {
+    use std::io::Read;
+
+    // 117 bytes are encoded to 156 characters, which are two full lines,
+    // so the encoded chunks can be joined without rewrapping.
+    const CHUNK_SIZE: usize = 117 * 8192;
+
+    let mut file = std::fs::File::open(path)?;
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+    let mut first = true;
+    loop {
+        chunk.clear();
+        file.by_ref()
+            .take(CHUNK_SIZE as u64)
+            .read_to_end(&mut chunk)?;
+        if chunk.is_empty() {
+            break;
+        }
+        if !first {
+            out.write_all(b"\r\n")?;
+        }
+        first = false;
+        out.write_all(wrapped_base64_encode(&chunk).as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Encodes the file at `path` like [`wrapped_base64_encode`].
+///
+/// Used for attachments that are encrypted or signed and thus
+/// cannot be streamed into the rendered message.
+fn wrapped_base64_encode_file(path: &std::path::Path) -> Result<String> {
+    let mut encoded = Vec::new();
+    write_wrapped_base64_file(path, &mut encoded)?;
+    Ok(String::from_utf8(encoded)?)
+}
+
+/// Attachment that is encoded directly into the rendered message.
+#[derive(Debug)]
+struct StreamedFile {
+    /// Unique text used as body of the attachment part
+    /// until the message is rendered.
+    placeholder: String,
+
+    path: std::path::PathBuf,
+}
+
+/// Replaces the placeholder of `file` in the rendered `message` by the encoded file,
+/// which is written directly into the returned buffer.
+fn splice_streamed_file(message: String, file: &StreamedFile) -> Result<Vec<u8>> {
+    let pos = message
+        .find(&file.placeholder)
+        .ok_or_else(|| format_err!("attachment placeholder not found in rendered message"))?;
+    let (head, tail) = message.split_at(pos);
+    let tail = tail.get(file.placeholder.len()..).unwrap_or_default();
+
+    let file_size = std::fs::metadata(&file.path)?.len();
+    let encoded_len = usize::try_from(wrapped_base64_len(file_size))?;
+    let mut out = Vec::with_capacity(head.len() + encoded_len + tail.len());
+    out.extend_from_slice(head.as_bytes());
+    write_wrapped_base64_file(&file.path, &mut out)?;
+    out.extend_from_slice(tail.as_bytes());
+    Ok(out)
+}
+
 async fn build_body_file(
     context: &Context,
     msg: &Message,
     base_name: &str,
-) -> Result<(PartBuilder, String)> {
-    let blob = msg
+    stream: bool,
+) -> Result<(PartBuilder, String, Option<StreamedFile>)> {
+    // Not using a blob here as the file may be outside the blob directory
+    // until the message is sent, see `Config::PassthroughAttachments`.
+    let path = msg
         .param
-        .get_blob(Param::File, context, true)
-        .await?
+        .get_path(Param::File, context)?
         .ok_or_else(|| format_err!("msg has no filename"))?;
-    let suffix = blob.suffix().unwrap_or("dat");
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let suffix = dc_get_filesuffix_lc(&file_name).unwrap_or_else(|| "dat".to_string());
 
     // Get file name to use for sending.  For privacy purposes, we do
     // not transfer the original filenames eg. for images; these names
@@ -1248,14 +1537,14 @@ async fn build_body_file(
                 .to_string(),
             &suffix
         ),
-        _ => blob.as_file_name().to_string(),
+        _ => file_name.clone(),
     };
 
     /* check mimetype */
     let mimetype: mime::Mime = match msg.param.get(Param::MimeType) {
         Some(mtype) => mtype.parse()?,
         None => {
-            if let Some(res) = message::guess_msgtype_from_suffix(blob.as_rel_path()) {
+            if let Some(res) = message::guess_msgtype_from_suffix(&path) {
                 res.1.parse()?
             } else {
                 mime::APPLICATION_OCTET_STREAM
@@ -1273,16 +1562,25 @@ async fn build_body_file(
         maybe_encode_words(&filename_to_send)
     );
 
-    let body = std::fs::read(blob.to_abs_path())?;
-    let encoded_body = wrapped_base64_encode(&body);
+    let (body, streamed_file) = if stream {
+        let file = StreamedFile {
+            placeholder: format!("attachment-{}-{}", dc_create_id(), dc_create_id()),
+            path: path.into(),
+        };
+        (file.placeholder.clone(), Some(file))
+    } else {
+        let encoded_body =
+            task::spawn_blocking(move || wrapped_base64_encode_file(path.as_ref())).await?;
+        (encoded_body, None)
+    };
 
     let mail = PartBuilder::new()
         .content_type(&mimetype)
         .header(("Content-Disposition", cd_value))
         .header(("Content-Transfer-Encoding", "base64"))
-        .body(encoded_body);
+        .body(body);
 
-    Ok((mail, filename_to_send))
+    Ok((mail, filename_to_send, streamed_file))
 }
 
 fn build_selfavatar_file(context: &Context, path: &str) -> Result<String> {
@@ -1299,6 +1597,29 @@ fn recipients_contain_addr(recipients: &[(String, String)], addr: &str) -> bool
         .any(|(_, cur)| cur.to_lowercase() == addr_lc)
 }
 
+/// Returns the lowercased names of the headers omitted using `Config::OmitHeaders`.
+async fn omitted_headers(context: &Context) -> Result<Vec<String>> {
+    let omit_headers = context
+        .get_config(Config::OmitHeaders)
+        .await?
+        .unwrap_or_default();
+    Ok(omit_headers
+        .split(',')
+        .map(|name| name.trim().to_lowercase())
+        .filter(|name| {
+            OMITTABLE_HEADERS
+                .iter()
+                .any(|omittable| omittable.eq_ignore_ascii_case(name))
+        })
+        .collect())
+}
+
+/// Returns true for headers only understood by chat clients,
+/// which are not sent in classic email mode.
+fn is_chat_header(header: &Header) -> bool {
+    header.name.to_lowercase().starts_with("chat-") || header.name == "Ephemeral-Timer"
+}
+
 async fn is_file_size_okay(context: &Context, msg: &Message) -> Result<bool> {
     match msg.param.get_path(Param::File, context)? {
         Some(path) => {
@@ -1439,6 +1760,51 @@ fn test_wrapped_base64_encode() {
         assert_eq!(wrapped_base64_encode(input), output);
     }
 
+    #[test]
+    fn test_wrapped_base64_encode_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("file.bin");
+        let data: Vec<u8> = (0..2_000_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &data)?;
+        assert_eq!(
+            wrapped_base64_encode_file(&path)?,
+            wrapped_base64_encode(&data)
+        );
+
+        std::fs::write(&path, b"")?;
+        assert_eq!(wrapped_base64_encode_file(&path)?, "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_wrapped_base64_len() {
+        for size in &[0, 1, 2, 3, 57, 58, 59, 60, 117, 118, 1000] {
+            let data = vec![0x42; *size];
+            assert_eq!(
+                wrapped_base64_len(*size as u64),
+                wrapped_base64_encode(&data).len() as u64
+            );
+        }
+    }
+
+    #[async_std::test]
+    async fn test_render_streamed_attachment() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat = t.create_chat_with_contact("bob", "bob@example.net").await;
+
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        let file = t.get_blobdir().join("data.bin");
+        async_std::fs::write(&file, &data).await?;
+        let mut msg = Message::new(Viewtype::File);
+        msg.set_file(file.to_str().unwrap(), None);
+        let sent = t.send_msg(chat.id, &mut msg).await;
+
+        let payload = sent.payload();
+        assert!(!payload.contains("attachment-"));
+        assert!(payload.contains(&wrapped_base64_encode(&data)));
+        Ok(())
+    }
+
     #[test]
     fn test_needs_encoding() {
         assert!(!needs_encoding(""));
@@ -1522,6 +1888,125 @@ async fn test_subject_outgoing() {
         assert_eq!(first_subject_str(t).await, "Message from Alice");
     }
 
+    #[async_std::test]
+    async fn test_subject_explicit_and_prefix() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat_id = t
+            .create_chat_with_contact("Dave", "dave@example.com")
+            .await
+            .id;
+        chat_id.set_subject_prefix(&t, Some("[ticket-42]")).await?;
+        assert_eq!(
+            Chat::load_from_db(&t, chat_id).await?.get_subject_prefix(),
+            Some("[ticket-42]")
+        );
+
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("Hi".to_string()));
+        chat::prepare_msg(&t, chat_id, &mut msg).await?;
+        let mf = MimeFactory::from_msg(&t, &msg, false).await?;
+        assert_eq!(
+            mf.subject_str(&t).await?,
+            "[ticket-42] Message from alice@example.com"
+        );
+
+        // explicitly set subjects are used as is
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("Hi".to_string()));
+        msg.set_subject("Invoice for March".to_string());
+        chat::prepare_msg(&t, chat_id, &mut msg).await?;
+        let mf = MimeFactory::from_msg(&t, &msg, false).await?;
+        assert_eq!(mf.subject_str(&t).await?, "Invoice for March");
+
+        chat_id.set_subject_prefix(&t, None).await?;
+        let mut msg = Message::new(Viewtype::Text);
+        msg.set_text(Some("Hi".to_string()));
+        chat::prepare_msg(&t, chat_id, &mut msg).await?;
+        let mf = MimeFactory::from_msg(&t, &msg, false).await?;
+        assert_eq!(mf.subject_str(&t).await?, "Message from alice@example.com");
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_omit_headers() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat = t.create_chat_with_contact("Bob", "bob@example.net").await;
+        let payload = t.send_text(chat.id, "hi").await.payload();
+        assert!(payload.contains("Chat-Version:"));
+
+        // Required headers cannot be omitted.
+        t.set_config(Config::OmitHeaders, Some("chat-version, From,Date"))
+            .await?;
+        t.set_config(Config::CoarseDate, Some("1")).await?;
+        let payload = t.send_text(chat.id, "hi").await.payload();
+        assert!(!payload.contains("Chat-Version:"));
+        assert!(payload.contains("From:"));
+        let date = payload
+            .lines()
+            .find(|line| line.starts_with("Date: "))
+            .unwrap();
+        assert!(date.ends_with(":00 +0000"));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_plain_email_mode() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat = t.create_chat_with_contact("Bob", "bob@example.net").await;
+        let contact_id = chat::get_chat_contacts(&t, chat.id).await?[0];
+        let mut contact = Contact::load_from_db(&t, contact_id).await?;
+        assert!(!contact.is_plain_email());
+        contact.set_plain_email(&t, true).await?;
+        assert!(Contact::load_from_db(&t, contact_id)
+            .await?
+            .is_plain_email());
+
+        t.send_text(chat.id, "first message").await;
+        let sent = t.send_text(chat.id, "second message").await;
+        let payload = sent.payload();
+        assert!(!payload.contains("Chat-Version:"));
+        assert!(!payload.contains("Chat-Disposition-Notification-To:"));
+        assert!(payload.contains("In-Reply-To:"));
+        assert_eq!(
+            Message::load_from_db(&t, sent.sender_msg_id)
+                .await?
+                .get_subject(),
+            "Re: Message from alice@example.com"
+        );
+        assert!(payload.contains("wrote:"));
+        assert!(payload.contains("> first message"));
+
+        contact.set_plain_email(&t, false).await?;
+        let payload = t.send_text(chat.id, "third message").await.payload();
+        assert!(payload.contains("Chat-Version:"));
+        assert!(!payload.contains("wrote:"));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_sign_only_mode() -> Result<()> {
+        let alice = TestContext::new_alice().await;
+        let bob = TestContext::new_bob().await;
+        let chat = alice.create_chat(&bob).await;
+        let contact_id = chat::get_chat_contacts(&alice, chat.id).await?[0];
+        let mut contact = Contact::load_from_db(&alice, contact_id).await?;
+        assert!(!contact.is_sign_only());
+        contact.set_sign_only(&alice, true).await?;
+
+        let sent = alice.send_text(chat.id, "signed message").await;
+        let payload = sent.payload();
+        assert!(payload.contains("multipart/signed"));
+        assert!(payload.contains("-----BEGIN PGP SIGNATURE-----"));
+        assert!(!payload.contains("-----BEGIN PGP MESSAGE-----"));
+
+        bob.recv_msg(&sent).await;
+        let msg = bob.get_last_msg().await;
+        assert_eq!(msg.get_text(), Some("signed message".to_string()));
+        assert!(msg.is_signed_only());
+        assert!(!msg.get_showpadlock());
+        Ok(())
+    }
+
     #[async_std::test]
     async fn test_subject_unicode() {
         // 4. Receive messages with unicode characters and make sure that we do not panic (we do not care about the result)
@@ -21,6 +21,7 @@
 use crate::headerdef::{HeaderDef, HeaderDefMap};
 use crate::key::Fingerprint;
 use crate::location;
+use crate::media;
 use crate::message;
 use crate::param::{Param, Params};
 use crate::peerstate::Peerstate;
@@ -54,6 +55,10 @@ pub struct MimeMessage {
     /// this set is empty.
     pub signatures: HashSet<Fingerprint>,
 
+    /// True if a message is not encrypted, but PGP/MIME signed
+    /// with a valid signature of the sender.
+    pub(crate) signed_only: bool,
+
     pub gossipped_addr: HashSet<String>,
     pub is_forwarded: bool,
     pub is_system_message: SystemMessage,
@@ -122,6 +127,19 @@ pub enum SystemMessage {
     // Chat protection state changed
     ChatProtectionEnabled = 11,
     ChatProtectionDisabled = 12,
+
+    /// Device settings sent to the other devices of the user.
+    DeviceSettingsChanged = 13,
+
+    /// Local info message counting messages deleted by the ephemeral timer.
+    MessagesDisappeared = 14,
+
+    /// Local info message about an ephemeral timer change waiting for approval.
+    EphemeralTimerChangePending = 15,
+
+    /// Hidden message sending a new group secret to the remaining members,
+    /// see `Config::GroupRekeying`.
+    GroupRekeyed = 16,
 }
 
 impl Default for SystemMessage {
@@ -175,6 +193,8 @@ pub async fn from_bytes(context: &Context, body: &[u8]) -> Result<Self> {
         // remove headers that are allowed _only_ in the encrypted part
         headers.remove("secure-join-fingerprint");
         headers.remove("chat-verified");
+        headers.remove("chat-group-epoch");
+        headers.remove("chat-group-secret");
 
         // Memory location for a possible decrypted message.
         let mut mail_raw = Vec::new();
@@ -249,6 +269,18 @@ pub async fn from_bytes(context: &Context, body: &[u8]) -> Result<Self> {
                 }
             };
 
+        let signed_only = if warn_empty_signature {
+            false
+        } else {
+            match e2ee::validate_signed_only(context, &mail).await {
+                Ok(signatures) => !signatures.is_empty(),
+                Err(err) => {
+                    warn!(context, "signature validation failed: {}", err);
+                    false
+                }
+            }
+        };
+
         let mut parser = MimeMessage {
             parts: Vec::new(),
             header: headers,
@@ -259,6 +291,7 @@ pub async fn from_bytes(context: &Context, body: &[u8]) -> Result<Self> {
 
             // only non-empty if it was a valid autocrypt message
             signatures,
+            signed_only,
             gossipped_addr,
             is_forwarded: false,
             mdn_reports: Vec::new(),
@@ -318,6 +351,10 @@ fn parse_system_message_headers(&mut self, context: &Context) {
                 self.is_system_message = SystemMessage::ChatProtectionEnabled;
             } else if value == "protection-disabled" {
                 self.is_system_message = SystemMessage::ChatProtectionDisabled;
+            } else if value == "device-settings-changed" {
+                self.is_system_message = SystemMessage::DeviceSettingsChanged;
+            } else if value == "group-rekeyed" {
+                self.is_system_message = SystemMessage::GroupRekeyed;
             }
         }
     }
@@ -988,6 +1025,16 @@ async fn do_add_single_file_part(
                 part.param.set_int(Param::Width, width as i32);
                 part.param.set_int(Param::Height, height as i32);
             }
+        } else if matches!(
+            msg_type,
+            Viewtype::Audio | Viewtype::Voice | Viewtype::Video
+        ) {
+            // may be overwritten by the `Chat-Duration` header later
+            if let Some(duration_ms) =
+                media::get_duration_ms(&mut std::io::Cursor::new(decoded_data))
+            {
+                part.param.set_int(Param::Duration, duration_ms as i32);
+            }
         }
 
         part.typ = msg_type;
@@ -1004,6 +1051,8 @@ async fn do_add_single_file_part(
     fn do_add_single_part(&mut self, mut part: Part) {
         if self.was_encrypted() {
             part.param.set_int(Param::GuaranteeE2ee, 1);
+        } else if self.signed_only {
+            part.param.set_int(Param::SignedOnly, 1);
         }
         self.parts.push(part);
     }
@@ -0,0 +1,205 @@
+//! # Network policy
+//!
+//! On metered connections, eg. mobile data, the UI sets [`NetworkPolicy::Metered`].
+//! Messages larger than [`METERED_DOWNLOAD_LIMIT`] are then not downloaded but deferred
+//! until the policy is set back to [`NetworkPolicy::Unrestricted`]. Size and sender of
+//! deferred messages are known from the prefetched headers, so the UI can list them
+//! using [`Context::get_deferred_downloads`] and download single messages anyway
+//! using [`Context::download_deferred`].
+
+use anyhow::{ensure, Result};
+use serde::Serialize;
+
+use crate::context::Context;
+
+/// Messages larger than this are not downloaded on metered connections.
+pub const METERED_DOWNLOAD_LIMIT: u32 = 256 * 1024;
+
+/// Kind of network connection, set by the UI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum NetworkPolicy {
+    /// Download all messages.
+    Unrestricted = 0,
+
+    /// Defer downloading messages larger than [`METERED_DOWNLOAD_LIMIT`].
+    Metered = 1,
+}
+
+impl Default for NetworkPolicy {
+    fn default() -> Self {
+        NetworkPolicy::Unrestricted
+    }
+}
+
+/// A message whose download was deferred because of [`NetworkPolicy::Metered`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeferredDownload {
+    /// ID to pass to [`Context::download_deferred`].
+    pub id: u32,
+
+    /// Content of the `From:` header.
+    pub sender: String,
+
+    /// Size of the message in bytes.
+    pub size: u32,
+}
+
+impl Context {
+    /// Sets the network policy, typically called by the UI when the connection changes.
+    ///
+    /// When the policy is set to [`NetworkPolicy::Unrestricted`],
+    /// deferred messages are downloaded.
+    pub async fn set_network_policy(&self, policy: NetworkPolicy) {
+        let old_policy = std::mem::replace(&mut *self.network_policy.write().await, policy);
+        if old_policy != policy && policy == NetworkPolicy::Unrestricted {
+            self.maybe_network().await;
+        }
+    }
+
+    /// Returns the network policy set using [`Context::set_network_policy`].
+    pub async fn get_network_policy(&self) -> NetworkPolicy {
+        *self.network_policy.read().await
+    }
+
+    /// Returns the messages whose download was deferred.
+    pub async fn get_deferred_downloads(&self) -> Result<Vec<DeferredDownload>> {
+        self.sql
+            .query_map(
+                "SELECT id, sender, size FROM deferred_downloads ORDER BY id;",
+                paramsv![],
+                |row| {
+                    Ok(DeferredDownload {
+                        id: row.get(0)?,
+                        sender: row.get(1)?,
+                        size: row.get(2)?,
+                    })
+                },
+                |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+            )
+            .await
+    }
+
+    /// Downloads a deferred message regardless of the network policy.
+    pub async fn download_deferred(&self, id: u32) -> Result<()> {
+        let updated = self
+            .sql
+            .execute(
+                "UPDATE deferred_downloads SET forced=1 WHERE id=?;",
+                paramsv![id],
+            )
+            .await?;
+        ensure!(updated > 0, "no deferred download with id {}", id);
+        self.maybe_network().await;
+        Ok(())
+    }
+}
+
+/// Returns true if a message of the given size should not be downloaded now.
+pub(crate) async fn should_defer(context: &Context, size: Option<u32>) -> bool {
+    context.get_network_policy().await == NetworkPolicy::Metered
+        && size.unwrap_or_default() > METERED_DOWNLOAD_LIMIT
+}
+
+/// Remembers a message that is not downloaded because of the network policy.
+pub(crate) async fn defer_download(
+    context: &Context,
+    folder: &str,
+    uid: u32,
+    size: u32,
+    sender: &str,
+) -> Result<()> {
+    context
+        .sql
+        .execute(
+            "INSERT OR IGNORE INTO deferred_downloads (folder, uid, size, sender)
+             VALUES (?, ?, ?, ?);",
+            paramsv![folder, uid, size, sender],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Returns the UIDs of deferred messages in `folder` which should be downloaded now.
+pub(crate) async fn get_due_downloads(context: &Context, folder: &str) -> Result<Vec<u32>> {
+    let metered = context.get_network_policy().await == NetworkPolicy::Metered;
+    context
+        .sql
+        .query_map(
+            "SELECT uid FROM deferred_downloads
+             WHERE folder=? AND (forced=1 OR NOT ?)
+             ORDER BY uid;",
+            paramsv![folder, metered],
+            |row| row.get::<_, u32>(0),
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await
+}
+
+/// Forgets deferred messages in `folder`, either after downloading them
+/// or because their UIDs became invalid. `None` forgets all messages in the folder.
+pub(crate) async fn remove_deferred(
+    context: &Context,
+    folder: &str,
+    uids: Option<&[u32]>,
+) -> Result<()> {
+    match uids {
+        Some(uids) => {
+            for uid in uids {
+                context
+                    .sql
+                    .execute(
+                        "DELETE FROM deferred_downloads WHERE folder=? AND uid=?;",
+                        paramsv![folder, *uid],
+                    )
+                    .await?;
+            }
+        }
+        None => {
+            context
+                .sql
+                .execute(
+                    "DELETE FROM deferred_downloads WHERE folder=?;",
+                    paramsv![folder],
+                )
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestContext;
+
+    #[async_std::test]
+    async fn test_deferred_downloads() -> Result<()> {
+        let t = TestContext::new().await;
+        assert_eq!(t.get_network_policy().await, NetworkPolicy::Unrestricted);
+        assert!(!should_defer(&t, Some(METERED_DOWNLOAD_LIMIT + 1)).await);
+
+        t.set_network_policy(NetworkPolicy::Metered).await;
+        assert!(!should_defer(&t, Some(METERED_DOWNLOAD_LIMIT)).await);
+        assert!(should_defer(&t, Some(METERED_DOWNLOAD_LIMIT + 1)).await);
+
+        defer_download(&t, "INBOX", 5, 1_000_000, "bob@example.net").await?;
+        defer_download(&t, "INBOX", 7, 2_000_000, "claire@example.net").await?;
+        defer_download(&t, "INBOX", 7, 2_000_000, "claire@example.net").await?;
+        let deferred = t.get_deferred_downloads().await?;
+        assert_eq!(deferred.len(), 2);
+        assert!(get_due_downloads(&t, "INBOX").await?.is_empty());
+
+        // Single messages can be downloaded on metered connections.
+        t.download_deferred(deferred.get(1).unwrap().id).await?;
+        assert_eq!(get_due_downloads(&t, "INBOX").await?, vec![7]);
+        remove_deferred(&t, "INBOX", Some(&[7])).await?;
+        assert!(get_due_downloads(&t, "INBOX").await?.is_empty());
+
+        t.set_network_policy(NetworkPolicy::Unrestricted).await;
+        assert_eq!(get_due_downloads(&t, "INBOX").await?, vec![5]);
+        remove_deferred(&t, "INBOX", None).await?;
+        assert!(t.get_deferred_downloads().await?.is_empty());
+        Ok(())
+    }
+}
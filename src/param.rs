@@ -51,6 +51,9 @@ pub enum Param {
     /// 'c' nor 'e' are preset, the messages is only transport encrypted.
     ErroneousE2ee = b'e',
 
+    /// For Messages: message is not encrypted, but has a valid signature of the sender.
+    SignedOnly = b'g',
+
     /// For Messages: force unencrypted message, a value from `ForcePlaintext` enum.
     ForcePlaintext = b'u',
 
@@ -114,6 +117,9 @@ pub enum Param {
     /// For Jobs: space-separated list of message recipients
     Recipients = b'R',
 
+    /// For Jobs: space-separated list of message IDs
+    MsgIds = b'J',
+
     /// For Groups
     ///
     /// An unpromoted group has not had any messages sent to it and thus only exists on the
@@ -126,6 +132,26 @@ pub enum Param {
     /// For Groups and Contacts
     ProfileImage = b'i',
 
+    /// For Contacts: load remote content of HTML messages from this contact
+    LoadRemoteContent = b'L',
+
+    /// For Contacts: send messages to this contact as classic email,
+    /// see `Contact::set_plain_email`
+    PlainEmail = b'C',
+
+    /// For Contacts: fingerprint of the key imported manually,
+    /// see `Contact::import_public_key`
+    PinnedKey = b'k',
+
+    /// For Contacts: send messages to this contact signed but unencrypted,
+    /// see `Contact::set_sign_only`
+    SignOnly = b's',
+
+    /// For Contacts: the contact is the user on another account,
+    /// see `Contact::set_self_alias`.
+    /// For Messages: the message was sent by such a contact to a group.
+    SelfAlias = b'W',
+
     /// For Chats
     Selftalk = b'K',
 
@@ -137,6 +163,53 @@ pub enum Param {
     /// For Chats
     Devicetalk = b'D',
 
+    /// For Chats: prefix added to the subject of outgoing messages, eg. `[project]`
+    SubjectPrefix = b'p',
+
+    /// For Chats: if set, ephemeral timer changes are only applied if sent by verified contacts
+    EphemeralVerifiedOnly = b'v',
+
+    /// For Chats: maximum ephemeral timer duration in seconds applied from received messages
+    EphemeralMaxTimer = b'x',
+
+    /// For Chats: epoch of the group secret, incremented on each re-keying,
+    /// see `Config::GroupRekeying`
+    GroupEpoch = b'j',
+
+    /// For Chats: group secret of the current epoch
+    GroupSecret = b'z',
+
+    /// For Chats: notification profile as JSON, see `NotificationProfile`
+    NotificationProfile = b'N',
+
+    /// For Chats: when the ephemeral timer starts, see `ChatId::set_ephemeral_timer_start`
+    EphemeralTimerStart = b'X',
+
+    /// For Messages: blob with the full text of a received message
+    /// whose text was truncated because of `Config::MaxTextLength`
+    FullText = b'B',
+
+    /// For Chats: save the raw mime-headers of incoming messages, see `ChatId::set_save_mime`
+    SaveMime = b'Z',
+
+    /// For Chats: ephemeral timer change waiting for approval,
+    /// see `ChatId::approve_ephemeral_timer_change`
+    EphemeralPendingTimer = b'1',
+
+    /// For Chats: timer changes by unverified contacts need approval, see `TimerPolicy`
+    EphemeralApproveUnverified = b'2',
+
+    /// For Chats: previous group secret still accepted for a grace period after re-keying,
+    /// as `<epoch>:<valid until>:<secret>`
+    GroupPrevSecret = b'3',
+
+    /// For Chats: what is deleted when the ephemeral timer expires,
+    /// see `ChatId::set_ephemeral_expiry_mode`
+    EphemeralExpiryMode = b'Y',
+
+    /// For Chats: read receipt policy, see `MdnPolicy`
+    MdnPolicy = b'y',
+
     /// For MDN-sending job
     MsgId = b'I',
 }
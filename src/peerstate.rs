@@ -137,6 +137,50 @@ pub fn from_gossip(gossip_header: &Aheader, message_time: i64) -> Self {
         }
     }
 
+    /// Creates a peerstate for a key found in the Web Key Directory.
+    ///
+    /// The key is stored like a gossiped key, so it is replaced by keys announced by
+    /// the peer using Autocrypt. Keys fetched over WKD are not authenticated,
+    /// so no encryption preference is assumed.
+    pub fn from_wkd(addr: &str, key: SignedPublicKey, timestamp: i64) -> Self {
+        Peerstate {
+            addr: addr.to_string(),
+            last_seen: 0,
+            last_seen_autocrypt: 0,
+            prefer_encrypt: EncryptPreference::NoPreference,
+            public_key: None,
+            public_key_fingerprint: None,
+            gossip_key_fingerprint: Some(key.fingerprint()),
+            gossip_key: Some(key),
+            gossip_timestamp: timestamp,
+            verified_key: None,
+            verified_key_fingerprint: None,
+            to_save: Some(ToSave::All),
+            fingerprint_changed: false,
+        }
+    }
+
+    /// Creates a peerstate for a key imported manually by the user.
+    ///
+    /// The key is stored like a key received in an Autocrypt header.
+    pub fn from_pinned_key(addr: &str, key: SignedPublicKey) -> Self {
+        Peerstate {
+            addr: addr.to_string(),
+            last_seen: 0,
+            last_seen_autocrypt: 0,
+            prefer_encrypt: EncryptPreference::Mutual,
+            public_key_fingerprint: Some(key.fingerprint()),
+            public_key: Some(key),
+            gossip_key: None,
+            gossip_key_fingerprint: None,
+            gossip_timestamp: 0,
+            verified_key: None,
+            verified_key_fingerprint: None,
+            to_save: Some(ToSave::All),
+            fingerprint_changed: false,
+        }
+    }
+
     pub async fn from_addr(context: &Context, addr: &str) -> Result<Option<Peerstate>> {
         let query = "SELECT addr, last_seen, last_seen_autocrypt, prefer_encrypted, public_key, \
                      gossip_timestamp, gossip_key, public_key_fingerprint, gossip_key_fingerprint, \
@@ -5,12 +5,14 @@
 use std::io::Cursor;
 
 use anyhow::{bail, ensure, format_err, Result};
+use chrono::SubsecRound;
 use pgp::armor::BlockType;
 use pgp::composed::{
     Deserializable, KeyType as PgpKeyType, Message, SecretKeyParamsBuilder, SignedPublicKey,
-    SignedPublicSubKey, SignedSecretKey, SubkeyParamsBuilder,
+    SignedPublicSubKey, SignedSecretKey, StandaloneSignature, SubkeyParamsBuilder,
 };
 use pgp::crypto::{HashAlgorithm, SymmetricKeyAlgorithm};
+use pgp::packet::{SignatureConfig, SignatureType, SignatureVersion, Subpacket};
 use pgp::types::{
     CompressionAlgorithm, KeyTrait, Mpi, PublicKeyTrait, SecretKeyTrait, StringToKey,
 };
@@ -335,6 +337,53 @@ pub async fn pk_decrypt(
     }
 }
 
+/// Creates an ASCII-armored detached signature of `plain`
+/// using `private_key_for_signing`.
+pub async fn pk_sign_detached(
+    plain: Vec<u8>,
+    private_key_for_signing: SignedSecretKey,
+) -> Result<String> {
+    async_std::task::spawn_blocking(move || {
+        let config = SignatureConfig::new_v4(
+            SignatureVersion::V4,
+            SignatureType::Binary,
+            private_key_for_signing.algorithm(),
+            HashAlgorithm::SHA2_256,
+            vec![
+                Subpacket::SignatureCreationTime(chrono::Utc::now().trunc_subsecs(0)),
+                Subpacket::Issuer(private_key_for_signing.key_id()),
+            ],
+            vec![],
+        );
+        let signature = config.sign(&private_key_for_signing, || "".into(), Cursor::new(plain))?;
+        let encoded_signature = StandaloneSignature::new(signature).to_armored_string(None)?;
+        Ok(encoded_signature)
+    })
+    .await
+}
+
+/// Validates a detached signature of `content`.
+///
+/// Returns fingerprints of all keys from the `public_keys_for_validation` keyring that
+/// have valid signatures.
+pub async fn pk_validate_detached(
+    content: Vec<u8>,
+    signature: Vec<u8>,
+    public_keys_for_validation: Keyring<SignedPublicKey>,
+) -> Result<HashSet<Fingerprint>> {
+    async_std::task::spawn_blocking(move || {
+        let (signature, _) = StandaloneSignature::from_armor_single(Cursor::new(signature))?;
+        let fingerprints = public_keys_for_validation
+            .keys()
+            .iter()
+            .filter(|pkey| signature.verify(&pkey.primary_key, &content).is_ok())
+            .map(DcKey::fingerprint)
+            .collect();
+        Ok(fingerprints)
+    })
+    .await
+}
+
 /// Symmetric encryption.
 pub async fn symm_encrypt(passphrase: &str, plain: &[u8]) -> Result<String> {
     let lit_msg = Message::new_literal_bytes("", plain);
@@ -597,4 +646,35 @@ async fn test_decrypt_signed_no_sigret() {
         .unwrap();
         assert_eq!(plain, CLEARTEXT);
     }
+
+    #[async_std::test]
+    async fn test_sign_detached() {
+        let signature = pk_sign_detached(CLEARTEXT.to_vec(), KEYS.alice_secret.clone())
+            .await
+            .unwrap();
+        assert!(signature.starts_with("-----BEGIN PGP SIGNATURE-----"));
+
+        let mut keyring = Keyring::new();
+        keyring.add(KEYS.alice_public.clone());
+        keyring.add(KEYS.bob_public.clone());
+        let valid_signatures = pk_validate_detached(
+            CLEARTEXT.to_vec(),
+            signature.as_bytes().to_vec(),
+            keyring.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(valid_signatures.len(), 1);
+        assert!(valid_signatures.contains(&DcKey::fingerprint(&KEYS.alice_public)));
+
+        // Modified content
+        let valid_signatures = pk_validate_detached(
+            b"This is another test".to_vec(),
+            signature.as_bytes().to_vec(),
+            keyring,
+        )
+        .await
+        .unwrap();
+        assert!(valid_signatures.is_empty());
+    }
 }
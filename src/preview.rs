@@ -0,0 +1,141 @@
+//! # Document previews
+//!
+//! Core does not render documents such as PDF files itself. Instead, the embedding
+//! application can register a [`PreviewGenerator`] using
+//! [`Context::set_preview_generator`], which is then asked to render the first page of
+//! a document into a JPEG image.
+//!
+//! Generated previews are cached in the blob directory next to the attachment as
+//! `<blobname>-preview.jpg`. A preview is regenerated if the attachment is newer than
+//! the cached preview. Previews of deleted attachments are removed by housekeeping
+//! together with the attachment.
+
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_std::path::PathBuf;
+
+use crate::constants::Viewtype;
+use crate::context::Context;
+use crate::dc_tools::dc_write_file;
+use crate::message::Message;
+
+/// Suffix of cached preview files in the blob directory.
+///
+/// Housekeeping keeps files with this suffix as long as the attachment is in use.
+pub(crate) const PREVIEW_SUFFIX: &str = "-preview.jpg";
+
+/// Renders previews of documents.
+///
+/// Implemented by the embedding application, eg. using the platform's PDF renderer.
+pub trait PreviewGenerator: fmt::Debug + Send + Sync {
+    /// Renders the first page of the document at `path` into a JPEG image.
+    ///
+    /// `mimetype` is the MIME type of the attachment as known to core.
+    /// Return an error if the document type is not supported.
+    fn generate(&self, path: &std::path::Path, mimetype: &str) -> Result<Vec<u8>>;
+}
+
+impl Context {
+    /// Registers the generator used by [`Message::get_preview_path`].
+    ///
+    /// Pass `None` to unregister the generator; already cached previews are still returned.
+    pub async fn set_preview_generator(&self, generator: Option<Arc<dyn PreviewGenerator>>) {
+        *self.preview_generator.write().await = generator;
+    }
+}
+
+impl Message {
+    /// Returns the path to a JPEG preview of the first page of a document attachment.
+    ///
+    /// The preview is generated using the registered [`PreviewGenerator`] on first use
+    /// and cached in the blob directory afterwards.
+    /// Returns `None` if the message is not a document
+    /// or if no preview can be generated.
+    pub async fn get_preview_path(&self, context: &Context) -> Result<Option<PathBuf>> {
+        if self.viewtype != Viewtype::File {
+            return Ok(None);
+        }
+        let file = match self.get_file(context) {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+        let mut preview = file.clone().into_os_string();
+        preview.push(PREVIEW_SUFFIX);
+        let preview = PathBuf::from(preview);
+
+        let file_modified = async_std::fs::metadata(&file).await?.modified()?;
+        if let Ok(metadata) = async_std::fs::metadata(&preview).await {
+            if metadata.modified()? >= file_modified {
+                return Ok(Some(preview));
+            }
+        }
+
+        let generator = match context.preview_generator.read().await.clone() {
+            Some(generator) => generator,
+            None => return Ok(None),
+        };
+        let mimetype = self
+            .get_filemime()
+            .unwrap_or_else(|| "application/octet-stream".to_string());
+        let path = file.clone();
+        let generated =
+            async_std::task::spawn_blocking(move || generator.generate(path.as_ref(), &mimetype))
+                .await;
+        match generated {
+            Ok(buf) => {
+                dc_write_file(context, &preview, &buf).await?;
+                Ok(Some(preview))
+            }
+            Err(err) => {
+                info!(context, "Cannot generate preview for {}: {}", self.id, err);
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::chat;
+    use crate::test_utils::TestContext;
+
+    #[derive(Debug)]
+    struct FakeGenerator;
+
+    impl PreviewGenerator for FakeGenerator {
+        fn generate(&self, _path: &std::path::Path, mimetype: &str) -> Result<Vec<u8>> {
+            anyhow::ensure!(mimetype == "application/pdf", "unsupported");
+            Ok(b"fake jpeg".to_vec())
+        }
+    }
+
+    #[async_std::test]
+    async fn test_get_preview_path() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat = t.get_self_chat().await;
+
+        let file = t.get_blobdir().join("document.pdf");
+        async_std::fs::write(&file, b"%PDF-1.4").await?;
+        let mut msg = Message::new(Viewtype::File);
+        msg.set_file(file.to_str().unwrap(), Some("application/pdf"));
+        chat::send_msg(&t, chat.id, &mut msg).await?;
+        let msg = Message::load_from_db(&t, msg.id).await?;
+
+        // no generator registered
+        assert!(msg.get_preview_path(&t).await?.is_none());
+
+        t.set_preview_generator(Some(Arc::new(FakeGenerator))).await;
+        let preview = msg.get_preview_path(&t).await?.unwrap();
+        assert!(preview.to_str().unwrap().ends_with(PREVIEW_SUFFIX));
+        assert_eq!(async_std::fs::read(&preview).await?, b"fake jpeg");
+
+        // cached previews are returned even without a generator
+        t.set_preview_generator(None).await;
+        assert_eq!(msg.get_preview_path(&t).await?, Some(preview));
+        Ok(())
+    }
+}
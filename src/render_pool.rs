@@ -0,0 +1,76 @@
+//! # Rendering pool
+//!
+//! Rendering outgoing messages, encoding their attachments and encrypting them is
+//! CPU-bound and may take long for large messages. Renderings therefore take a slot of
+//! the context's [`RenderPool`] and run their expensive steps on blocking threads.
+//! The number of slots is bounded by the number of CPUs, so large messages neither
+//! block the executor nor occupy all threads, and small messages rendered
+//! concurrently are not stuck behind them.
+
+use async_std::channel::{self, Receiver, Sender};
+
+/// Pool limiting the number of messages rendered at the same time.
+#[derive(Debug)]
+pub(crate) struct RenderPool {
+    sender: Sender<()>,
+    receiver: Receiver<()>,
+}
+
+/// Slot of a [`RenderPool`], released when dropped.
+#[derive(Debug)]
+pub(crate) struct RenderSlot<'a> {
+    pool: &'a RenderPool,
+}
+
+impl RenderPool {
+    /// Creates a pool rendering at most `size` messages at the same time.
+    pub(crate) fn new(size: usize) -> Self {
+        let size = size.max(1);
+        let (sender, receiver) = channel::bounded(size);
+        for _ in 0..size {
+            sender.try_send(()).ok();
+        }
+        Self { sender, receiver }
+    }
+
+    /// Waits for a free slot.
+    pub(crate) async fn acquire(&self) -> RenderSlot<'_> {
+        // The pool holds both ends of the channel, so it is never closed.
+        self.receiver.recv().await.ok();
+        RenderSlot { pool: self }
+    }
+}
+
+impl Default for RenderPool {
+    fn default() -> Self {
+        Self::new(num_cpus::get())
+    }
+}
+
+impl Drop for RenderSlot<'_> {
+    fn drop(&mut self) {
+        self.pool.sender.try_send(()).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::future::timeout;
+    use std::time::Duration;
+
+    #[async_std::test]
+    async fn test_render_pool() {
+        let pool = RenderPool::new(2);
+        let first = pool.acquire().await;
+        let _second = pool.acquire().await;
+        assert!(timeout(Duration::from_millis(100), pool.acquire())
+            .await
+            .is_err());
+
+        drop(first);
+        assert!(timeout(Duration::from_millis(100), pool.acquire())
+            .await
+            .is_ok());
+    }
+}
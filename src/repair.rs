@@ -0,0 +1,192 @@
+//! # Repairing inconsistent database state
+//!
+//! Bugs in current or previous versions, crashes or manual changes to the database
+//! may leave the stored state inconsistent. [`Context::repair`] fixes such
+//! inconsistencies on request, e.g. from a debug menu, and reports what was fixed.
+
+use anyhow::{bail, Result};
+
+use crate::chat::{self, ChatId};
+use crate::constants::{
+    Chattype, DC_CHAT_ID_LAST_SPECIAL, DC_CONTACT_ID_LAST_SPECIAL, DC_CONTACT_ID_SELF,
+};
+use crate::context::Context;
+use crate::ephemeral::start_ephemeral_timers;
+use crate::events::EventType;
+
+/// What is repaired by [`Context::repair`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+#[repr(u32)]
+pub enum RepairKind {
+    /// Starts the ephemeral timers of seen messages whose timer was not started.
+    EphemeralTimers = 1,
+
+    /// Restores the contact of 1:1 chats without members from the messages of the chat
+    /// and removes member rows of deleted chats or contacts.
+    ChatMembers = 2,
+
+    /// Recomputes unread counters.
+    ///
+    /// Not supported, unread counters are not stored but counted on every query.
+    UnreadCounters = 3,
+
+    /// Rebuilds the full-text search index.
+    ///
+    /// Not supported, messages are searched without an index.
+    SearchIndex = 4,
+
+    /// Re-links orphaned blob files to their messages.
+    ///
+    /// Not supported, unreferenced blob files are deleted by housekeeping instead.
+    OrphanedBlobs = 5,
+}
+
+/// Fixes applied by [`Context::repair`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RepairReport {
+    /// Number of fixed database rows.
+    pub fixed: usize,
+}
+
+impl Context {
+    /// Repairs inconsistencies of the kind `kind` and returns what was fixed.
+    ///
+    /// Returns an error for kinds that are not supported.
+    pub async fn repair(&self, kind: RepairKind) -> Result<RepairReport> {
+        let fixed = match kind {
+            RepairKind::EphemeralTimers => start_ephemeral_timers(self).await?,
+            RepairKind::ChatMembers => repair_chat_members(self).await?,
+            RepairKind::UnreadCounters | RepairKind::SearchIndex | RepairKind::OrphanedBlobs => {
+                bail!("Repairing {:?} is not supported", kind)
+            }
+        };
+        info!(self, "Repaired {:?}: {} rows fixed", kind, fixed);
+        Ok(RepairReport { fixed })
+    }
+}
+
+async fn repair_chat_members(context: &Context) -> Result<usize> {
+    let mut fixed = context
+        .sql
+        .execute(
+            "DELETE FROM chats_contacts \
+             WHERE chat_id NOT IN (SELECT id FROM chats) \
+             OR contact_id NOT IN (SELECT id FROM contacts)",
+            paramsv![],
+        )
+        .await?;
+
+    let chat_ids = context
+        .sql
+        .query_map(
+            "SELECT id FROM chats c \
+             WHERE type=? AND id>? \
+             AND NOT EXISTS (SELECT 1 FROM chats_contacts WHERE chat_id=c.id)",
+            paramsv![Chattype::Single, DC_CHAT_ID_LAST_SPECIAL],
+            |row| row.get::<_, ChatId>(0),
+            |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+        )
+        .await?;
+    for chat_id in chat_ids {
+        // The contact is the sender of incoming messages and the recipient of outgoing ones.
+        let contact_id: Option<u32> = context
+            .sql
+            .query_get_value(
+                "SELECT CASE WHEN from_id=? THEN to_id ELSE from_id END AS contact_id \
+                 FROM msgs \
+                 WHERE chat_id=? \
+                 AND contact_id>? \
+                 AND contact_id IN (SELECT id FROM contacts) \
+                 ORDER BY id DESC LIMIT 1",
+                paramsv![DC_CONTACT_ID_SELF, chat_id, DC_CONTACT_ID_LAST_SPECIAL],
+            )
+            .await?;
+        match contact_id {
+            Some(contact_id) => {
+                if chat::add_to_chat_contacts_table(context, chat_id, contact_id).await {
+                    fixed += 1;
+                    context.emit_event(EventType::ChatModified(chat_id));
+                }
+            }
+            None => warn!(
+                context,
+                "Cannot restore contact of chat {} without messages", chat_id
+            ),
+        }
+    }
+    Ok(fixed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::chat::get_chat_contacts;
+    use crate::ephemeral::Timer;
+    use crate::message::{Message, MessageState};
+    use crate::test_utils::TestContext;
+
+    #[async_std::test]
+    async fn test_repair_chat_members() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat = t.create_chat_with_contact("bob", "bob@example.net").await;
+        t.send_text(chat.id, "hi").await;
+        let members = get_chat_contacts(&t, chat.id).await?;
+        assert_eq!(members.len(), 1);
+        assert_eq!(t.repair(RepairKind::ChatMembers).await?.fixed, 0);
+
+        t.sql
+            .execute(
+                "DELETE FROM chats_contacts WHERE chat_id=?",
+                paramsv![chat.id],
+            )
+            .await?;
+        t.sql
+            .execute(
+                "INSERT INTO chats_contacts (chat_id, contact_id) VALUES (?, ?)",
+                paramsv![12345, members[0]],
+            )
+            .await?;
+        assert_eq!(t.repair(RepairKind::ChatMembers).await?.fixed, 2);
+        assert_eq!(get_chat_contacts(&t, chat.id).await?, members);
+        assert_eq!(
+            t.sql
+                .count(
+                    "SELECT COUNT(*) FROM chats_contacts WHERE chat_id=?",
+                    paramsv![12345]
+                )
+                .await?,
+            0
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_repair_unsupported() {
+        let t = TestContext::new_alice().await;
+        assert!(t.repair(RepairKind::UnreadCounters).await.is_err());
+        assert!(t.repair(RepairKind::SearchIndex).await.is_err());
+        assert!(t.repair(RepairKind::OrphanedBlobs).await.is_err());
+    }
+
+    #[async_std::test]
+    async fn test_repair_ephemeral_timers() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let chat = t.get_self_chat().await;
+        chat.id
+            .set_ephemeral_timer(&t, Timer::Enabled { duration: 60 })
+            .await?;
+        let msg_id = t.send_text(chat.id, "hi").await.sender_msg_id;
+        t.sql
+            .execute(
+                "UPDATE msgs SET ephemeral_timestamp=0, state=? WHERE id=?",
+                paramsv![MessageState::OutDelivered, msg_id],
+            )
+            .await?;
+
+        assert!(t.repair(RepairKind::EphemeralTimers).await?.fixed >= 1);
+        let msg = Message::load_from_db(&t, msg_id).await?;
+        assert!(msg.get_ephemeral_timestamp() > 0);
+        Ok(())
+    }
+}
@@ -82,8 +82,9 @@ async fn inbox_loop(ctx: Context, started: Sender<()>, inbox_handlers: ImapConne
         let mut jobs_loaded = 0;
         let mut info = InterruptInfo::default();
         loop {
+            let job_batch_size = ctx.get_hint().await.job_batch_size();
             match job::load_next(&ctx, Thread::Imap, &info).await {
-                Some(job) if jobs_loaded <= 20 => {
+                Some(job) if jobs_loaded <= job_batch_size => {
                     jobs_loaded += 1;
                     job::perform_job(&ctx, job::Connection::Inbox(&mut connection), job).await;
                     info = Default::default();
@@ -197,6 +198,9 @@ async fn fetch_idle(ctx: &Context, connection: &mut Imap, folder: Config) -> Int
 
             connection.connectivity.set_connected(ctx).await;
 
+            // deliver coalesced events before going idle
+            ctx.events.flush();
+
             // idle
             if connection.can_idle() {
                 match connection.idle(ctx, Some(watch_folder)).await {
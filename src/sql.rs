@@ -1,54 +1,138 @@
 //! # SQLite wrapper
+//!
+//! Reading statements use a pool of connections, so they run concurrently. All
+//! statements modifying the database use a single writer connection, for which
+//! writers wait in turn. SQLite allows only one writer at a time anyway, so this
+//! avoids `SQLITE_BUSY` errors between writers of the same context.
+//!
+//! Writes run on blocking threads, so waiting for the writer connection and
+//! for SQLite does not block the async executor. Therefore, statement parameters
+//! created with [`paramsv!`] are owned values.
 
 use async_std::path::Path;
 use async_std::sync::RwLock;
+use async_std::task;
 
 use std::collections::HashSet;
 use std::convert::TryFrom;
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use anyhow::{bail, format_err, Context as _, Result};
 use async_std::prelude::*;
-use rusqlite::OpenFlags;
+use rusqlite::types::{ToSqlOutput, Value};
+use rusqlite::{Connection, OpenFlags};
 
 use crate::blob::BlobObject;
-use crate::chat::{add_device_msg, update_device_icon, update_saved_messages_icon};
+use crate::chat::{add_device_msg, update_device_icon, update_saved_messages_icon, ChatId};
 use crate::config::Config;
 use crate::constants::{Viewtype, DC_CHAT_ID_TRASH};
 use crate::context::Context;
 use crate::dc_tools::{dc_delete_file, time};
 use crate::ephemeral::start_ephemeral_timers;
-use crate::message::Message;
+use crate::events::{Event, EventType, Events};
+use crate::message::{Message, MsgId};
 use crate::param::{Param, Params};
 use crate::peerstate::Peerstate;
+use crate::preview::PREVIEW_SUFFIX;
 use crate::stock_str;
 
 #[macro_export]
 macro_rules! paramsv {
     () => {
-        rusqlite::params_from_iter(Vec::<&dyn $crate::ToSql>::new())
+        rusqlite::params_from_iter(Vec::<$crate::sql::SqlParam>::new())
     };
     ($($param:expr),+ $(,)?) => {
-        rusqlite::params_from_iter(vec![$(&$param as &dyn $crate::ToSql),+])
+        rusqlite::params_from_iter(vec![$($crate::sql::SqlParam::new(&$param)),+])
     };
 }
 
+/// Statement parameter owning its value, created by [`paramsv!`].
+///
+/// Owned parameters can be moved to the blocking thread running the statement.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct SqlParam(std::result::Result<Value, String>);
+
+impl SqlParam {
+    pub fn new(param: &dyn crate::ToSql) -> Self {
+        SqlParam(match rusqlite::ToSql::to_sql(param) {
+            Ok(ToSqlOutput::Borrowed(value)) => Ok(value.into()),
+            Ok(ToSqlOutput::Owned(value)) => Ok(value),
+            #[allow(unreachable_patterns)]
+            Ok(_) => Err("unsupported parameter type".to_string()),
+            Err(err) => Err(err.to_string()),
+        })
+    }
+}
+
+impl rusqlite::ToSql for SqlParam {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        match &self.0 {
+            Ok(value) => Ok(ToSqlOutput::Borrowed(value.into())),
+            Err(err) => Err(rusqlite::Error::ToSqlConversionFailure(err.clone().into())),
+        }
+    }
+}
+
 mod migrations;
 
 /// A wrapper around the underlying Sqlite3 object.
 #[derive(Debug)]
 pub struct Sql {
     pool: RwLock<Option<r2d2::Pool<r2d2_sqlite::SqliteConnectionManager>>>,
+
+    /// Connection used for all writes, only locked on blocking threads.
+    writer: Arc<Mutex<Option<Connection>>>,
+
+    /// Logging of slow queries, see `Config::SlowQueryThreshold`.
+    slow_query_log: RwLock<Option<SlowQueryLog>>,
 }
 
 impl Default for Sql {
     fn default() -> Self {
         Self {
             pool: RwLock::new(None),
+            writer: Arc::new(Mutex::new(None)),
+            slow_query_log: RwLock::new(None),
         }
     }
 }
 
+/// Where and from which duration on slow queries are logged.
+#[derive(Debug)]
+struct SlowQueryLog {
+    context_id: u32,
+    events: Events,
+    threshold: Duration,
+}
+
+fn open_flags(readonly: bool) -> OpenFlags {
+    let mut open_flags = OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    if readonly {
+        open_flags.insert(OpenFlags::SQLITE_OPEN_READ_ONLY);
+    } else {
+        open_flags.insert(OpenFlags::SQLITE_OPEN_READ_WRITE);
+        open_flags.insert(OpenFlags::SQLITE_OPEN_CREATE);
+    }
+    open_flags
+}
+
+/// Sets up a new connection, must not modify the database.
+fn init_connection(c: &mut Connection) -> rusqlite::Result<()> {
+    c.execute_batch(&format!(
+        "PRAGMA secure_delete=on;
+         PRAGMA busy_timeout = {};
+         PRAGMA temp_store=memory; -- Avoid SQLITE_IOERR_GETTEMPPATH errors on Android
+         ",
+        Duration::from_secs(10).as_millis()
+    ))?;
+    // Replaces the busy timeout by a handler also waiting up to 10 seconds.
+    #[cfg(feature = "metrics")]
+    c.busy_handler(Some(busy_handler))?;
+    Ok(())
+}
+
 impl Sql {
     pub fn new() -> Sql {
         Self::default()
@@ -62,36 +146,29 @@ pub async fn is_open(&self) -> bool {
     /// Closes all underlying Sqlite connections.
     pub async fn close(&self) {
         let _ = self.pool.write().await.take();
+        self.set_writer(None).await;
         // drop closes the connection
     }
 
+    /// Replaces the writer connection once it is not used anymore.
+    async fn set_writer(&self, conn: Option<Connection>) {
+        let writer = Arc::clone(&self.writer);
+        task::spawn_blocking(move || {
+            *writer.lock().unwrap_or_else(|err| err.into_inner()) = conn;
+        })
+        .await;
+    }
+
     pub fn new_pool(
         dbfile: &Path,
         readonly: bool,
     ) -> anyhow::Result<r2d2::Pool<r2d2_sqlite::SqliteConnectionManager>> {
-        let mut open_flags = OpenFlags::SQLITE_OPEN_NO_MUTEX;
-        if readonly {
-            open_flags.insert(OpenFlags::SQLITE_OPEN_READ_ONLY);
-        } else {
-            open_flags.insert(OpenFlags::SQLITE_OPEN_READ_WRITE);
-            open_flags.insert(OpenFlags::SQLITE_OPEN_CREATE);
-        }
-
         // this actually creates min_idle database handles just now.
         // therefore, with_init() must not try to modify the database as otherwise
         // we easily get busy-errors (eg. table-creation, journal_mode etc. should be done on only one handle)
         let mgr = r2d2_sqlite::SqliteConnectionManager::file(dbfile)
-            .with_flags(open_flags)
-            .with_init(|c| {
-                c.execute_batch(&format!(
-                    "PRAGMA secure_delete=on;
-                     PRAGMA busy_timeout = {};
-                     PRAGMA temp_store=memory; -- Avoid SQLITE_IOERR_GETTEMPPATH errors on Android
-                     ",
-                    Duration::from_secs(10).as_millis()
-                ))?;
-                Ok(())
-            });
+            .with_flags(open_flags(readonly))
+            .with_init(init_connection);
 
         let pool = r2d2::Pool::builder()
             .min_idle(Some(2))
@@ -119,16 +196,20 @@ pub async fn open(
         }
 
         *self.pool.write().await = Some(Self::new_pool(dbfile, readonly)?);
+        let mut writer = Connection::open_with_flags(dbfile, open_flags(readonly))?;
+        init_connection(&mut writer)?;
+        self.set_writer(Some(writer)).await;
 
         if !readonly {
-            {
-                let conn = self.get_conn().await?;
+            self.write(|conn| {
                 // journal_mode is persisted, it is sufficient to change it only for one handle.
                 conn.pragma_update(None, "journal_mode", &"WAL".to_string())?;
 
                 // Default synchronous=FULL is much slower. NORMAL is sufficient for WAL mode.
                 conn.pragma_update(None, "synchronous", &"NORMAL".to_string())?;
-            }
+                Ok(())
+            })
+            .await?;
 
             // (1) update low-level database structure.
             // this should be done before updates that use high-level objects that
@@ -198,6 +279,7 @@ pub async fn open(
             }
         }
 
+        self.update_slow_query_log(context).await?;
         info!(context, "Opened database {:?}.", dbfile);
 
         Ok(())
@@ -207,10 +289,17 @@ pub async fn open(
     pub async fn execute(
         &self,
         query: impl AsRef<str>,
-        params: impl rusqlite::Params,
+        params: impl rusqlite::Params + Send + 'static,
     ) -> Result<usize> {
-        let conn = self.get_conn().await?;
-        let res = conn.execute(query.as_ref(), params)?;
+        let query = query.as_ref().to_string();
+        let (res, query, elapsed) = self
+            .write(move |conn| {
+                let start = Instant::now();
+                let res = conn.execute(&query, params)?;
+                Ok((res, query, start.elapsed()))
+            })
+            .await?;
+        self.log_write_if_slow(&query, elapsed).await;
         Ok(res)
     }
 
@@ -218,11 +307,18 @@ pub async fn execute(
     pub async fn insert(
         &self,
         query: impl AsRef<str>,
-        params: impl rusqlite::Params,
+        params: impl rusqlite::Params + Send + 'static,
     ) -> anyhow::Result<usize> {
-        let conn = self.get_conn().await?;
-        conn.execute(query.as_ref(), params)?;
-        Ok(usize::try_from(conn.last_insert_rowid())?)
+        let query = query.as_ref().to_string();
+        let (row_id, query, elapsed) = self
+            .write(move |conn| {
+                let start = Instant::now();
+                conn.execute(&query, params)?;
+                Ok((conn.last_insert_rowid(), query, start.elapsed()))
+            })
+            .await?;
+        self.log_write_if_slow(&query, elapsed).await;
+        Ok(usize::try_from(row_id)?)
     }
 
     /// Prepares and executes the statement and maps a function over the resulting rows.
@@ -242,9 +338,14 @@ pub async fn query_map<T, F, G, H>(
         let sql = sql.as_ref();
 
         let conn = self.get_conn().await?;
-        let mut stmt = conn.prepare(sql)?;
-        let res = stmt.query_map(params, f)?;
-        g(res)
+        let start = Instant::now();
+        let res = {
+            let mut stmt = conn.prepare(sql)?;
+            let rows = stmt.query_map(params, f)?;
+            g(rows)
+        };
+        self.log_if_slow(&conn, sql, start).await;
+        res
     }
 
     pub async fn get_conn(
@@ -259,6 +360,29 @@ pub async fn get_conn(
         Ok(conn)
     }
 
+    /// Runs `f` with the writer connection on a blocking thread.
+    ///
+    /// Waits until the writer connection is free without blocking the executor.
+    /// The connection must be used for all statements modifying the database,
+    /// `f` must not wait for other database operations.
+    pub async fn write<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    {
+        let writer = Arc::clone(&self.writer);
+        task::spawn_blocking(move || {
+            // A panic while writing only rolls back the open transaction,
+            // so the connection can still be used.
+            let mut guard = writer.lock().unwrap_or_else(|err| err.into_inner());
+            let conn = guard
+                .as_mut()
+                .ok_or_else(|| format_err!("No SQL connection"))?;
+            f(conn)
+        })
+        .await
+    }
+
     /// Used for executing `SELECT COUNT` statements only. Returns the resulting count.
     pub async fn count(
         &self,
@@ -287,7 +411,9 @@ pub async fn query_row<T, F>(
         F: FnOnce(&rusqlite::Row) -> rusqlite::Result<T>,
     {
         let conn = self.get_conn().await?;
+        let start = Instant::now();
         let res = conn.query_row(query.as_ref(), params, f)?;
+        self.log_if_slow(&conn, query.as_ref(), start).await;
         Ok(res)
     }
 
@@ -300,20 +426,22 @@ pub async fn transaction<G, H>(&self, callback: G) -> anyhow::Result<H>
         H: Send + 'static,
         G: Send + 'static + FnOnce(&mut rusqlite::Transaction<'_>) -> anyhow::Result<H>,
     {
-        let mut conn = self.get_conn().await?;
-        let mut transaction = conn.transaction()?;
-        let ret = callback(&mut transaction);
-
-        match ret {
-            Ok(ret) => {
-                transaction.commit()?;
-                Ok(ret)
-            }
-            Err(err) => {
-                transaction.rollback()?;
-                Err(err)
+        self.write(move |conn| {
+            let mut transaction = conn.transaction()?;
+            let ret = callback(&mut transaction);
+
+            match ret {
+                Ok(ret) => {
+                    transaction.commit()?;
+                    Ok(ret)
+                }
+                Err(err) => {
+                    transaction.rollback()?;
+                    Err(err)
+                }
             }
-        }
+        })
+        .await
     }
 
     /// Query the database if the requested table already exists.
@@ -357,7 +485,10 @@ pub async fn query_row_optional<T, F>(
         F: FnOnce(&rusqlite::Row) -> rusqlite::Result<T>,
     {
         let conn = self.get_conn().await?;
-        let res = match conn.query_row(sql.as_ref(), params, f) {
+        let start = Instant::now();
+        let res = conn.query_row(sql.as_ref(), params, f);
+        self.log_if_slow(&conn, sql.as_ref(), start).await;
+        let res = match res {
             Ok(res) => Ok(Some(res)),
             Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
             Err(rusqlite::Error::InvalidColumnType(_, _, rusqlite::types::Type::Null)) => Ok(None),
@@ -381,6 +512,61 @@ pub async fn query_get_value<T>(
             .await
     }
 
+    /// Enables or disables logging slow queries according to `Config::SlowQueryThreshold`.
+    pub(crate) async fn update_slow_query_log(&self, context: &Context) -> Result<()> {
+        let threshold = context.get_config_int(Config::SlowQueryThreshold).await?;
+        *self.slow_query_log.write().await = if threshold > 0 {
+            Some(SlowQueryLog {
+                context_id: context.id,
+                events: context.events.clone(),
+                threshold: Duration::from_millis(threshold as u64),
+            })
+        } else {
+            None
+        };
+        Ok(())
+    }
+
+    /// Logs the query and its query plan as a warning
+    /// if it took longer than `Config::SlowQueryThreshold` since `start`.
+    async fn log_if_slow(&self, conn: &Connection, query: &str, start: Instant) {
+        let elapsed = start.elapsed();
+        let slow_query_log = self.slow_query_log.read().await;
+        let slow_query_log = match &*slow_query_log {
+            Some(slow_query_log) if elapsed >= slow_query_log.threshold => slow_query_log,
+            _ => return,
+        };
+        let plan = explain_query_plan(conn, query).unwrap_or_else(|err| err.to_string());
+        slow_query_log.events.emit(Event {
+            id: slow_query_log.context_id,
+            typ: EventType::Warning(format!(
+                "Slow query took {:?}: {}\nQuery plan: {}",
+                elapsed,
+                query.trim(),
+                plan
+            )),
+        });
+    }
+
+    /// Logs a statement run on the writer connection like [`Sql::log_if_slow`].
+    ///
+    /// The query plan is explained using a connection from the pool,
+    /// as the writer connection is only used on blocking threads.
+    async fn log_write_if_slow(&self, query: &str, elapsed: Duration) {
+        let is_slow = matches!(
+            &*self.slow_query_log.read().await,
+            Some(slow_query_log) if elapsed >= slow_query_log.threshold
+        );
+        if !is_slow {
+            return;
+        }
+        if let (Ok(conn), Some(start)) =
+            (self.get_conn().await, Instant::now().checked_sub(elapsed))
+        {
+            self.log_if_slow(&conn, query, start).await;
+        }
+    }
+
     /// Set private configuration options.
     ///
     /// Setting `None` deletes the value.  On failure an error message
@@ -469,6 +655,28 @@ pub async fn housekeeping(context: &Context) -> Result<()> {
     if let Err(err) = crate::ephemeral::delete_expired_messages(context).await {
         warn!(context, "Failed to delete expired messages: {}", err);
     }
+    match crate::ephemeral::delete_old_info_msgs(context).await {
+        Ok(true) => context.emit_event(EventType::MsgsChanged {
+            chat_id: ChatId::new(0),
+            msg_id: MsgId::new(0),
+        }),
+        Ok(false) => {}
+        Err(err) => warn!(context, "Failed to delete old info messages: {}", err),
+    }
+    match crate::ephemeral::delete_seen_view_once_msgs(context).await {
+        Ok(true) => context.emit_event(EventType::MsgsChanged {
+            chat_id: ChatId::new(0),
+            msg_id: MsgId::new(0),
+        }),
+        Ok(false) => {}
+        Err(err) => warn!(context, "Failed to delete seen view-once messages: {}", err),
+    }
+    if let Err(err) = crate::deletion::finalize_pending_deletions(context).await {
+        warn!(context, "Failed to finalize pending deletions: {}", err);
+    }
+    if let Err(err) = crate::blob::sync_pending_blobs(context).await {
+        warn!(context, "Failed to sync blob files: {}", err);
+    }
 
     let mut files_in_use = HashSet::new();
     let mut unreferenced_count = 0;
@@ -481,6 +689,13 @@ pub async fn housekeeping(context: &Context) -> Result<()> {
         Param::File,
     )
     .await?;
+    maybe_add_from_param(
+        &context.sql,
+        &mut files_in_use,
+        "SELECT param FROM msgs  WHERE chat_id!=3;",
+        Param::FullText,
+    )
+    .await?;
     maybe_add_from_param(
         &context.sql,
         &mut files_in_use,
@@ -519,6 +734,22 @@ pub async fn housekeeping(context: &Context) -> Result<()> {
         .await
         .context("housekeeping: failed to SELECT value FROM config")?;
 
+    context
+        .sql
+        .query_map(
+            "SELECT avatar FROM avatar_history;",
+            paramsv![],
+            |row| row.get::<_, String>(0),
+            |rows| {
+                for row in rows {
+                    maybe_add_file(&mut files_in_use, row?);
+                }
+                Ok(())
+            },
+        )
+        .await
+        .context("housekeeping: failed to SELECT avatar FROM avatar_history")?;
+
     info!(context, "{} files in use.", files_in_use.len(),);
     /* go through directory and delete unused files */
     let p = context.get_blobdir();
@@ -539,7 +770,7 @@ pub async fn housekeeping(context: &Context) -> Result<()> {
                 if is_file_in_use(&files_in_use, None, &name_s)
                     || is_file_in_use(&files_in_use, Some(".increation"), &name_s)
                     || is_file_in_use(&files_in_use, Some(".waveform"), &name_s)
-                    || is_file_in_use(&files_in_use, Some("-preview.jpg"), &name_s)
+                    || is_file_in_use(&files_in_use, Some(PREVIEW_SUFFIX), &name_s)
                 {
                     continue;
                 }
@@ -669,6 +900,26 @@ async fn prune_tombstones(sql: &Sql) -> Result<()> {
     Ok(())
 }
 
+/// Returns the query plan of `query` as returned by `EXPLAIN QUERY PLAN`.
+fn explain_query_plan(conn: &Connection, query: &str) -> rusqlite::Result<String> {
+    let mut stmt = conn.prepare(&format!("EXPLAIN QUERY PLAN {}", query))?;
+    // Parameters are left unbound, they do not change the plan.
+    let mut rows = stmt.raw_query();
+    let mut details = Vec::new();
+    while let Some(row) = rows.next()? {
+        details.push(row.get::<_, String>(3)?);
+    }
+    Ok(details.join("; "))
+}
+
+/// Counts retries of busy database operations, giving up after about 10 seconds.
+#[cfg(feature = "metrics")]
+fn busy_handler(attempt: i32) -> bool {
+    crate::metrics::SQL_BUSY_RETRIES.inc();
+    std::thread::sleep(Duration::from_millis(10));
+    attempt < 1000
+}
+
 #[cfg(test)]
 mod test {
     use async_std::fs::File;
@@ -803,6 +1054,85 @@ async fn test_db_reopen() -> Result<()> {
         Ok(())
     }
 
+    #[async_std::test]
+    async fn test_write_closed() {
+        let sql = Sql::new();
+        assert!(sql
+            .execute("DELETE FROM config;", paramsv![])
+            .await
+            .is_err());
+        assert!(sql.write(|_| Ok(())).await.is_err());
+    }
+
+    #[async_std::test]
+    async fn test_concurrent_writes() -> Result<()> {
+        let t = TestContext::new().await;
+        let tasks: Vec<_> = (0..20)
+            .map(|i| {
+                let t = t.clone();
+                async_std::task::spawn(async move {
+                    t.sql
+                        .transaction(move |transaction| {
+                            for j in 0..50 {
+                                transaction.execute(
+                                    "INSERT INTO config (keyname, value) VALUES (?, ?);",
+                                    paramsv![format!("key{}-{}", i, j), "value"],
+                                )?;
+                            }
+                            Ok(())
+                        })
+                        .await?;
+                    t.sql
+                        .execute(
+                            "UPDATE config SET value='updated' WHERE keyname=?;",
+                            paramsv![format!("key{}-0", i)],
+                        )
+                        .await
+                })
+            })
+            .collect();
+        for task in tasks {
+            assert_eq!(task.await?, 1);
+        }
+        assert_eq!(
+            t.sql
+                .count(
+                    "SELECT COUNT(*) FROM config WHERE keyname LIKE 'key%';",
+                    paramsv![]
+                )
+                .await?,
+            1000
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_slow_query_log() -> Result<()> {
+        let t = TestContext::new().await;
+        t.set_config(Config::SlowQueryThreshold, Some("1")).await?;
+        t.sql
+            .query_map(
+                "WITH RECURSIVE cnt(x) AS (SELECT 1 UNION ALL SELECT x+1 FROM cnt LIMIT 1000000) \
+                 SELECT x FROM cnt WHERE x=?",
+                paramsv![-1],
+                |row| row.get::<_, i64>(0),
+                |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+            )
+            .await?;
+        loop {
+            if let EventType::Warning(msg) = t.evtracker.recv().await? {
+                if msg.starts_with("Slow query took") {
+                    assert!(msg.contains("Query plan:"));
+                    break;
+                }
+            }
+        }
+
+        t.set_config(Config::SlowQueryThreshold, None).await?;
+        assert!(t.sql.slow_query_log.read().await.is_none());
+        Ok(())
+    }
+
     #[async_std::test]
     async fn test_migration_flags() -> Result<()> {
         let t = TestContext::new().await;
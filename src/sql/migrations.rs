@@ -1,10 +1,12 @@
 use anyhow::Result;
+use rusqlite::types::ValueRef;
 
 use crate::config::Config;
 use crate::constants::ShowEmails;
 use crate::context::Context;
 use crate::dc_tools::EmailAddress;
 use crate::imap;
+use crate::message::compress_mime_headers;
 use crate::provider::get_provider_by_domain;
 use crate::sql::Sql;
 
@@ -475,6 +477,275 @@ pub async fn run(context: &Context, sql: &Sql) -> Result<(bool, bool, bool, bool
         sql.execute_migration("UPDATE chats SET archived=1 WHERE blocked=2;", 78)
             .await?;
     }
+    if dbversion < 79 {
+        info!(context, "[migration] v79");
+        // the index is used to speed up get_first_msg_on_or_after()
+        sql.execute_migration(
+            "CREATE INDEX IF NOT EXISTS msgs_index8 ON msgs (chat_id, timestamp);",
+            79,
+        )
+        .await?;
+    }
+    if dbversion < 80 {
+        info!(context, "[migration] v80");
+        // messages and chats deleted by the user, that can still be restored
+        sql.execute_migration(
+            r#"CREATE TABLE pending_deletions (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 batch INTEGER NOT NULL,
+                 delete_after INTEGER NOT NULL,
+                 msg_id INTEGER NOT NULL DEFAULT 0,
+                 chat_id INTEGER NOT NULL DEFAULT 0,
+                 orig_state INTEGER NOT NULL DEFAULT 0);"#,
+            80,
+        )
+        .await?;
+    }
+    if dbversion < 81 {
+        info!(context, "[migration] v81");
+        // local usage statistics, one row per week since the epoch
+        sql.execute_migration(
+            r#"CREATE TABLE usage_stats (
+                 week INTEGER PRIMARY KEY,
+                 msgs_sent INTEGER NOT NULL DEFAULT 0,
+                 msgs_received INTEGER NOT NULL DEFAULT 0,
+                 msgs_encrypted INTEGER NOT NULL DEFAULT 0,
+                 storage_bytes INTEGER NOT NULL DEFAULT 0);"#,
+            81,
+        )
+        .await?;
+    }
+    if dbversion < 82 {
+        info!(context, "[migration] v82");
+        // previous avatars of groups (chat_id) and contacts (contact_id)
+        sql.execute_migration(
+            r#"CREATE TABLE avatar_history (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 chat_id INTEGER NOT NULL DEFAULT 0,
+                 contact_id INTEGER NOT NULL DEFAULT 0,
+                 avatar TEXT NOT NULL,
+                 valid_until INTEGER NOT NULL);
+               CREATE INDEX avatar_history_index1 ON avatar_history (chat_id, contact_id);"#,
+            82,
+        )
+        .await?;
+    }
+    if dbversion < 83 {
+        info!(context, "[migration] v83");
+        // messages not downloaded because of the network policy
+        sql.execute_migration(
+            r#"CREATE TABLE deferred_downloads (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 folder TEXT NOT NULL,
+                 uid INTEGER NOT NULL,
+                 size INTEGER NOT NULL,
+                 sender TEXT NOT NULL DEFAULT '',
+                 forced INTEGER NOT NULL DEFAULT 0,
+                 UNIQUE(folder, uid));"#,
+            83,
+        )
+        .await?;
+    }
+    if dbversion < 84 {
+        info!(context, "[migration] v84");
+        sql.execute_migration(
+            "ALTER TABLE msgs ADD COLUMN view_once INTEGER NOT NULL DEFAULT 0;",
+            84,
+        )
+        .await?;
+    }
+
+    if dbversion < 85 {
+        info!(context, "[migration] v85");
+        // Summary of the last message of each chat, maintained by triggers
+        // so that the chatlist does not need to look up the last messages.
+        // The last message is the newest message which is not hidden, or the draft (state 19).
+        sql.execute_migration(
+            r#"ALTER TABLE chats ADD COLUMN last_msg_id INTEGER NOT NULL DEFAULT 0;
+               ALTER TABLE chats ADD COLUMN last_msg_timestamp INTEGER NOT NULL DEFAULT 0;
+               UPDATE chats SET last_msg_id=IFNULL((
+                   SELECT id FROM msgs
+                    WHERE chat_id=chats.id AND (hidden=0 OR state=19)
+                    ORDER BY timestamp DESC, id DESC LIMIT 1), 0)
+                 WHERE id>0;
+               UPDATE chats SET
+                   last_msg_timestamp=IFNULL((SELECT timestamp FROM msgs WHERE id=chats.last_msg_id), 0)
+                 WHERE id>0;
+               CREATE TRIGGER msgs_chat_summary_insert AFTER INSERT ON msgs
+               BEGIN
+               UPDATE chats SET last_msg_id=IFNULL((
+                   SELECT id FROM msgs
+                    WHERE chat_id=chats.id AND (hidden=0 OR state=19)
+                    ORDER BY timestamp DESC, id DESC LIMIT 1), 0)
+                 WHERE id=NEW.chat_id;
+               UPDATE chats SET
+                   last_msg_timestamp=IFNULL((SELECT timestamp FROM msgs WHERE id=chats.last_msg_id), 0)
+                 WHERE id=NEW.chat_id;
+               END;
+               CREATE TRIGGER msgs_chat_summary_delete AFTER DELETE ON msgs
+               BEGIN
+               UPDATE chats SET last_msg_id=IFNULL((
+                   SELECT id FROM msgs
+                    WHERE chat_id=chats.id AND (hidden=0 OR state=19)
+                    ORDER BY timestamp DESC, id DESC LIMIT 1), 0)
+                 WHERE id=OLD.chat_id;
+               UPDATE chats SET
+                   last_msg_timestamp=IFNULL((SELECT timestamp FROM msgs WHERE id=chats.last_msg_id), 0)
+                 WHERE id=OLD.chat_id;
+               END;
+               CREATE TRIGGER msgs_chat_summary_update
+               AFTER UPDATE OF chat_id, hidden, state, timestamp ON msgs
+               BEGIN
+               UPDATE chats SET last_msg_id=IFNULL((
+                   SELECT id FROM msgs
+                    WHERE chat_id=chats.id AND (hidden=0 OR state=19)
+                    ORDER BY timestamp DESC, id DESC LIMIT 1), 0)
+                 WHERE id IN (OLD.chat_id, NEW.chat_id);
+               UPDATE chats SET
+                   last_msg_timestamp=IFNULL((SELECT timestamp FROM msgs WHERE id=chats.last_msg_id), 0)
+                 WHERE id IN (OLD.chat_id, NEW.chat_id);
+               END;"#,
+            85,
+        )
+        .await?;
+    }
+    if dbversion < 86 {
+        info!(context, "[migration] v86");
+        // used to find expired messages and messages to delete from the server
+        sql.execute_migration(
+            r#"CREATE INDEX IF NOT EXISTS msgs_index9 ON msgs (ephemeral_timestamp);
+               CREATE INDEX IF NOT EXISTS msgs_index10 ON msgs (server_folder, server_uid);"#,
+            86,
+        )
+        .await?;
+    }
+    if dbversion < 87 {
+        info!(context, "[migration] v87");
+        // raw MIME messages are stored compressed in a separate table,
+        // so they do not bloat the msgs table and are only loaded when needed
+        sql.transaction(move |transaction| {
+            transaction.execute_batch(
+                r#"CREATE TABLE msgs_mime_headers (
+                     msg_id INTEGER PRIMARY KEY,
+                     mime_headers BLOB NOT NULL);
+                   CREATE TRIGGER msgs_mime_headers_delete AFTER DELETE ON msgs
+                   BEGIN
+                   DELETE FROM msgs_mime_headers WHERE msg_id=OLD.id;
+                   END;
+                   CREATE TRIGGER msgs_mime_headers_trash AFTER UPDATE OF chat_id ON msgs
+                   WHEN NEW.chat_id=3
+                   BEGIN
+                   DELETE FROM msgs_mime_headers WHERE msg_id=NEW.id;
+                   END;"#,
+            )?;
+            {
+                let mut stmt = transaction.prepare(
+                    "SELECT id, mime_headers FROM msgs \
+                     WHERE chat_id!=3 AND length(mime_headers)>0;",
+                )?;
+                let mut insert = transaction.prepare(
+                    "INSERT INTO msgs_mime_headers (msg_id, mime_headers) VALUES (?, ?);",
+                )?;
+                let mut rows = stmt.query(paramsv![])?;
+                while let Some(row) = rows.next()? {
+                    let msg_id: i64 = row.get(0)?;
+                    let mime_headers = match row.get_ref(1)? {
+                        ValueRef::Text(data) | ValueRef::Blob(data) => data,
+                        _ => continue,
+                    };
+                    insert.execute(paramsv![msg_id, compress_mime_headers(mime_headers)?])?;
+                }
+            }
+            transaction.execute_batch("UPDATE msgs SET mime_headers='';")?;
+            transaction.execute(
+                "UPDATE config SET value=? WHERE keyname=?;",
+                paramsv![format!("{}", 87), VERSION_CFG],
+            )?;
+            Ok(())
+        })
+        .await?;
+    }
+    if dbversion < 88 {
+        info!(context, "[migration] v88");
+        // private notes of the user, removed together with the message
+        sql.execute_migration(
+            r#"CREATE TABLE msgs_notes (
+                 msg_id INTEGER PRIMARY KEY,
+                 note TEXT NOT NULL);
+               CREATE TRIGGER msgs_notes_delete AFTER DELETE ON msgs
+               BEGIN
+               DELETE FROM msgs_notes WHERE msg_id=OLD.id;
+               END;
+               CREATE TRIGGER msgs_notes_trash AFTER UPDATE OF chat_id ON msgs
+               WHEN NEW.chat_id=3
+               BEGIN
+               DELETE FROM msgs_notes WHERE msg_id=NEW.id;
+               END;"#,
+            88,
+        )
+        .await?;
+    }
+    if dbversion < 89 {
+        info!(context, "[migration] v89");
+        // tombstones of messages removed by `delete_device_after`,
+        // which may be downloaded again when searching the server
+        sql.execute_migration(
+            "ALTER TABLE msgs ADD COLUMN device_expired INTEGER NOT NULL DEFAULT 0;",
+            89,
+        )
+        .await?;
+    }
+    if dbversion < 90 {
+        info!(context, "[migration] v90");
+        // starred messages kept although they were due for deletion,
+        // set once `MsgPreserved` is emitted for them
+        sql.execute_migration(
+            "ALTER TABLE msgs ADD COLUMN preserved INTEGER NOT NULL DEFAULT 0;",
+            90,
+        )
+        .await?;
+    }
+    if dbversion < 91 {
+        info!(context, "[migration] v91");
+        // message-id of the first message of the email thread,
+        // used to group email threads by references without scanning `mime_references`
+        sql.execute_migration(
+            r#"ALTER TABLE msgs ADD COLUMN thread_root TEXT NOT NULL DEFAULT '';
+               UPDATE msgs
+                 SET thread_root=substr(mime_references,
+                                        instr(mime_references, '<')+1,
+                                        instr(mime_references, '>')-instr(mime_references, '<')-1)
+                 WHERE instr(mime_references, '>')>instr(mime_references, '<')+1;
+               UPDATE msgs
+                 SET thread_root=substr(mime_in_reply_to,
+                                        instr(mime_in_reply_to, '<')+1,
+                                        instr(mime_in_reply_to, '>')-instr(mime_in_reply_to, '<')-1)
+                 WHERE thread_root=''
+                   AND instr(mime_in_reply_to, '>')>instr(mime_in_reply_to, '<')+1;
+               CREATE INDEX IF NOT EXISTS msgs_index11 ON msgs (thread_root);"#,
+            91,
+        )
+        .await?;
+    }
+    if dbversion < 92 {
+        info!(context, "[migration] v92");
+        // remove previous avatars together with their chat or contact
+        sql.execute_migration(
+            r#"DELETE FROM avatar_history
+                 WHERE (chat_id!=0 AND chat_id NOT IN (SELECT id FROM chats))
+                    OR (contact_id!=0 AND contact_id NOT IN (SELECT id FROM contacts));
+               CREATE TRIGGER avatar_history_chat_delete AFTER DELETE ON chats
+               BEGIN
+               DELETE FROM avatar_history WHERE chat_id=OLD.id;
+               END;
+               CREATE TRIGGER avatar_history_contact_delete AFTER DELETE ON contacts
+               BEGIN
+               DELETE FROM avatar_history WHERE contact_id=OLD.id;
+               END;"#,
+            92,
+        )
+        .await?;
+    }
 
     Ok((
         recalc_fingerprints,
@@ -0,0 +1,189 @@
+//! # Local usage statistics
+//!
+//! Counts sent and received messages per week, how many of them were encrypted,
+//! and how much storage the account uses. The statistics never leave the device;
+//! they are only stored in the database so the UI can show them to the user.
+
+use std::ops::Range;
+
+use anyhow::Result;
+use async_std::prelude::*;
+use serde::Serialize;
+
+use crate::context::Context;
+use crate::dc_tools::time;
+
+/// Length of a week in seconds.
+const WEEK: i64 = 7 * 24 * 60 * 60;
+
+/// Usage statistics of a single week.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UsageStats {
+    /// Timestamp of the start of the week.
+    pub week_start: i64,
+
+    /// Number of messages sent during the week.
+    pub msgs_sent: u32,
+
+    /// Number of messages received during the week.
+    pub msgs_received: u32,
+
+    /// Number of sent and received messages that were encrypted.
+    pub msgs_encrypted: u32,
+
+    /// Size of the database and the blob directory in bytes,
+    /// as last measured during the week, 0 if never measured.
+    pub storage_bytes: u64,
+}
+
+impl UsageStats {
+    /// Returns the share of encrypted messages, between 0.0 and 1.0.
+    pub fn encryption_ratio(&self) -> f64 {
+        let total = self.msgs_sent + self.msgs_received;
+        if total == 0 {
+            0.0
+        } else {
+            f64::from(self.msgs_encrypted) / f64::from(total)
+        }
+    }
+}
+
+async fn ensure_week(context: &Context, week: i64) -> Result<()> {
+    context
+        .sql
+        .execute(
+            "INSERT OR IGNORE INTO usage_stats (week) VALUES (?);",
+            paramsv![week],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Counts a message sent now.
+pub(crate) async fn record_sent(context: &Context, encrypted: bool) -> Result<()> {
+    let week = time() / WEEK;
+    ensure_week(context, week).await?;
+    context
+        .sql
+        .execute(
+            "UPDATE usage_stats SET msgs_sent=msgs_sent+1, msgs_encrypted=msgs_encrypted+?
+             WHERE week=?;",
+            paramsv![encrypted as i32, week],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Counts a message received now.
+pub(crate) async fn record_received(context: &Context, encrypted: bool) -> Result<()> {
+    let week = time() / WEEK;
+    ensure_week(context, week).await?;
+    context
+        .sql
+        .execute(
+            "UPDATE usage_stats SET msgs_received=msgs_received+1, msgs_encrypted=msgs_encrypted+?
+             WHERE week=?;",
+            paramsv![encrypted as i32, week],
+        )
+        .await?;
+    Ok(())
+}
+
+async fn storage_bytes(context: &Context) -> Result<u64> {
+    let mut bytes = async_std::fs::metadata(context.get_dbfile()).await?.len();
+    let mut dir = async_std::fs::read_dir(context.get_blobdir()).await?;
+    while let Some(entry) = dir.next().await {
+        if let Ok(metadata) = entry?.metadata().await {
+            if metadata.is_file() {
+                bytes += metadata.len();
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+/// Measures the storage used by the account and stores it for the current week.
+///
+/// Called from housekeeping.
+pub(crate) async fn record_storage(context: &Context) -> Result<()> {
+    let bytes = storage_bytes(context).await?;
+    let week = time() / WEEK;
+    ensure_week(context, week).await?;
+    context
+        .sql
+        .execute(
+            "UPDATE usage_stats SET storage_bytes=? WHERE week=?;",
+            paramsv![bytes as i64, week],
+        )
+        .await?;
+    Ok(())
+}
+
+impl Context {
+    /// Returns the usage statistics of the weeks overlapping the given range of timestamps,
+    /// oldest first.
+    ///
+    /// Weeks without any recorded activity are omitted.
+    pub async fn get_usage_stats(&self, range: Range<i64>) -> Result<Vec<UsageStats>> {
+        let stats = self
+            .sql
+            .query_map(
+                "SELECT week, msgs_sent, msgs_received, msgs_encrypted, storage_bytes
+                 FROM usage_stats
+                 WHERE week>=? AND week<?
+                 ORDER BY week;",
+                paramsv![
+                    range.start.div_euclid(WEEK),
+                    (range.end + WEEK - 1).div_euclid(WEEK)
+                ],
+                |row| {
+                    let week: i64 = row.get(0)?;
+                    let storage_bytes: i64 = row.get(4)?;
+                    Ok(UsageStats {
+                        week_start: week * WEEK,
+                        msgs_sent: row.get(1)?,
+                        msgs_received: row.get(2)?,
+                        msgs_encrypted: row.get(3)?,
+                        storage_bytes: storage_bytes.max(0) as u64,
+                    })
+                },
+                |rows| rows.collect::<Result<Vec<_>, _>>().map_err(Into::into),
+            )
+            .await?;
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestContext;
+
+    #[async_std::test]
+    async fn test_usage_stats() -> Result<()> {
+        let t = TestContext::new_alice().await;
+        let now = time();
+        assert!(t.get_usage_stats(0..now + WEEK).await?.is_empty());
+
+        record_sent(&t, true).await?;
+        record_sent(&t, false).await?;
+        record_received(&t, true).await?;
+        record_storage(&t).await?;
+
+        let stats = t.get_usage_stats(now - WEEK..now + 1).await?;
+        assert_eq!(stats.len(), 1);
+        let week = stats.first().unwrap();
+        assert!(week.week_start <= now && now < week.week_start + WEEK);
+        assert_eq!(week.msgs_sent, 2);
+        assert_eq!(week.msgs_received, 1);
+        assert_eq!(week.msgs_encrypted, 2);
+        assert!(week.storage_bytes > 0);
+        assert!((week.encryption_ratio() - 2.0 / 3.0).abs() < 1e-9);
+
+        assert!(t
+            .get_usage_stats(now + WEEK..now + 2 * WEEK)
+            .await?
+            .is_empty());
+        Ok(())
+    }
+}
@@ -258,6 +258,18 @@ pub enum StockMessage {
 
     #[strum(props(fallback = "Forwarded"))]
     Forwarded = 97,
+
+    #[strum(props(fallback = "Message deletion timer change by %1$s was rejected."))]
+    MsgEphemeralTimerChangeRejected = 98,
+
+    #[strum(props(fallback = "The key of this contact was imported manually."))]
+    KeyImportedManually = 99,
+
+    #[strum(props(fallback = "%1$s messages disappeared."))]
+    MsgsDisappeared = 100,
+
+    #[strum(props(fallback = "Message deletion timer change by %1$s is waiting for approval."))]
+    MsgEphemeralTimerChangePending = 101,
 }
 
 impl StockMessage {
@@ -840,6 +852,38 @@ pub(crate) async fn forwarded(context: &Context) -> String {
     translated(context, StockMessage::Forwarded).await
 }
 
+/// Stock string: `Message deletion timer change by %1$s was rejected.`.
+pub(crate) async fn msg_ephemeral_timer_change_rejected(
+    context: &Context,
+    contact: impl AsRef<str>,
+) -> String {
+    translated(context, StockMessage::MsgEphemeralTimerChangeRejected)
+        .await
+        .replace1(contact)
+}
+
+/// Stock string: `The key of this contact was imported manually.`.
+pub(crate) async fn key_imported_manually(context: &Context) -> String {
+    translated(context, StockMessage::KeyImportedManually).await
+}
+
+/// Stock string: `Message deletion timer change by %1$s is waiting for approval.`.
+pub(crate) async fn msg_ephemeral_timer_change_pending(
+    context: &Context,
+    contact: impl AsRef<str>,
+) -> String {
+    translated(context, StockMessage::MsgEphemeralTimerChangePending)
+        .await
+        .replace1(contact)
+}
+
+/// Stock string: `%1$s messages disappeared.`.
+pub(crate) async fn msgs_disappeared(context: &Context, count: u32) -> String {
+    translated(context, StockMessage::MsgsDisappeared)
+        .await
+        .replace1(count.to_string())
+}
+
 impl Context {
     /// Set the stock string for the [StockMessage].
     ///
@@ -0,0 +1,79 @@
+//! # Storage sinks for exports
+//!
+//! Backups are written through a [`StorageSink`], so the embedding application can
+//! stream them directly to external storage, eg. the Storage Access Framework on
+//! Android, iCloud or S3, instead of writing a temporary file first and copying it
+//! afterwards. [`FileSink`] writes to the local filesystem and is used by
+//! [`imex`](crate::imex::imex).
+
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context as TaskContext, Poll};
+
+use anyhow::Result;
+use async_std::fs::{self, File};
+use async_std::io::{self, Write};
+use async_std::path::PathBuf;
+use async_std::prelude::*;
+use async_trait::async_trait;
+
+/// Destination of an export.
+///
+/// The export is written using [`Write`], which provides writing and flushing.
+/// [`StorageSink::finalize`] is called once after all data has been written.
+#[async_trait]
+pub trait StorageSink: Write + Unpin + Send + Sync + fmt::Debug {
+    /// Completes the export, eg. by committing an upload.
+    ///
+    /// Not called if writing fails; the sink should discard partially written data
+    /// when it is dropped without being finalized.
+    async fn finalize(self: Box<Self>) -> Result<()>;
+}
+
+/// Writes an export to a temporary file which is renamed to its destination when finalized.
+#[derive(Debug)]
+pub struct FileSink {
+    file: File,
+    temp_path: PathBuf,
+    dest_path: PathBuf,
+}
+
+impl FileSink {
+    /// Creates the temporary file `temp_path`, which is renamed to `dest_path` when finalized.
+    pub async fn create(temp_path: PathBuf, dest_path: PathBuf) -> Result<Self> {
+        let file = File::create(&temp_path).await?;
+        Ok(Self {
+            file,
+            temp_path,
+            dest_path,
+        })
+    }
+}
+
+impl Write for FileSink {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.file).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.file).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.file).poll_close(cx)
+    }
+}
+
+#[async_trait]
+impl StorageSink for FileSink {
+    async fn finalize(mut self: Box<Self>) -> Result<()> {
+        self.file.flush().await?;
+        self.file.sync_all().await?;
+        fs::rename(&self.temp_path, &self.dest_path).await?;
+        Ok(())
+    }
+}
@@ -27,6 +27,7 @@
 use crate::context::Context;
 use crate::dc_receive_imf::dc_receive_imf;
 use crate::dc_tools::EmailAddress;
+use crate::ephemeral;
 use crate::events::{Event, EventType};
 use crate::job::Action;
 use crate::key::{self, DcKey};
@@ -99,6 +100,8 @@ async fn new_named(name: Option<String>) -> Self {
         let ctx = Context::new("FakeOS".into(), dbfile.into(), id)
             .await
             .expect("failed to create context");
+        // Tests delete expired messages explicitly, the ephemeral task would race with them.
+        ephemeral::stop_ephemeral_task(&ctx).await;
 
         let events = ctx.get_event_emitter();
 
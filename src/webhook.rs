@@ -0,0 +1,275 @@
+//! # Event webhook
+//!
+//! For headless deployments such as bots, events can additionally be delivered to a
+//! local HTTP endpoint or a unix socket, so supervisors can observe account activity
+//! without linking against the library. Each event is sent as a JSON object in the
+//! body of a `POST` request. If a secret is configured, the body is signed with
+//! HMAC-SHA256 and the signature is sent hex-encoded in the `X-Deltachat-Signature`
+//! header. Failed deliveries are retried with exponential backoff; events are dropped
+//! if the endpoint does not accept them after the retries.
+
+use std::time::Duration;
+
+use anyhow::{bail, format_err, Result};
+use async_std::channel::{self, Receiver, Sender};
+use async_std::path::{Path, PathBuf};
+use async_std::prelude::*;
+use async_std::task;
+use hmac::{Hmac, Mac, NewMac};
+use serde::Serialize;
+use sha2::Sha256;
+
+use crate::dc_tools::time;
+use crate::events::{Event, EventJson};
+
+/// Header carrying the HMAC-SHA256 signature of the request body.
+const SIGNATURE_HEADER: &str = "X-Deltachat-Signature";
+
+/// Number of events queued for delivery, older events are dropped when the queue is full.
+const QUEUE_SIZE: usize = 1_000;
+
+/// Delay before the first retry, doubled for each further retry.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Where events are delivered to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebhookEndpoint {
+    /// HTTP URL, eg. `http://localhost:8080/events`.
+    Http(String),
+
+    /// Path of a unix socket accepting HTTP requests.
+    UnixSocket(PathBuf),
+}
+
+/// Configuration of an event webhook.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Webhook {
+    /// Where events are delivered to.
+    pub endpoint: WebhookEndpoint,
+
+    /// Secret used to sign the requests, `None` to send unsigned requests.
+    pub secret: Option<String>,
+
+    /// Number of retries after a failed delivery.
+    pub retries: u32,
+}
+
+impl Webhook {
+    /// Creates a webhook delivering events to the given endpoint with 3 retries.
+    pub fn new(endpoint: WebhookEndpoint, secret: Option<String>) -> Self {
+        Self {
+            endpoint,
+            secret,
+            retries: 3,
+        }
+    }
+
+    /// Starts delivering events sent to the returned queue.
+    ///
+    /// Delivery stops when the queue is dropped.
+    pub(crate) fn start(self) -> WebhookQueue {
+        let (sender, receiver) = channel::bounded(QUEUE_SIZE);
+        task::spawn(self.run(receiver.clone()));
+        WebhookQueue { sender, receiver }
+    }
+
+    async fn run(self, receiver: Receiver<Event>) {
+        while let Ok(event) = receiver.recv().await {
+            let body = match serde_json::to_vec(&WebhookPayload::from(&event)) {
+                Ok(body) => body,
+                Err(_) => continue,
+            };
+            let mut delay = RETRY_DELAY;
+            for attempt in 0..=self.retries {
+                if attempt > 0 {
+                    task::sleep(delay).await;
+                    delay *= 2;
+                }
+                if self.deliver(&body).await.is_ok() {
+                    break;
+                }
+            }
+        }
+    }
+
+    async fn deliver(&self, body: &[u8]) -> Result<()> {
+        let signature = self
+            .secret
+            .as_ref()
+            .map(|secret| hex::encode(hmac_sha256(secret.as_bytes(), body)));
+        match &self.endpoint {
+            WebhookEndpoint::Http(url) => {
+                let mut req = surf::post(url)
+                    .body(body.to_vec())
+                    .content_type(surf::http::mime::JSON)
+                    .build();
+                if let Some(signature) = signature {
+                    req.insert_header(SIGNATURE_HEADER, signature);
+                }
+                let response = surf::Client::new()
+                    .send(req)
+                    .await
+                    .map_err(|err| format_err!("{}", err))?;
+                if !response.status().is_success() {
+                    bail!("webhook returned status {}", response.status());
+                }
+                Ok(())
+            }
+            WebhookEndpoint::UnixSocket(path) => deliver_unix(path, body, signature).await,
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn deliver_unix(path: &Path, body: &[u8], signature: Option<String>) -> Result<()> {
+    use async_std::os::unix::net::UnixStream;
+
+    let mut stream = UnixStream::connect(path).await?;
+    let mut request = format!(
+        "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n",
+        body.len()
+    );
+    if let Some(signature) = signature {
+        request += &format!("{}: {}\r\n", SIGNATURE_HEADER, signature);
+    }
+    request += "\r\n";
+    stream.write_all(request.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.flush().await?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    let response = String::from_utf8_lossy(&response);
+    let status = response
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(|| format_err!("invalid response from webhook socket"))?;
+    if !(200..300).contains(&status) {
+        bail!("webhook returned status {}", status);
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn deliver_unix(_path: &Path, _body: &[u8], _signature: Option<String>) -> Result<()> {
+    bail!("unix sockets are not supported on this platform")
+}
+
+/// Queue of events waiting for delivery to a webhook.
+#[derive(Debug)]
+pub(crate) struct WebhookQueue {
+    sender: Sender<Event>,
+    receiver: Receiver<Event>,
+}
+
+impl WebhookQueue {
+    /// Queues an event for delivery, dropping the oldest queued event if the queue is full.
+    pub(crate) fn push(&self, event: Event) {
+        if let Err(channel::TrySendError::Full(event)) = self.sender.try_send(event) {
+            let _ = self.receiver.try_recv();
+            let _ = self.sender.try_send(event);
+        }
+    }
+}
+
+impl Drop for WebhookQueue {
+    fn drop(&mut self) {
+        self.sender.close();
+    }
+}
+
+/// JSON body of a webhook request.
+#[derive(Debug, Serialize)]
+struct WebhookPayload {
+    #[serde(flatten)]
+    event: EventJson,
+
+    /// Timestamp at which the event was queued.
+    timestamp: i64,
+}
+
+impl From<&Event> for WebhookPayload {
+    fn from(event: &Event) -> Self {
+        Self {
+            event: event.into(),
+            timestamp: time(),
+        }
+    }
+}
+
+/// Computes the HMAC-SHA256 of `message`.
+fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    // HMAC accepts keys of any length.
+    let mut mac = Hmac::<Sha256>::new_varkey(key).expect("HMAC can take key of any size");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::EventType;
+
+    #[test]
+    fn test_hmac_sha256() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            hex::encode(hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        // RFC 4231, test case 6, key longer than the block size
+        assert_eq!(
+            hex::encode(hmac_sha256(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            )),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[cfg(unix)]
+    #[async_std::test]
+    async fn test_webhook_unix_socket() -> Result<()> {
+        use async_std::os::unix::net::UnixListener;
+
+        let dir = tempfile::tempdir()?;
+        let path: PathBuf = dir.path().join("webhook.sock").into();
+        let listener = UnixListener::bind(&path).await?;
+
+        let queue = Webhook::new(
+            WebhookEndpoint::UnixSocket(path),
+            Some("secret".to_string()),
+        )
+        .start();
+        queue.push(Event {
+            id: 1,
+            typ: EventType::Info("hello".to_string()),
+        });
+
+        let (mut stream, _) = listener.accept().await?;
+        let mut buf = vec![0; 4096];
+        let mut request = Vec::new();
+        while !request.ends_with(b"}") {
+            let n = stream.read(&mut buf).await?;
+            request.extend_from_slice(buf.get(..n).unwrap());
+        }
+        stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").await?;
+        drop(stream);
+
+        let request = String::from_utf8(request)?;
+        let (head, body) = request.split_at(request.find("\r\n\r\n").unwrap() + 4);
+        assert!(head.starts_with("POST / HTTP/1.1\r\n"));
+        let signature = hex::encode(hmac_sha256(b"secret", body.as_bytes()));
+        assert!(head.contains(&format!("X-Deltachat-Signature: {}\r\n", signature)));
+
+        let payload: serde_json::Value = serde_json::from_str(body)?;
+        assert_eq!(payload["context_id"], 1);
+        assert_eq!(payload["event_id"], 100);
+        assert_eq!(payload["event"]["type"], "Info");
+        assert_eq!(payload["event"]["data"], "hello");
+        Ok(())
+    }
+}
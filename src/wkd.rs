@@ -0,0 +1,190 @@
+//! # Web Key Directory
+//!
+//! Looks up OpenPGP keys of contacts not using Autocrypt in the
+//! [Web Key Directory](https://datatracker.ietf.org/doc/draft-koch-openpgp-webkey-service/)
+//! of their email provider.
+//!
+//! Found keys are stored in the peerstate of the contact like gossiped keys,
+//! without an encryption preference as they are not authenticated.
+//! The lookup runs in a `WkdLookup` job queued when a contact is created.
+//! Keys announced by the contact using Autocrypt later take precedence.
+
+use anyhow::{bail, format_err, Result};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use sha1::{Digest, Sha1};
+
+use crate::config::Config;
+use crate::contact::{addr_cmp, Contact};
+use crate::context::Context;
+use crate::dc_tools::{time, EmailAddress};
+use crate::events::EventType;
+use crate::key::{DcKey, KeySource, SignedPublicKey};
+use crate::peerstate::Peerstate;
+
+/// Alphabet of the z-base-32 encoding used for the hashed local part.
+const ZBASE32_ALPHABET: &[u8; 32] = b"ybndrfg8ejkmcpqxot1uwisza345h769";
+
+#[allow(clippy::indexing_slicing)]
+fn zbase32(data: &[u8]) -> String {
+    let mut res = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for byte in data {
+        buffer = (buffer << 8) | u32::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            res.push(ZBASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        res.push(ZBASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    res
+}
+
+/// Returns the URLs of the advanced and the direct WKD method, in this order.
+fn wkd_urls(addr: &str) -> Result<Vec<String>> {
+    let addr: EmailAddress = addr.parse()?;
+    let local = addr.local.to_lowercase();
+    let domain = addr.domain.to_lowercase();
+    let hash = zbase32(&Sha1::digest(local.as_bytes()));
+    let local = utf8_percent_encode(&addr.local, NON_ALPHANUMERIC);
+    Ok(vec![
+        format!(
+            "https://openpgpkey.{}/.well-known/openpgpkey/{}/hu/{}?l={}",
+            domain, domain, hash, local
+        ),
+        format!(
+            "https://{}/.well-known/openpgpkey/hu/{}?l={}",
+            domain, hash, local
+        ),
+    ])
+}
+
+/// Returns the address of an OpenPGP user ID like `Joe Doe <joe@example.org>`.
+///
+/// User IDs without angle brackets are taken as a bare address.
+fn user_id_addr(user_id: &str) -> &str {
+    match (user_id.rfind('<'), user_id.rfind('>')) {
+        (Some(start), Some(end)) if start < end => user_id.get(start + 1..end).unwrap_or_default(),
+        _ => user_id.trim(),
+    }
+}
+
+/// Parses a key returned by a WKD server and checks that it belongs to `addr`.
+fn parse_key(addr: &str, data: &[u8]) -> Result<SignedPublicKey> {
+    let key = match SignedPublicKey::from_slice(data) {
+        Ok(key) => key,
+        Err(_) => SignedPublicKey::from_asc(&String::from_utf8_lossy(data))?.0,
+    };
+    key.verify()?;
+    if !key
+        .details
+        .users
+        .iter()
+        .any(|user| addr_cmp(user_id_addr(user.id.id()), addr))
+    {
+        bail!("key has no user ID for {}", addr);
+    }
+    Ok(key)
+}
+
+async fn fetch(url: &str) -> Result<Option<Vec<u8>>> {
+    let mut response = surf::get(url).await.map_err(|err| format_err!("{}", err))?;
+    if !response.status().is_success() {
+        return Ok(None);
+    }
+    let body = response
+        .body_bytes()
+        .await
+        .map_err(|err| format_err!("{}", err))?;
+    Ok(Some(body))
+}
+
+/// Looks up the key of `addr` in the Web Key Directory.
+pub(crate) async fn lookup_key(context: &Context, addr: &str) -> Result<Option<SignedPublicKey>> {
+    for url in wkd_urls(addr)? {
+        match fetch(&url).await {
+            Ok(Some(data)) => match parse_key(addr, &data) {
+                Ok(key) => return Ok(Some(key)),
+                Err(err) => warn!(context, "Invalid key from {}: {}", url, err),
+            },
+            Ok(None) => {}
+            Err(err) => info!(context, "WKD lookup at {} failed: {}", url, err),
+        }
+    }
+    Ok(None)
+}
+
+/// Looks up the key of a contact in the Web Key Directory if there is no key for it yet.
+///
+/// Does nothing unless `Config::WkdLookup` is enabled.
+/// Returns true if a key was found and stored.
+pub(crate) async fn import_key_for_contact(context: &Context, contact_id: u32) -> Result<bool> {
+    if !context.get_config_bool(Config::WkdLookup).await? {
+        return Ok(false);
+    }
+    let contact = Contact::load_from_db(context, contact_id).await?;
+    let addr = contact.get_addr();
+    if Peerstate::from_addr(context, addr).await?.is_some() {
+        return Ok(false);
+    }
+    let key = match lookup_key(context, addr).await? {
+        Some(key) => key,
+        None => return Ok(false),
+    };
+
+    info!(context, "Found key for {} in Web Key Directory", addr);
+    let peerstate = Peerstate::from_wkd(addr, key, time());
+    peerstate.save_to_db(&context.sql, true).await?;
+    context.emit_event(EventType::ContactKeyImported {
+        contact_id,
+        source: KeySource::Wkd,
+    });
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zbase32() {
+        // Example from the WKD draft.
+        assert_eq!(
+            zbase32(&Sha1::digest(b"joe.doe")),
+            "iy9q119eutrkn8s1mk4r39qejnbu3n5q"
+        );
+    }
+
+    #[test]
+    fn test_wkd_urls() {
+        assert_eq!(
+            wkd_urls("Joe.Doe@Example.ORG").unwrap(),
+            vec![
+                "https://openpgpkey.example.org/.well-known/openpgpkey/example.org/hu/iy9q119eutrkn8s1mk4r39qejnbu3n5q?l=Joe%2EDoe",
+                "https://example.org/.well-known/openpgpkey/hu/iy9q119eutrkn8s1mk4r39qejnbu3n5q?l=Joe%2EDoe",
+            ]
+        );
+        assert!(wkd_urls("not an address").is_err());
+    }
+
+    #[test]
+    fn test_user_id_addr() {
+        assert_eq!(user_id_addr("Joe Doe <joe@example.org>"), "joe@example.org");
+        assert_eq!(user_id_addr("joe@example.org"), "joe@example.org");
+        assert_eq!(
+            user_id_addr("<evil-joe@example.org>"),
+            "evil-joe@example.org"
+        );
+        assert!(!addr_cmp(
+            user_id_addr("<evil-joe@example.org>"),
+            "joe@example.org"
+        ));
+        assert!(addr_cmp(
+            user_id_addr("Joe <Joe@Example.org>"),
+            "joe@example.org"
+        ));
+    }
+}